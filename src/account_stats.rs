@@ -0,0 +1,183 @@
+//! Submission statistics and health per account.
+//!
+//! Multi-account farms only saw aggregates, so one account the pool rejects, e.g. because its
+//! reward recipient isn't set to the pool, went unnoticed among the others. Besides the outcomes
+//! of its submissions every account keeps the best raw deadline of the recent completed rounds.
+//! The best of `n` uniform raw deadlines is expected around `2^64 / n`, which turns the mean of
+//! those into an estimate of the capacity the account effectively mines with.
+
+use std::collections::VecDeque;
+
+const DEADLINE_RANGE: f64 = 18_446_744_073_709_551_616.0; // 2^64
+/// Completed rounds the capacity is estimated from.
+const CAPACITY_ROUNDS: usize = 360;
+/// Rounds before there is an estimate, a few lucky rounds are far off.
+const MIN_CAPACITY_ROUNDS: usize = 10;
+/// Answered submissions before the acceptance rate counts for the health.
+const MIN_SUBMISSIONS: u64 = 5;
+const TIB: f64 = 1024.0 * 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountHealth {
+    Healthy,
+    /// Most submissions are rejected by the pool.
+    Rejected,
+    /// The deadlines suggest less than half of the plotted capacity.
+    Underperforming,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AccountStats {
+    pub submissions: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub failed: u64,
+    pub stale: u64,
+    /// Best accepted deadline in seconds.
+    pub best_deadline: Option<u64>,
+    plotted_nonces: u64,
+    // best raw deadline of the recent completed rounds
+    round_bests: VecDeque<u64>,
+}
+
+/// What the metrics summary and `/api/status` show of an account.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountSummary {
+    pub submissions: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub failed: u64,
+    pub stale: u64,
+    pub acceptance_rate: Option<f64>,
+    pub best_deadline: Option<u64>,
+    pub plotted_tib: f64,
+    pub effective_tib: Option<f64>,
+    pub health: AccountHealth,
+}
+
+impl AccountStats {
+    pub fn record_accepted(&mut self, deadline: u64) {
+        self.submissions += 1;
+        self.accepted += 1;
+        self.best_deadline = Some(
+            self.best_deadline
+                .map_or(deadline, |best| best.min(deadline)),
+        );
+    }
+
+    pub fn record_rejected(&mut self) {
+        self.submissions += 1;
+        self.rejected += 1;
+    }
+
+    pub fn record_failed(&mut self) {
+        self.submissions += 1;
+        self.failed += 1;
+    }
+
+    pub fn record_stale(&mut self) {
+        self.stale += 1;
+    }
+
+    /// Records the best raw deadline of a completed round. A changed plot set starts the estimate
+    /// over, the old rounds describe other plots.
+    pub fn record_round(&mut self, best_raw: u64, plotted_nonces: u64) {
+        if plotted_nonces != self.plotted_nonces {
+            self.round_bests.clear();
+            self.plotted_nonces = plotted_nonces;
+        }
+        self.round_bests.push_back(best_raw);
+        while self.round_bests.len() > CAPACITY_ROUNDS {
+            self.round_bests.pop_front();
+        }
+    }
+
+    /// Percent of the answered submissions the pool accepted.
+    pub fn acceptance_rate(&self) -> Option<f64> {
+        let answered = self.accepted + self.rejected;
+        (answered > 0).then(|| self.accepted as f64 * 100.0 / answered as f64)
+    }
+
+    /// Nonces the deadlines of the recent rounds suggest.
+    pub fn effective_nonces(&self) -> Option<u64> {
+        if self.round_bests.len() < MIN_CAPACITY_ROUNDS {
+            return None;
+        }
+        let mean = self
+            .round_bests
+            .iter()
+            .map(|&best| best as f64)
+            .sum::<f64>()
+            / self.round_bests.len() as f64;
+        Some((DEADLINE_RANGE / mean.max(1.0)) as u64)
+    }
+
+    pub fn health(&self) -> AccountHealth {
+        if self.accepted + self.rejected >= MIN_SUBMISSIONS
+            && self.acceptance_rate().is_some_and(|rate| rate < 50.0)
+        {
+            return AccountHealth::Rejected;
+        }
+        match self.effective_nonces() {
+            Some(effective) if effective * 2 < self.plotted_nonces => {
+                AccountHealth::Underperforming
+            }
+            _ => AccountHealth::Healthy,
+        }
+    }
+
+    pub fn summary(&self) -> AccountSummary {
+        let tib = |nonces: u64| nonces as f64 * 262_144.0 / TIB;
+        AccountSummary {
+            submissions: self.submissions,
+            accepted: self.accepted,
+            rejected: self.rejected,
+            failed: self.failed,
+            stale: self.stale,
+            acceptance_rate: self.acceptance_rate(),
+            best_deadline: self.best_deadline,
+            plotted_tib: tib(self.plotted_nonces),
+            effective_tib: self.effective_nonces().map(tib),
+            health: self.health(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_health() {
+        let mut stats = AccountStats::default();
+        stats.record_accepted(500);
+        stats.record_accepted(300);
+        stats.record_failed();
+        assert_eq!(stats.best_deadline, Some(300));
+        assert_eq!(stats.acceptance_rate(), Some(100.0));
+        for _ in 0..4 {
+            stats.record_rejected();
+        }
+        assert_eq!(stats.submissions, 7);
+        assert_eq!(stats.health(), AccountHealth::Rejected);
+
+        let mut stats = AccountStats::default();
+        let plotted = 1 << 20;
+        // the best of 2^20 nonces, then of a quarter of them
+        for _ in 0..MIN_CAPACITY_ROUNDS {
+            stats.record_round(1 << 44, plotted);
+        }
+        assert_eq!(stats.effective_nonces(), Some(plotted));
+        assert_eq!(stats.health(), AccountHealth::Healthy);
+        for _ in 0..CAPACITY_ROUNDS {
+            stats.record_round(1 << 46, plotted);
+        }
+        assert_eq!(stats.effective_nonces(), Some(plotted / 4));
+        assert_eq!(stats.health(), AccountHealth::Underperforming);
+
+        stats.record_round(1 << 44, plotted * 2);
+        assert_eq!(stats.effective_nonces(), None);
+        assert_eq!(stats.summary().plotted_tib, 0.5);
+    }
+}
@@ -0,0 +1,122 @@
+//! Bearer tokens of the HTTP API, `api_read_tokens` and `api_admin_tokens`.
+//!
+//! A read token opens the GET endpoints, an admin token every endpoint. Without any token the
+//! GET endpoints are open to everyone who reaches `api_listen`. The POST endpoints change what is
+//! mined, they always need an admin token and stay closed while none is configured.
+
+use crate::http_server::Response;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Admin,
+}
+
+impl Scope {
+    /// Scope needed for a request with `method`.
+    pub fn of(method: &str) -> Scope {
+        match method {
+            "GET" | "HEAD" | "OPTIONS" => Scope::Read,
+            _ => Scope::Admin,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ApiAuth {
+    read: Vec<String>,
+    admin: Vec<String>,
+}
+
+impl ApiAuth {
+    pub fn new(read: Vec<String>, admin: Vec<String>) -> ApiAuth {
+        ApiAuth { read, admin }
+    }
+
+    /// Scope granted by an `Authorization` header, None without a known bearer token.
+    fn granted(&self, authorization: Option<&str>) -> Option<Scope> {
+        let (scheme, token) = authorization?.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+        let token = token.trim();
+        if self.admin.iter().any(|admin| same(admin, token)) {
+            Some(Scope::Admin)
+        } else if self.read.iter().any(|read| same(read, token)) {
+            Some(Scope::Read)
+        } else {
+            None
+        }
+    }
+
+    /// The error response for a request whose `Authorization` header doesn't grant `needed`.
+    pub fn check(&self, authorization: Option<&str>, needed: Scope) -> Result<(), Response> {
+        match needed {
+            Scope::Read if self.read.is_empty() && self.admin.is_empty() => return Ok(()),
+            Scope::Admin if self.admin.is_empty() => {
+                return Err(Response::error(
+                    "403 Forbidden",
+                    "set api_admin_tokens to use this endpoint",
+                ))
+            }
+            _ => {}
+        }
+        match self.granted(authorization) {
+            Some(granted) if granted >= needed => Ok(()),
+            Some(_) => Err(Response::error("403 Forbidden", "needs an admin token")),
+            None => Err(Response::error("401 Unauthorized", "missing or unknown token")),
+        }
+    }
+}
+
+/// Compares without stopping at the first difference, the time taken tells nothing about the
+/// token.
+fn same(expected: &str, token: &str) -> bool {
+    expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(result: Result<(), Response>) -> &'static str {
+        match result {
+            Ok(()) => "200 OK",
+            Err(response) => response.status,
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let open = ApiAuth::default();
+        assert_eq!(status(open.check(None, Scope::Read)), "200 OK");
+        assert_eq!(status(open.check(None, Scope::Admin)), "403 Forbidden");
+
+        let auth = ApiAuth::new(vec!["reader".to_owned()], vec!["admin".to_owned()]);
+        assert_eq!(status(auth.check(None, Scope::Read)), "401 Unauthorized");
+        assert_eq!(
+            status(auth.check(Some("Bearer nobody"), Scope::Read)),
+            "401 Unauthorized"
+        );
+        assert_eq!(
+            status(auth.check(Some("Basic reader"), Scope::Read)),
+            "401 Unauthorized"
+        );
+        assert_eq!(status(auth.check(Some("Bearer reader"), Scope::Read)), "200 OK");
+        assert_eq!(status(auth.check(Some("bearer  admin"), Scope::Read)), "200 OK");
+        assert_eq!(
+            status(auth.check(Some("Bearer reader"), Scope::Admin)),
+            "403 Forbidden"
+        );
+        assert_eq!(status(auth.check(Some("Bearer admin"), Scope::Admin)), "200 OK");
+
+        // an admin token alone closes the GET endpoints too
+        let admin_only = ApiAuth::new(Vec::new(), vec!["admin".to_owned()]);
+        assert_eq!(status(admin_only.check(None, Scope::Read)), "401 Unauthorized");
+    }
+}
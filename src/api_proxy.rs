@@ -0,0 +1,181 @@
+//! The HTTP API behind a reverse proxy and in the browser: `api_base_path`, `api_cors_origins`
+//! and `api_trusted_proxies`.
+//!
+//! A proxy that passes the path on unchanged, e.g. `location /miner/` in nginx, is served with
+//! `api_base_path: /miner`. Pages of other origins may call the API once their origin is allowed,
+//! the preflight `OPTIONS` requests of browsers are answered without a token. Behind a trusted
+//! proxy the API's logs name the client from `X-Forwarded-For` instead of the proxy.
+
+use crate::http_server::{Request, Response};
+use std::net::IpAddr;
+
+// how long browsers may cache a preflight answer, in seconds
+const PREFLIGHT_MAX_AGE: u32 = 600;
+
+#[derive(Default)]
+pub struct ApiProxy {
+    base_path: String,
+    cors_origins: Vec<String>,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+/// `base_path` with a leading and without a trailing slash, empty for the root.
+pub fn normalize_base_path(base_path: &str) -> String {
+    let base_path = base_path.trim_matches('/');
+    if base_path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", base_path)
+    }
+}
+
+impl ApiProxy {
+    pub fn new(base_path: &str, cors_origins: Vec<String>, trusted_proxies: Vec<IpAddr>) -> ApiProxy {
+        ApiProxy {
+            base_path: normalize_base_path(base_path),
+            cors_origins,
+            trusted_proxies,
+        }
+    }
+
+    /// `path` below the base path, None for paths outside of it.
+    pub fn endpoint<'a>(&self, path: &'a str) -> Option<&'a str> {
+        match path.strip_prefix(self.base_path.as_str())? {
+            "" => Some("/"),
+            endpoint if endpoint.starts_with('/') => Some(endpoint),
+            _ => None,
+        }
+    }
+
+    /// The origin a response may be shared with, for the request's `Origin` header.
+    fn allowed_origin<'a>(&self, origin: Option<&'a str>) -> Option<&'a str> {
+        let origin = origin?;
+        self.cors_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+            .then_some(origin)
+    }
+
+    /// Lets the page of an allowed origin read `response`.
+    pub fn share(&self, request: &Request, mut response: Response) -> Response {
+        if let Some(origin) = self.allowed_origin(request.header("origin")) {
+            response
+                .headers
+                .push(("Access-Control-Allow-Origin", origin.to_owned()));
+            response.headers.push(("Vary", "Origin".to_owned()));
+        }
+        response
+    }
+
+    /// The answer to a browser's preflight request, None for other requests.
+    pub fn preflight(&self, request: &Request) -> Option<Response> {
+        if request.method != "OPTIONS" || request.header("access-control-request-method").is_none()
+        {
+            return None;
+        }
+        let mut response = Response {
+            status: "200 OK",
+            content_type: "text/plain",
+            headers: Vec::new(),
+            body: String::new(),
+        };
+        if self.allowed_origin(request.header("origin")).is_some() {
+            response.headers = vec![
+                ("Access-Control-Allow-Methods", "GET, POST".to_owned()),
+                (
+                    "Access-Control-Allow-Headers",
+                    "Authorization, Content-Type".to_owned(),
+                ),
+                ("Access-Control-Max-Age", PREFLIGHT_MAX_AGE.to_string()),
+            ];
+        }
+        Some(self.share(request, response))
+    }
+
+    /// The client of a request from `peer`. For a trusted proxy that's the last address in
+    /// `X-Forwarded-For` that isn't one of the trusted proxies.
+    pub fn client(&self, peer: IpAddr, request: &Request) -> IpAddr {
+        if !self.trusted_proxies.contains(&peer) {
+            return peer;
+        }
+        let forwarded = request.header("x-forwarded-for").unwrap_or_default();
+        forwarded
+            .rsplit(',')
+            .map_while(|address| address.trim().parse::<IpAddr>().ok())
+            .find(|address| !self.trusted_proxies.contains(address))
+            .unwrap_or(peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request(method: &str, headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: method.to_owned(),
+            path: "/".to_owned(),
+            headers: headers
+                .iter()
+                .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+            params: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let proxy = ApiProxy::new("miner/", Vec::new(), Vec::new());
+        assert_eq!(proxy.endpoint("/miner/api/status"), Some("/api/status"));
+        assert_eq!(proxy.endpoint("/miner"), Some("/"));
+        assert_eq!(proxy.endpoint("/miner2/api/status"), None);
+        assert_eq!(proxy.endpoint("/api/status"), None);
+        let root = ApiProxy::default();
+        assert_eq!(root.endpoint("/api/status"), Some("/api/status"));
+    }
+
+    #[test]
+    fn test_cors() {
+        let proxy = ApiProxy::new("", vec!["https://farm.example".to_owned()], Vec::new());
+        let preflight = request(
+            "OPTIONS",
+            &[
+                ("origin", "https://farm.example"),
+                ("access-control-request-method", "POST"),
+            ],
+        );
+        let response = proxy.preflight(&preflight).unwrap();
+        assert!(response
+            .headers
+            .contains(&("Access-Control-Allow-Origin", "https://farm.example".to_owned())));
+        assert!(response
+            .headers
+            .iter()
+            .any(|(name, _)| *name == "Access-Control-Allow-Headers"));
+
+        let other = request("GET", &[("origin", "https://evil.example")]);
+        let response = proxy.share(&other, Response::json(String::new()));
+        assert!(response.headers.is_empty());
+        assert!(proxy.preflight(&other).is_none());
+    }
+
+    #[test]
+    fn test_client() {
+        let proxy = ApiProxy::new("", Vec::new(), vec!["10.0.0.1".parse().unwrap()]);
+        let forwarded = request("GET", &[("x-forwarded-for", "1.2.3.4, 5.6.7.8, 10.0.0.1")]);
+        assert_eq!(
+            proxy.client("10.0.0.1".parse().unwrap(), &forwarded),
+            "5.6.7.8".parse::<IpAddr>().unwrap()
+        );
+        // anybody else could claim any address
+        assert_eq!(
+            proxy.client("5.6.7.8".parse().unwrap(), &forwarded),
+            "5.6.7.8".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            proxy.client("10.0.0.1".parse().unwrap(), &request("GET", &[])),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+}
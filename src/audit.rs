@@ -0,0 +1,264 @@
+//! Append-only audit log of nonce submissions.
+//!
+//! Every submission attempt is written as one JSON object per line to
+//! `<dir>/submissions-YYYY-MM-DD.jsonl` (UTC), so a new file is started every day. The files are
+//! meant as evidence when pool payouts don't match what the miner submitted.
+//!
+//! With `audit_backfill_hours` the recent records are replayed into the metrics at startup, so a
+//! restart doesn't reset the success rates and best deadlines to zero.
+
+use crate::com::api::RejectionReason;
+use crate::metrics::MinerMetrics;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize)]
+pub struct SubmissionRecord<'a> {
+    pub timestamp_ms: u64,
+    pub height: u64,
+    pub account_id: u64,
+    pub nonce: u64,
+    pub deadline: u64,
+    pub deadline_unadjusted: u64,
+    pub pool: &'a str,
+    pub attempt: u32,
+    pub result: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_deadline: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+}
+
+/// The part of a record the metrics are rebuilt from.
+#[derive(Deserialize)]
+struct ReplayedRecord {
+    timestamp_ms: u64,
+    account_id: u64,
+    deadline: u64,
+    result: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+pub struct AuditLog {
+    dir: PathBuf,
+    current: Option<(String, File)>,
+}
+
+impl AuditLog {
+    pub fn new(dir: PathBuf) -> Self {
+        AuditLog { dir, current: None }
+    }
+
+    pub fn write(&mut self, record: &SubmissionRecord) {
+        if let Err(e) = self.try_write(record) {
+            error!("audit log: can't write to {}: {}", self.dir.display(), e);
+        }
+    }
+
+    fn try_write(&mut self, record: &SubmissionRecord) -> std::io::Result<()> {
+        let date = utc_date(record.timestamp_ms / 1000);
+        if self
+            .current
+            .as_ref()
+            .map(|(d, _)| d != &date)
+            .unwrap_or(true)
+        {
+            fs::create_dir_all(&self.dir)?;
+            let path = self.dir.join(format!("submissions-{}.jsonl", date));
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            self.current = Some((date, file));
+        }
+
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        // single write call per line, so a crash never leaves interleaved records
+        let (_, file) = self.current.as_mut().unwrap();
+        file.write_all(&line)
+    }
+}
+
+/// Replays the records in `dir` written since `since_ms` into `metrics`, returns how many counted.
+/// Lines that don't parse, like one cut short by a crash, are skipped.
+pub fn backfill(dir: &Path, since_ms: u64, metrics: &mut MinerMetrics) -> usize {
+    let first = format!("submissions-{}.jsonl", utc_date(since_ms / 1000));
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("submissions-") && name >= first.as_str())
+            })
+            .collect(),
+        Err(e) => {
+            warn!("audit log: can't backfill from {}: {}", dir.display(), e);
+            return 0;
+        }
+    };
+    files.sort();
+
+    let mut replayed = 0;
+    let mut last_accepted = None;
+    for path in files {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("audit log: can't read {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        for record in text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<ReplayedRecord>(line).ok())
+            .filter(|record| record.timestamp_ms >= since_ms)
+        {
+            match record.result.as_str() {
+                "accepted" => {
+                    metrics.record_submission_success(record.account_id, record.deadline);
+                    last_accepted = Some(record.timestamp_ms);
+                }
+                "rejected" | "pool_busy" => metrics.record_rejection(
+                    record.account_id,
+                    record
+                        .reason
+                        .as_deref()
+                        .and_then(RejectionReason::parse)
+                        .unwrap_or(RejectionReason::Other),
+                ),
+                "failed" => metrics.record_submission_failure(record.account_id),
+                "stale" => metrics.record_stale_submission(record.account_id),
+                _ => continue,
+            }
+            replayed += 1;
+        }
+    }
+    // the last submission happened before the restart, not now
+    metrics.last_submission = last_accepted.and_then(|timestamp_ms| {
+        Instant::now().checked_sub(Duration::from_millis(now_ms().saturating_sub(timestamp_ms)))
+    });
+    replayed
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD` (UTC).
+fn utc_date(unix_secs: u64) -> String {
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = (unix_secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utc_date() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(1_700_000_000), "2023-11-14");
+    }
+
+    #[test]
+    fn test_record_is_single_line() {
+        let record = SubmissionRecord {
+            timestamp_ms: 1,
+            height: 2,
+            account_id: 3,
+            nonce: 4,
+            deadline: 5,
+            deadline_unadjusted: 6,
+            pool: "http://pool",
+            attempt: 1,
+            result: "rejected",
+            pool_deadline: None,
+            error_code: Some(1004),
+            message: Some("line\nbreak"),
+            reason: None,
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(!json.contains('\n'));
+        assert!(!json.contains("pool_deadline"));
+    }
+
+    #[test]
+    fn test_backfill() {
+        let dir = std::env::temp_dir().join(format!(
+            "signum-miner-audit-backfill-{}",
+            std::process::id()
+        ));
+        let record = |timestamp_ms, result, reason| SubmissionRecord {
+            timestamp_ms,
+            height: 2,
+            account_id: 7,
+            nonce: 4,
+            deadline: timestamp_ms,
+            deadline_unadjusted: 0,
+            pool: "http://pool",
+            attempt: 1,
+            result,
+            pool_deadline: None,
+            error_code: None,
+            message: None,
+            reason,
+        };
+        let mut log = AuditLog::new(dir.clone());
+        // the day before is too old
+        log.write(&record(1_699_900_000_000, "accepted", None));
+        log.write(&record(1_700_000_000_000, "accepted", None));
+        log.write(&record(1_700_000_001_000, "accepted", None));
+        log.write(&record(
+            1_700_000_002_000,
+            "rejected",
+            Some("unknown_account"),
+        ));
+        log.write(&record(1_700_000_003_000, "failed", None));
+        log.write(&record(1_700_000_004_000, "duplicate", None));
+        drop(log);
+        // a line cut short by a crash
+        OpenOptions::new()
+            .append(true)
+            .open(dir.join("submissions-2023-11-14.jsonl"))
+            .unwrap()
+            .write_all(b"{\"timestamp_ms\":")
+            .unwrap();
+
+        let mut metrics = MinerMetrics::new();
+        assert_eq!(backfill(&dir, 1_699_999_000_000, &mut metrics), 4);
+        assert_eq!(
+            (
+                metrics.total_submissions,
+                metrics.successful_submissions,
+                metrics.failed_submissions
+            ),
+            (4, 2, 2)
+        );
+        assert_eq!(
+            metrics.rejections_by_reason[&RejectionReason::UnknownAccount],
+            1
+        );
+        assert_eq!(metrics.best_deadlines[&7], 1_700_000_000_000);
+        assert!(metrics.last_submission.is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+//! `bench-hash`: nonces per second of every hashing backend built into this binary.
+//!
+//! The CPU backends are the scalar Rust implementation, which is always built, and the SIMD
+//! extension selected at build time. Both hash the same random scoops on `cpu_threads` threads and
+//! have to agree on the best deadline. A GPU backend, if the binary has one, hashes on the
+//! configured device. `--pin` writes the faster CPU backend to the config as `cpu_hasher`.
+
+use crate::config::Cfg;
+use crate::cpu_worker::{find_best_deadline, CpuHasher};
+use crate::hardware::simd_extension;
+use rand::Rng;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Nonces hashed per call, the size of a typical read buffer.
+const CHUNK_NONCES: usize = 65536;
+const SCOOP_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub backend: String,
+    /// What `cpu_hasher` selects this backend, none for GPUs.
+    pub hasher: Option<CpuHasher>,
+    pub threads: usize,
+    pub nonces_per_sec: f64,
+    /// False if the best deadline differs from the scalar implementation's.
+    pub verified: bool,
+}
+
+/// `bench-hash`: prints the results as a table or as one JSON document. False if a backend got the
+/// deadlines wrong or pinning failed.
+pub fn bench_hash(
+    cfg: &Cfg,
+    config: &str,
+    duration: Duration,
+    pin_fastest: bool,
+    json: bool,
+) -> bool {
+    let results = run(cfg, duration);
+    if json {
+        match serde_json::to_string(&results) {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("bench-hash: can't serialize results: {}", e),
+        }
+    } else {
+        print_table(&results);
+    }
+    let verified = results.iter().all(|result| result.verified);
+    if !verified {
+        error!("bench-hash: a backend found different deadlines than the scalar implementation");
+    }
+
+    if pin_fastest {
+        let hasher = match fastest_cpu(&results) {
+            Some(hasher) => hasher,
+            None => {
+                error!("bench-hash: no CPU backend hashed correctly, nothing pinned");
+                return false;
+            }
+        };
+        if let Err(e) = pin(Path::new(config), hasher) {
+            error!("bench-hash: can't write {}: {}", config, e);
+            return false;
+        }
+        info!(
+            "bench-hash: cpu_hasher '{}' written to {}",
+            hasher.name(),
+            config
+        );
+    }
+    verified
+}
+
+fn run(cfg: &Cfg, duration: Duration) -> Vec<BenchResult> {
+    let mut rng = rand::thread_rng();
+    let mut data = vec![0u8; CHUNK_NONCES * SCOOP_SIZE];
+    rng.fill(&mut data[..]);
+    let mut gensig = [0u8; 32];
+    rng.fill(&mut gensig[..]);
+    let threads = cfg.cpu_threads.max(1);
+
+    let expected = find_best_deadline(&data, CHUNK_NONCES as u64, &gensig, CpuHasher::Scalar);
+    let mut results = vec![bench_cpu(
+        "scalar".to_owned(),
+        CpuHasher::Scalar,
+        threads,
+        duration,
+        &data,
+        &gensig,
+        expected,
+    )];
+    if simd_extension() != "none" {
+        results.push(bench_cpu(
+            simd_extension().to_owned(),
+            CpuHasher::Auto,
+            threads,
+            duration,
+            &data,
+            &gensig,
+            expected,
+        ));
+    }
+    if let Some(result) = bench_gpu(cfg, duration, &data, gensig, expected) {
+        results.push(result);
+    }
+    results
+}
+
+fn bench_cpu(
+    backend: String,
+    hasher: CpuHasher,
+    threads: usize,
+    duration: Duration,
+    data: &[u8],
+    gensig: &[u8; 32],
+    expected: (u64, u64),
+) -> BenchResult {
+    let verified = find_best_deadline(data, CHUNK_NONCES as u64, gensig, hasher) == expected;
+    let start = Instant::now();
+    let hashed: u64 = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut hashed = 0;
+                    while start.elapsed() < duration {
+                        black_box(find_best_deadline(
+                            black_box(data),
+                            CHUNK_NONCES as u64,
+                            gensig,
+                            hasher,
+                        ));
+                        hashed += CHUNK_NONCES as u64;
+                    }
+                    hashed
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap_or(0)).sum()
+    });
+    BenchResult {
+        backend,
+        hasher: Some(hasher),
+        threads,
+        nonces_per_sec: hashed as f64 / start.elapsed().as_secs_f64(),
+        verified,
+    }
+}
+
+#[cfg(feature = "opencl")]
+fn bench_gpu(
+    cfg: &Cfg,
+    duration: Duration,
+    _data: &[u8],
+    gensig: [u8; 32],
+    _expected: (u64, u64),
+) -> Option<BenchResult> {
+    use crate::miner::Buffer;
+    use crate::ocl::{gpu_hash, gpu_transfer, GpuBuffer, GpuContext, KernelSource};
+    use std::sync::Arc;
+
+    let kernel_src = match KernelSource::from_cfg(cfg).load() {
+        Ok(src) => src,
+        Err(e) => {
+            error!("bench-hash: OpenCL: {}", e);
+            return None;
+        }
+    };
+    let context = Arc::new(GpuContext::new(
+        cfg.gpu_platform,
+        cfg.gpu_device,
+        cfg.gpu_nonces_per_cache,
+        false,
+        &kernel_src,
+    ));
+    let buffer = GpuBuffer::new(&context, 1);
+    let data_gpu = buffer.get_gpu_data()?;
+    let start = Instant::now();
+    let mut hashed = 0;
+    while start.elapsed() < duration {
+        gpu_transfer(&context, &buffer, gensig);
+        black_box(gpu_hash(&context, cfg.gpu_nonces_per_cache, &data_gpu));
+        hashed += cfg.gpu_nonces_per_cache as u64;
+    }
+    Some(BenchResult {
+        backend: "OpenCL".to_owned(),
+        hasher: None,
+        threads: 1,
+        nonces_per_sec: hashed as f64 / start.elapsed().as_secs_f64(),
+        // the device hashes zeroed buffers here, its results are covered by `selftest`
+        verified: true,
+    })
+}
+
+#[cfg(any(feature = "metal", feature = "wgpu"))]
+fn bench_gpu(
+    cfg: &Cfg,
+    duration: Duration,
+    data: &[u8],
+    gensig: [u8; 32],
+    expected: (u64, u64),
+) -> Option<BenchResult> {
+    use crate::gpu_worker_host::HostGpu;
+
+    #[cfg(feature = "metal")]
+    let (backend, context) = (
+        "Metal",
+        crate::mtl::MetalContext::new(cfg.gpu_device, cfg.gpu_nonces_per_cache),
+    );
+    #[cfg(feature = "wgpu")]
+    let (backend, context) = match crate::wgpu_backend::WgpuContext::new(
+        cfg.gpu_device,
+        cfg.gpu_nonces_per_cache,
+        cfg.gpu_workgroup_size,
+    ) {
+        Ok(context) => ("wgpu", context),
+        Err(e) => {
+            error!("bench-hash: wgpu: {}", e);
+            return None;
+        }
+    };
+
+    let nonces = cfg.gpu_nonces_per_cache.min(CHUNK_NONCES);
+    let chunk = &data[..nonces * SCOOP_SIZE];
+    let verified = nonces < CHUNK_NONCES || context.hash(chunk, &gensig) == expected;
+    let start = Instant::now();
+    let mut hashed = 0;
+    while start.elapsed() < duration {
+        black_box(context.hash(chunk, &gensig));
+        hashed += nonces as u64;
+    }
+    Some(BenchResult {
+        backend: backend.to_owned(),
+        hasher: None,
+        threads: 1,
+        nonces_per_sec: hashed as f64 / start.elapsed().as_secs_f64(),
+        verified,
+    })
+}
+
+#[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
+fn bench_gpu(
+    _cfg: &Cfg,
+    _duration: Duration,
+    _data: &[u8],
+    _gensig: [u8; 32],
+    _expected: (u64, u64),
+) -> Option<BenchResult> {
+    None
+}
+
+fn print_table(results: &[BenchResult]) {
+    println!(
+        "{:<10} {:>8} {:>16} {:>12}  cpu_hasher",
+        "backend", "threads", "nonces/s", "MiB/s"
+    );
+    for result in results {
+        println!(
+            "{:<10} {:>8} {:>16.0} {:>12.1}  {}{}",
+            result.backend,
+            result.threads,
+            result.nonces_per_sec,
+            result.nonces_per_sec * SCOOP_SIZE as f64 / 1024.0 / 1024.0,
+            result.hasher.map_or("-", CpuHasher::name),
+            if result.verified {
+                ""
+            } else {
+                "  (wrong deadlines!)"
+            }
+        );
+    }
+}
+
+/// The faster of the CPU backends that found the right deadlines.
+fn fastest_cpu(results: &[BenchResult]) -> Option<CpuHasher> {
+    results
+        .iter()
+        .filter(|result| result.verified)
+        .filter_map(|result| result.hasher.map(|hasher| (hasher, result.nonces_per_sec)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(hasher, _)| hasher)
+}
+
+/// Sets `cpu_hasher` in the config file, the rest of the file is left as it is.
+fn pin(config: &Path, hasher: CpuHasher) -> io::Result<()> {
+    let text = fs::read_to_string(config)?;
+    fs::write(config, with_cpu_hasher(&text, hasher))
+}
+
+fn with_cpu_hasher(text: &str, hasher: CpuHasher) -> String {
+    let line = format!("cpu_hasher: '{}'", hasher.name());
+    let mut found = false;
+    let mut lines: Vec<String> = text
+        .lines()
+        .map(|l| {
+            if l.starts_with("cpu_hasher:") {
+                found = true;
+                // keep the comment aligned behind the value
+                match l.find('#') {
+                    Some(i) if i > line.len() => format!("{:<w$}{}", line, &l[i..], w = i),
+                    _ => line.clone(),
+                }
+            } else {
+                l.to_owned()
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(line);
+    }
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_cpu_hasher() {
+        let text =
+            "url: 'x'\ncpu_hasher: 'auto'                    # default auto\ncpu_threads: 0\n";
+        assert_eq!(
+            with_cpu_hasher(text, CpuHasher::Scalar),
+            "url: 'x'\ncpu_hasher: 'scalar'                  # default auto\ncpu_threads: 0\n"
+        );
+        assert_eq!(
+            with_cpu_hasher("url: 'x'\n", CpuHasher::Auto),
+            "url: 'x'\ncpu_hasher: 'auto'\n"
+        );
+    }
+
+    #[test]
+    fn test_fastest_cpu_skips_wrong_results() {
+        let result = |hasher, nonces_per_sec, verified| BenchResult {
+            backend: String::new(),
+            hasher,
+            threads: 1,
+            nonces_per_sec,
+            verified,
+        };
+        let results = [
+            result(Some(CpuHasher::Scalar), 10.0, true),
+            result(Some(CpuHasher::Auto), 50.0, false),
+            result(None, 100.0, true),
+        ];
+        assert_eq!(fastest_cpu(&results), Some(CpuHasher::Scalar));
+    }
+}
@@ -0,0 +1,311 @@
+//! Free list for the read buffers.
+//!
+//! Readers take empty buffers and workers give them back once hashed. With dozens of readers and
+//! workers a single channel becomes a contention point, so the pool is split into lock-free
+//! shards: every thread pushes to and pops from its own shard first and steals from the others
+//! when it's empty. Only a reader that finds the whole pool empty takes a lock to sleep.
+//!
+//! The pool counts the buffers of every consumer (the CPU workers and each GPU, by buffer id)
+//! and how many of them are free. The counters are shared with the metrics, so a front end can
+//! see live whether buffers pile up in the pool or are all in flight.
+
+use crate::miner::Buffer;
+use crossbeam_queue::ArrayQueue;
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+// upper bound for a sleeping reader to recheck the shards, covers a missed wakeup
+const WAIT_TIMEOUT: Duration = Duration::from_millis(10);
+
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_INDEX: Cell<usize> = const { Cell::new(usize::MAX) };
+}
+
+fn thread_index() -> usize {
+    THREAD_INDEX.with(|index| {
+        if index.get() == usize::MAX {
+            index.set(NEXT_THREAD.fetch_add(1, Ordering::Relaxed));
+        }
+        index.get()
+    })
+}
+
+/// Total and free buffers per consumer, indexed by buffer id.
+#[derive(Debug)]
+pub struct BufferCounters {
+    consumers: Vec<ConsumerCounters>,
+}
+
+#[derive(Debug, Default)]
+struct ConsumerCounters {
+    total: AtomicUsize,
+    free: AtomicUsize,
+}
+
+impl BufferCounters {
+    fn new(capacity: usize) -> BufferCounters {
+        // buffer ids are 0 for the CPU and 1.. for the GPUs, every id has at least one buffer
+        BufferCounters {
+            consumers: (0..=capacity).map(|_| ConsumerCounters::default()).collect(),
+        }
+    }
+
+    fn get(&self, id: usize) -> Option<&ConsumerCounters> {
+        self.consumers.get(id)
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            consumers: self
+                .consumers
+                .iter()
+                .enumerate()
+                .map(|(id, counters)| ConsumerStats {
+                    id,
+                    total: counters.total.load(Ordering::Relaxed),
+                    free: counters.free.load(Ordering::Relaxed),
+                })
+                .filter(|consumer| consumer.total > 0)
+                .collect(),
+        }
+    }
+}
+
+/// Snapshot of the buffer counters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BufferPoolStats {
+    pub consumers: Vec<ConsumerStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ConsumerStats {
+    pub id: usize,
+    pub total: usize,
+    pub free: usize,
+}
+
+impl ConsumerStats {
+    /// Buffers being read into, queued for or being hashed.
+    pub fn in_flight(&self) -> usize {
+        self.total.saturating_sub(self.free)
+    }
+
+    pub fn name(&self) -> String {
+        match self.id {
+            0 => "cpu".to_owned(),
+            id => format!("gpu{}", id),
+        }
+    }
+}
+
+impl BufferPoolStats {
+    pub fn total(&self) -> usize {
+        self.consumers.iter().map(|consumer| consumer.total).sum()
+    }
+
+    pub fn free(&self) -> usize {
+        self.consumers.iter().map(|consumer| consumer.free).sum()
+    }
+}
+
+impl fmt::Display for BufferPoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{} free", self.free(), self.total())?;
+        for consumer in &self.consumers {
+            write!(
+                f,
+                ", {}: {} in flight",
+                consumer.name(),
+                consumer.in_flight()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct BufferPool {
+    shards: Vec<ArrayQueue<Box<dyn Buffer + Send>>>,
+    counters: Arc<BufferCounters>,
+    waiters: AtomicUsize,
+    lock: Mutex<()>,
+    available: Condvar,
+}
+
+impl BufferPool {
+    /// Creates an empty pool for up to `capacity` buffers split into `shard_count` shards.
+    pub fn new(capacity: usize, shard_count: usize) -> BufferPool {
+        let shard_count = shard_count.clamp(1, capacity.max(1));
+        BufferPool {
+            // every shard can hold all buffers, so a push never fails
+            shards: (0..shard_count)
+                .map(|_| ArrayQueue::new(capacity.max(1)))
+                .collect(),
+            counters: Arc::new(BufferCounters::new(capacity)),
+            waiters: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Adds a new buffer to the pool, unlike `push` which returns a buffer taken from it.
+    pub fn add(&self, buffer: Box<dyn Buffer + Send>) {
+        if let Some(counters) = self.counters.get(buffer.get_id()) {
+            counters.total.fetch_add(1, Ordering::Relaxed);
+        }
+        self.push(buffer);
+    }
+
+    pub fn push(&self, buffer: Box<dyn Buffer + Send>) {
+        let shard = thread_index() % self.shards.len();
+        if let Err(buffer) = self.push_to(shard, buffer) {
+            // only possible if more buffers than the capacity were pushed
+            error!("buffer pool: shard full, dropping buffer {}", buffer.get_id());
+            return;
+        }
+        self.wake();
+    }
+
+    /// Returns several buffers at once, waking the sleeping readers only once.
+    pub fn push_batch(&self, buffers: impl IntoIterator<Item = Box<dyn Buffer + Send>>) {
+        let shard = thread_index() % self.shards.len();
+        for buffer in buffers {
+            if let Err(buffer) = self.push_to(shard, buffer) {
+                error!("buffer pool: shard full, dropping buffer {}", buffer.get_id());
+            }
+        }
+        self.wake();
+    }
+
+    pub fn try_pop(&self) -> Option<Box<dyn Buffer + Send>> {
+        let first = thread_index();
+        let buffer = (0..self.shards.len())
+            .find_map(|i| self.shards[(first + i) % self.shards.len()].pop())?;
+        if let Some(counters) = self.counters.get(buffer.get_id()) {
+            counters.free.fetch_sub(1, Ordering::Relaxed);
+        }
+        Some(buffer)
+    }
+
+    /// Live counters, for the metrics.
+    pub fn counters(&self) -> Arc<BufferCounters> {
+        self.counters.clone()
+    }
+
+    // counted as free before it's visible in the shard, a pop never takes the counter below zero
+    fn push_to(
+        &self,
+        shard: usize,
+        buffer: Box<dyn Buffer + Send>,
+    ) -> Result<(), Box<dyn Buffer + Send>> {
+        let counters = self.counters.get(buffer.get_id());
+        if let Some(counters) = counters {
+            counters.free.fetch_add(1, Ordering::Relaxed);
+        }
+        self.shards[shard].push(buffer).inspect_err(|_| {
+            if let Some(counters) = counters {
+                counters.free.fetch_sub(1, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Takes a buffer, sleeps until one is returned if the pool is empty.
+    pub fn pop(&self) -> Box<dyn Buffer + Send> {
+        if let Some(buffer) = self.try_pop() {
+            return buffer;
+        }
+
+        let mut guard = self.lock();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        loop {
+            if let Some(buffer) = self.try_pop() {
+                self.waiters.fetch_sub(1, Ordering::SeqCst);
+                return buffer;
+            }
+            guard = match self.available.wait_timeout(guard, WAIT_TIMEOUT) {
+                Ok((guard, _)) => guard,
+                Err(poisoned) => {
+                    error!("buffer pool: mutex poisoned, recovering...");
+                    poisoned.into_inner().0
+                }
+            };
+        }
+    }
+
+    fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock();
+            self.available.notify_all();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        match self.lock.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("buffer pool: mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::CpuBuffer;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_pop_steals_from_other_shards() {
+        let pool = Arc::new(BufferPool::new(4, 4));
+        let pusher = pool.clone();
+        thread::spawn(move || {
+            pusher.push_batch((0..4).map(|_| Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>))
+        })
+        .join()
+        .unwrap();
+
+        for _ in 0..4 {
+            assert!(pool.try_pop().is_some());
+        }
+        assert!(pool.try_pop().is_none());
+    }
+
+    #[test]
+    fn test_pop_waits_for_push() {
+        let pool = Arc::new(BufferPool::new(1, 2));
+        let pusher = pool.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            pusher.push(Box::new(CpuBuffer::new(0)));
+        });
+        pool.pop();
+        handle.join().unwrap();
+        assert!(pool.try_pop().is_none());
+    }
+
+    #[test]
+    fn test_stats() {
+        let pool = BufferPool::new(3, 1);
+        pool.add(Box::new(CpuBuffer::new(0)));
+        pool.add(Box::new(CpuBuffer::new(0)));
+        assert_eq!(pool.counters().stats().to_string(), "2/2 free, cpu: 0 in flight");
+
+        let buffer = pool.pop();
+        let stats = pool.counters().stats();
+        assert_eq!(stats.total(), 2);
+        assert_eq!(stats.free(), 1);
+        assert_eq!(stats.consumers[0].in_flight(), 1);
+
+        pool.push(buffer);
+        assert_eq!(pool.counters().stats().free(), 2);
+    }
+}
@@ -0,0 +1,174 @@
+//! How much of the plot data came from the page cache instead of the disk, per drive.
+//!
+//! Buffered reads of plots that are still in memory, after `page_cache: warm` or because a small
+//! farm was just read, run at memory speed. The read speeds then flatter the disks and say nothing
+//! about whether they keep up once the cache is gone. On Linux 6.5 and later `cachestat` counts
+//! the cached pages of a region before it's read. Elsewhere, or where the kernel refuses it, a
+//! read faster than `MEMORY_SPEED_MIBS` counts as served from the cache. Direct I/O always goes to
+//! the disk and isn't looked at.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Faster than a single disk delivers buffered reads.
+const MEMORY_SPEED_MIBS: f64 = 6.0 * 1024.0;
+/// Shorter reads are too quick to time.
+const MIN_TIMED_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct DriveCache {
+    pub read_bytes: u64,
+    pub cached_bytes: u64,
+}
+
+impl DriveCache {
+    /// Percent of the bytes read that came from memory.
+    pub fn cached_share(&self) -> f64 {
+        if self.read_bytes == 0 {
+            return 0.0;
+        }
+        self.cached_bytes as f64 * 100.0 / self.read_bytes as f64
+    }
+}
+
+static DRIVES: Mutex<BTreeMap<String, DriveCache>> = Mutex::new(BTreeMap::new());
+// cachestat failed for good, timing from now on
+static NO_CACHESTAT: AtomicBool = AtomicBool::new(false);
+
+fn drives() -> MutexGuard<'static, BTreeMap<String, DriveCache>> {
+    match DRIVES.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("cache stats: mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+/// Bytes of the `len` at `start` of `fh` that are in the page cache, None without cachestat.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn cached_bytes<F: std::os::unix::io::AsRawFd>(fh: &F, start: u64, len: usize) -> Option<u64> {
+    // the same number on every architecture
+    const SYS_CACHESTAT: libc::c_long = 451;
+
+    // read by the kernel only
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Range {
+        off: u64,
+        len: u64,
+    }
+    #[allow(dead_code)]
+    #[repr(C)]
+    #[derive(Default)]
+    struct Stat {
+        nr_cache: u64,
+        nr_dirty: u64,
+        nr_writeback: u64,
+        nr_evicted: u64,
+        nr_recently_evicted: u64,
+    }
+
+    if NO_CACHESTAT.load(Ordering::Relaxed) {
+        return None;
+    }
+    let range = Range {
+        off: start,
+        len: len as u64,
+    };
+    let mut stat = Stat::default();
+    let ret = unsafe {
+        libc::syscall(
+            SYS_CACHESTAT,
+            fh.as_raw_fd(),
+            &range as *const Range,
+            &mut stat as *mut Stat,
+            0,
+        )
+    };
+    if ret != 0 {
+        let e = std::io::Error::last_os_error();
+        if matches!(e.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EPERM))
+            && !NO_CACHESTAT.swap(true, Ordering::Relaxed)
+        {
+            debug!(
+                "cache stats: no cachestat ({}), timing the reads instead",
+                e
+            );
+        }
+        return None;
+    }
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+    Some((stat.nr_cache * page_size).min(len as u64))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn cached_bytes<F>(_fh: &F, _start: u64, _len: usize) -> Option<u64> {
+    None
+}
+
+/// `len` if reading it took memory speed, 0 if it took a disk.
+pub fn by_timing(len: usize, elapsed: Duration) -> u64 {
+    if len < MIN_TIMED_BYTES {
+        return 0;
+    }
+    let mibs = len as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64().max(1e-9);
+    if mibs > MEMORY_SPEED_MIBS {
+        len as u64
+    } else {
+        0
+    }
+}
+
+pub fn record(drive: &str, read_bytes: u64, cached_bytes: u64) {
+    if read_bytes == 0 {
+        return;
+    }
+    let mut drives = drives();
+    let stats = drives.entry(drive.to_owned()).or_default();
+    stats.read_bytes += read_bytes;
+    stats.cached_bytes += cached_bytes.min(read_bytes);
+}
+
+pub fn snapshot() -> BTreeMap<String, DriveCache> {
+    drives().clone()
+}
+
+/// Lines for the metrics summary, only drives that were served from the cache at all.
+pub fn summary() -> String {
+    let mut summary = String::new();
+    for (drive, stats) in drives().iter().filter(|(_, stats)| stats.cached_bytes > 0) {
+        summary.push_str(&format!(
+            "  {}: {:.1}% of {:.2} GiB from memory\n",
+            drive,
+            stats.cached_share(),
+            stats.read_bytes as f64 / 1024.0 / 1024.0 / 1024.0
+        ));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_stats() {
+        let mib = 1024 * 1024;
+        assert_eq!(
+            by_timing(64 * mib, Duration::from_millis(2)),
+            64 * mib as u64
+        );
+        assert_eq!(by_timing(64 * mib, Duration::from_millis(200)), 0);
+        assert_eq!(by_timing(4096, Duration::from_nanos(1)), 0);
+
+        record("cache-test", 400, 100);
+        record("cache-test", 600, 0);
+        let stats = snapshot()["cache-test"];
+        assert_eq!(stats.read_bytes, 1000);
+        assert_eq!(stats.cached_share(), 10.0);
+        assert!(summary().contains("  cache-test: 10.0% of"));
+    }
+}
@@ -0,0 +1,83 @@
+//! Differences between two plot scans, logged by the periodic capacity refresh.
+
+use crate::poc_hashing::NONCE_SIZE;
+use std::collections::HashMap;
+
+const TIB: f64 = 1024.0 * 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, PartialEq)]
+pub struct CapacityDelta {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub old_bytes: u64,
+    pub new_bytes: u64,
+}
+
+impl CapacityDelta {
+    /// `old` and `new` map plot paths to their nonces, `None` if the same plots were found.
+    pub fn between(old: &HashMap<String, u64>, new: &HashMap<String, u64>) -> Option<Self> {
+        let mut added: Vec<String> = new
+            .iter()
+            .filter(|&(path, nonces)| old.get(path) != Some(nonces))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut removed: Vec<String> = old
+            .iter()
+            .filter(|&(path, nonces)| new.get(path) != Some(nonces))
+            .map(|(path, _)| path.clone())
+            .collect();
+        if added.is_empty() && removed.is_empty() {
+            return None;
+        }
+        added.sort();
+        removed.sort();
+        let bytes = |plots: &HashMap<String, u64>| plots.values().sum::<u64>() * NONCE_SIZE as u64;
+        Some(CapacityDelta {
+            added,
+            removed,
+            old_bytes: bytes(old),
+            new_bytes: bytes(new),
+        })
+    }
+
+    pub fn delta_bytes(&self) -> i64 {
+        self.new_bytes as i64 - self.old_bytes as i64
+    }
+
+    pub fn log(&self, prefix: &str) {
+        info!(
+            "{}capacity changed: {} files added, {} removed, {:+.4} TiB, total {:.4} TiB",
+            prefix,
+            self.added.len(),
+            self.removed.len(),
+            self.delta_bytes() as f64 / TIB,
+            self.new_bytes as f64 / TIB
+        );
+        for path in &self.added {
+            info!("  + {}", path);
+        }
+        for path in &self.removed {
+            info!("  - {}", path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plots(plots: &[(&str, u64)]) -> HashMap<String, u64> {
+        plots.iter().map(|&(path, nonces)| (path.to_owned(), nonces)).collect()
+    }
+
+    #[test]
+    fn test_capacity_delta() {
+        let old = plots(&[("a", 4), ("b", 4)]);
+        assert_eq!(CapacityDelta::between(&old, &old), None);
+
+        let delta = CapacityDelta::between(&old, &plots(&[("b", 4), ("c", 8)])).unwrap();
+        assert_eq!(delta.added, vec!["c".to_owned()]);
+        assert_eq!(delta.removed, vec!["a".to_owned()]);
+        assert_eq!(delta.delta_bytes(), 4 * NONCE_SIZE as i64);
+    }
+}
@@ -0,0 +1,203 @@
+//! Chains the miner can run against.
+//!
+//! `mainnet` and `testnet` are built in, compatible forks can be added under `chains` in the
+//! config. Every pool entry selects a chain by name, at startup the miner asks each endpoint for
+//! its `getConstants` and refuses to start if the reported identity doesn't match.
+
+use crate::com::client::{Client, ConnectionSettings, ProxyDetails};
+use crate::config::Cfg;
+use crate::deadline_format::{self, REFERENCE_BLOCK_TIME};
+use std::collections::HashMap;
+use url::Url;
+
+pub const DEFAULT_CHAIN: &str = "mainnet";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainCfg {
+    /// Id of the genesis block, `None` skips this part of the identity check.
+    #[serde(default)]
+    pub genesis_block_id: Option<String>,
+
+    pub address_prefix: String,
+
+    /// API port of the chain's nodes, used for node urls without explicit port.
+    pub default_port: u16,
+
+    /// Seconds per block, for showing deadlines as time. Replaced by what `getConstants` reports.
+    #[serde(default = "default_block_time")]
+    pub block_time: u64,
+}
+
+fn default_block_time() -> u64 {
+    REFERENCE_BLOCK_TIME
+}
+
+pub fn builtin_chains() -> HashMap<String, ChainCfg> {
+    let mut chains = HashMap::new();
+    chains.insert(
+        "mainnet".to_owned(),
+        ChainCfg {
+            genesis_block_id: Some("3444294670862540038".to_owned()),
+            address_prefix: "S".to_owned(),
+            default_port: 8125,
+            block_time: REFERENCE_BLOCK_TIME,
+        },
+    );
+    chains.insert(
+        "testnet".to_owned(),
+        ChainCfg {
+            genesis_block_id: None,
+            address_prefix: "TS".to_owned(),
+            default_port: 6876,
+            block_time: REFERENCE_BLOCK_TIME,
+        },
+    );
+    chains
+}
+
+/// Looks up a chain, entries from the config take precedence over the built in ones.
+pub fn resolve(custom: &HashMap<String, ChainCfg>, name: &str) -> Option<ChainCfg> {
+    custom
+        .get(name)
+        .cloned()
+        .or_else(|| builtin_chains().remove(name))
+}
+
+/// Adds the chain's default API port to plain http node urls that don't specify one.
+pub fn with_default_port(mut url: Url, chain: &ChainCfg) -> Url {
+    if url.scheme() == "http" && url.port().is_none() {
+        let _ = url.set_port(Some(chain.default_port));
+    }
+    url
+}
+
+fn check_identity(
+    chain: &ChainCfg,
+    genesis_block_id: Option<&str>,
+    address_prefix: Option<&str>,
+) -> Result<(), String> {
+    if let (Some(expected), Some(actual)) = (chain.genesis_block_id.as_deref(), genesis_block_id) {
+        if expected != actual {
+            return Err(format!(
+                "genesis block {} doesn't match the configured {}",
+                actual, expected
+            ));
+        }
+    }
+    if let Some(actual) = address_prefix {
+        if actual != chain.address_prefix {
+            return Err(format!(
+                "address prefix {} doesn't match the configured {}",
+                actual, chain.address_prefix
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Shows deadlines with the block time `source` reports, if it does.
+fn adopt_block_time(source: &Url, chain: &ChainCfg, reported: Option<u64>) {
+    if let Some(secs) = reported.filter(|&secs| secs > 0) {
+        if secs != chain.block_time {
+            warn!(
+                "chain: {} reports {}s blocks, configured are {}s, using {}s",
+                source, secs, chain.block_time, secs
+            );
+        }
+        deadline_format::set_block_time(secs);
+    }
+}
+
+/// Verifies that every pool endpoint serves the chain it is configured for. Endpoints that don't
+/// implement `getConstants` (most pools) can't be verified and are only warned about.
+pub async fn validate_chains(cfg: &Cfg) -> Result<(), String> {
+    let inner = ConnectionSettings {
+        timeout: cfg.timeout,
+        pool_max_idle_per_host: 1,
+        keep_alive: 0,
+        http2: cfg.http2,
+    }
+    .build();
+
+    for pool in &cfg.pools {
+        let chain = resolve(&cfg.chains, &pool.chain)
+            .ok_or_else(|| format!("unknown chain '{}' for {}", pool.chain, pool.url))?;
+        let client = Client::new(
+            pool.url.clone(),
+            HashMap::new(),
+            inner.clone(),
+            0,
+            ProxyDetails::Disabled,
+            HashMap::new(),
+        );
+        match client.get_constants().await {
+            Ok(constants) => {
+                check_identity(
+                    &chain,
+                    constants.genesis_block_id.as_deref(),
+                    constants.address_prefix.as_deref(),
+                )
+                .map_err(|e| format!("{} is not on chain '{}': {}", pool.url, pool.chain, e))?;
+                info!("chain: {} verified as '{}'", pool.url, pool.chain);
+                adopt_block_time(&pool.url, &chain, constants.block_time);
+            }
+            Err(_) => warn!(
+                "chain: can't verify {} (no getConstants), assuming '{}'",
+                pool.url, pool.chain
+            ),
+        }
+    }
+
+    // the node knows the chain's parameters best
+    if let (Some(node_url), Some(chain)) = (&cfg.node_url, resolve(&cfg.chains, &cfg.chain)) {
+        let client = Client::new(
+            node_url.clone(),
+            HashMap::new(),
+            inner,
+            0,
+            ProxyDetails::Disabled,
+            HashMap::new(),
+        );
+        if let Ok(constants) = client.get_constants().await {
+            adopt_block_time(node_url, &chain, constants.block_time);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_identity() {
+        let mainnet = resolve(&HashMap::new(), "mainnet").unwrap();
+        assert!(check_identity(&mainnet, Some("3444294670862540038"), Some("S")).is_ok());
+        assert!(check_identity(&mainnet, None, None).is_ok());
+        assert!(check_identity(&mainnet, Some("1"), Some("S")).is_err());
+        assert!(check_identity(&mainnet, None, Some("TS")).is_err());
+    }
+
+    #[test]
+    fn test_custom_chain_overrides_builtin() {
+        let mut custom = HashMap::new();
+        let fork = ChainCfg {
+            genesis_block_id: None,
+            address_prefix: "F".to_owned(),
+            default_port: 9000,
+            block_time: 60,
+        };
+        custom.insert("mainnet".to_owned(), fork.clone());
+        assert_eq!(resolve(&custom, "mainnet"), Some(fork));
+        assert!(resolve(&custom, "unknown").is_none());
+    }
+
+    #[test]
+    fn test_with_default_port() {
+        let testnet = resolve(&HashMap::new(), "testnet").unwrap();
+        let url = with_default_port(Url::parse("http://localhost").unwrap(), &testnet);
+        assert_eq!(url.port(), Some(6876));
+        let url = with_default_port(Url::parse("https://node.example").unwrap(), &testnet);
+        assert_eq!(url.port(), None);
+    }
+}
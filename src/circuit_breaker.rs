@@ -0,0 +1,172 @@
+//! Circuit breaker for failing drives.
+//!
+//! A dying drive fails every read, slowly, and keeps buffers busy that healthy drives could use.
+//! Once a drive spends its error budget within the window the circuit opens and the drive isn't
+//! read at all until the cooldown is over. The first round after that is a probe: a successful
+//! read closes the circuit again, an error opens it for another cooldown.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BreakerCfg {
+    /// Errors within `window` that open the circuit, 0 disables the breaker.
+    pub error_budget: u32,
+    pub window: Duration,
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen,
+}
+
+/// What a read outcome changed, for logging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    None,
+    Opened,
+    Closed,
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    state: State,
+    errors: VecDeque<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        CircuitBreaker {
+            state: State::Closed,
+            errors: VecDeque::new(),
+        }
+    }
+
+    /// Whether the drive may be read, moves an open circuit to half-open once the cooldown is
+    /// over.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            State::Closed | State::HalfOpen => true,
+            State::Open { until } if now >= until => {
+                self.state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } => false,
+        }
+    }
+
+    pub fn record_error(&mut self, cfg: &BreakerCfg, now: Instant) -> Transition {
+        if cfg.error_budget == 0 {
+            return Transition::None;
+        }
+        self.errors.push_back(now);
+        while self
+            .errors
+            .front()
+            .is_some_and(|&t| now.duration_since(t) > cfg.window)
+        {
+            self.errors.pop_front();
+        }
+        let trip = match self.state {
+            State::HalfOpen => true,
+            State::Closed => self.errors.len() >= cfg.error_budget as usize,
+            State::Open { .. } => false,
+        };
+        if trip {
+            self.state = State::Open {
+                until: now + cfg.cooldown,
+            };
+            self.errors.clear();
+            Transition::Opened
+        } else {
+            Transition::None
+        }
+    }
+
+    pub fn record_success(&mut self) -> Transition {
+        if self.state == State::HalfOpen {
+            self.state = State::Closed;
+            Transition::Closed
+        } else {
+            Transition::None
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Open { .. })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self.state {
+            State::Closed => "closed",
+            State::Open { .. } => "open",
+            State::HalfOpen => "half-open",
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: BreakerCfg = BreakerCfg {
+        error_budget: 3,
+        window: Duration::from_secs(60),
+        cooldown: Duration::from_secs(300),
+    };
+
+    #[test]
+    fn test_budget_within_window_opens() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new();
+        assert_eq!(breaker.record_error(&CFG, start), Transition::None);
+        // the first error left the window
+        let later = start + Duration::from_secs(61);
+        assert_eq!(breaker.record_error(&CFG, later), Transition::None);
+        assert_eq!(breaker.record_error(&CFG, later), Transition::None);
+        assert!(breaker.allow(later));
+        assert_eq!(breaker.record_error(&CFG, later), Transition::Opened);
+        assert!(!breaker.allow(later + Duration::from_secs(299)));
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new();
+        for _ in 0..3 {
+            breaker.record_error(&CFG, start);
+        }
+        let probe = start + CFG.cooldown;
+        assert!(breaker.allow(probe));
+        assert_eq!(breaker.as_str(), "half-open");
+        // a failed probe opens the circuit right away
+        assert_eq!(breaker.record_error(&CFG, probe), Transition::Opened);
+        assert!(!breaker.allow(probe));
+
+        let probe = probe + CFG.cooldown;
+        assert!(breaker.allow(probe));
+        assert_eq!(breaker.record_success(), Transition::Closed);
+        assert_eq!(breaker.as_str(), "closed");
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut breaker = CircuitBreaker::new();
+        for _ in 0..100 {
+            assert_eq!(
+                breaker.record_error(&BreakerCfg::default(), Instant::now()),
+                Transition::None
+            );
+        }
+        assert!(breaker.allow(Instant::now()));
+    }
+}
@@ -2,6 +2,9 @@ use bytes::Bytes;
 use serde::de::{self, DeserializeOwned};
 use std::fmt;
 
+/// Longest body kept as the message of a response that isn't JSON.
+const MAX_ERROR_MESSAGE: usize = 512;
+
 #[allow(dead_code)]
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -20,15 +23,57 @@ pub struct GetMiningInfoRequest<'a> {
     pub request_type: &'a str,
 }
 
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlockRequest<'a> {
+    pub request_type: &'a str,
+    pub height: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockResponse {
+    pub block: String,
+
+    #[serde(deserialize_with = "from_str_or_int")]
+    pub height: u64,
+
+    #[serde(deserialize_with = "from_str_or_int")]
+    pub generator: u64,
+
+    /// Seconds since the genesis block, 0 if the node doesn't report it.
+    #[serde(default, deserialize_with = "from_str_or_int")]
+    pub timestamp: u64,
+
+    #[serde(default, deserialize_with = "from_str_or_int")]
+    pub base_target: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConstantsResponse {
+    #[serde(default)]
+    pub genesis_block_id: Option<String>,
+
+    #[serde(default)]
+    pub address_prefix: Option<String>,
+
+    /// Seconds per block, not reported by every node.
+    #[serde(default)]
+    pub block_time: Option<u64>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubmitNonceResponse {
+    #[serde(deserialize_with = "from_str_or_int")]
     pub deadline: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MiningInfoResponse {
+    #[serde(deserialize_with = "gensig_hex")]
     pub generation_signature: String,
 
     #[serde(deserialize_with = "from_str_or_int")]
@@ -61,10 +106,97 @@ pub struct PoolError {
     pub message: String,
 }
 
+/// Why a pool refused a submission, classified from the free-form error message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RejectionReason {
+    DeadlineExceeded,
+    UnknownAccount,
+    Stale,
+    RateLimited,
+    Other,
+}
+
+impl RejectionReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RejectionReason::DeadlineExceeded => "deadline_exceeded",
+            RejectionReason::UnknownAccount => "unknown_account",
+            RejectionReason::Stale => "stale",
+            RejectionReason::RateLimited => "rate_limited",
+            RejectionReason::Other => "other",
+        }
+    }
+
+    /// The reason `as_str` returned `reason` for.
+    pub fn parse(reason: &str) -> Option<RejectionReason> {
+        [
+            RejectionReason::DeadlineExceeded,
+            RejectionReason::UnknownAccount,
+            RejectionReason::Stale,
+            RejectionReason::RateLimited,
+            RejectionReason::Other,
+        ]
+        .into_iter()
+        .find(|r| r.as_str() == reason)
+    }
+
+    /// What the user can do about it, if anything.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            RejectionReason::DeadlineExceeded => Some(
+                "the pool's target deadline is lower than the configured one, \
+                 check target_deadline and account_id_to_target_deadline",
+            ),
+            RejectionReason::UnknownAccount => Some(
+                "the pool doesn't know this account, check the reward recipient assignment",
+            ),
+            RejectionReason::Stale => {
+                Some("the round ended before the submission arrived, check the drive speed")
+            }
+            RejectionReason::RateLimited | RejectionReason::Other => None,
+        }
+    }
+}
+
+impl PoolError {
+    pub fn reason(&self) -> RejectionReason {
+        let message = self.message.to_lowercase();
+        let contains = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+        if message.is_empty()
+            || contains(&["limit exceeded", "rate limit", "too many requests", "busy"])
+        {
+            RejectionReason::RateLimited
+        } else if message.contains("deadline")
+            && contains(&["exceed", "too high", "too large", "greater", "above"])
+        {
+            RejectionReason::DeadlineExceeded
+        } else if contains(&["unknown account", "account not found", "unknown accountid"]) {
+            RejectionReason::UnknownAccount
+        } else if contains(&["stale", "wrong height", "old block", "height", "generation signature"])
+        {
+            RejectionReason::Stale
+        } else {
+            RejectionReason::Other
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FetchError {
     Http(reqwest::Error),
     Pool(PoolError),
+    /// The body exceeded the limit in bytes, it was not read to the end.
+    TooLarge(usize),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Http(e) => write!(f, "{}", e),
+            FetchError::Pool(e) => write!(f, "pool error {}: {}", e.code, e.message),
+            FetchError::TooLarge(limit) => write!(f, "response larger than {} bytes", limit),
+        }
+    }
 }
 
 impl From<reqwest::Error> for FetchError {
@@ -93,29 +225,167 @@ where
         }
 
         fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
-            v.parse::<u64>().map_err(de::Error::custom)
+            v.trim().parse::<u64>().map_err(de::Error::custom)
         }
 
         fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
             Ok(v)
         }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v).map_err(|_| de::Error::custom(format!("negative number {}", v)))
+        }
+
+        // some pools send deadlines as 1234.0
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            if v.fract() == 0.0 && v >= 0.0 && v < u64::MAX as f64 {
+                Ok(v as u64)
+            } else {
+                Err(de::Error::custom(format!("not an unsigned integer: {}", v)))
+            }
+        }
     }
 
     deserializer.deserialize_any(StringOrIntVisitor)
 }
 
+/// A generation signature has to be 32 bytes of hex, anything else would fail when decoded.
+fn gensig_hex<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let s: String = de::Deserialize::deserialize(deserializer)?;
+    if s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(s)
+    } else {
+        Err(de::Error::custom(format!(
+            "invalid generation signature '{}'",
+            s.chars().take(80).collect::<String>()
+        )))
+    }
+}
+
 pub fn parse_json_result<T: DeserializeOwned>(body: &Bytes) -> Result<T, PoolError> {
     match serde_json::from_slice(body) {
         Ok(x) => Ok(x),
         _ => match serde_json::from_slice::<PoolErrorWrapper>(body) {
             Ok(x) => Err(x.error),
             _ => {
-                let v = body.to_vec();
+                let v = &body[..body.len().min(MAX_ERROR_MESSAGE)];
                 Err(PoolError {
                     code: 0,
-                    message: String::from_utf8_lossy(&v).to_string(),
+                    message: String::from_utf8_lossy(v).to_string(),
                 })
             }
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(message: &str) -> RejectionReason {
+        PoolError {
+            code: 0,
+            message: message.to_owned(),
+        }
+        .reason()
+    }
+
+    #[test]
+    fn test_rejection_reason() {
+        assert_eq!(reason(""), RejectionReason::RateLimited);
+        assert_eq!(reason("limit exceeded"), RejectionReason::RateLimited);
+        assert_eq!(
+            reason("Deadline exceeds the pool's target deadline"),
+            RejectionReason::DeadlineExceeded
+        );
+        assert_eq!(reason("Unknown account"), RejectionReason::UnknownAccount);
+        assert_eq!(reason("Submitted on wrong height"), RejectionReason::Stale);
+        assert_eq!(reason("stale submission"), RejectionReason::Stale);
+        assert_eq!(reason("Nonce verification failed"), RejectionReason::Other);
+    }
+
+    const GENSIG: &str = "6ec823b5fd86c4aee9f7c3453cacaf4a43296f48ede77e70060ca8225c2855d0";
+
+    #[test]
+    fn test_tolerant_numbers() {
+        let body = format!(
+            r#"{{"generationSignature":"{}","baseTarget":" 70312 ","height":1234.0,"targetDeadline":"86400"}}"#,
+            GENSIG
+        );
+        let mining_info: MiningInfoResponse = parse_json_result(&Bytes::from(body)).unwrap();
+        assert_eq!(mining_info.base_target, 70312);
+        assert_eq!(mining_info.height, 1234);
+        assert_eq!(mining_info.target_deadline, 86400);
+
+        let res: SubmitNonceResponse =
+            parse_json_result(&Bytes::from_static(br#"{"deadline":"42"}"#)).unwrap();
+        assert_eq!(res.deadline, 42);
+        assert!(
+            parse_json_result::<SubmitNonceResponse>(&Bytes::from_static(br#"{"deadline":-1}"#))
+                .is_err()
+        );
+        assert!(
+            parse_json_result::<SubmitNonceResponse>(&Bytes::from_static(br#"{"deadline":1.5}"#))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_invalid_gensig() {
+        for gensig in ["", "abc", &GENSIG[..62], GENSIG.replace('6', "g").as_str()] {
+            let body = format!(
+                r#"{{"generationSignature":"{}","baseTarget":1,"height":1}}"#,
+                gensig
+            );
+            assert!(parse_json_result::<MiningInfoResponse>(&Bytes::from(body)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_fuzz() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let valid = format!(
+            r#"{{"generationSignature":"{}","baseTarget":"70312","height":1234,"targetDeadline":86400,"deadline":42,"block":"1","generator":"2","error":{{"code":1,"message":"x"}}}}"#,
+            GENSIG
+        );
+        let mut rng = StdRng::seed_from_u64(2473);
+        for i in 0..20_000 {
+            let mut body = valid.clone().into_bytes();
+            match i % 4 {
+                0 => body.truncate(rng.gen_range(0, body.len())),
+                1 => {
+                    for _ in 0..rng.gen_range(1, 8) {
+                        let at = rng.gen_range(0, body.len());
+                        body[at] = rng.gen();
+                    }
+                }
+                2 => {
+                    let at = rng.gen_range(0, body.len());
+                    let junk: Vec<u8> = (0..rng.gen_range(1, 64)).map(|_| rng.gen()).collect();
+                    body.splice(at..at, junk);
+                }
+                _ => {
+                    body = vec![0; rng.gen_range(0, 4096)];
+                    rng.fill(&mut body[..]);
+                }
+            }
+            let body = Bytes::from(body);
+
+            // must never panic, and what it accepts has to be usable
+            match parse_json_result::<MiningInfoResponse>(&body) {
+                Ok(mining_info) => {
+                    crate::poc_hashing::decode_gensig(&mining_info.generation_signature);
+                }
+                Err(e) => assert!(e.message.chars().count() <= MAX_ERROR_MESSAGE),
+            }
+            let _ = parse_json_result::<SubmitNonceResponse>(&body);
+            let _ = parse_json_result::<BlockResponse>(&body);
+            let _ = parse_json_result::<ConstantsResponse>(&body);
+        }
+    }
+}
@@ -1,13 +1,19 @@
 use crate::com::api::*;
-use reqwest::{Client as InnerClient, header::{HeaderMap, HeaderName}};
+use crate::rotation::AccountRotation;
+use bytes::{Bytes, BytesMut};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+    Client as InnerClient, StatusCode,
+};
 #[cfg(feature = "async_io")]
 use tokio::sync::Mutex;
 #[cfg(not(feature = "async_io"))]
 use std::sync::Mutex;
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard as StdMutexGuard};
+use std::time::{Duration, Instant};
 use url::form_urlencoded::byte_serialize;
 use url::Url;
 use hostname::get;
@@ -18,9 +24,31 @@ pub struct Client {
     inner: InnerClient,
     account_id_to_secret_phrase: Arc<HashMap<u64, String>>,
     base_uri: Url,
-    total_size_gb: usize,
     proxy_details: ProxyDetails,
     headers: Arc<Mutex<HeaderMap>>,
+    mining_info_cache: Arc<StdMutex<Option<MiningInfoCache>>>,
+    deadline_floor: DeadlineFloor,
+    // per pool headers, rendered for every request
+    header_templates: Arc<Vec<(HeaderName, String)>>,
+    capacity_gb: Arc<AtomicUsize>,
+    // capacity the last answered request carried, 0 before the first
+    declared_gb: Arc<AtomicUsize>,
+    rotation: Arc<AccountRotation>,
+}
+
+// Pools sometimes send long max-ages, honoring them blindly would delay the start of new rounds.
+const MAX_MINING_INFO_CACHE_SECS: u64 = 4;
+
+// Far above anything a pool or node answers, a body this large is never read to the end.
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// Last mining info received, used for conditional requests and to skip parsing unchanged bodies.
+#[derive(Debug)]
+struct MiningInfoCache {
+    etag: Option<HeaderValue>,
+    body: Bytes,
+    mining_info: MiningInfoResponse,
+    fresh_until: Option<Instant>,
 }
 
 /// Parameters used for nonce submission.
@@ -60,12 +88,108 @@ impl PartialOrd for SubmissionParameters {
     }
 }
 
+/// Values of the placeholders in header templates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TemplateVars {
+    pub hostname: String,
+    pub capacity_gb: usize,
+    /// Only known for submissions.
+    pub account_id: Option<u64>,
+    /// Account the submission's block is mined for, see `rotation`.
+    pub rotation_account: Option<u64>,
+}
+
+/// Replaces `{name}`, `{version}`, `{hostname}`, `{capacity_gb}`, `{capacity_tib}`,
+/// `{account_id}` and `{rotation_account}` in a header template. Unknown placeholders are left as
+/// they are.
+pub fn render_template(template: &str, vars: &TemplateVars) -> String {
+    template
+        .replace("{name}", env!("CARGO_PKG_NAME"))
+        .replace("{version}", env!("CARGO_PKG_VERSION"))
+        .replace("{hostname}", &vars.hostname)
+        .replace("{capacity_gb}", &vars.capacity_gb.to_string())
+        .replace(
+            "{capacity_tib}",
+            &format!("{:.3}", vars.capacity_gb as f64 / 1024.0),
+        )
+        .replace(
+            "{account_id}",
+            &vars.account_id.map(|a| a.to_string()).unwrap_or_default(),
+        )
+        .replace(
+            "{rotation_account}",
+            &vars
+                .rotation_account
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+        )
+}
+
+/// Minimum improvement over the best deadline a pool already has before another one is sent.
+/// Pools that only count the best deadline of a round gain nothing from small improvements.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeadlineFloor {
+    /// Fraction of the previous deadline, e.g. 0.1 for at least 10% better.
+    pub factor: f64,
+    pub secs: u64,
+}
+
+impl DeadlineFloor {
+    pub fn allows(&self, previous: u64, deadline: u64) -> bool {
+        let improvement = previous.saturating_sub(deadline);
+        improvement > 0
+            && improvement >= self.secs
+            && improvement as f64 >= previous as f64 * self.factor
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum ProxyDetails {
     Enabled,
     Disabled,
 }
 
+/// Settings of the HTTP connection pool shared by all clients.
+#[derive(Clone, Debug)]
+pub struct ConnectionSettings {
+    /// Request timeout in milliseconds.
+    pub timeout: u64,
+    /// Idle connections kept open per host.
+    pub pool_max_idle_per_host: usize,
+    /// Seconds an idle connection is kept alive, 0 disables keep-alive.
+    pub keep_alive: u64,
+    /// Negotiate HTTP/2 via ALPN so requests to the same host share one connection.
+    pub http2: bool,
+}
+
+impl ConnectionSettings {
+    /// Builds the underlying HTTP client. It is reference counted internally, clones share the
+    /// connection pool.
+    pub fn build(&self) -> InnerClient {
+        let keep_alive = if self.keep_alive > 0 {
+            Some(Duration::from_secs(self.keep_alive))
+        } else {
+            None
+        };
+
+        let mut builder = InnerClient::builder()
+            .timeout(Duration::from_millis(self.timeout))
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(keep_alive)
+            .tcp_keepalive(keep_alive);
+
+        builder = if self.http2 {
+            builder
+                .http2_keep_alive_interval(keep_alive)
+                .http2_keep_alive_while_idle(keep_alive.is_some())
+        } else {
+            builder.http1_only()
+        };
+
+        builder.build().unwrap()
+    }
+}
+
 impl Client {
     fn ua() -> String {
         format!("signum-miner/{}", env!("CARGO_PKG_VERSION"))
@@ -107,7 +231,7 @@ impl Client {
     pub fn new(
         base_uri: Url,
         mut secret_phrases: HashMap<u64, String>,
-        timeout: u64,
+        inner: InnerClient,
         total_size_gb: usize,
         proxy_details: ProxyDetails,
         additional_headers: HashMap<String, String>,
@@ -118,21 +242,91 @@ impl Client {
 
         let headers = Client::submit_nonce_headers(proxy_details.clone(), total_size_gb, additional_headers);
 
-        let client = InnerClient::builder()
-            .timeout(Duration::from_millis(timeout))
-            .build()
-            .unwrap();
-
         Self {
-            inner: client,
+            inner,
             account_id_to_secret_phrase: Arc::new(secret_phrases),
             base_uri,
-            total_size_gb,
             proxy_details,
             headers: Arc::new(Mutex::new(headers)),
+            mining_info_cache: Arc::new(StdMutex::new(None)),
+            deadline_floor: DeadlineFloor::default(),
+            header_templates: Arc::new(Vec::new()),
+            capacity_gb: Arc::new(AtomicUsize::new(total_size_gb)),
+            declared_gb: Arc::new(AtomicUsize::new(0)),
+            rotation: Arc::new(AccountRotation::default()),
+        }
+    }
+
+    /// Headers some pool software expects, see `render_template`. `user_agent` replaces the
+    /// default `signum-miner/<version>`.
+    pub fn with_header_templates(
+        mut self,
+        user_agent: Option<String>,
+        templates: HashMap<String, String>,
+    ) -> Self {
+        let mut header_templates = Vec::new();
+        let user_agent = user_agent.map(|ua| ("User-Agent".to_owned(), ua));
+        for (name, template) in user_agent.into_iter().chain(templates) {
+            match HeaderName::from_bytes(name.as_bytes()) {
+                Ok(name) => header_templates.push((name, template)),
+                Err(_) => warn!("{}: invalid header name '{}', ignored", self.base_uri, name),
+            }
+        }
+        self.header_templates = Arc::new(header_templates);
+        self
+    }
+
+    fn apply_header_templates(
+        &self,
+        headers: &mut HeaderMap,
+        submission: Option<&SubmissionParameters>,
+    ) {
+        if self.header_templates.is_empty() {
+            return;
+        }
+        let vars = TemplateVars {
+            hostname: get()
+                .ok()
+                .and_then(|h| h.into_string().ok())
+                .unwrap_or_default(),
+            capacity_gb: self.capacity_gb.load(AtomicOrdering::Relaxed),
+            account_id: submission.map(|submission| submission.account_id),
+            rotation_account: submission
+                .and_then(|submission| self.rotation.account_for(submission.height)),
+        };
+        for (name, template) in self.header_templates.iter() {
+            match HeaderValue::from_str(&render_template(template, &vars)) {
+                Ok(value) => {
+                    headers.insert(name.clone(), value);
+                }
+                Err(_) => debug!("{}: header {} isn't a valid value", self.base_uri, name),
+            }
         }
     }
 
+    /// Accounts whose turn a block is, for the `{rotation_account}` placeholder.
+    pub fn with_account_rotation(mut self, rotation: Arc<AccountRotation>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_deadline_floor(mut self, deadline_floor: DeadlineFloor) -> Self {
+        self.deadline_floor = deadline_floor;
+        self
+    }
+
+    pub fn deadline_floor(&self) -> DeadlineFloor {
+        self.deadline_floor
+    }
+
+    pub fn base_uri(&self) -> &Url {
+        &self.base_uri
+    }
+
+    pub fn has_secret_phrase(&self, account_id: u64) -> bool {
+        self.account_id_to_secret_phrase.contains_key(&account_id)
+    }
+
     pub fn uri_for(&self, path: &str) -> Url {
         let mut url = self.base_uri.clone();
         url.path_segments_mut()
@@ -143,8 +337,8 @@ impl Client {
     }
 
     #[cfg(feature = "async_io")]
-    pub async fn update_capacity(&mut self, total_size_gb: usize) {
-        self.total_size_gb = total_size_gb;
+    pub async fn update_capacity(&self, total_size_gb: usize) {
+        self.capacity_gb.store(total_size_gb, AtomicOrdering::Relaxed);
         if self.proxy_details == ProxyDetails::Enabled {
             let mut headers = self.headers.lock().await;
             headers.insert("X-Capacity", total_size_gb.to_string().parse().unwrap());
@@ -152,33 +346,159 @@ impl Client {
     }
 
     #[cfg(not(feature = "async_io"))]
-    pub fn update_capacity(&mut self, total_size_gb: usize) {
-        self.total_size_gb = total_size_gb;
+    pub fn update_capacity(&self, total_size_gb: usize) {
+        self.capacity_gb.store(total_size_gb, AtomicOrdering::Relaxed);
         if self.proxy_details == ProxyDetails::Enabled {
             let mut headers = self.headers.lock().unwrap();
             headers.insert("X-Capacity", total_size_gb.to_string().parse().unwrap());
         }
     }
 
+    /// Whether requests carry the capacity, in `X-Capacity` or a header template.
+    fn declares_capacity(&self) -> bool {
+        self.proxy_details == ProxyDetails::Enabled
+            || self
+                .header_templates
+                .iter()
+                .any(|(_, template)| template.contains("{capacity_"))
+    }
+
+    // the endpoint answered a request carrying the current capacity
+    fn record_declared(&self) {
+        if self.declares_capacity() {
+            self.declared_gb.store(
+                self.capacity_gb.load(AtomicOrdering::Relaxed),
+                AtomicOrdering::Relaxed,
+            );
+        }
+    }
+
+    /// Capacity in GB the endpoint last saw, None if requests don't declare it.
+    pub fn declared_capacity_gb(&self) -> Option<usize> {
+        if !self.declares_capacity() {
+            return None;
+        }
+        Some(self.declared_gb.load(AtomicOrdering::Relaxed)).filter(|&gb| gb > 0)
+    }
+
+    /// Whether the endpoint hasn't seen the current capacity yet.
+    pub fn capacity_outdated(&self) -> bool {
+        self.declares_capacity()
+            && self.declared_capacity_gb() != Some(self.capacity_gb.load(AtomicOrdering::Relaxed))
+    }
+
+    /// Sends the headers right away, with a mining info request past the cache.
+    pub async fn announce(&self) -> Result<(), FetchError> {
+        if let Some(cache) = self.mining_info_cache().as_mut() {
+            cache.fresh_until = None;
+            cache.etag = None;
+        }
+        self.get_mining_info().await.map(|_| ())
+    }
+
+    fn mining_info_cache(&self) -> StdMutexGuard<'_, Option<MiningInfoCache>> {
+        match self.mining_info_cache.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("client: mining info cache mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+
+    async fn request_mining_info(&self, headers: HeaderMap) -> reqwest::Result<reqwest::Response> {
+        self.inner
+            .get(self.uri_for("burst"))
+            .headers(headers)
+            .query(&GetMiningInfoRequest {
+                request_type: "getMiningInfo",
+            })
+            .send()
+            .await
+    }
+
     pub async fn get_mining_info(&self) -> Result<MiningInfoResponse, FetchError> {
         #[cfg(feature = "async_io")]
-        let headers = { self.headers.lock().await.clone() };
+        let mut headers = { self.headers.lock().await.clone() };
         #[cfg(not(feature = "async_io"))]
-        let headers = { self.headers.lock().unwrap().clone() };
+        let mut headers = { self.headers.lock().unwrap().clone() };
+        self.apply_header_templates(&mut headers, None);
+
+        if let Some(cache) = self.mining_info_cache().as_ref() {
+            if cache.fresh_until.is_some_and(|t| Instant::now() < t) {
+                return Ok(cache.mining_info.clone());
+            }
+            if let Some(etag) = &cache.etag {
+                headers.insert(IF_NONE_MATCH, etag.clone());
+            }
+        }
+
+        let mut res = self.request_mining_info(headers.clone()).await?;
+        self.record_declared();
+
+        if res.status() == StatusCode::NOT_MODIFIED {
+            let fresh_until = max_age(res.headers()).map(|secs| {
+                Instant::now() + Duration::from_secs(secs.min(MAX_MINING_INFO_CACHE_SECS))
+            });
+            if let Some(cache) = self.mining_info_cache().as_mut() {
+                cache.fresh_until = fresh_until;
+                return Ok(cache.mining_info.clone());
+            }
+            // nothing cached to reuse, the empty body isn't mining info
+            headers.remove(IF_NONE_MATCH);
+            res = self.request_mining_info(headers).await?;
+        }
+
+        let fresh_until = max_age(res.headers())
+            .map(|secs| Instant::now() + Duration::from_secs(secs.min(MAX_MINING_INFO_CACHE_SECS)));
+
+        let etag = res.headers().get(ETAG).cloned();
+        let body = read_body(res).await?;
+
+        let mut cache = self.mining_info_cache();
+        if let Some(cache) = cache.as_mut().filter(|cache| cache.body == body) {
+            cache.etag = etag;
+            cache.fresh_until = fresh_until;
+            return Ok(cache.mining_info.clone());
+        }
 
+        let mining_info: MiningInfoResponse = parse_json_result(&body)?;
+        *cache = Some(MiningInfoCache {
+            etag,
+            body,
+            mining_info: mining_info.clone(),
+            fresh_until,
+        });
+        Ok(mining_info)
+    }
+
+    /// Block at `height`, only supported by nodes.
+    pub async fn get_block(&self, height: u64) -> Result<BlockResponse, FetchError> {
+        let res = self
+            .inner
+            .get(self.uri_for("burst"))
+            .query(&GetBlockRequest {
+                request_type: "getBlock",
+                height,
+            })
+            .send()
+            .await?;
+
+        parse_json_result(&read_body(res).await?).map_err(FetchError::from)
+    }
+
+    /// Chain constants, only supported by nodes.
+    pub async fn get_constants(&self) -> Result<ConstantsResponse, FetchError> {
         let res = self
             .inner
             .get(self.uri_for("burst"))
-            .headers(headers)
             .query(&GetMiningInfoRequest {
-                request_type: "getMiningInfo",
+                request_type: "getConstants",
             })
             .send()
-            .await?
-            .bytes()
             .await?;
 
-        parse_json_result(&res).map_err(FetchError::from)
+        parse_json_result(&read_body(res).await?).map_err(FetchError::from)
     }
 
     pub async fn submit_nonce(
@@ -207,6 +527,7 @@ impl Client {
         let mut headers = { self.headers.lock().await.clone() };
         #[cfg(not(feature = "async_io"))]
         let mut headers = { self.headers.lock().unwrap().clone() };
+        self.apply_header_templates(&mut headers, Some(submission_data));
         headers.insert(
             "X-Deadline",
             submission_data.deadline.to_string().parse().unwrap(),
@@ -220,18 +541,55 @@ impl Client {
             .post(uri)
             .headers(headers)
             .send()
-            .await?
-            .bytes()
             .await?;
+        self.record_declared();
 
-        parse_json_result(&res).map_err(FetchError::from)
+        parse_json_result(&read_body(res).await?).map_err(FetchError::from)
     }
 }
 
+/// Reads the body of `res`, at most `MAX_RESPONSE_SIZE` bytes, so a misbehaving endpoint can't
+/// make the miner buffer an unbounded response.
+async fn read_body(mut res: reqwest::Response) -> Result<Bytes, FetchError> {
+    if res
+        .content_length()
+        .is_some_and(|len| len > MAX_RESPONSE_SIZE as u64)
+    {
+        return Err(FetchError::TooLarge(MAX_RESPONSE_SIZE));
+    }
+    let mut body = BytesMut::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            return Err(FetchError::TooLarge(MAX_RESPONSE_SIZE));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// Parses `max-age` out of a `Cache-Control` header, `no-cache`/`no-store` disable caching.
+fn max_age(headers: &HeaderMap) -> Option<u64> {
+    let cache_control = headers.get(CACHE_CONTROL)?.to_str().ok()?;
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        if directive.eq_ignore_ascii_case("no-cache") || directive.eq_ignore_ascii_case("no-store")
+        {
+            return None;
+        }
+        if let Some(secs) = directive.strip_prefix("max-age=") {
+            max_age = secs.parse().ok();
+        }
+    }
+    max_age.filter(|&secs| secs > 0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http_server::{serve, serve_local, Response};
     use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
 
     static BASE_URL: &str = "https://europe.signum.network/";
 
@@ -265,6 +623,179 @@ mod tests {
         assert!(submit_params_1 > submit_params_2);
     }
 
+    #[test]
+    fn test_max_age() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(max_age(&headers), None);
+
+        headers.insert(CACHE_CONTROL, "public, max-age=3".parse().unwrap());
+        assert_eq!(max_age(&headers), Some(3));
+
+        headers.insert(CACHE_CONTROL, "max-age=0".parse().unwrap());
+        assert_eq!(max_age(&headers), None);
+
+        headers.insert(CACHE_CONTROL, "no-cache, max-age=3".parse().unwrap());
+        assert_eq!(max_age(&headers), None);
+    }
+
+    #[test]
+    fn test_deadline_floor() {
+        assert!(DeadlineFloor::default().allows(100, 99));
+        assert!(!DeadlineFloor::default().allows(100, 100));
+
+        let floor = DeadlineFloor {
+            factor: 0.1,
+            secs: 5,
+        };
+        assert!(!floor.allows(100, 95));
+        assert!(floor.allows(100, 90));
+        assert!(!floor.allows(20, 16));
+        assert!(floor.allows(20, 15));
+    }
+
+    #[test]
+    fn test_render_template() {
+        let vars = TemplateVars {
+            hostname: "rig1".to_owned(),
+            capacity_gb: 2048,
+            account_id: Some(42),
+            rotation_account: Some(7),
+        };
+        assert_eq!(
+            render_template("{hostname}/{capacity_gb}/{capacity_tib}/{account_id}/{other}", &vars),
+            "rig1/2048/2.000/42/{other}"
+        );
+        assert_eq!(render_template("for={rotation_account}", &vars), "for=7");
+        assert_eq!(
+            render_template("{name}/{version}", &TemplateVars::default()),
+            format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(render_template("id={account_id}", &TemplateVars::default()), "id=");
+    }
+
+    #[test]
+    fn test_declared_capacity() {
+        let client = |proxy_details| {
+            Client::new(
+                Url::parse(BASE_URL).unwrap(),
+                HashMap::new(),
+                InnerClient::new(),
+                12,
+                proxy_details,
+                HashMap::new(),
+            )
+        };
+        let silent = client(ProxyDetails::Disabled);
+        assert!(!silent.capacity_outdated());
+        let templated = silent.with_header_templates(
+            None,
+            HashMap::from([("X-Capacity".to_owned(), "{capacity_gb}".to_owned())]),
+        );
+        assert!(templated.capacity_outdated());
+
+        let proxy = client(ProxyDetails::Enabled);
+        assert_eq!(proxy.declared_capacity_gb(), None);
+        proxy.record_declared();
+        assert_eq!(proxy.declared_capacity_gb(), Some(12));
+        assert!(!proxy.capacity_outdated());
+        proxy.capacity_gb.store(20, AtomicOrdering::Relaxed);
+        assert!(proxy.capacity_outdated());
+    }
+
+    fn mining_info() -> Response {
+        Response::json(
+            serde_json::json!({
+                "generationSignature": hex::encode([1u8; 32]),
+                "baseTarget": "70000",
+                "height": "5",
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_connection_settings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let connections = connections.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    connections.fetch_add(1, AtomicOrdering::SeqCst);
+                    tokio::spawn(serve(stream, |_| async { mining_info() }));
+                }
+            }
+        });
+        let settings = ConnectionSettings {
+            timeout: 200,
+            pool_max_idle_per_host: 1,
+            keep_alive: 90,
+            http2: false,
+        };
+        let client = |url: &Url, inner: &InnerClient| {
+            Client::new(
+                url.clone(),
+                HashMap::new(),
+                inner.clone(),
+                0,
+                ProxyDetails::Disabled,
+                HashMap::new(),
+            )
+        };
+
+        // the endpoints of a pool go over the same kept alive connection
+        let inner = settings.build();
+        client(&url, &inner).get_mining_info().await.unwrap();
+        client(&url, &inner).get_mining_info().await.unwrap();
+        assert_eq!(connections.load(AtomicOrdering::SeqCst), 1);
+
+        // and share the timeout
+        let slow = serve_local(|_| async {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            mining_info()
+        })
+        .await;
+        assert!(client(&slow, &inner).get_mining_info().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_without_cache() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = serve_local({
+            let requests = requests.clone();
+            move |_| {
+                // a pool answering 304 to a miner that has nothing cached
+                let first = requests.fetch_add(1, AtomicOrdering::SeqCst) == 0;
+                async move {
+                    if first {
+                        Response {
+                            status: "304 Not Modified",
+                            content_type: "application/json",
+                            headers: Vec::new(),
+                            body: String::new(),
+                        }
+                    } else {
+                        mining_info()
+                    }
+                }
+            }
+        })
+        .await;
+        let client = Client::new(
+            url,
+            HashMap::new(),
+            InnerClient::new(),
+            0,
+            ProxyDetails::Disabled,
+            HashMap::new(),
+        );
+        assert_eq!(client.get_mining_info().await.unwrap().height, 5);
+        assert_eq!(requests.load(AtomicOrdering::SeqCst), 2);
+    }
+
     #[tokio::test]
     async fn test_get_mining_info_and_submit_nonce() {
         let mut secret = HashMap::new();
@@ -273,7 +804,13 @@ mod tests {
         let client = Client::new(
             Url::parse(BASE_URL).unwrap(),
             secret,
-            5000,
+            ConnectionSettings {
+                timeout: 5000,
+                pool_max_idle_per_host: 8,
+                keep_alive: 90,
+                http2: true,
+            }
+            .build(),
             12,
             ProxyDetails::Enabled,
             HashMap::new(),
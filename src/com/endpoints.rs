@@ -0,0 +1,368 @@
+//! Set of endpoints serving the configured pool.
+//!
+//! Some pools offer several regional URLs. All of them are probed periodically and mining happens
+//! against the healthy endpoint with the best priority and, among those, the lowest `getMiningInfo`
+//! round trip. An endpoint whose mining info or submissions fail `failover_after` times in a row is
+//! skipped until it answers a probe again, the probe also moves mining back to it.
+//!
+//! Some pool software keeps the capacity a miner declared first. An endpoint mining moves to gets
+//! the capacity and miner headers right away if it hasn't seen the current capacity, instead of
+//! with the next regular request.
+
+use crate::com::api::{FetchError, MiningInfoResponse};
+use crate::com::client::Client;
+use crate::metrics::SharedMetrics;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use url::Url;
+
+#[derive(Clone, Copy)]
+struct EndpointStatus {
+    latency_ms: Option<u64>,
+    healthy: bool,
+    consecutive_failures: u32,
+}
+
+impl Default for EndpointStatus {
+    fn default() -> Self {
+        EndpointStatus {
+            latency_ms: None,
+            healthy: true,
+            consecutive_failures: 0,
+        }
+    }
+}
+
+pub struct LatencyProbe {
+    pub url: String,
+    pub latency_ms: Option<u64>,
+    /// Capacity in GB the endpoint last saw.
+    pub declared_gb: Option<usize>,
+}
+
+pub struct PoolEndpoints {
+    clients: Vec<Client>,
+    // lower is preferred, the same priority goes by latency
+    priorities: Vec<u32>,
+    status: Mutex<Vec<EndpointStatus>>,
+    active: AtomicUsize,
+    failover_after: u32,
+    // failovers count as network errors
+    metrics: SharedMetrics,
+}
+
+impl PoolEndpoints {
+    /// `clients` with their priority.
+    pub fn new(clients: Vec<(Client, u32)>, failover_after: u32, metrics: SharedMetrics) -> Self {
+        assert!(!clients.is_empty(), "at least one pool endpoint required");
+        let (clients, priorities): (Vec<Client>, Vec<u32>) = clients.into_iter().unzip();
+        let status = vec![EndpointStatus::default(); clients.len()];
+        let preferred = rank(&priorities, &status)[0];
+        Self {
+            clients,
+            priorities,
+            status: Mutex::new(status),
+            active: AtomicUsize::new(preferred),
+            failover_after: failover_after.max(1),
+            metrics,
+        }
+    }
+
+    pub fn active(&self) -> &Client {
+        &self.clients[self.active.load(Ordering::SeqCst)]
+    }
+
+    pub fn clients(&self) -> &[Client] {
+        &self.clients
+    }
+
+    pub fn count(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn status(&self) -> std::sync::MutexGuard<'_, Vec<EndpointStatus>> {
+        self.status.lock().unwrap_or_else(|poisoned| {
+            error!("endpoints: status mutex poisoned, recovering...");
+            poisoned.into_inner()
+        })
+    }
+
+    fn record_success(&self, i: usize) {
+        let mut status = self.status();
+        status[i].healthy = true;
+        status[i].consecutive_failures = 0;
+    }
+
+    /// Returns true if this failure took the endpoint out of mining.
+    fn record_failure(&self, i: usize) -> bool {
+        let mut status = self.status();
+        status[i].consecutive_failures += 1;
+        if status[i].consecutive_failures >= self.failover_after {
+            status[i].healthy = false;
+        }
+        !status[i].healthy
+    }
+
+    fn ranked(&self) -> Vec<usize> {
+        rank(&self.priorities, &self.status())
+    }
+
+    async fn fail_over(&self, from: usize, to: usize) {
+        warn!(
+            "{: <80}",
+            format!(
+                "pool endpoint {} failed {} times in a row, failing over to {}",
+                self.clients[from].base_uri(),
+                self.failover_after,
+                self.clients[to].base_uri()
+            )
+        );
+        self.active.store(to, Ordering::SeqCst);
+        {
+            #[cfg(feature = "async_io")]
+            let mut metrics = self.metrics.write().await;
+            #[cfg(not(feature = "async_io"))]
+            let mut metrics = match self.metrics.write() {
+                Ok(guard) => guard,
+                Err(poisoned) => {
+                    error!("metrics: mutex poisoned during failover, recovering...");
+                    poisoned.into_inner()
+                }
+            };
+            metrics.record_network_error();
+        }
+        self.declare_capacity(to).await;
+    }
+
+    async fn declare_capacity(&self, i: usize) {
+        let client = &self.clients[i];
+        if !client.capacity_outdated() {
+            return;
+        }
+        match client.announce().await {
+            Ok(()) => info!(
+                "{: <80}",
+                format!(
+                    "pool endpoint {}: declared capacity {}GB",
+                    client.base_uri(),
+                    client.declared_capacity_gb().unwrap_or(0)
+                )
+            ),
+            Err(e) => warn!(
+                "{: <80}",
+                format!(
+                    "pool endpoint {}: can't declare the capacity: {}",
+                    client.base_uri(),
+                    e
+                )
+            ),
+        }
+    }
+
+    /// Counts a submission's outcome against the endpoint at `url`, an answer from the pool is a
+    /// success even if it rejected the deadline. Submissions to other URLs are ignored.
+    pub async fn record_submission(&self, url: &Url, answered: bool) {
+        let Some(i) = self
+            .clients
+            .iter()
+            .position(|client| client.base_uri() == url)
+        else {
+            return;
+        };
+        if answered {
+            self.record_success(i);
+            return;
+        }
+        if !self.record_failure(i) || self.active.load(Ordering::SeqCst) != i {
+            return;
+        }
+        // without a probe, the next submission attempt shows whether the endpoint works
+        if let Some(&next) = self.ranked().iter().find(|&&next| next != i) {
+            self.fail_over(i, next).await;
+        }
+    }
+
+    async fn switch_to(&self, i: usize) {
+        let old = self.active.swap(i, Ordering::SeqCst);
+        if old != i {
+            info!(
+                "{: <80}",
+                format!(
+                    "switching pool endpoint: {} -> {}",
+                    self.clients[old].base_uri(),
+                    self.clients[i].base_uri()
+                )
+            );
+            self.declare_capacity(i).await;
+        }
+    }
+
+    pub async fn get_mining_info(&self) -> Result<MiningInfoResponse, FetchError> {
+        let active = self.active.load(Ordering::SeqCst);
+        let err = match self.clients[active].get_mining_info().await {
+            Ok(mining_info) => {
+                self.record_success(active);
+                return Ok(mining_info);
+            }
+            Err(e) => e,
+        };
+        if !self.record_failure(active) {
+            return Err(err);
+        }
+
+        for i in self.ranked() {
+            if i == active {
+                continue;
+            }
+            match self.clients[i].get_mining_info().await {
+                Ok(mining_info) => {
+                    self.record_success(i);
+                    self.fail_over(active, i).await;
+                    return Ok(mining_info);
+                }
+                Err(_) => {
+                    self.record_failure(i);
+                }
+            }
+        }
+        Err(err)
+    }
+
+    /// Measures the round trip of every endpoint and switches to the fastest healthy one.
+    pub async fn probe_latencies(&self) -> Vec<LatencyProbe> {
+        let mut probes = Vec::with_capacity(self.clients.len());
+        for (i, client) in self.clients.iter().enumerate() {
+            let start = Instant::now();
+            let latency_ms = match client.get_mining_info().await {
+                Ok(_) => Some(start.elapsed().as_millis() as u64),
+                Err(_) => None,
+            };
+            {
+                let mut status = self.status();
+                status[i].healthy = latency_ms.is_some();
+                if latency_ms.is_some() {
+                    status[i].latency_ms = latency_ms;
+                    status[i].consecutive_failures = 0;
+                }
+            }
+            probes.push(LatencyProbe {
+                url: client.base_uri().to_string(),
+                latency_ms,
+                declared_gb: client.declared_capacity_gb(),
+            });
+        }
+
+        if let Some(&best) = self.ranked().first() {
+            self.switch_to(best).await;
+        }
+        probes
+    }
+}
+
+/// Healthy endpoints by priority and latency, unmeasured ones last. Without a healthy endpoint
+/// all of them, so there is always one to try.
+fn rank(priorities: &[u32], status: &[EndpointStatus]) -> Vec<usize> {
+    let mut ranked: Vec<usize> = (0..status.len()).filter(|&i| status[i].healthy).collect();
+    if ranked.is_empty() {
+        ranked = (0..status.len()).collect();
+    }
+    ranked.sort_by_key(|&i| (priorities[i], status[i].latency_ms.unwrap_or(u64::MAX)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::com::client::{ConnectionSettings, ProxyDetails};
+    use crate::http_server::{serve_local, Response};
+    use crate::metrics::new_shared_metrics;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    /// A pool answering `getMiningInfo` after `delay`.
+    async fn pool(delay: Duration) -> Client {
+        let url = serve_local(move |_| async move {
+            tokio::time::sleep(delay).await;
+            Response::json(
+                serde_json::json!({
+                    "generationSignature": hex::encode([1u8; 32]),
+                    "baseTarget": "70000",
+                    "height": "5",
+                })
+                .to_string(),
+            )
+        })
+        .await;
+        let connection = ConnectionSettings {
+            timeout: 5000,
+            pool_max_idle_per_host: 1,
+            keep_alive: 0,
+            http2: false,
+        };
+        Client::new(
+            url,
+            HashMap::new(),
+            connection.build(),
+            0,
+            ProxyDetails::Disabled,
+            HashMap::new(),
+        )
+    }
+
+    fn status(latency_ms: Option<u64>, healthy: bool) -> EndpointStatus {
+        EndpointStatus {
+            latency_ms,
+            healthy,
+            consecutive_failures: 0,
+        }
+    }
+
+    #[test]
+    fn test_rank() {
+        let priorities = [1, 0, 0, 1];
+        let endpoints = [
+            status(Some(10), true),
+            status(Some(90), true),
+            status(Some(20), true),
+            status(None, true),
+        ];
+        // the primaries by latency, then the backups
+        assert_eq!(rank(&priorities, &endpoints), [2, 1, 0, 3]);
+
+        let endpoints = [
+            status(Some(10), true),
+            status(Some(90), false),
+            status(Some(20), false),
+            status(None, true),
+        ];
+        assert_eq!(rank(&priorities, &endpoints), [0, 3]);
+        assert_eq!(
+            rank(&[0, 0], &[status(None, false), status(Some(5), false)]),
+            [1, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_latencies_picks_fastest() {
+        let slow = pool(Duration::from_millis(200)).await;
+        let fast = pool(Duration::ZERO).await;
+        let fast_url = fast.base_uri().clone();
+        let metrics = new_shared_metrics(
+            String::new(),
+            None,
+            Vec::new(),
+            None,
+            None,
+            Vec::new(),
+            None,
+        );
+        let endpoints = PoolEndpoints::new(vec![(slow, 0), (fast, 0)], 1, metrics);
+        assert_eq!(endpoints.active().base_uri(), endpoints.clients()[0].base_uri());
+
+        let probes = endpoints.probe_latencies().await;
+        assert!(probes.iter().all(|probe| probe.latency_ms.is_some()));
+        assert!(probes[1].latency_ms < probes[0].latency_ms);
+        assert_eq!(endpoints.active().base_uri(), &fast_url);
+    }
+}
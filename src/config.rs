@@ -1,8 +1,27 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::net::IpAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+use crate::chains::{self, ChainCfg};
+use crate::circuit_breaker::BreakerCfg;
+use crate::cpu_worker::CpuHasher;
+use crate::deadline_format::DeadlineFormat;
+use crate::drive_labels;
+use crate::energy::EnergyCfg;
+use crate::io_priority::IoPriority;
+use crate::low_memory;
+use crate::page_cache::PageCache;
+use crate::preflight::Preflight;
+use crate::quota::QuotaCfg;
+use crate::remote_config;
+use crate::rotation::RotationEntry;
+use crate::scheduler::ReaderScheduler;
 use crate::plot::SCOOP_SIZE;
+use crate::plot_order::PlotOrder;
+use crate::sparse::SparsePlotAction;
+use crate::topology::CoreSelection;
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Serialize)]
@@ -14,38 +33,244 @@ pub enum Benchmark {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Cfg {
+    /// Label of this mining context, used in logs and metrics when several are configured.
+    #[serde(default)]
+    pub name: String,
+
+    /// Additional mining contexts, each overriding keys of this config. Buffers, reader and hash
+    /// worker threads are shared by all contexts and set up from the top level.
+    #[serde(default)]
+    pub instances: Vec<serde_yaml::Mapping>,
+
     #[serde(default = "default_secret_phrase")]
     pub account_id_to_secret_phrase: HashMap<u64, String>,
 
     #[serde(default)]
     pub plot_dirs: Vec<PathBuf>,
 
-    pub url: ::url::Url,
+    /// Drives (`LABEL=`, `UUID=` or paths) to wait for before mining starts.
+    #[serde(default)]
+    pub wait_for_drives: Vec<String>,
+
+    #[serde(default = "default_wait_for_drives_timeout")]
+    pub wait_for_drives_timeout: u64,
+
+    #[serde(default)]
+    pub raw_plots: Vec<RawPlotCfg>,
+
+    #[serde(default = "default_sparse_plots")]
+    pub sparse_plots: SparsePlotAction,
+
+    #[serde(default = "default_plot_order")]
+    pub plot_order: PlotOrder,
+
+    /// Plot file names or paths read first with `plot_order: custom`.
+    #[serde(default)]
+    pub plot_order_custom: Vec<String>,
+
+    /// Capacity caps per directory, drive or account.
+    #[serde(default)]
+    pub quotas: Vec<QuotaCfg>,
+
+    /// Mine legacy PoC1 plots (and verify the layout of every plot at startup).
+    #[serde(default)]
+    pub poc1_support: bool,
+
+    #[serde(default)]
+    pub plot_encryption_key: Option<String>,
+
+    #[serde(default)]
+    pub url: Option<::url::Url>,
+
+    #[serde(default)]
+    pub pools: Vec<PoolCfg>,
+
+    #[serde(default = "default_chain")]
+    pub chain: String,
+
+    #[serde(default)]
+    pub chains: HashMap<String, ChainCfg>,
+
+    #[serde(default = "default_latency_check_interval")]
+    pub latency_check_interval: u64,
+
+    /// Failed mining infos or submissions in a row before another pool endpoint takes over.
+    #[serde(default = "default_pool_failover_after")]
+    pub pool_failover_after: u32,
+
+    /// Windows in seconds the submission latency percentiles and availability are reported for.
+    #[serde(default = "default_pool_slo_windows")]
+    pub pool_slo_windows: Vec<u64>,
+
+    #[serde(default)]
+    pub fallback_node: Option<FallbackNodeCfg>,
+
+    /// Polled for mining info along with the pool, a new block from any of them starts the round.
+    /// Submissions still only go to the pool.
+    #[serde(default)]
+    pub mining_info_sources: Vec<::url::Url>,
+
+    #[serde(default)]
+    pub node_url: Option<::url::Url>,
+
+    #[serde(default)]
+    pub hooks: HashMap<String, String>,
+
+    #[serde(default = "default_explorer_url")]
+    pub explorer_url: Option<::url::Url>,
+
+    #[serde(default)]
+    pub audit_log_dir: Option<PathBuf>,
+
+    /// Hours of the audit log replayed into the metrics at startup, 0 starts them from zero.
+    #[serde(default)]
+    pub audit_backfill_hours: u64,
+
+    /// Address of the miner's HTTP API, see `http_api`. Only the first context's is used.
+    #[serde(default)]
+    pub api_listen: Option<String>,
+
+    /// Tokens for the API's GET endpoints, see `api_auth`.
+    #[serde(default)]
+    pub api_read_tokens: Vec<String>,
+
+    /// Tokens for every endpoint of the API, the POST endpoints are closed without one.
+    #[serde(default)]
+    pub api_admin_tokens: Vec<String>,
+
+    /// PEM certificate chain and private key, the API is served over HTTPS with both.
+    #[serde(default)]
+    pub api_tls_cert: Option<PathBuf>,
+
+    #[serde(default)]
+    pub api_tls_key: Option<PathBuf>,
+
+    /// Path prefix of every API endpoint, for a reverse proxy that passes it on, see `api_proxy`.
+    #[serde(default)]
+    pub api_base_path: Option<String>,
+
+    /// Origins of the pages that may call the API from a browser, `*` for any.
+    #[serde(default)]
+    pub api_cors_origins: Vec<String>,
+
+    /// Reverse proxies whose `X-Forwarded-For` names the API's clients in the logs.
+    #[serde(default)]
+    pub api_trusted_proxies: Vec<IpAddr>,
+
+    /// Set by `--dry-run`: submissions are logged and audited instead of sent.
+    #[serde(skip)]
+    pub dry_run: bool,
+
+    #[serde(default)]
+    pub payout_tracking: Option<PayoutTrackingCfg>,
+
+    #[serde(default)]
+    pub energy: Option<EnergyCfg>,
+
+    #[serde(default)]
+    pub fleet: Option<FleetCfg>,
+
+    /// Signed overlay replacing top level keys of this config, see `remote_config`.
+    #[serde(default)]
+    pub remote_config: Option<RemoteConfigCfg>,
+
+    /// Artificial read faults for testing, deliberately not in the example config.
+    #[serde(default)]
+    pub fault_injection: HashMap<String, DriveFaultsCfg>,
 
     #[serde(default = "default_hdd_reader_thread_count")]
     pub hdd_reader_thread_count: usize,
 
+    /// I/O priority of the reads by drive id, plot path prefix or `*`, see `io_priority`.
+    #[serde(default)]
+    pub io_priority: HashMap<String, IoPriority>,
+
+    /// What runs the per drive read tasks, `per_drive` ignores `hdd_reader_thread_count`.
+    #[serde(default = "default_reader_scheduler")]
+    pub reader_scheduler: ReaderScheduler,
+
     #[serde(default = "default_hdd_use_direct_io")]
     pub hdd_use_direct_io: bool,
 
     #[serde(default = "default_hdd_wakeup_after")]
     pub hdd_wakeup_after: i64,
 
+    /// Page cache handling of scoops read without direct I/O, see `page_cache`.
+    #[serde(default = "default_page_cache")]
+    pub page_cache: PageCache,
+
+    /// Plot file handles kept open between rounds, see `fd_pool`. 0 keeps all of them open.
+    #[serde(default = "default_max_open_files")]
+    pub max_open_files: usize,
+
+    #[serde(default = "default_pre_seek")]
+    pub pre_seek: bool,
+
+    /// Upper bound of a random delay before the reads of a round start, 0 for none.
+    #[serde(default)]
+    pub round_start_jitter_ms: u64,
+
+    /// Read errors within `drive_error_window` seconds after which a drive isn't read for
+    /// `drive_cooldown` seconds, 0 disables the circuit breaker.
+    #[serde(default = "default_drive_error_budget")]
+    pub drive_error_budget: u32,
+
+    #[serde(default = "default_drive_error_window")]
+    pub drive_error_window: u64,
+
+    #[serde(default = "default_drive_cooldown")]
+    pub drive_cooldown: u64,
+
     #[serde(default = "default_cpu_threads")]
     pub cpu_threads: usize,
 
     #[serde(default = "default_cpu_worker_task_count")]
     pub cpu_worker_task_count: usize,
 
+    /// Shabal implementation of the CPU workers, see `bench-hash`.
+    #[serde(default = "default_cpu_hasher")]
+    pub cpu_hasher: CpuHasher,
+
+    #[serde(default)]
+    pub tokio_worker_threads: usize,
+
+    #[serde(default = "default_tokio_max_blocking_threads")]
+    pub tokio_max_blocking_threads: usize,
+
+    #[serde(default = "default_tokio_thread_name")]
+    pub tokio_thread_name: String,
+
     #[serde(default = "default_cpu_nonces_per_cache")]
     pub cpu_nonces_per_cache: usize,
 
     #[serde(default = "default_io_buffer_size")]
     pub io_buffer_size: usize,
 
+    /// Preset for boards with little RAM: one hashing thread, one small buffer, no GPU.
+    #[serde(default)]
+    pub low_memory: bool,
+
+    /// Memory the miner may use in MiB, 0 for no limit.
+    #[serde(default)]
+    pub memory_limit_mb: u64,
+
+    /// Locks the read buffers in RAM so they can't be swapped out, see `mlock`.
+    #[serde(default)]
+    pub lock_buffers: bool,
+
     #[serde(default = "default_cpu_thread_pinning")]
     pub cpu_thread_pinning: bool,
 
+    #[serde(default = "default_cpu_thread_cores")]
+    pub cpu_thread_cores: CoreSelection,
+
+    /// Separate hashing pools for big and little cores on hybrid CPUs.
+    #[serde(default)]
+    pub cpu_split_pools: bool,
+
+    #[serde(default = "default_reader_thread_cores")]
+    pub reader_thread_cores: CoreSelection,
+
     #[serde(default = "default_gpu_threads")]
     pub gpu_threads: usize,
 
@@ -67,6 +292,34 @@ pub struct Cfg {
     #[serde(default = "default_gpu_async")]
     pub gpu_async: bool,
 
+    /// Fraction of GPU chunks hashed again on the CPU to catch wrong deadlines, 0 disables it.
+    #[serde(default = "default_gpu_verify_fraction")]
+    pub gpu_verify_fraction: f64,
+
+    /// Deadline mismatches per round tolerated before raising an error.
+    #[serde(default)]
+    pub gpu_verify_max_mismatches: u32,
+
+    /// Check once per GPU whether compressing transfers could pay off, see `transfer_compression`.
+    /// Only a check, transfers are never compressed.
+    #[serde(default)]
+    pub gpu_transfer_compression: bool,
+
+    /// Experimental: read plots straight into device memory on GPUs sharing memory with the host.
+    #[serde(default)]
+    pub gpu_direct_storage: bool,
+
+    /// Work group size of the wgpu backend, 0 autotunes it.
+    #[serde(default)]
+    pub gpu_workgroup_size: u32,
+
+    /// External OpenCL kernel, recompiled when the file changes.
+    #[serde(default)]
+    pub gpu_kernel_path: Option<PathBuf>,
+
+    #[serde(default)]
+    pub gpu_kernel_sha256: Option<String>,
+
     #[serde(default = "default_target_deadline")]
     pub target_deadline: u64,
 
@@ -79,21 +332,67 @@ pub struct Cfg {
     #[serde(default = "default_timeout")]
     pub timeout: u64,
 
+    /// Connectivity check of the pools and accounts before the first round.
+    #[serde(default = "default_preflight")]
+    pub preflight: Preflight,
+
+    #[serde(default = "default_http_pool_max_idle_per_host")]
+    pub http_pool_max_idle_per_host: usize,
+
+    #[serde(default = "default_http_keep_alive")]
+    pub http_keep_alive: u64,
+
+    #[serde(default = "default_http2")]
+    pub http2: bool,
+
     #[serde(default = "default_send_proxy_details")]
     pub send_proxy_details: bool,
 
+    /// User-Agent template of pools that don't set their own.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+
     #[serde(default = "default_additional_headers")]
     pub additional_headers: HashMap<String, String>,
 
+    /// Accounts the blocks are mined for in turn, see `rotation`.
+    #[serde(default)]
+    pub account_rotation: Vec<RotationEntry>,
+
     #[serde(default = "default_capacity_check_interval")]
     pub capacity_check_interval: u64,
 
+    /// Round state handed to the new process on a rolling upgrade (SIGUSR2).
+    #[serde(default = "default_upgrade_state_file")]
+    pub upgrade_state_file: PathBuf,
+
+    /// Plots taken out of mining, written by the `retire-plot` command.
+    #[serde(default = "default_retire_list")]
+    pub retire_list: PathBuf,
+
+    /// Base target of every block, for the difficulty trend.
+    #[serde(default = "default_difficulty_history_file")]
+    pub difficulty_history_file: Option<PathBuf>,
+
+    /// Holds the miner id generated at the first start.
+    #[serde(default = "default_miner_id_file")]
+    pub miner_id_file: PathBuf,
+
+    /// Read from `miner_id_file` at startup.
+    #[serde(skip)]
+    pub miner_id: String,
+
     #[serde(default = "default_console_log_level")]
     pub console_log_level: String,
 
     #[serde(default = "default_logfile_log_level")]
     pub logfile_log_level: String,
 
+    /// Levels per module (`reader`, `plot`, `client`, `gpu` or any module name), overriding
+    /// the console and log file levels. Reloaded from the config file while running.
+    #[serde(default)]
+    pub log_levels: HashMap<String, String>,
+
     #[serde(default = "default_logfile_max_count")]
     pub logfile_max_count: u32,
 
@@ -106,6 +405,9 @@ pub struct Cfg {
     #[serde(default = "default_logfile_log_pattern")]
     pub logfile_log_pattern: String,
 
+    #[serde(default = "default_deadline_format")]
+    pub deadline_format: DeadlineFormat,
+
     #[serde(default = "default_show_progress")]
     pub show_progress: bool,
 
@@ -115,9 +417,144 @@ pub struct Cfg {
     #[serde(default = "default_submit_only_best")]
     pub submit_only_best: bool,
 
+    #[serde(default = "default_deadline_outlier_window")]
+    pub deadline_outlier_window: usize,
+
+    #[serde(default = "default_deadline_outlier_threshold")]
+    pub deadline_outlier_threshold: f64,
+
     pub benchmark_only: Option<Benchmark>,
 }
 
+/// A plot written directly to a block device. The geometry has to be given explicitly as there
+/// is no file name to derive it from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawPlotCfg {
+    pub device: PathBuf,
+
+    /// Byte offset of the plot on the device.
+    #[serde(default)]
+    pub offset: u64,
+
+    pub account_id: u64,
+    pub start_nonce: u64,
+    pub nonces: u64,
+}
+
+/// An endpoint of the pool. Several endpoints (e.g. regional mirrors) can be configured, mining
+/// happens against the one with the lowest latency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolCfg {
+    pub url: ::url::Url,
+
+    #[serde(default = "default_chain")]
+    pub chain: String,
+
+    /// Another deadline is only sent once it beats the best one the pool has by this fraction
+    /// and by `min_improvement_secs`.
+    #[serde(default)]
+    pub min_improvement: f64,
+
+    #[serde(default)]
+    pub min_improvement_secs: u64,
+
+    /// Replaces the default User-Agent, a template like the values of `headers`.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+
+    /// Headers the pool software expects, values are templates, see `render_template`.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// Lower is preferred, endpoints of the same priority are picked by latency.
+    #[serde(default)]
+    pub priority: u32,
+}
+
+/// Node used for solo mining while the pool is unreachable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallbackNodeCfg {
+    pub url: ::url::Url,
+
+    #[serde(default = "default_secret_phrase")]
+    pub account_id_to_secret_phrase: HashMap<u64, String>,
+
+    #[serde(default = "default_fallback_after_failures")]
+    pub after_failures: u32,
+
+    #[serde(default = "default_fallback_probe_interval")]
+    pub probe_interval: u64,
+}
+
+/// Faults injected into the reads of a drive. The key in `fault_injection` is a drive id, a plot
+/// path prefix or `*` for every drive; rates are probabilities per read chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriveFaultsCfg {
+    #[serde(default)]
+    pub error_rate: f64,
+
+    #[serde(default)]
+    pub delay_rate: f64,
+
+    #[serde(default)]
+    pub delay_ms: u64,
+
+    /// Only half of the chunk arrives, the rest of its nonces aren't hashed.
+    #[serde(default)]
+    pub short_read_rate: f64,
+}
+
+/// Management server the miner registers with, to be tracked across IP changes and reinstalls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetCfg {
+    pub url: String,
+
+    #[serde(default = "default_fleet_interval")]
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfigCfg {
+    pub url: String,
+
+    /// Defaults to `url` with `.sig` appended.
+    #[serde(default)]
+    pub signature_url: Option<String>,
+
+    /// Ed25519 key the overlay must be signed with, hex encoded.
+    pub public_key: String,
+
+    #[serde(default = "default_remote_config_cache")]
+    pub cache: PathBuf,
+
+    #[serde(default = "default_remote_config_timeout")]
+    pub timeout: u64,
+}
+
+/// Pool API queried for balances and shares of the mined accounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutTrackingCfg {
+    /// `{account_id}` is replaced by the numeric account id.
+    pub url: String,
+
+    #[serde(default = "default_payout_pending_field")]
+    pub pending_field: Option<String>,
+
+    #[serde(default)]
+    pub paid_field: Option<String>,
+
+    #[serde(default = "default_payout_shares_field")]
+    pub shares_field: Option<String>,
+
+    /// The account's best deadline of the running round, known deadlines aren't beaten again
+    /// after a restart in the middle of a block.
+    #[serde(default = "default_payout_best_deadline_field")]
+    pub best_deadline_field: Option<String>,
+
+    #[serde(default = "default_payout_interval")]
+    pub interval: u64,
+}
+
 impl<'de> Deserialize<'de> for Benchmark {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -136,10 +573,82 @@ fn default_secret_phrase() -> HashMap<u64, String> {
     HashMap::new()
 }
 
+fn default_sparse_plots() -> SparsePlotAction {
+    SparsePlotAction::Warn
+}
+
+fn default_plot_order() -> PlotOrder {
+    PlotOrder::Modified
+}
+
+fn default_chain() -> String {
+    chains::DEFAULT_CHAIN.to_owned()
+}
+
+fn default_pool_slo_windows() -> Vec<u64> {
+    vec![3600, 86_400]
+}
+
+fn default_pool_failover_after() -> u32 {
+    3
+}
+
+fn default_latency_check_interval() -> u64 {
+    300
+}
+
+fn default_explorer_url() -> Option<::url::Url> {
+    ::url::Url::parse("https://explorer.signum.network").ok()
+}
+
+fn default_payout_pending_field() -> Option<String> {
+    Some("/pendingBalance".to_owned())
+}
+
+fn default_payout_shares_field() -> Option<String> {
+    Some("/nConf".to_owned())
+}
+
+fn default_payout_best_deadline_field() -> Option<String> {
+    Some("/currentRoundBestDeadline".to_owned())
+}
+
+fn default_payout_interval() -> u64 {
+    600
+}
+
+fn default_remote_config_cache() -> PathBuf {
+    PathBuf::from("remote-config.yaml")
+}
+
+fn default_remote_config_timeout() -> u64 {
+    10000
+}
+
+fn default_fleet_interval() -> u64 {
+    300
+}
+
+fn default_fallback_after_failures() -> u32 {
+    3
+}
+
+fn default_fallback_probe_interval() -> u64 {
+    60
+}
+
+fn default_pre_seek() -> bool {
+    true
+}
+
 fn default_hdd_reader_thread_count() -> usize {
     0
 }
 
+fn default_reader_scheduler() -> ReaderScheduler {
+    ReaderScheduler::Rayon
+}
+
 fn default_hdd_use_direct_io() -> bool {
     true
 }
@@ -148,14 +657,46 @@ fn default_hdd_wakeup_after() -> i64 {
     240
 }
 
+fn default_page_cache() -> PageCache {
+    PageCache::Keep
+}
+
+fn default_max_open_files() -> usize {
+    1024
+}
+
+fn default_drive_error_budget() -> u32 {
+    5
+}
+
+fn default_drive_error_window() -> u64 {
+    600
+}
+
+fn default_drive_cooldown() -> u64 {
+    1800
+}
+
 fn default_cpu_threads() -> usize {
     0
 }
 
+fn default_cpu_hasher() -> CpuHasher {
+    CpuHasher::Auto
+}
+
 fn default_cpu_worker_task_count() -> usize {
     0
 }
 
+fn default_tokio_max_blocking_threads() -> usize {
+    512
+}
+
+fn default_tokio_thread_name() -> String {
+    "signum-miner-rt".to_owned()
+}
+
 fn default_cpu_nonces_per_cache() -> usize {
     65536
 }
@@ -168,6 +709,14 @@ fn default_cpu_thread_pinning() -> bool {
     false
 }
 
+fn default_cpu_thread_cores() -> CoreSelection {
+    CoreSelection::Performance
+}
+
+fn default_reader_thread_cores() -> CoreSelection {
+    CoreSelection::Efficiency
+}
+
 fn default_gpu_threads() -> usize {
     0
 }
@@ -184,6 +733,10 @@ fn default_gpu_worker_task_count() -> usize {
     0
 }
 
+fn default_wait_for_drives_timeout() -> u64 {
+    300
+}
+
 fn default_gpu_nonces_per_cache() -> usize {
     1_048_576
 }
@@ -196,6 +749,10 @@ fn default_gpu_async() -> bool {
     false
 }
 
+fn default_gpu_verify_fraction() -> f64 {
+    0.01
+}
+
 fn default_target_deadline() -> u64 {
     u64::from(u32::MAX)
 }
@@ -212,10 +769,42 @@ fn default_timeout() -> u64 {
     5000
 }
 
+fn default_preflight() -> Preflight {
+    Preflight::Warn
+}
+
+fn default_http_pool_max_idle_per_host() -> usize {
+    32
+}
+
+fn default_http_keep_alive() -> u64 {
+    90
+}
+
+fn default_http2() -> bool {
+    true
+}
+
 fn default_send_proxy_details() -> bool {
     false
 }
 
+fn default_upgrade_state_file() -> PathBuf {
+    PathBuf::from("upgrade-state.json")
+}
+
+fn default_difficulty_history_file() -> Option<PathBuf> {
+    Some(PathBuf::from("difficulty-history.csv"))
+}
+
+fn default_miner_id_file() -> PathBuf {
+    PathBuf::from("miner-id")
+}
+
+fn default_retire_list() -> PathBuf {
+    PathBuf::from("retired_plots.txt")
+}
+
 fn default_capacity_check_interval() -> u64 {
     21600
 }
@@ -240,6 +829,10 @@ fn default_logfile_max_size() -> u64 {
     20
 }
 
+fn default_deadline_format() -> DeadlineFormat {
+    DeadlineFormat::Seconds
+}
+
 fn default_console_log_pattern() -> String {
     "\r{d(%H:%M:%S.%3f%z)} [{h({l}):<5}] [{T}] [{t}] - {M}:{m}{n}".to_owned()
 }
@@ -260,12 +853,104 @@ fn default_submit_only_best() -> bool {
     true
 }
 
+fn default_deadline_outlier_window() -> usize {
+    30
+}
+
+fn default_deadline_outlier_threshold() -> f64 {
+    2.0
+}
+
 pub fn load_cfg(config: &str) -> Result<Cfg, String> {
     let cfg_str = fs::read_to_string(config)
         .map_err(|e| format!("Failed to open config file '{}': {}. Please check that the file exists and is readable.", config, e))?;
 
     let cfg: Cfg = serde_yaml::from_str(&cfg_str)
         .map_err(|e| format!("Failed to parse config file '{}': {}. Please check YAML syntax.", config, e))?;
+    let cfg = match &cfg.remote_config {
+        Some(remote) => {
+            let mut value: serde_yaml::Mapping = serde_yaml::from_str(&cfg_str)
+                .map_err(|e| format!("Failed to parse config file '{}': {}", config, e))?;
+            if !apply_remote_overlay(config, remote, &mut value)? {
+                return check_cfg(config, cfg);
+            }
+            serde_yaml::from_value(serde_yaml::Value::Mapping(value)).map_err(|e| {
+                format!("Failed to parse config file '{}' with the remote overlay: {}", config, e)
+            })?
+        }
+        None => cfg,
+    };
+    check_cfg(config, cfg)
+}
+
+/// Applies the cached remote overlay to `value`, false if there is none yet.
+fn apply_remote_overlay(
+    config: &str,
+    remote: &RemoteConfigCfg,
+    value: &mut serde_yaml::Mapping,
+) -> Result<bool, String> {
+    match remote_config::load_cached(remote) {
+        Ok(Some(overlay)) => {
+            remote_config::apply(value, overlay);
+            Ok(true)
+        }
+        Ok(None) => Ok(false),
+        Err(e) => Err(format!("Remote overlay of '{}' rejected: {}", config, e)),
+    }
+}
+
+/// Loads all mining contexts of a config. Without `instances` this is just the config itself,
+/// otherwise every instance is the top level config with the instance's keys replaced.
+pub fn load_cfgs(config: &str) -> Result<Vec<Cfg>, String> {
+    let base = load_cfg(config)?;
+    if base.instances.is_empty() {
+        return Ok(vec![base]);
+    }
+
+    let cfg_str = fs::read_to_string(config)
+        .map_err(|e| format!("Failed to open config file '{}': {}", config, e))?;
+    let mut base_value: serde_yaml::Mapping = serde_yaml::from_str(&cfg_str)
+        .map_err(|e| format!("Failed to parse config file '{}': {}", config, e))?;
+    if let Some(remote) = &base.remote_config {
+        apply_remote_overlay(config, remote, &mut base_value)?;
+    }
+    base_value.remove(&serde_yaml::Value::from("instances"));
+
+    base.instances
+        .iter()
+        .enumerate()
+        .map(|(i, instance)| {
+            let mut value = base_value.clone();
+            for (key, v) in instance {
+                value.insert(key.clone(), v.clone());
+            }
+            let mut cfg: Cfg = serde_yaml::from_value(serde_yaml::Value::Mapping(value))
+                .map_err(|e| format!("Failed to parse instance {} of '{}': {}", i, config, e))?;
+            if cfg.name.is_empty() {
+                cfg.name = format!("instance{}", i);
+            }
+            check_cfg(config, cfg)
+        })
+        .collect()
+}
+
+fn check_cfg(config: &str, cfg: Cfg) -> Result<Cfg, String> {
+    if cfg.url.is_none() && cfg.pools.is_empty() {
+        return Err(format!(
+            "Configuration error in '{}': no pool configured. Please set 'url' or 'pools'.",
+            config
+        ));
+    }
+
+    for chain in std::iter::once(&cfg.chain).chain(cfg.pools.iter().map(|pool| &pool.chain)) {
+        if chains::resolve(&cfg.chains, chain).is_none() {
+            return Err(format!(
+                "Configuration error in '{}': unknown chain '{}'. Built in chains are mainnet and testnet, \
+                others have to be defined under 'chains'.",
+                config, chain
+            ));
+        }
+    }
 
     if cfg.hdd_use_direct_io {
         let cpu_nonces_per_cache = cfg.io_buffer_size / SCOOP_SIZE as usize;
@@ -281,25 +966,62 @@ pub fn load_cfg(config: &str) -> Result<Cfg, String> {
     Ok(validate_cfg(cfg))
 }
 
-pub fn validate_cfg(mut cfg: Cfg) -> Cfg {
-    let cores = num_cpus::get();
-    if cfg.cpu_threads == 0 {
-        cfg.cpu_threads = cores;
-    } else if cfg.cpu_threads > cores {
-        warn!(
-            "cpu_threads exceeds number of cores ({}), using ({}) threads",
-            cores, cores
-        );
-        cfg.cpu_threads = cores;
-    };
+/// The async readers run on the runtime, one thread per drive up to the core count. Without them
+/// the runtime only does networking and two threads are plenty.
+fn auto_tokio_worker_threads(drives: usize, cores: usize, async_io: bool) -> usize {
+    if async_io {
+        drives.min(cores).max(2)
+    } else {
+        2
+    }
+}
+
+/// The runtime shared by all mining contexts, sized for the most demanding one.
+pub fn build_runtime(cfgs: &[Cfg]) -> std::io::Result<tokio::runtime::Runtime> {
+    let worker_threads = cfgs
+        .iter()
+        .map(|cfg| cfg.tokio_worker_threads)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let max_blocking_threads = cfgs
+        .iter()
+        .map(|cfg| cfg.tokio_max_blocking_threads)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    info!(
+        "runtime-threads={}, max-blocking-threads={}",
+        worker_threads, max_blocking_threads
+    );
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .max_blocking_threads(max_blocking_threads)
+        .thread_name(cfgs.first().map_or_else(default_tokio_thread_name, |cfg| {
+            cfg.tokio_thread_name.clone()
+        }))
+        .enable_all()
+        .build()
+}
 
+/// Drops plot dirs that don't exist or aren't directories.
+pub fn filter_plot_dirs(cfg: &mut Cfg) {
     #[allow(clippy::iter_overeager_cloned)]
     let filtered_dirs: Vec<PathBuf> = cfg
         .plot_dirs
         .iter()
         .cloned()
         .filter(|plot_dir| {
-            if !plot_dir.exists() {
+            // a drive referred to by label may be plugged in later
+            if drive_labels::is_reference(plot_dir) {
+                if drive_labels::resolve_dir(plot_dir).is_none() {
+                    warn!(
+                        "{} isn't mounted, its plots are added once it is",
+                        plot_dir.display()
+                    );
+                }
+                true
+            } else if !plot_dir.exists() {
                 warn!("path {} does not exist", plot_dir.to_str().unwrap());
                 false
             } else if !plot_dir.is_dir() {
@@ -311,6 +1033,88 @@ pub fn validate_cfg(mut cfg: Cfg) -> Cfg {
         })
         .collect();
     cfg.plot_dirs = filtered_dirs;
+}
+
+pub fn validate_cfg(mut cfg: Cfg) -> Cfg {
+    let cores = num_cpus::get();
+    if cfg.cpu_threads == 0 {
+        cfg.cpu_threads = cores;
+    } else if cfg.cpu_threads > cores {
+        warn!(
+            "cpu_threads exceeds number of cores ({}), using ({}) threads",
+            cores, cores
+        );
+        cfg.cpu_threads = cores;
+    };
+    low_memory::apply(&mut cfg);
+
+    // drives that are waited for are checked after the wait
+    if cfg.wait_for_drives.is_empty() {
+        filter_plot_dirs(&mut cfg);
+    }
+
+    if cfg.tokio_worker_threads == 0 {
+        cfg.tokio_worker_threads = auto_tokio_worker_threads(
+            cfg.plot_dirs.len() + cfg.raw_plots.len(),
+            cores,
+            cfg!(feature = "async_io"),
+        );
+    }
+
+    if cfg.page_cache == PageCache::Warm && cfg.hdd_use_direct_io {
+        warn!("page_cache warm reads through the page cache, turning off hdd_use_direct_io");
+        cfg.hdd_use_direct_io = false;
+    }
+
+    if !(0.0..=1.0).contains(&cfg.gpu_verify_fraction) {
+        warn!(
+            "gpu_verify_fraction {} is outside 0..1, clamping",
+            cfg.gpu_verify_fraction
+        );
+        cfg.gpu_verify_fraction = cfg.gpu_verify_fraction.clamp(0.0, 1.0);
+    }
+
+    if cfg.plot_order == PlotOrder::Custom && cfg.plot_order_custom.is_empty() {
+        warn!("plot_order custom without plot_order_custom, plots are read by path");
+    }
+
+    if let Some(url) = cfg.url.clone() {
+        if !cfg.pools.iter().any(|pool| pool.url == url) {
+            cfg.pools.insert(
+                0,
+                PoolCfg {
+                    url,
+                    chain: cfg.chain.clone(),
+                    min_improvement: 0.0,
+                    min_improvement_secs: 0,
+                    user_agent: None,
+                    headers: HashMap::new(),
+                    priority: 0,
+                },
+            );
+        }
+    }
+
+    for pool in &mut cfg.pools {
+        if pool.user_agent.is_none() {
+            pool.user_agent = cfg.user_agent.clone();
+        }
+    }
+
+    if let Some(chain) = chains::resolve(&cfg.chains, &cfg.chain) {
+        cfg.node_url = cfg
+            .node_url
+            .take()
+            .map(|url| chains::with_default_port(url, &chain));
+        if let Some(fallback_node) = cfg.fallback_node.as_mut() {
+            fallback_node.url = chains::with_default_port(fallback_node.url.clone(), &chain);
+        }
+        cfg.mining_info_sources = cfg
+            .mining_info_sources
+            .drain(..)
+            .map(|url| chains::with_default_port(url, &chain))
+            .collect();
+    }
 
     cfg
 }
@@ -323,6 +1127,14 @@ impl Cfg {
     pub fn benchmark_io(&self) -> bool {
         matches!(self.benchmark_only, Some(Benchmark::IO))
     }
+
+    pub fn breaker_cfg(&self) -> BreakerCfg {
+        BreakerCfg {
+            error_budget: self.drive_error_budget,
+            window: Duration::from_secs(self.drive_error_window),
+            cooldown: Duration::from_secs(self.drive_cooldown),
+        }
+    }
 }
 
 
@@ -356,4 +1168,76 @@ mod tests {
         let expected_path = PathBuf::from("test_data");
         assert_eq!(cfg.plot_dirs, vec![expected_path]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_auto_tokio_worker_threads() {
+        assert_eq!(auto_tokio_worker_threads(12, 8, true), 8);
+        assert_eq!(auto_tokio_worker_threads(3, 8, true), 3);
+        assert_eq!(auto_tokio_worker_threads(1, 8, true), 2);
+        assert_eq!(auto_tokio_worker_threads(12, 8, false), 2);
+    }
+
+    #[test]
+    fn test_load_cfgs_instances() {
+        let file = std::env::temp_dir()
+            .join(format!("signum-miner-instances-{}.yaml", std::process::id()));
+        std::fs::write(
+            &file,
+            concat!(
+                "timeout: 5000\n",
+                "url: 'http://localhost'\n",
+                "target_deadline: 1000\n",
+                "instances:\n",
+                "  - name: 'mainnet'\n",
+                "  - name: 'testnet'\n",
+                "    url: 'http://localhost:6876'\n",
+                "    chain: 'testnet'\n",
+                "    timeout: 2000\n",
+            ),
+        )
+        .unwrap();
+        let cfgs = load_cfgs(file.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(cfgs.len(), 2);
+        assert_eq!(cfgs[0].name, "mainnet");
+        assert_eq!(cfgs[0].url.as_ref().unwrap().port(), None);
+        assert_eq!(cfgs[0].timeout, 5000);
+        assert_eq!(cfgs[1].name, "testnet");
+        assert_eq!(cfgs[1].url.as_ref().unwrap().port(), Some(6876));
+        assert_eq!(cfgs[1].chain, "testnet");
+        assert_eq!(cfgs[1].timeout, 2000);
+        // keys the instance doesn't set come from the top level
+        assert_eq!(cfgs[1].target_deadline, 1000);
+        assert!(cfgs.iter().all(|cfg| cfg.instances.is_empty()));
+    }
+
+    #[test]
+    fn test_build_runtime() {
+        let file =
+            std::env::temp_dir().join(format!("signum-miner-runtime-{}.yaml", std::process::id()));
+        std::fs::write(
+            &file,
+            concat!(
+                "url: 'http://localhost'\n",
+                "tokio_worker_threads: 2\n",
+                "tokio_max_blocking_threads: 4\n",
+                "tokio_thread_name: 'test-rt'\n",
+                "instances:\n",
+                "  - name: 'mainnet'\n",
+                "  - name: 'testnet'\n",
+                "    tokio_worker_threads: 3\n",
+            ),
+        )
+        .unwrap();
+        let cfgs = load_cfgs(file.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        // the busiest context sizes the runtime for all of them
+        let runtime = build_runtime(&cfgs).unwrap();
+        assert_eq!(runtime.metrics().num_workers(), 3);
+        let name = runtime.spawn_blocking(|| std::thread::current().name().map(str::to_owned));
+        let name = runtime.block_on(name).unwrap();
+        assert_eq!(name.as_deref(), Some("test-rt"));
+    }
+}
@@ -1,18 +1,15 @@
+use crate::buffer_pool::BufferPool;
 use crate::miner::{Buffer, NonceData};
-#[cfg(any(
-    test,
-    feature = "neon",
-    not(any(
-        feature = "simd_avx512f",
-        feature = "simd_avx2",
-        feature = "simd_avx",
-        feature = "simd_sse2",
-    ))
-))]
 use crate::poc_hashing::find_best_deadline_rust;
 use crate::reader::ReadReply;
-use crossbeam_channel::{Receiver, Sender};
-use tokio::sync::mpsc::Sender as TokioSender;
+use crate::split_pools::HashingPool;
+use crate::stages::Stage;
+use crossbeam_channel::Receiver;
+use rayon::prelude::*;
+use serde::de::{self, Deserialize, Deserializer};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
 #[cfg(any(feature = "simd", feature = "neon"))]
 use libc::c_void;
@@ -72,183 +69,309 @@ extern "C" {
     );
 }
 
+/// Which shabal implementation the CPU workers use, `auto` is the SIMD extension the binary was
+/// built with. `bench-hash` compares them and can pin the faster one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum CpuHasher {
+    Auto,
+    Scalar,
+}
+
+impl<'de> Deserialize<'de> for CpuHasher {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "auto" | "simd" => Ok(CpuHasher::Auto),
+            "scalar" => Ok(CpuHasher::Scalar),
+            _ => Err(de::Error::custom(format!(
+                "unknown cpu_hasher '{}' (auto, scalar)",
+                s
+            ))),
+        }
+    }
+}
+
+impl CpuHasher {
+    /// Name as written in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            CpuHasher::Auto => "auto",
+            CpuHasher::Scalar => "scalar",
+        }
+    }
+}
+
+static SCALAR: AtomicBool = AtomicBool::new(false);
+
+pub fn set_cpu_hasher(hasher: CpuHasher) {
+    SCALAR.store(hasher == CpuHasher::Scalar, Ordering::Relaxed);
+}
+
+pub fn cpu_hasher() -> CpuHasher {
+    if SCALAR.load(Ordering::Relaxed) {
+        CpuHasher::Scalar
+    } else {
+        CpuHasher::Auto
+    }
+}
+
 pub fn create_cpu_worker_task(
     benchmark: bool,
     thread_pool: rayon::ThreadPool,
     rx_read_replies: Receiver<ReadReply>,
-    tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
-    tx_nonce_data: TokioSender<NonceData>,
+    buffer_pool: Arc<BufferPool>,
 ) -> impl FnOnce() + Send + 'static {
     move || {
-        for read_reply in rx_read_replies {
-            let task = hash(
-                read_reply,
-                tx_empty_buffers.clone(),
-                tx_nonce_data.clone(),
-                benchmark,
-            );
-
-            thread_pool.spawn(task);
+        let batch_size = thread_pool.current_num_threads().max(1);
+        for read_reply in rx_read_replies.iter() {
+            // take whatever else is queued with the same wakeup, one pool task spreads it over
+            // the idle threads instead of one injector push per chunk
+            let (batch, marker) = next_batch(read_reply, &rx_read_replies, batch_size);
+            if !batch.is_empty() {
+                let buffer_pool = buffer_pool.clone();
+                thread_pool.spawn(move || hash_batch(batch, &buffer_pool, benchmark));
+            }
+            if let Some(marker) = marker {
+                forward_round_finished(&marker);
+            }
         }
     }
 }
 
-pub fn hash(
-    read_reply: ReadReply,
-    tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
-    tx_nonce_data: TokioSender<NonceData>,
+/// Hashes on one of several pools sharing `rx_read_replies`. A pool only takes the next batch
+/// when it's done with the last, so slower pools take fewer chunks.
+pub fn create_pooled_cpu_worker_task(
     benchmark: bool,
+    hashing_pool: HashingPool,
+    rx_read_replies: Receiver<ReadReply>,
+    buffer_pool: Arc<BufferPool>,
 ) -> impl FnOnce() + Send + 'static {
     move || {
-        let mut buffer = read_reply.buffer;
-
-        if read_reply.info.len == 0 || benchmark {
-            if read_reply.info.finished {
-                let deadline = u64::MAX;
-                let _ = tx_nonce_data.blocking_send(NonceData {
-                    height: read_reply.info.height,
-                    block: read_reply.info.block,
-                    base_target: read_reply.info.base_target,
-                    deadline,
-                    nonce: 0,
-                    reader_task_processed: read_reply.info.finished,
-                    account_id: read_reply.info.account_id,
-                });
+        let batch_size = hashing_pool.batch_size.max(1);
+        for read_reply in rx_read_replies.iter() {
+            let (batch, marker) = next_batch(read_reply, &rx_read_replies, batch_size);
+            if !batch.is_empty() {
+                hashing_pool
+                    .pool
+                    .install(|| hash_batch(batch, &buffer_pool, benchmark));
+            }
+            if let Some(marker) = marker {
+                forward_round_finished(&marker);
             }
-            let _ = tx_empty_buffers.send(buffer);
-            return;
         }
+    }
+}
 
-        if read_reply.info.len == 1 && read_reply.info.gpu_signal > 0 {
-            return;
+/// `first` and the chunks queued behind it, up to `batch_size`. A round finished marker ends the
+/// batch and is returned on its own, it must not reach the miner before the chunks ahead of it.
+fn next_batch(
+    first: ReadReply,
+    rx_read_replies: &Receiver<ReadReply>,
+    batch_size: usize,
+) -> (Vec<ReadReply>, Option<ReadReply>) {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut next = Some(first);
+    while let Some(read_reply) = next.take() {
+        if read_reply.info.round_finished {
+            return (batch, Some(read_reply));
         }
+        batch.push(read_reply);
+        if batch.len() < batch_size {
+            next = rx_read_replies.try_recv().ok();
+        }
+    }
+    (batch, None)
+}
+
+/// Hashes a batch in parallel and returns its buffers to the pool at once, waking the waiting
+/// readers a single time.
+fn hash_batch(batch: Vec<ReadReply>, buffer_pool: &BufferPool, benchmark: bool) {
+    let buffers: Vec<Box<dyn Buffer + Send>> = batch
+        .into_par_iter()
+        .filter_map(|read_reply| hash(read_reply, benchmark))
+        .collect();
+    buffer_pool.push_batch(buffers);
+}
 
-        #[allow(unused_assignments)]
-        let mut deadline: u64 = u64::MAX;
-        #[allow(unused_assignments)]
-        let mut offset: u64 = 0;
+/// The last drive of the round is done, tells the miner once the CPU workers hashed all chunks
+/// of the round. The marker's dummy buffer isn't pooled.
+fn forward_round_finished(marker: &ReadReply) {
+    let header = &marker.info.header;
+    header.barrier.wait_hashed(&header.cancel);
+    let _ = header.tx_nonce_data.blocking_send(NonceData {
+        height: marker.info.header.height,
+        block: marker.info.header.block,
+        base_target: marker.info.header.base_target,
+        deadline: u64::MAX,
+        nonce: 0,
+        reader_task_processed: false,
+        round_finished: true,
+        account_id: 0,
+        drive_id: marker.info.header.drive_id.clone(),
+    });
+}
+
+/// Hashes a chunk and returns its buffer for the pool, None for the dummy buffers of signals.
+fn hash(read_reply: ReadReply, benchmark: bool) -> Option<Box<dyn Buffer + Send>> {
+    // the chunk counts as hashed once `read_reply.info` drops, after its result is sent
+    let mut buffer = read_reply.buffer;
+
+    if read_reply.info.len == 0 || benchmark {
+        if read_reply.info.finished {
+            let deadline = u64::MAX;
+            let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+                height: read_reply.info.header.height,
+                block: read_reply.info.header.block,
+                base_target: read_reply.info.header.base_target,
+                deadline,
+                nonce: 0,
+                reader_task_processed: read_reply.info.finished,
+                round_finished: false,
+                account_id: read_reply.info.account_id,
+                drive_id: read_reply.info.header.drive_id.clone(),
+            });
+        }
+        return Some(buffer);
+    }
+
+    if read_reply.info.len == 1 && read_reply.info.gpu_signal > 0 {
+        return None;
+    }
+
+    // the round is over, its queued chunks aren't worth hashing
+    if read_reply.info.header.cancel.is_cancelled() {
+        buffer.unmap();
+        return Some(buffer);
+    }
 
-        let bs = buffer.get_buffer_for_writing();
+    let bs = buffer.get_buffer_for_writing();
 #[cfg(feature = "async_io")]
-        let bs = bs.blocking_lock();
+    let bs = crate::utils::lock_sync(&bs);
 #[cfg(not(feature = "async_io"))]
-        let bs = match bs.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                error!("cpu_worker: buffer mutex poisoned, recovering...");
-                poisoned.into_inner()
-            }
-        };
-
-        #[cfg(feature = "simd_avx512f")]
-        unsafe {
-            find_best_deadline_avx512f(
-                bs.as_ptr() as *mut c_void,
-                (read_reply.info.len as u64) / 64,
-                read_reply.info.gensig.as_ptr() as *const c_void,
-                &mut deadline,
-                &mut offset,
-            );
+    let bs = match bs.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("cpu_worker: buffer mutex poisoned, recovering...");
+            poisoned.into_inner()
         }
+    };
 
-        #[cfg(feature = "simd_avx2")]
-        unsafe {
-            find_best_deadline_avx2(
-                bs.as_ptr() as *mut c_void,
-                (read_reply.info.len as u64) / 64,
-                read_reply.info.gensig.as_ptr() as *const c_void,
-                &mut deadline,
-                &mut offset,
-            );
-        }
+    let hashing = Instant::now();
+    let (deadline, offset) = find_best_deadline(
+        &bs,
+        (read_reply.info.len as u64) / 64,
+        &read_reply.info.header.gensig,
+        cpu_hasher(),
+    );
 
-        #[cfg(feature = "simd_avx")]
-        unsafe {
-            find_best_deadline_avx(
-                bs.as_ptr() as *mut c_void,
-                (read_reply.info.len as u64) / 64,
-                read_reply.info.gensig.as_ptr() as *const c_void,
-                &mut deadline,
-                &mut offset,
-            );
-        }
+    read_reply.info.header.stages.add(Stage::Hashing, hashing.elapsed());
 
-        #[cfg(feature = "simd_sse2")]
-        unsafe {
-            find_best_deadline_sse2(
-                bs.as_ptr() as *mut c_void,
-                (read_reply.info.len as u64) / 64,
-                read_reply.info.gensig.as_ptr() as *const c_void,
-                &mut deadline,
-                &mut offset,
-            );
-        }
+    let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+        height: read_reply.info.header.height,
+        block: read_reply.info.header.block,
+        base_target: read_reply.info.header.base_target,
+        deadline,
+        nonce: offset.saturating_add(read_reply.info.start_nonce),
+        reader_task_processed: read_reply.info.finished,
+        round_finished: false,
+        account_id: read_reply.info.account_id,
+        drive_id: read_reply.info.header.drive_id.clone(),
+    });
 
-        #[cfg(feature = "neon")]
-        unsafe {
-            #[cfg(target_arch = "arm")]
-            let neon = is_arm_feature_detected!("neon");
-            #[cfg(target_arch = "aarch64")]
-            let neon = true;
-            if neon {
-                find_best_deadline_neon(
-                    bs.as_ptr() as *mut c_void,
-                    (read_reply.info.len as u64) / 64,
-                    read_reply.info.gensig.as_ptr() as *const c_void,
-                    &mut deadline,
-                    &mut offset,
-                );
-            } else {
-                let result = find_best_deadline_rust(
-                    &bs,
-                    (read_reply.info.len as u64) / 64,
-                    &*read_reply.info.gensig,
-                );
-                deadline = result.0;
-                offset = result.1;
-            }
-        }
+    drop(bs);
+    Some(buffer)
+}
+
+
+/// Best deadline and its offset in nonces, hashed with the SIMD extension the binary was built
+/// with unless `hasher` asks for the scalar implementation.
+pub fn find_best_deadline(
+    data: &[u8],
+    nonce_count: u64,
+    gensig: &[u8; 32],
+    hasher: CpuHasher,
+) -> (u64, u64) {
+    if hasher == CpuHasher::Scalar || !cfg!(any(feature = "simd", feature = "neon")) {
+        return find_best_deadline_rust(data, nonce_count, gensig);
+    }
+
+    #[allow(unused_assignments, unused_mut)]
+    let mut deadline: u64 = u64::MAX;
+    #[allow(unused_assignments, unused_mut)]
+    let mut offset: u64 = 0;
 
-        #[cfg(not(any(
-            feature = "simd_avx512f",
-            feature = "simd_avx2",
-            feature = "simd_avx",
-            feature = "simd_sse2",
-            feature = "neon"
-        )))]
-        {
-            let result = find_best_deadline_rust(
-                &bs,
-                (read_reply.info.len as u64) / 64,
-                &*read_reply.info.gensig,
+    #[cfg(feature = "simd_avx512f")]
+    unsafe {
+        find_best_deadline_avx512f(
+            data.as_ptr() as *mut c_void,
+            nonce_count,
+            gensig.as_ptr() as *const c_void,
+            &mut deadline,
+            &mut offset,
+        );
+    }
+
+    #[cfg(feature = "simd_avx2")]
+    unsafe {
+        find_best_deadline_avx2(
+            data.as_ptr() as *mut c_void,
+            nonce_count,
+            gensig.as_ptr() as *const c_void,
+            &mut deadline,
+            &mut offset,
+        );
+    }
+
+    #[cfg(feature = "simd_avx")]
+    unsafe {
+        find_best_deadline_avx(
+            data.as_ptr() as *mut c_void,
+            nonce_count,
+            gensig.as_ptr() as *const c_void,
+            &mut deadline,
+            &mut offset,
+        );
+    }
+
+    #[cfg(feature = "simd_sse2")]
+    unsafe {
+        find_best_deadline_sse2(
+            data.as_ptr() as *mut c_void,
+            nonce_count,
+            gensig.as_ptr() as *const c_void,
+            &mut deadline,
+            &mut offset,
+        );
+    }
+
+    #[cfg(feature = "neon")]
+    unsafe {
+        #[cfg(target_arch = "arm")]
+        let neon = is_arm_feature_detected!("neon");
+        #[cfg(target_arch = "aarch64")]
+        let neon = true;
+        if neon {
+            find_best_deadline_neon(
+                data.as_ptr() as *mut c_void,
+                nonce_count,
+                gensig.as_ptr() as *const c_void,
+                &mut deadline,
+                &mut offset,
             );
-            deadline = result.0;
-            offset = result.1;
+        } else {
+            return find_best_deadline_rust(data, nonce_count, gensig);
         }
-
-        let _ = tx_nonce_data.blocking_send(NonceData {
-            height: read_reply.info.height,
-            block: read_reply.info.block,
-            base_target: read_reply.info.base_target,
-            deadline,
-            nonce: offset.saturating_add(read_reply.info.start_nonce),
-            reader_task_processed: read_reply.info.finished,
-            account_id: read_reply.info.account_id,
-        });
-
-        let _ = tx_empty_buffers.send(buffer);
     }
-}
 
+    (deadline, offset)
+}
 
 #[cfg(test)]
 mod tests {
     use crate::poc_hashing::find_best_deadline_rust;
-    use hex;
-    use std::u64;
-
-    #[cfg(any(feature = "simd", feature = "neon"))]
-    use libc::c_void;
 
     #[test]
     fn test_deadline_hashing() {
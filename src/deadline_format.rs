@@ -0,0 +1,129 @@
+//! How deadlines are shown in logs and the metrics summary.
+//!
+//! Set once at startup from `deadline_format`, so every module prints deadlines the same way.
+//!
+//! A deadline counts seconds of a chain with Signum's 240s blocks. On a chain with another block
+//! time, e.g. a fork or after a change of the chain's parameters, it's scaled before it's shown as
+//! days and hours. The block time comes from the chain's config and is replaced by the one the
+//! pools or the node report.
+
+use serde::de::{self, Deserialize, Deserializer};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// Block time in seconds the deadlines are calculated for.
+pub const REFERENCE_BLOCK_TIME: u64 = 240;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DeadlineFormat {
+    /// `266706`
+    Seconds,
+    /// `3d 2h 5m 6s`
+    Human,
+    /// `266706 (3d 2h 5m 6s)`
+    Both,
+}
+
+impl<'de> Deserialize<'de> for DeadlineFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "seconds" => Ok(DeadlineFormat::Seconds),
+            "human" => Ok(DeadlineFormat::Human),
+            "both" => Ok(DeadlineFormat::Both),
+            _ => Err(de::Error::custom(format!(
+                "unknown deadline_format '{}' (seconds, human, both)",
+                s
+            ))),
+        }
+    }
+}
+
+static FORMAT: OnceLock<DeadlineFormat> = OnceLock::new();
+static BLOCK_TIME: AtomicU64 = AtomicU64::new(REFERENCE_BLOCK_TIME);
+
+/// Only the first call has an effect, all mining contexts share the format.
+pub fn set_deadline_format(format: DeadlineFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Block time of the mined chain in seconds, 0 is ignored.
+pub fn set_block_time(secs: u64) {
+    if secs > 0 {
+        BLOCK_TIME.store(secs, Ordering::Relaxed);
+    }
+}
+
+pub fn block_time() -> u64 {
+    BLOCK_TIME.load(Ordering::Relaxed)
+}
+
+/// Seconds `deadline` stands for with blocks of `block_time` seconds.
+fn scale(deadline: u64, block_time: u64) -> u64 {
+    (u128::from(deadline) * u128::from(block_time) / u128::from(REFERENCE_BLOCK_TIME))
+        .min(u128::from(u64::MAX)) as u64
+}
+
+pub fn format_deadline(deadline: u64) -> String {
+    format_deadline_as(
+        deadline,
+        *FORMAT.get().unwrap_or(&DeadlineFormat::Seconds),
+        block_time(),
+    )
+}
+
+/// The raw deadline is kept for `Seconds`, it's what pools and nodes show.
+fn format_deadline_as(deadline: u64, format: DeadlineFormat, block_time: u64) -> String {
+    match format {
+        DeadlineFormat::Seconds => deadline.to_string(),
+        DeadlineFormat::Human => humanize(scale(deadline, block_time)),
+        DeadlineFormat::Both => format!(
+            "{} ({})",
+            deadline,
+            humanize(scale(deadline, block_time))
+        ),
+    }
+}
+
+/// Leading zero units are left out, e.g. `5m 0s`.
+fn humanize(secs: u64) -> String {
+    let units = [
+        (secs / 86_400, "d"),
+        (secs % 86_400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+    ];
+    let first = units.iter().position(|&(n, _)| n > 0).unwrap_or(3);
+    units[first..]
+        .iter()
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_deadline() {
+        assert_eq!(format_deadline_as(266_706, DeadlineFormat::Seconds, 240), "266706");
+        assert_eq!(format_deadline_as(266_706, DeadlineFormat::Human, 240), "3d 2h 5m 6s");
+        assert_eq!(
+            format_deadline_as(300, DeadlineFormat::Both, 240),
+            "300 (5m 0s)"
+        );
+        assert_eq!(format_deadline_as(0, DeadlineFormat::Human, 240), "0s");
+    }
+
+    #[test]
+    fn test_other_block_time() {
+        // a fork with 60s blocks
+        assert_eq!(format_deadline_as(960, DeadlineFormat::Both, 60), "960 (4m 0s)");
+        assert_eq!(format_deadline_as(960, DeadlineFormat::Seconds, 60), "960");
+        assert_eq!(scale(u64::MAX, 480), u64::MAX);
+    }
+}
@@ -0,0 +1,153 @@
+//! Per drive deadline statistics.
+//!
+//! Raw deadlines are uniformly distributed over `[0, 2^64)`, so the best raw deadline a drive
+//! finds in a round is expected to be around `2^64 / nonces`. Normalising each round's best by that
+//! value yields a score with a mean of 1.0. A drive whose scores are systematically higher than
+//! its capacity predicts almost always returns garbage for a part of its plots (silent corruption,
+//! holes, wrong account/nonce metadata in the file names).
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+const DEADLINE_RANGE: f64 = 18_446_744_073_709_551_616.0; // 2^64
+
+pub struct OutlierReport {
+    pub drive_id: String,
+    pub mean_score: f64,
+    pub rounds: usize,
+}
+
+pub struct DeadlineOutlierDetector {
+    window: usize,
+    threshold: f64,
+    drive_id_to_nonces: HashMap<String, u64>,
+    history: HashMap<String, VecDeque<f64>>,
+    flagged: HashSet<String>,
+}
+
+impl DeadlineOutlierDetector {
+    pub fn new(window: usize, threshold: f64) -> Self {
+        Self {
+            window,
+            threshold,
+            drive_id_to_nonces: HashMap::new(),
+            history: HashMap::new(),
+            flagged: HashSet::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.window > 0
+    }
+
+    /// Updates the capacity per drive. History of drives whose capacity changed is dropped, as
+    /// the old scores don't describe the new plot set.
+    pub fn set_capacities(&mut self, drive_id_to_nonces: HashMap<String, u64>) {
+        self.history.retain(|drive_id, _| {
+            drive_id_to_nonces.get(drive_id) == self.drive_id_to_nonces.get(drive_id)
+        });
+        self.flagged
+            .retain(|drive_id| drive_id_to_nonces.contains_key(drive_id));
+        self.drive_id_to_nonces = drive_id_to_nonces;
+    }
+
+    /// Records the best raw deadline every drive found in a completed round and returns the drives
+    /// that newly crossed the threshold.
+    pub fn record_round(&mut self, drive_id_to_best: &HashMap<Arc<str>, u64>) -> Vec<OutlierReport> {
+        let mut reports = Vec::new();
+        if !self.enabled() {
+            return reports;
+        }
+
+        for (drive_id, best) in drive_id_to_best {
+            let nonces = match self.drive_id_to_nonces.get(drive_id.as_ref()) {
+                Some(&nonces) if nonces > 0 => nonces,
+                _ => continue,
+            };
+
+            let score = *best as f64 * nonces as f64 / DEADLINE_RANGE;
+            let history = self.history.entry(drive_id.to_string()).or_default();
+            history.push_back(score);
+            while history.len() > self.window {
+                history.pop_front();
+            }
+
+            // wait for half a window to avoid flagging drives because of a few unlucky rounds
+            if history.len() < (self.window / 2).max(1) {
+                continue;
+            }
+
+            let mean_score = history.iter().sum::<f64>() / history.len() as f64;
+            if mean_score > self.threshold {
+                if self.flagged.insert(drive_id.to_string()) {
+                    reports.push(OutlierReport {
+                        drive_id: drive_id.to_string(),
+                        mean_score,
+                        rounds: history.len(),
+                    });
+                }
+            } else if self.flagged.remove(drive_id.as_ref()) {
+                info!(
+                    "deadline stats: drive {} back to normal (score={:.2})",
+                    drive_id, mean_score
+                );
+            }
+        }
+
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round(drive: &str, best: u64) -> HashMap<Arc<str>, u64> {
+        let mut m = HashMap::new();
+        m.insert(Arc::from(drive), best);
+        m
+    }
+
+    #[test]
+    fn test_healthy_drive_is_not_flagged() {
+        let mut detector = DeadlineOutlierDetector::new(10, 2.0);
+        let mut caps = HashMap::new();
+        caps.insert("a".to_owned(), 1024);
+        detector.set_capacities(caps);
+
+        // exactly the expected best deadline every round
+        let expected = (DEADLINE_RANGE / 1024.0) as u64;
+        for _ in 0..20 {
+            assert!(detector.record_round(&round("a", expected)).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bad_drive_is_flagged_once() {
+        let mut detector = DeadlineOutlierDetector::new(10, 2.0);
+        let mut caps = HashMap::new();
+        caps.insert("a".to_owned(), 1024);
+        detector.set_capacities(caps);
+
+        let bad = (DEADLINE_RANGE / 1024.0 * 5.0) as u64;
+        let mut flagged = 0;
+        for _ in 0..20 {
+            flagged += detector.record_round(&round("a", bad)).len();
+        }
+        assert_eq!(flagged, 1);
+    }
+
+    #[test]
+    fn test_capacity_change_resets_history() {
+        let mut detector = DeadlineOutlierDetector::new(4, 2.0);
+        let mut caps = HashMap::new();
+        caps.insert("a".to_owned(), 1024);
+        detector.set_capacities(caps.clone());
+
+        let bad = (DEADLINE_RANGE / 1024.0 * 5.0) as u64;
+        detector.record_round(&round("a", bad));
+        caps.insert("a".to_owned(), 2048);
+        detector.set_capacities(caps);
+        assert!(!detector.history.contains_key("a"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Network difficulty over time.
+//!
+//! Every new block's base target is recorded with its time, in memory and appended to
+//! `difficulty_history_file` so the history survives restarts. The difficulty is the genesis base
+//! target divided by the block's, so it grows with the network's capacity. Comparing the last day
+//! and week with the ones before tells whether worse deadlines come from a growing network rather
+//! than from the miner.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Base target of the genesis block, difficulty 1.
+const GENESIS_BASE_TARGET: u64 = 18_325_193_796;
+/// About two weeks of blocks, enough to compare one week with the one before.
+const HISTORY: usize = 5040;
+const DAY: i64 = 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifficultySample {
+    pub height: u64,
+    /// Unix time the block was seen.
+    pub time: i64,
+    pub base_target: u64,
+}
+
+impl DifficultySample {
+    pub fn difficulty(&self) -> f64 {
+        GENESIS_BASE_TARGET as f64 / self.base_target.max(1) as f64
+    }
+
+    fn parse(line: &str) -> Option<DifficultySample> {
+        let mut fields = line
+            .split(',')
+            .map(|field| field.trim().parse::<i64>().ok());
+        let (height, time, base_target) = (fields.next()??, fields.next()??, fields.next()??);
+        Some(DifficultySample {
+            height: u64::try_from(height).ok()?,
+            time,
+            base_target: u64::try_from(base_target).ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DifficultyHistory {
+    samples: VecDeque<DifficultySample>,
+    file: Option<PathBuf>,
+}
+
+impl DifficultyHistory {
+    /// Loads the recorded history, lines that don't parse are skipped.
+    pub fn load(file: Option<PathBuf>) -> DifficultyHistory {
+        let mut history = DifficultyHistory {
+            samples: VecDeque::new(),
+            file: None,
+        };
+        if let Some(content) = file.as_ref().and_then(|file| fs::read_to_string(file).ok()) {
+            for sample in content.lines().filter_map(DifficultySample::parse) {
+                history.push(sample);
+            }
+        }
+        history.file = file;
+        history
+    }
+
+    pub fn record(&mut self, height: u64, time: i64, base_target: u64) {
+        if self
+            .samples
+            .back()
+            .is_some_and(|last| last.height == height)
+        {
+            return;
+        }
+        let sample = DifficultySample {
+            height,
+            time,
+            base_target,
+        };
+        self.push(sample);
+        if let Some(file) = &self.file {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)
+                .and_then(|mut f| writeln!(f, "{},{},{}", height, time, base_target));
+            if let Err(e) = written {
+                warn!("difficulty history: can't write {}: {}", file.display(), e);
+            }
+        }
+    }
+
+    fn push(&mut self, sample: DifficultySample) {
+        self.samples.push_back(sample);
+        while self.samples.len() > HISTORY {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Keeps the `keep` most recent blocks in memory, the file keeps all.
+    pub fn truncate(&mut self, keep: usize) {
+        while self.samples.len() > keep {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> Option<&DifficultySample> {
+        self.samples.back()
+    }
+
+    /// Average difficulty of the blocks seen in `[from, to)`.
+    fn average(&self, from: i64, to: i64) -> Option<f64> {
+        let (sum, count) = self
+            .samples
+            .iter()
+            .filter(|sample| sample.time >= from && sample.time < to)
+            .fold((0.0, 0), |(sum, count), sample| {
+                (sum + sample.difficulty(), count + 1)
+            });
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    /// Change in percent of the average difficulty over the last `days` against the `days` before.
+    pub fn trend(&self, now: i64, days: i64) -> Option<f64> {
+        let recent = self.average(now - days * DAY, now + 1)?;
+        let before = self.average(now - 2 * days * DAY, now - days * DAY)?;
+        Some((recent / before - 1.0) * 100.0)
+    }
+
+    pub fn summary(&self, now: i64) -> Option<String> {
+        let latest = self.latest()?;
+        let trend = |days| {
+            self.trend(now, days)
+                .map(|trend| format!("{:+.1}%", trend))
+                .unwrap_or_else(|| "-".to_owned())
+        };
+        Some(format!(
+            "{:.0} (base target {}), last day {}, last week {}",
+            latest.difficulty(),
+            latest.base_target,
+            trend(1),
+            trend(7)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trend() {
+        let mut history = DifficultyHistory::default();
+        // a day at difficulty 100, then a day at 110
+        for i in 0..360 {
+            history.record(i, i as i64 * 240, GENESIS_BASE_TARGET / 100);
+        }
+        for i in 360..720 {
+            history.record(i, i as i64 * 240, GENESIS_BASE_TARGET / 110);
+        }
+        history.record(719, 0, 1);
+        let now = 720 * 240;
+        assert!((history.trend(now, 1).unwrap() - 10.0).abs() < 0.01);
+        assert_eq!(history.trend(now, 7), None);
+        assert_eq!(history.latest().unwrap().height, 719);
+    }
+
+    #[test]
+    fn test_load() {
+        let file = std::env::temp_dir().join(format!(
+            "signum-miner-difficulty-{}.csv",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&file);
+        let mut history = DifficultyHistory::load(Some(file.clone()));
+        history.record(1, 100, 1000);
+        history.record(2, 340, 2000);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .write_all(b"garbage\n")
+            .unwrap();
+
+        let loaded = DifficultyHistory::load(Some(file.clone()));
+        assert_eq!(loaded.samples, history.samples);
+        let _ = fs::remove_file(&file);
+    }
+}
@@ -0,0 +1,325 @@
+//! Drives referred to by filesystem label or UUID.
+//!
+//! `LABEL=plots1` and `UUID=0a1b...` name a filesystem instead of the place it happens to be
+//! mounted. They resolve to the current mount point: on Linux through `/dev/disk/by-label` and
+//! `/dev/disk/by-uuid`, on Windows by the volume name and serial number (`UUID=1A2B-3C4D`) of every
+//! drive letter, on macOS through `/Volumes/<label>` (labels only).
+//!
+//! A plot dir like `LABEL=plots1/plots` is resolved on every plot rescan, so a drive that gets
+//! another letter or mount point, or is plugged in later, is picked up where it is now.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriveRef {
+    Label(String),
+    Uuid(String),
+}
+
+impl DriveRef {
+    /// Parses `LABEL=<label>` or `UUID=<uuid>`, None for anything else, e.g. a plain path.
+    pub fn parse(s: &str) -> Option<DriveRef> {
+        let (kind, id) = s.split_once('=')?;
+        if id.is_empty() {
+            return None;
+        }
+        match kind.to_uppercase().as_str() {
+            "LABEL" => Some(DriveRef::Label(id.to_owned())),
+            "UUID" => Some(DriveRef::Uuid(id.to_owned())),
+            _ => None,
+        }
+    }
+
+    /// Where the filesystem is mounted right now.
+    pub fn mount_point(&self) -> Option<PathBuf> {
+        mount_point(self)
+    }
+}
+
+impl fmt::Display for DriveRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriveRef::Label(label) => write!(f, "LABEL={}", label),
+            DriveRef::Uuid(uuid) => write!(f, "UUID={}", uuid),
+        }
+    }
+}
+
+/// Splits `LABEL=plots1/plots` into the drive and the path on it, None for plain paths.
+fn split_reference(dir: &Path) -> Option<(DriveRef, String)> {
+    let dir = dir.to_string_lossy();
+    let (drive, sub) = match dir.find(['/', '\\']) {
+        Some(i) => (&dir[..i], &dir[i + 1..]),
+        None => (&dir[..], ""),
+    };
+    DriveRef::parse(drive).map(|drive| (drive, sub.to_owned()))
+}
+
+pub fn is_reference(dir: &Path) -> bool {
+    split_reference(dir).is_some()
+}
+
+/// The current path of a plot dir, plain paths stay as they are. None while the drive isn't
+/// mounted.
+pub fn resolve_dir(dir: &Path) -> Option<PathBuf> {
+    match split_reference(dir) {
+        Some((drive, sub)) => drive.mount_point().map(|mount_point| {
+            if sub.is_empty() {
+                mount_point
+            } else {
+                mount_point.join(sub)
+            }
+        }),
+        None => Some(dir.to_path_buf()),
+    }
+}
+
+/// Resolves plot dirs, leaving out those on drives that aren't mounted.
+pub fn resolve_plot_dirs(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .filter_map(|dir| {
+            let resolved = resolve_dir(dir);
+            match &resolved {
+                Some(path) if path.as_path() != dir.as_path() => {
+                    debug!("{} is {}", dir.display(), path.display())
+                }
+                Some(_) => {}
+                None => debug!("{} isn't mounted", dir.display()),
+            }
+            resolved
+        })
+        .collect()
+}
+
+/// Whether a drive is mounted or a plain path exists.
+fn present(entry: &str) -> bool {
+    resolve_dir(Path::new(entry)).is_some_and(|path| path.exists())
+}
+
+/// Blocks until every entry is mounted or `timeout` passed. USB enclosures can take a while to
+/// enumerate after a reboot, starting without them would mine with part of the capacity.
+pub fn wait_for_drives(entries: &[String], timeout: Duration) {
+    let missing = |entries: &[String]| -> Vec<String> {
+        entries
+            .iter()
+            .filter(|entry| !present(entry))
+            .cloned()
+            .collect()
+    };
+    let mut waiting = missing(entries);
+    if waiting.is_empty() {
+        return;
+    }
+    info!(
+        "waiting up to {}s for drives: {}",
+        timeout.as_secs(),
+        waiting.join(", ")
+    );
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        thread::sleep(POLL_INTERVAL);
+        waiting = missing(&waiting);
+        if waiting.is_empty() {
+            info!("all drives present after {}s", start.elapsed().as_secs());
+            return;
+        }
+    }
+    warn!(
+        "drives still missing after {}s, mining without them: {}",
+        timeout.as_secs(),
+        waiting.join(", ")
+    );
+}
+
+/// Escapes a label like udev does for the `/dev/disk/by-label` links.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn escape_label(label: &str) -> String {
+    label
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"#+-.:=@_".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("\\x{:02x}", b)
+            }
+        })
+        .collect()
+}
+
+/// Devices and mount points in `/proc/self/mounts`, which escapes blanks as octal.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_mounts(mounts: &str) -> Vec<(String, PathBuf)> {
+    let unescape = |s: &str| {
+        let mut out = Vec::with_capacity(s.len());
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+            match octal {
+                Some(b) if bytes[i] == b'\\' => {
+                    out.push(b);
+                    i += 4;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mount_point = fields.next()?;
+            Some((unescape(device), PathBuf::from(unescape(mount_point))))
+        })
+        .collect()
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use std::fs;
+
+        fn mount_point(drive: &DriveRef) -> Option<PathBuf> {
+            let (dir, id, case_sensitive) = match drive {
+                DriveRef::Label(label) => ("/dev/disk/by-label", escape_label(label), true),
+                DriveRef::Uuid(uuid) => ("/dev/disk/by-uuid", uuid.clone(), false),
+            };
+            let link = fs::read_dir(dir).ok()?.flatten().find(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if case_sensitive {
+                    name == id
+                } else {
+                    name.eq_ignore_ascii_case(&id)
+                }
+            })?;
+            let device = fs::canonicalize(link.path()).ok()?;
+            let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+            parse_mounts(&mounts)
+                .into_iter()
+                .find(|(source, _)| {
+                    source.starts_with("/dev/")
+                        && fs::canonicalize(source).map(|s| s == device).unwrap_or(false)
+                })
+                .map(|(_, mount_point)| mount_point)
+        }
+    } else if #[cfg(target_os = "macos")] {
+        fn mount_point(drive: &DriveRef) -> Option<PathBuf> {
+            match drive {
+                DriveRef::Label(label) => {
+                    let path = Path::new("/Volumes").join(label);
+                    path.is_dir().then_some(path)
+                }
+                DriveRef::Uuid(_) => None,
+            }
+        }
+    } else if #[cfg(windows)] {
+        use std::ffi::OsStr;
+        use std::iter::once;
+        use std::os::windows::ffi::OsStrExt;
+        use winapi::um::fileapi::{GetLogicalDrives, GetVolumeInformationW};
+
+        fn mount_point(drive: &DriveRef) -> Option<PathBuf> {
+            let letters = unsafe { GetLogicalDrives() };
+            (0..26u8)
+                .filter(|i| letters & (1 << i) != 0)
+                .map(|i| format!("{}:\\", (b'A' + i) as char))
+                .find(|root| {
+                    let root_encoded: Vec<u16> =
+                        OsStr::new(root).encode_wide().chain(once(0)).collect();
+                    let mut name = [0u16; 261];
+                    let mut serial = 0u32;
+                    if unsafe {
+                        GetVolumeInformationW(
+                            root_encoded.as_ptr(),
+                            name.as_mut_ptr(),
+                            name.len() as u32,
+                            &mut serial,
+                            std::ptr::null_mut(),
+                            std::ptr::null_mut(),
+                            std::ptr::null_mut(),
+                            0,
+                        )
+                    } == 0
+                    {
+                        return false;
+                    }
+                    match drive {
+                        DriveRef::Label(label) => {
+                            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                            String::from_utf16_lossy(&name[..len]).eq_ignore_ascii_case(label)
+                        }
+                        DriveRef::Uuid(uuid) => {
+                            format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff)
+                                .eq_ignore_ascii_case(uuid)
+                        }
+                    }
+                })
+                .map(PathBuf::from)
+        }
+    } else {
+        fn mount_point(_drive: &DriveRef) -> Option<PathBuf> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            DriveRef::parse("LABEL=plots1"),
+            Some(DriveRef::Label("plots1".to_owned()))
+        );
+        assert_eq!(
+            DriveRef::parse("uuid=1A2B-3C4D"),
+            Some(DriveRef::Uuid("1A2B-3C4D".to_owned()))
+        );
+        assert_eq!(DriveRef::parse("/mnt/plots1"), None);
+        assert_eq!(DriveRef::parse("LABEL="), None);
+
+        assert!(is_reference(Path::new("LABEL=plots1/plots")));
+        assert!(!is_reference(Path::new("/mnt/a=b/plots")));
+        assert!(!is_reference(Path::new("D:\\plots")));
+        assert_eq!(
+            resolve_dir(Path::new("/mnt/plots1")),
+            Some(PathBuf::from("/mnt/plots1"))
+        );
+        assert_eq!(
+            resolve_dir(Path::new("LABEL=signum-miner-test-missing/plots")),
+            None
+        );
+        assert_eq!(
+            DriveRef::Label("plots1".to_owned()).to_string(),
+            "LABEL=plots1"
+        );
+    }
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = "/dev/sdb1 /mnt/my\\040plots ext4 rw,relatime 0 0\n\
+                      proc /proc proc rw 0 0\n";
+        assert_eq!(
+            parse_mounts(mounts),
+            vec![
+                ("/dev/sdb1".to_owned(), PathBuf::from("/mnt/my plots")),
+                ("proc".to_owned(), PathBuf::from("/proc")),
+            ]
+        );
+        assert_eq!(escape_label("my plots/1"), "my\\x20plots\\x2f1");
+    }
+}
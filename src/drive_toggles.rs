@@ -0,0 +1,63 @@
+//! Drives taken out of mining at runtime, `POST /api/drive`.
+//!
+//! A disabled drive stays in the config but its plots are left out of the next scan. It isn't
+//! read and its capacity isn't reported to the pool, e.g. to keep a disk quiet during a backup.
+//! Toggling a drive rescans right away, a round already reading it finishes the drive. Disabled
+//! drives are forgotten on restart.
+
+use std::collections::BTreeSet;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug, Default)]
+pub struct DriveToggles {
+    disabled: Mutex<BTreeSet<String>>,
+}
+
+impl DriveToggles {
+    fn lock(&self) -> MutexGuard<'_, BTreeSet<String>> {
+        match self.disabled.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("drive toggles: mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+
+    /// Whether this changed anything.
+    pub fn set(&self, drive_id: &str, enabled: bool) -> bool {
+        let mut disabled = self.lock();
+        if enabled {
+            disabled.remove(drive_id)
+        } else {
+            disabled.insert(drive_id.to_owned())
+        }
+    }
+
+    pub fn is_disabled(&self, drive_id: &str) -> bool {
+        self.lock().contains(drive_id)
+    }
+
+    pub fn disabled(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle() {
+        let toggles = DriveToggles::default();
+        assert!(!toggles.is_disabled("sda"));
+        assert!(toggles.set("sda", false));
+        assert!(!toggles.set("sda", false));
+        assert!(toggles.set("sdb", false));
+        assert!(toggles.is_disabled("sda"));
+        assert_eq!(toggles.disabled(), ["sda", "sdb"]);
+        assert!(toggles.set("sda", true));
+        assert!(!toggles.set("sda", true));
+        assert_eq!(toggles.disabled(), ["sdb"]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Estimated energy cost of mining.
+//!
+//! The system is assumed to draw `power_watts` all the time, priced by a time-of-use schedule:
+//! every entry applies from its time of day until the next one, the last one wraps around
+//! midnight. The cost is charged whenever a round completes, for the time since the previous one,
+//! which makes it roughly the cost of a block, and is kept per day next to the pool balances.
+//! Times are local with `utc_offset_hours`, there is no daylight saving.
+
+use serde::de::{self, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+
+const SECS_PER_DAY: i64 = 86_400;
+/// Days of daily costs kept.
+const DAYS_KEPT: usize = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnergyCfg {
+    /// Estimated draw of the whole system while mining.
+    pub power_watts: f64,
+
+    #[serde(default)]
+    pub currency: String,
+
+    #[serde(default)]
+    pub utc_offset_hours: f64,
+
+    /// Price per kWh by time of day, a flat rate needs one entry.
+    pub prices: Vec<PriceCfg>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceCfg {
+    /// `HH:MM`, local time.
+    #[serde(deserialize_with = "time_of_day")]
+    pub from: u32,
+
+    pub price: f64,
+}
+
+/// Seconds since midnight of `HH:MM`.
+fn time_of_day<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split_once(':')
+        .and_then(|(h, m)| Some((h.trim().parse::<u32>().ok()?, m.trim().parse::<u32>().ok()?)))
+        .filter(|&(h, m)| h < 24 && m < 60)
+        .map(|(h, m)| h * 3600 + m * 60)
+        .ok_or_else(|| de::Error::custom(format!("invalid time of day '{}' (HH:MM)", s)))
+}
+
+#[derive(Debug, Clone)]
+pub struct EnergyMeter {
+    power_watts: f64,
+    currency: String,
+    utc_offset_secs: i64,
+    // by start time
+    prices: Vec<PriceCfg>,
+    // unix time of the last charge
+    last: Option<i64>,
+    pub last_block_cost: f64,
+    pub total_cost: f64,
+    // local day number to cost
+    by_day: BTreeMap<i64, f64>,
+}
+
+impl EnergyMeter {
+    pub fn new(cfg: EnergyCfg) -> EnergyMeter {
+        let mut prices = cfg.prices;
+        prices.sort_by_key(|price| price.from);
+        EnergyMeter {
+            power_watts: cfg.power_watts.max(0.0),
+            currency: cfg.currency,
+            utc_offset_secs: (cfg.utc_offset_hours * 3600.0) as i64,
+            prices,
+            last: None,
+            last_block_cost: 0.0,
+            total_cost: 0.0,
+            by_day: BTreeMap::new(),
+        }
+    }
+
+    /// Price per kWh at `secs` after midnight and when the next price starts.
+    fn price_at(&self, secs: i64) -> (f64, i64) {
+        let current = self
+            .prices
+            .iter()
+            .rev()
+            .find(|price| i64::from(price.from) <= secs)
+            .or_else(|| self.prices.last())
+            .map_or(0.0, |price| price.price);
+        let next = self
+            .prices
+            .iter()
+            .map(|price| i64::from(price.from))
+            .find(|&from| from > secs)
+            .unwrap_or(SECS_PER_DAY);
+        (current, next)
+    }
+
+    /// Charges the time since the last charge, `now` in unix seconds.
+    pub fn charge(&mut self, now: i64) {
+        let start = match self.last.replace(now) {
+            Some(last) if last < now => last,
+            _ => return,
+        };
+        let mut cost = 0.0;
+        let mut t = start + self.utc_offset_secs;
+        let end = now + self.utc_offset_secs;
+        while t < end {
+            let day = t.div_euclid(SECS_PER_DAY);
+            let secs = t.rem_euclid(SECS_PER_DAY);
+            let (price, next) = self.price_at(secs);
+            let segment_end = end.min(day * SECS_PER_DAY + next);
+            let kwh = self.power_watts / 1000.0 * (segment_end - t) as f64 / 3600.0;
+            cost += kwh * price;
+            *self.by_day.entry(day).or_insert(0.0) += kwh * price;
+            t = segment_end;
+        }
+        while self.by_day.len() > DAYS_KEPT {
+            self.by_day.pop_first();
+        }
+        self.last_block_cost = cost;
+        self.total_cost += cost;
+    }
+
+    /// Cost of the local day containing `now`.
+    pub fn day_cost(&self, now: i64) -> f64 {
+        let day = (now + self.utc_offset_secs).div_euclid(SECS_PER_DAY);
+        self.by_day.get(&day).copied().unwrap_or(0.0)
+    }
+
+    pub fn summary(&self, now: i64) -> String {
+        format!(
+            "{:.4} {cur} last block, {:.2} {cur} today, {:.2} {cur} total ({:.0} W)",
+            self.last_block_cost,
+            self.day_cost(now),
+            self.total_cost,
+            self.power_watts,
+            cur = self.currency
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meter() -> EnergyMeter {
+        let cfg: EnergyCfg = serde_yaml::from_str(
+            "power_watts: 1000\n\
+             currency: EUR\n\
+             prices:\n\
+             - from: '22:00'\n  price: 0.1\n\
+             - from: '06:00'\n  price: 0.3\n",
+        )
+        .unwrap();
+        EnergyMeter::new(cfg)
+    }
+
+    #[test]
+    fn test_time_of_use() {
+        let mut meter = meter();
+        let day = 10 * SECS_PER_DAY;
+        // 05:00 to 07:00: an hour at night, an hour at day rate
+        meter.charge(day + 5 * 3600);
+        meter.charge(day + 7 * 3600);
+        assert!((meter.last_block_cost - 0.4).abs() < 1e-9);
+        // 21:00 to 23:00 wraps into the night rate
+        meter.charge(day + 21 * 3600);
+        meter.charge(day + 23 * 3600);
+        assert!((meter.last_block_cost - 0.4).abs() < 1e-9);
+        assert!((meter.day_cost(day) - (0.4 + 14.0 * 0.3 + 0.4)).abs() < 1e-9);
+        assert!((meter.total_cost - meter.day_cost(day)).abs() < 1e-9);
+
+        assert!(serde_yaml::from_str::<PriceCfg>("from: '24:00'\nprice: 1").is_err());
+    }
+}
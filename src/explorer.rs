@@ -0,0 +1,59 @@
+//! Links to the configured block explorer.
+
+use url::Url;
+
+#[derive(Clone, Debug)]
+pub struct Explorer {
+    base: Url,
+}
+
+impl Explorer {
+    pub fn new(base: Url) -> Self {
+        Explorer { base }
+    }
+
+    fn link(&self, kind: &str, id: &str) -> Url {
+        let mut url = self.base.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(kind).push(id);
+        }
+        url
+    }
+
+    pub fn block(&self, block_id: &str) -> Url {
+        self.link("block", block_id)
+    }
+
+    pub fn account(&self, account_id: u64) -> Url {
+        self.link("address", &account_id.to_string())
+    }
+
+    #[allow(dead_code)]
+    pub fn transaction(&self, transaction_id: &str) -> Url {
+        self.link("tx", transaction_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        let explorer = Explorer::new(Url::parse("https://explorer.example/").unwrap());
+        assert_eq!(
+            explorer.block("123").as_str(),
+            "https://explorer.example/block/123"
+        );
+        assert_eq!(
+            explorer.account(42).as_str(),
+            "https://explorer.example/address/42"
+        );
+
+        let explorer = Explorer::new(Url::parse("https://example.org/testnet").unwrap());
+        assert_eq!(
+            explorer.transaction("7").as_str(),
+            "https://example.org/testnet/tx/7"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Read faults on demand, to exercise the error handling of the reader and the drive circuit
+//! breaker, or to check that alerting fires, without waiting for a drive to die.
+//!
+//! Configured in the `fault_injection` section, which the example config doesn't mention on
+//! purpose. Every read chunk of a matching drive rolls the dice for an error, a delay and a
+//! short read independently.
+
+use crate::config::DriveFaultsCfg;
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// What happens to one read chunk.
+#[derive(Debug, Default, PartialEq)]
+pub struct Faults {
+    pub error: bool,
+    pub delay: Option<Duration>,
+    pub short_read: bool,
+}
+
+pub struct FaultInjector {
+    drives: HashMap<String, DriveFaultsCfg>,
+}
+
+impl FaultInjector {
+    /// `None` without any configured faults, so the read path pays nothing.
+    pub fn new(drives: HashMap<String, DriveFaultsCfg>) -> Option<FaultInjector> {
+        if drives.is_empty() {
+            return None;
+        }
+        for (drive, faults) in &drives {
+            warn!(
+                "fault injection for {}: errors={}, delays={} ({}ms), short reads={}",
+                drive, faults.error_rate, faults.delay_rate, faults.delay_ms, faults.short_read_rate
+            );
+        }
+        Some(FaultInjector { drives })
+    }
+
+    fn lookup(&self, drive_id: &str, path: &str) -> Option<&DriveFaultsCfg> {
+        self.drives
+            .get(drive_id)
+            .or_else(|| {
+                self.drives
+                    .iter()
+                    .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+                    .max_by_key(|(prefix, _)| prefix.len())
+                    .map(|(_, faults)| faults)
+            })
+            .or_else(|| self.drives.get("*"))
+    }
+
+    pub fn roll(&self, drive_id: &str, path: &str) -> Faults {
+        let faults = match self.lookup(drive_id, path) {
+            Some(faults) => faults,
+            None => return Faults::default(),
+        };
+        let mut rng = thread_rng();
+        let mut hit = |rate: f64| rate > 0.0 && rng.gen::<f64>() < rate;
+        Faults {
+            error: hit(faults.error_rate),
+            delay: if hit(faults.delay_rate) {
+                Some(Duration::from_millis(faults.delay_ms))
+            } else {
+                None
+            },
+            short_read: hit(faults.short_read_rate),
+        }
+    }
+}
+
+/// Half of the chunk, still a whole number of scoops.
+pub fn short_read(bytes_read: usize) -> usize {
+    bytes_read / 2 / 64 * 64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faults(error_rate: f64, delay_rate: f64, short_read_rate: f64) -> DriveFaultsCfg {
+        DriveFaultsCfg {
+            error_rate,
+            delay_rate,
+            delay_ms: 10,
+            short_read_rate,
+        }
+    }
+
+    #[test]
+    fn test_roll() {
+        let mut drives = HashMap::new();
+        drives.insert("/mnt/bad".to_owned(), faults(1.0, 1.0, 0.0));
+        drives.insert("/mnt/bad/slow".to_owned(), faults(0.0, 1.0, 1.0));
+        let injector = FaultInjector::new(drives).unwrap();
+
+        assert_eq!(
+            injector.roll("sdb", "/mnt/bad/1_0_8"),
+            Faults {
+                error: true,
+                delay: Some(Duration::from_millis(10)),
+                short_read: false,
+            }
+        );
+        assert_eq!(
+            injector.roll("sdb", "/mnt/bad/slow/1_0_8"),
+            Faults {
+                error: false,
+                delay: Some(Duration::from_millis(10)),
+                short_read: true,
+            }
+        );
+        assert_eq!(injector.roll("sdc", "/mnt/good/1_0_8"), Faults::default());
+        assert!(FaultInjector::new(HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_short_read() {
+        assert_eq!(short_read(64 * 5), 128);
+        assert_eq!(short_read(64), 0);
+    }
+}
@@ -0,0 +1,147 @@
+//! Plot file handles kept open between rounds.
+//!
+//! Plots used to reopen their file at every round, which costs an open per plot and round and,
+//! with thousands of plot files, runs into the open files limit. Now a plot parks its handle here
+//! once it's read and takes it back in its next `prepare`. With more plots than `max_open_files`
+//! the least recently used handles are closed, those plots open their file again when it's their
+//! turn. The limit is per process, so all mining contexts share one pool. Handles of plots being
+//! read, at most one per drive, aren't counted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// Path and offset of a plot, raw plots share their device's path, and the plot's owner id.
+/// A rescan opens the same file for a new plot while the old one is still around, the old plot
+/// dropping its handle must not close the new one's.
+pub type Key = (String, u64, u64);
+
+#[derive(Default)]
+pub struct FdPool {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // 0 means unlimited
+    limit: usize,
+    tick: u64,
+    files: HashMap<Key, (u64, File)>,
+    // last use -> key, the first entry is closed first
+    lru: BTreeMap<u64, Key>,
+}
+
+impl FdPool {
+    pub fn set_limit(&self, limit: usize) {
+        let mut inner = self.lock();
+        inner.limit = limit;
+        inner.evict();
+    }
+
+    pub fn take(&self, key: &Key) -> Option<File> {
+        let mut inner = self.lock();
+        let (tick, file) = inner.files.remove(key)?;
+        inner.lru.remove(&tick);
+        Some(file)
+    }
+
+    pub fn put(&self, key: Key, file: File) {
+        let mut inner = self.lock();
+        inner.tick += 1;
+        let tick = inner.tick;
+        if let Some((previous, _)) = inner.files.insert(key.clone(), (tick, file)) {
+            inner.lru.remove(&previous);
+        }
+        inner.lru.insert(tick, key);
+        inner.evict();
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.lock().files.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        match self.inner.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("fd pool: mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn evict(&mut self) {
+        while self.limit > 0 && self.files.len() > self.limit {
+            match self.lru.pop_first() {
+                Some((_, key)) => {
+                    self.files.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+fn pool() -> &'static FdPool {
+    static POOL: OnceLock<FdPool> = OnceLock::new();
+    POOL.get_or_init(FdPool::default)
+}
+
+/// Maximum number of parked handles, 0 keeps all of them open.
+pub fn set_limit(limit: usize) {
+    pool().set_limit(limit);
+}
+
+/// A new owner id for the handles of a plot.
+pub fn new_owner() -> u64 {
+    static OWNERS: AtomicU64 = AtomicU64::new(0);
+    OWNERS.fetch_add(1, Ordering::Relaxed)
+}
+
+pub fn take(key: &Key) -> Option<File> {
+    pool().take(key)
+}
+
+pub fn put(key: Key, file: File) {
+    pool().put(key, file);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_closes_least_recently_used() {
+        let path = std::env::temp_dir().join(format!("signum-miner-fd-{}", std::process::id()));
+        fs::write(&path, b"plot").unwrap();
+        let open = || File::open(&path).unwrap();
+        let key = |i: u64| (path.display().to_string(), i, 0);
+
+        let pool = FdPool::default();
+        pool.set_limit(2);
+        pool.put(key(0), open());
+        pool.put(key(1), open());
+        pool.put(key(0), pool.take(&key(0)).unwrap());
+        pool.put(key(2), open());
+        assert_eq!(pool.len(), 2);
+        assert!(pool.take(&key(1)).is_none());
+        assert!(pool.take(&key(0)).is_some());
+        assert!(pool.take(&key(0)).is_none());
+
+        pool.set_limit(0);
+        for i in 0..10 {
+            pool.put(key(i), open());
+        }
+        assert_eq!(pool.len(), 10);
+        pool.set_limit(3);
+        assert_eq!(pool.len(), 3);
+        assert!(pool.take(&key(9)).is_some());
+        drop(pool);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+//! Stable miner id and registration with a fleet management server.
+//!
+//! The id is generated at the first start and kept in `miner_id_file`, so a machine stays the
+//! same miner across IP changes and, as long as the file is kept, reinstalls. With a `fleet`
+//! section the miner posts its id and a few facts about itself to the server periodically.
+
+use crate::config::FleetCfg;
+use rand::prelude::*;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client as InnerClient;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Returns the id stored at `path`, None if there is none yet.
+pub fn load_id(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(id) if !id.trim().is_empty() => Ok(Some(id.trim().to_owned())),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the id stored at `path`, generating and storing a new one if there is none. The flag
+/// tells whether the id is new.
+pub fn load_or_create_id(path: &Path) -> io::Result<(String, bool)> {
+    if let Some(id) = load_id(path)? {
+        return Ok((id, false));
+    }
+    let id = new_id();
+    fs::write(path, format!("{}\n", id))?;
+    Ok((id, true))
+}
+
+/// Random UUID (version 4).
+pub fn new_id() -> String {
+    let mut bytes = [0u8; 16];
+    thread_rng().fill(&mut bytes);
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Registration {
+    pub miner_id: String,
+    pub hostname: String,
+    pub version: &'static str,
+    /// Names of the mining contexts, empty for an unnamed single one.
+    pub contexts: Vec<String>,
+    pub uptime: u64,
+}
+
+/// Registers with the fleet server every `interval` seconds, for as long as the miner runs.
+pub async fn register(cfg: FleetCfg, timeout: u64, mut registration: Registration) {
+    let inner = InnerClient::builder()
+        .timeout(Duration::from_millis(timeout))
+        .build()
+        .unwrap();
+    let started = Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_secs(cfg.interval.max(10)));
+    loop {
+        interval.tick().await;
+        registration.uptime = started.elapsed().as_secs();
+        let result = inner
+            .post(&cfg.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&registration).unwrap_or_default())
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        match result {
+            Ok(_) => debug!("fleet: registered as {}", registration.miner_id),
+            Err(e) => warn!("fleet: can't register with {}: {}", cfg.url, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_id() {
+        let id = new_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert!("89ab".contains(&id[19..20]));
+        assert_ne!(id, new_id());
+    }
+
+    #[test]
+    fn test_load_or_create_id() {
+        let path = std::env::temp_dir().join(format!("signum-miner-id-{}", std::process::id()));
+        assert_eq!(load_id(&path).unwrap(), None);
+        let (id, created) = load_or_create_id(&path).unwrap();
+        assert!(created);
+        assert_eq!(load_id(&path).unwrap().as_ref(), Some(&id));
+        assert_eq!(load_or_create_id(&path).unwrap(), (id, false));
+        fs::remove_file(&path).unwrap();
+    }
+}
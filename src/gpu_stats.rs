@@ -0,0 +1,178 @@
+//! Per-GPU hashing statistics.
+//!
+//! Every GPU worker updates its own counters without locking, like the buffer counters, and the
+//! metrics keep them for the summary. A GPU that holds chunks but hasn't returned a result for
+//! `STALL_SECS` is unhealthy: a hung kernel or driver shows up there before rounds stop finishing.
+//! A result pointing past the end of its batch counts as a kernel error.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds a GPU may hold chunks without returning a result.
+const STALL_SECS: u64 = 30;
+
+#[derive(Debug)]
+pub struct GpuStats {
+    device: String,
+    nonces: AtomicU64,
+    hashing_us: AtomicU64,
+    kernel_errors: AtomicU64,
+    // chunks hashed again on the CPU, see `gpu_verify`
+    verified: AtomicU64,
+    mismatches: AtomicU64,
+    queue_depth: AtomicUsize,
+    // chunks taken from the queue without a result yet
+    pending: AtomicUsize,
+    // unix time in ms, 0 for never
+    last_batch_ms: AtomicU64,
+    // since when the pending chunks wait for a result
+    waiting_since_ms: AtomicU64,
+}
+
+/// Snapshot of one GPU's counters.
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuSnapshot {
+    pub device: String,
+    pub nonces_per_sec: f64,
+    pub nonces: u64,
+    pub queue_depth: usize,
+    pub kernel_errors: u64,
+    pub verified: u64,
+    pub mismatches: u64,
+    pub last_batch_secs_ago: Option<u64>,
+    pub healthy: bool,
+}
+
+// only GPU builds have workers updating the counters
+#[cfg_attr(
+    not(any(feature = "opencl", feature = "metal", feature = "wgpu")),
+    allow(dead_code)
+)]
+impl GpuStats {
+    pub fn new(device: String) -> Arc<GpuStats> {
+        Arc::new(GpuStats {
+            device,
+            nonces: AtomicU64::new(0),
+            hashing_us: AtomicU64::new(0),
+            kernel_errors: AtomicU64::new(0),
+            verified: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+            queue_depth: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+            last_batch_ms: AtomicU64::new(0),
+            waiting_since_ms: AtomicU64::new(0),
+        })
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// A chunk was taken from the queue, `queued` chunks still wait behind it.
+    pub fn received(&self, queued: usize) {
+        self.queue_depth.store(queued, Ordering::Relaxed);
+        if self.pending.fetch_add(1, Ordering::Relaxed) == 0 {
+            self.waiting_since_ms.store(now_ms(), Ordering::Relaxed);
+        }
+    }
+
+    /// A received chunk was dropped without hashing, its round was over.
+    pub fn discarded(&self) {
+        let _ = self
+            .pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    /// A chunk of `nonces` was hashed in `elapsed`, `offset` is the position of the best deadline.
+    pub fn hashed(&self, nonces: u64, offset: u64, elapsed: Duration) {
+        if nonces > 0 && offset >= nonces {
+            self.kernel_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.nonces.fetch_add(nonces, Ordering::Relaxed);
+        self.hashing_us
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        let now = now_ms();
+        self.last_batch_ms.store(now, Ordering::Relaxed);
+        // progress, whatever still waits waits from now on
+        self.waiting_since_ms.store(now, Ordering::Relaxed);
+        self.discarded();
+    }
+
+    /// A chunk was hashed again on the CPU, `ok` if the deadlines matched.
+    pub fn verified(&self, ok: bool) {
+        self.verified.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.mismatches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> GpuSnapshot {
+        self.snapshot_at(now_ms())
+    }
+
+    fn snapshot_at(&self, now: u64) -> GpuSnapshot {
+        let nonces = self.nonces.load(Ordering::Relaxed);
+        let hashing_us = self.hashing_us.load(Ordering::Relaxed);
+        let last_batch_ms = self.last_batch_ms.load(Ordering::Relaxed);
+        let stalled = self.pending.load(Ordering::Relaxed) > 0
+            && now.saturating_sub(self.waiting_since_ms.load(Ordering::Relaxed))
+                > STALL_SECS * 1000;
+        GpuSnapshot {
+            device: self.device.clone(),
+            nonces_per_sec: if hashing_us == 0 {
+                0.0
+            } else {
+                nonces as f64 * 1_000_000.0 / hashing_us as f64
+            },
+            nonces,
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            kernel_errors: self.kernel_errors.load(Ordering::Relaxed),
+            verified: self.verified.load(Ordering::Relaxed),
+            mismatches: self.mismatches.load(Ordering::Relaxed),
+            last_batch_secs_ago: (last_batch_ms > 0)
+                .then(|| now.saturating_sub(last_batch_ms) / 1000),
+            healthy: !stalled,
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_stats() {
+        let stats = GpuStats::new("0:0".to_owned());
+        stats.received(3);
+        stats.hashed(1000, 10, Duration::from_millis(100));
+        stats.received(2);
+        stats.hashed(1000, 1000, Duration::from_millis(100));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.nonces, 2000);
+        assert_eq!(snapshot.nonces_per_sec, 10_000.0);
+        assert_eq!(snapshot.queue_depth, 2);
+        assert_eq!(snapshot.kernel_errors, 1);
+        assert!(snapshot.healthy);
+    }
+
+    #[test]
+    fn test_stalled_gpu_is_unhealthy() {
+        let stats = GpuStats::new("0:0".to_owned());
+        stats.received(0);
+        let since = stats.waiting_since_ms.load(Ordering::Relaxed);
+        assert!(stats.snapshot_at(since + STALL_SECS * 1000).healthy);
+        assert!(!stats.snapshot_at(since + STALL_SECS * 1000 + 1).healthy);
+
+        stats.discarded();
+        assert!(stats.snapshot_at(since + STALL_SECS * 2000).healthy);
+    }
+}
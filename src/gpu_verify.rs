@@ -0,0 +1,119 @@
+//! Spot checks of GPU deadlines on the CPU.
+//!
+//! Overclocked or failing cards can return wrong deadlines without any error, the miner then
+//! quietly submits worse deadlines than the plots hold. A random `gpu_verify_fraction` of the GPU
+//! chunks is hashed again on the CPU and the best deadlines are compared. More than
+//! `gpu_verify_max_mismatches` mismatches in a round raise an error. Only chunks the host still
+//! holds can be checked, `gpu_async` and `gpu_mem_mapping` aren't sampled.
+
+use crate::cpu_worker::{find_best_deadline, CpuHasher};
+use crate::gpu_stats::GpuStats;
+use rand::Rng;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+const SCOOP_SIZE: usize = 64;
+
+pub struct GpuVerifier {
+    fraction: f64,
+    max_mismatches: u32,
+    // height of the round the mismatches are counted for
+    height: AtomicU64,
+    mismatches: AtomicU32,
+}
+
+// only GPU builds have workers to verify
+#[cfg_attr(
+    not(any(feature = "opencl", feature = "metal", feature = "wgpu")),
+    allow(dead_code)
+)]
+impl GpuVerifier {
+    pub fn new(fraction: f64, max_mismatches: u32) -> GpuVerifier {
+        GpuVerifier {
+            fraction: fraction.clamp(0.0, 1.0),
+            max_mismatches,
+            height: AtomicU64::new(0),
+            mismatches: AtomicU32::new(0),
+        }
+    }
+
+    /// Whether the next chunk should be verified.
+    pub fn sample(&self) -> bool {
+        self.fraction > 0.0 && rand::thread_rng().gen_bool(self.fraction)
+    }
+
+    /// Hashes `data` on the CPU and compares with the GPU's best deadline. False on a mismatch.
+    pub fn verify(
+        &self,
+        stats: &GpuStats,
+        height: u64,
+        data: &[u8],
+        gensig: &[u8; 32],
+        gpu_deadline: u64,
+    ) -> bool {
+        let nonces = (data.len() / SCOOP_SIZE) as u64;
+        let (cpu_deadline, _) = find_best_deadline(data, nonces, gensig, CpuHasher::Auto);
+        let ok = cpu_deadline == gpu_deadline;
+        stats.verified(ok);
+        if !ok {
+            self.record_mismatch(stats, height, gpu_deadline, cpu_deadline);
+        }
+        ok
+    }
+
+    fn record_mismatch(&self, stats: &GpuStats, height: u64, gpu_deadline: u64, cpu_deadline: u64) {
+        if self.height.swap(height, Ordering::Relaxed) != height {
+            self.mismatches.store(0, Ordering::Relaxed);
+        }
+        let mismatches = self.mismatches.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "GPU {}: deadline mismatch at height {}, gpu={}, cpu={}",
+            stats.device(),
+            height,
+            gpu_deadline,
+            cpu_deadline
+        );
+        // alert once per round
+        if mismatches == self.max_mismatches + 1 {
+            error!(
+                "GPU {}: {} wrong deadlines in round {}, the card is unstable (overclock, \
+                 temperature, driver), its results can't be trusted",
+                stats.device(),
+                mismatches,
+                height
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        // verify hashes with the SIMD build's shabal, like the workers
+        #[cfg(any(
+            feature = "simd_avx512f",
+            feature = "simd_avx2",
+            feature = "simd_avx",
+            feature = "simd_sse2",
+            feature = "neon"
+        ))]
+        crate::init_cpu_extensions();
+
+        let mut data = vec![0u8; 256 * SCOOP_SIZE];
+        rand::thread_rng().fill(&mut data[..]);
+        let gensig = [7u8; 32];
+        let (deadline, _) = find_best_deadline(&data, 256, &gensig, CpuHasher::Scalar);
+
+        let stats = GpuStats::new("0:0".to_owned());
+        let verifier = GpuVerifier::new(1.0, 0);
+        assert!(verifier.sample());
+        assert!(verifier.verify(&stats, 1, &data, &gensig, deadline));
+        assert!(!verifier.verify(&stats, 1, &data, &gensig, deadline.wrapping_add(1)));
+
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.verified, snapshot.mismatches), (2, 1));
+        assert!(!GpuVerifier::new(0.0, 0).sample());
+    }
+}
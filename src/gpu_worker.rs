@@ -1,38 +1,47 @@
+use crate::buffer_pool::BufferPool;
+use crate::gpu_stats::GpuStats;
+use crate::gpu_verify::GpuVerifier;
 use crate::miner::{Buffer, NonceData};
 use crate::ocl::GpuContext;
 use crate::ocl::{gpu_hash, gpu_transfer};
 use crate::reader::ReadReply;
-use crossbeam_channel::{Receiver, Sender};
+use crate::stages::Stage;
+use crate::transfer_compression;
+use crossbeam_channel::Receiver;
 use std::sync::Arc;
+use std::time::Instant;
 use std::u64;
-use tokio::sync::mpsc;
 
 pub fn create_gpu_worker_task(
     benchmark: bool,
     rx_read_replies: Receiver<ReadReply>,
-    tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
-    tx_nonce_data: mpsc::UnboundedSender<NonceData>,
+    buffer_pool: Arc<BufferPool>,
     context_mu: Arc<GpuContext>,
+    stats: Arc<GpuStats>,
+    verifier: Arc<GpuVerifier>,
+    mut probe_compression: bool,
 ) -> impl FnOnce() + Send + 'static {
     move || {
-        for read_reply in rx_read_replies {
-            let buffer = read_reply.buffer;
+        for read_reply in rx_read_replies.iter() {
+            let mut buffer = read_reply.buffer;
             // handle empty buffers (read errors) && benchmark
             if read_reply.info.len == 0 || benchmark {
                 // forward 'drive finished signal'
                 if read_reply.info.finished {
                     let deadline = u64::MAX;
-                    let _ = tx_nonce_data.send(NonceData {
-                        height: read_reply.info.height,
-                        block: read_reply.info.block,
-                        base_target: read_reply.info.base_target,
+                    let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+                        height: read_reply.info.header.height,
+                        block: read_reply.info.header.block,
+                        base_target: read_reply.info.header.base_target,
                         deadline,
                         nonce: 0,
                         reader_task_processed: read_reply.info.finished,
+                        round_finished: false,
                         account_id: read_reply.info.account_id,
+                        drive_id: read_reply.info.header.drive_id.clone(),
                     });
                 }
-                let _ = tx_empty_buffers.send(buffer);
+                buffer_pool.push(buffer);
                 continue;
             }
 
@@ -41,30 +50,72 @@ pub fn create_gpu_worker_task(
                 continue;
             }
 
+            // the round is over, its queued chunks aren't worth hashing
+            if read_reply.info.header.cancel.is_cancelled() {
+                buffer.unmap();
+                buffer_pool.push(buffer);
+                continue;
+            }
+            stats.received(rx_read_replies.len());
+
+            let hashing = Instant::now();
             gpu_transfer(
                 &context_mu,
                 buffer.get_gpu_buffers().unwrap(),
-                *read_reply.info.gensig,
+                read_reply.info.header.gensig,
             );
             let result = gpu_hash(
                 &context_mu,
                 read_reply.info.len / 64,
                 buffer.get_gpu_data().as_ref().unwrap(),
             );
+            read_reply.info.header.stages.add(Stage::Hashing, hashing.elapsed());
             let deadline = result.0;
             let offset = result.1;
+            stats.hashed((read_reply.info.len / 64) as u64, offset, hashing.elapsed());
+
+            // the host copy is still intact after the transfer
+            let verify = verifier.sample();
+            if verify || probe_compression {
+                let mut_bs = buffer.get_buffer();
+                #[cfg(feature = "async_io")]
+                let bs = crate::utils::lock_sync(&mut_bs);
+                #[cfg(not(feature = "async_io"))]
+                let bs = match mut_bs.lock() {
+                    Ok(guard) => guard,
+                    Err(poisoned) => {
+                        error!("GPU: buffer mutex poisoned, recovering...");
+                        poisoned.into_inner()
+                    }
+                };
+                if verify {
+                    verifier.verify(
+                        &stats,
+                        read_reply.info.header.height,
+                        &bs[..read_reply.info.len],
+                        &read_reply.info.header.gensig,
+                        deadline,
+                    );
+                }
+                if probe_compression {
+                    probe_compression = false;
+                    transfer_compression::report(stats.device(), &bs[..read_reply.info.len]);
+                }
+            }
 
-            let _ = tx_nonce_data.send(NonceData {
-                height: read_reply.info.height,
-                block: read_reply.info.block,
-                base_target: read_reply.info.base_target,
+            let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+                height: read_reply.info.header.height,
+                block: read_reply.info.header.block,
+                base_target: read_reply.info.header.base_target,
                 deadline,
                 nonce: offset.saturating_add(read_reply.info.start_nonce),
                 reader_task_processed: read_reply.info.finished,
+                round_finished: false,
                 account_id: read_reply.info.account_id,
+                drive_id: read_reply.info.header.drive_id.clone(),
             });
 
-            let _ = tx_empty_buffers.send(buffer);
+            buffer_pool.push(buffer);
         }
     }
 }
@@ -74,7 +125,7 @@ pub fn create_gpu_worker_task(
 mod tests {
     use self::core::Event;
     use crate::ocl::gpu_hash;
-    use crate::ocl::GpuContext;
+    use crate::ocl::{GpuContext, KernelSource};
     use hex;
     use ocl_core as core;
     use std::sync::Arc;
@@ -91,7 +142,8 @@ mod tests {
             data[i * 32..i * 32 + 32].clone_from_slice(&gensig);
         }
 
-        let context = Arc::new(GpuContext::new(0, 0, 16, false));
+        let kernel_src = KernelSource::default().load().unwrap();
+        let context = Arc::new(GpuContext::new(0, 0, 16, false, &kernel_src));
 
         let buffer_gpu = unsafe {
             core::create_buffer::<_, u8>(&context.context, core::MEM_READ_ONLY, 64 * 16, None)
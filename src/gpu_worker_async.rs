@@ -1,140 +1,154 @@
+use crate::buffer_pool::BufferPool;
+use crate::cancel::CancelToken;
+use crate::gpu_stats::GpuStats;
 use crate::miner::{Buffer, NonceData};
 use crate::ocl::GpuContext;
 use crate::ocl::{gpu_hash, gpu_transfer, gpu_transfer_and_hash};
-use crate::reader::{BufferInfo, ReadReply};
-use crossbeam_channel::{Receiver, Sender};
-use futures::sync::mpsc;
-use futures::{Future, Sink};
+use crate::reader::{BufferInfo, ReadReply, RoundHeader};
+use crossbeam_channel::Receiver;
 use std::sync::Arc;
+use std::time::Instant;
 use std::u64;
+use tokio::sync::mpsc;
+
+// sends the result of the chunk hashed last
+fn send_result(info: &BufferInfo, (deadline, offset): (u64, u64)) {
+    let _ = info.header.tx_nonce_data.blocking_send(NonceData {
+        height: info.header.height,
+        block: info.header.block,
+        base_target: info.header.base_target,
+        deadline,
+        nonce: offset.saturating_add(info.start_nonce),
+        reader_task_processed: info.finished,
+        round_finished: false,
+        account_id: info.account_id,
+        drive_id: info.header.drive_id.clone(),
+    });
+}
 
 pub fn create_gpu_worker_task_async(
     benchmark: bool,
     rx_read_replies: Receiver<ReadReply>,
-    tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
-    tx_nonce_data: mpsc::Sender<NonceData>,
+    buffer_pool: Arc<BufferPool>,
     context_mu: Arc<GpuContext>,
-    num_drives: usize,
+    stats: Arc<GpuStats>,
 ) -> impl FnOnce() {
     move || {
         let mut new_round = true;
         let mut last_buffer_a = None;
         let mut last_buffer_info_a = BufferInfo {
+            header: Arc::new(RoundHeader {
+                height: 0,
+                block: 0,
+                base_target: 0,
+                gensig: [0u8; 32],
+                drive_id: Arc::from(""),
+                stages: Arc::default(),
+                cancel: CancelToken::default(),
+                barrier: Arc::default(),
+                tx_nonce_data: mpsc::channel(1).0,
+            }),
             len: 0,
-            height: 0,
-            block: 0,
-            base_target: 0,
-            gensig: Arc::new([0u8; 32]),
             start_nonce: 0,
             finished: false,
+            round_finished: false,
             account_id: 0,
             gpu_signal: 0,
+            hashing: None,
         };
-        let mut drive_count = 0;
         let (tx_sink, rx_sink) = crossbeam_channel::bounded(1);
-        let mut active_height = 0;
-        for read_reply in rx_read_replies {
+
+        // hashes the chunk held back for the next transfer, the contexts sharing this worker
+        // interleave their rounds so it may belong to another round than the incoming reply
+        let flush = |last_buffer_info_a: &mut BufferInfo, last_buffer_a: &Option<_>| {
+            if last_buffer_info_a.header.cancel.is_cancelled() {
+                // the round was interrupted, its last chunk is never hashed
+                stats.discarded();
+            } else {
+                let hashing = Instant::now();
+                let result = gpu_hash(
+                    &context_mu,
+                    last_buffer_info_a.len / 64,
+                    last_buffer_a.as_ref().unwrap(),
+                );
+                stats.hashed((last_buffer_info_a.len / 64) as u64, result.1, hashing.elapsed());
+                send_result(last_buffer_info_a, result);
+            }
+            if let Ok(sink_buffer) = rx_sink.try_recv() {
+                buffer_pool.push(sink_buffer);
+            }
+            // the chunk is hashed, the round's marker may go on to the miner
+            last_buffer_info_a.hashing = None;
+        };
+
+        for read_reply in rx_read_replies.iter() {
             let buffer = read_reply.buffer;
             // handle empty buffers (read errors) && benchmark
             if read_reply.info.len == 0 || benchmark {
                 // forward 'drive finished signal'
                 if read_reply.info.finished {
-                    let deadline = u64::MAX;
-                    tx_nonce_data
-                        .clone()
-                        .send(NonceData {
-                            height: read_reply.info.height,
-                            block: read_reply.info.block,
-                            base_target: read_reply.info.base_target,
-                            deadline,
-                            nonce: 0,
-                            reader_task_processed: read_reply.info.finished,
-                            account_id: read_reply.info.account_id,
-                        })
-                        .wait()
-                        .ok(); // Handle channel close gracefully
+                    let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+                        height: read_reply.info.header.height,
+                        block: read_reply.info.header.block,
+                        base_target: read_reply.info.header.base_target,
+                        deadline: u64::MAX,
+                        nonce: 0,
+                        reader_task_processed: read_reply.info.finished,
+                        round_finished: false,
+                        account_id: read_reply.info.account_id,
+                        drive_id: read_reply.info.header.drive_id.clone(),
+                    });
                 }
-                let _ = tx_empty_buffers.send(buffer); // Handle channel close gracefully
+                buffer_pool.push(buffer);
                 continue;
             }
 
+            let same_round = Arc::ptr_eq(
+                &last_buffer_info_a.header.barrier,
+                &read_reply.info.header.barrier,
+            );
+
             // process start signal
             if read_reply.info.gpu_signal == 1 {
                 if !new_round {
-                    if let Ok(sink_buffer) = rx_sink.try_recv() {
-                        let _ = tx_empty_buffers.send(sink_buffer); // Handle channel close gracefully
-                    }
+                    flush(&mut last_buffer_info_a, &last_buffer_a);
                 }
-                drive_count = 0;
-                active_height = read_reply.info.height;
                 new_round = true;
                 continue;
             }
 
-            // end signal
-            if read_reply.info.gpu_signal == 2 && active_height == read_reply.info.height {
-                drive_count += 1;
-                if drive_count == num_drives && !new_round {
-                    let result = gpu_hash(
-                        &context_mu,
-                        last_buffer_info_a.len / 64,
-                        last_buffer_a.as_ref().unwrap(),
-                    );
-                    let deadline = result.0;
-                    let offset = result.1;
-
-                    let _ = tx_nonce_data
-                        .clone()
-                        .send(NonceData {
-                            height: last_buffer_info_a.height,
-                            block: last_buffer_info_a.block,
-                            base_target: last_buffer_info_a.base_target,
-                            deadline,
-                            nonce: offset.saturating_add(last_buffer_info_a.start_nonce),
-                            reader_task_processed: last_buffer_info_a.finished,
-                            account_id: last_buffer_info_a.account_id,
-                        })
-                        .wait(); // Handle channel close gracefully
-                    if let Ok(sink_buffer) = rx_sink.try_recv() {
-                        let _ = tx_empty_buffers.send(sink_buffer); // Handle channel close gracefully
-                    }
-                }
-                continue;
-            }
+            // end signal, sent once the last drive of the round is done
             if read_reply.info.gpu_signal == 2 {
+                if !new_round && same_round {
+                    flush(&mut last_buffer_info_a, &last_buffer_a);
+                    new_round = true;
+                }
                 continue;
             }
+            stats.received(rx_read_replies.len());
 
+            if !new_round && !same_round {
+                flush(&mut last_buffer_info_a, &last_buffer_a);
+                new_round = true;
+            }
             if new_round {
                 gpu_transfer(
                     &context_mu,
                     buffer.get_gpu_buffers().unwrap(),
-                    *read_reply.info.gensig,
+                    read_reply.info.header.gensig,
                 );
             } else {
+                let hashing = Instant::now();
                 let result = gpu_transfer_and_hash(
                     &context_mu,
                     buffer.get_gpu_buffers().unwrap(),
                     last_buffer_info_a.len / 64,
                     last_buffer_a.as_ref().unwrap(),
                 );
-                let deadline = result.0;
-                let offset = result.1;
-
-                let _ = tx_nonce_data
-                    .clone()
-                    .send(NonceData {
-                        height: last_buffer_info_a.height,
-                        block: last_buffer_info_a.block,
-                        base_target: last_buffer_info_a.base_target,
-                        deadline,
-                        nonce: offset.saturating_add(last_buffer_info_a.start_nonce),
-                        reader_task_processed: last_buffer_info_a.finished,
-                        account_id: last_buffer_info_a.account_id,
-                    })
-                    .wait(); // Handle channel close gracefully
+                stats.hashed((last_buffer_info_a.len / 64) as u64, result.1, hashing.elapsed());
+                send_result(&last_buffer_info_a, result);
                 if let Ok(sink_buffer) = rx_sink.try_recv() {
-                    let _ = tx_empty_buffers.send(sink_buffer); // Handle channel close gracefully
+                    buffer_pool.push(sink_buffer);
                 }
             }
             last_buffer_a = buffer.get_gpu_data();
@@ -0,0 +1,172 @@
+//! Worker for GPU backends that hash from host visible memory (Metal, wgpu). Read buffers are plain
+//! host buffers, the backend copies them to the device itself.
+
+use crate::buffer_pool::BufferPool;
+use crate::gpu_stats::GpuStats;
+use crate::gpu_verify::GpuVerifier;
+use crate::miner::{Buffer, NonceData};
+use crate::reader::ReadReply;
+use crate::stages::Stage;
+use crate::transfer_compression;
+use crossbeam_channel::Receiver;
+use std::sync::Arc;
+use std::time::Instant;
+#[cfg(feature = "async_io")]
+use tokio::sync::Mutex;
+#[cfg(not(feature = "async_io"))]
+use std::sync::Mutex;
+
+const SCOOP_SIZE: usize = 64;
+
+pub trait HostGpu: Send {
+    /// Calculates the deadlines of the scoops in `data` and returns the best deadline and its
+    /// offset in nonces.
+    fn hash(&self, data: &[u8], gensig: &[u8; 32]) -> (u64, u64);
+}
+
+/// Best deadline and its offset, the first one wins on ties like in the OpenCL `find_min`.
+pub fn find_min(deadlines: impl Iterator<Item = u64>) -> (u64, u64) {
+    deadlines
+        .enumerate()
+        .fold((u64::MAX, 0), |best, (offset, deadline)| {
+            if deadline < best.0 {
+                (deadline, offset as u64)
+            } else {
+                best
+            }
+        })
+}
+
+/// Host buffer routed to a GPU thread, `id` is the thread's index + 1.
+pub struct HostGpuBuffer {
+    data: Arc<Mutex<Vec<u8>>>,
+    id: usize,
+}
+
+impl HostGpuBuffer {
+    pub fn new(nonces_per_cache: usize, id: usize) -> Self {
+        HostGpuBuffer {
+            data: Arc::new(Mutex::new(vec![0u8; nonces_per_cache * SCOOP_SIZE])),
+            id,
+        }
+    }
+}
+
+impl Buffer for HostGpuBuffer {
+    fn get_buffer(&mut self) -> Arc<Mutex<Vec<u8>>> {
+        self.data.clone()
+    }
+    fn get_buffer_for_writing(&mut self) -> Arc<Mutex<Vec<u8>>> {
+        self.data.clone()
+    }
+    fn unmap(&self) {}
+    fn get_id(&self) -> usize {
+        self.id
+    }
+}
+
+pub fn create_gpu_worker_task_host<G: HostGpu + 'static>(
+    benchmark: bool,
+    rx_read_replies: Receiver<ReadReply>,
+    buffer_pool: Arc<BufferPool>,
+    context: G,
+    stats: Arc<GpuStats>,
+    verifier: Arc<GpuVerifier>,
+    mut probe_compression: bool,
+) -> impl FnOnce() + Send + 'static {
+    move || {
+        for read_reply in rx_read_replies.iter() {
+            let mut buffer = read_reply.buffer;
+            // handle empty buffers (read errors) && benchmark
+            if read_reply.info.len == 0 || benchmark {
+                // forward 'drive finished signal'
+                if read_reply.info.finished {
+                    let deadline = u64::MAX;
+                    let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+                        height: read_reply.info.header.height,
+                        block: read_reply.info.header.block,
+                        base_target: read_reply.info.header.base_target,
+                        deadline,
+                        nonce: 0,
+                        reader_task_processed: read_reply.info.finished,
+                        round_finished: false,
+                        account_id: read_reply.info.account_id,
+                        drive_id: read_reply.info.header.drive_id.clone(),
+                    });
+                }
+                buffer_pool.push(buffer);
+                continue;
+            }
+
+            // consume and ignore all signals
+            if read_reply.info.len == 1 && read_reply.info.gpu_signal > 0 {
+                continue;
+            }
+
+            // the round is over, its queued chunks aren't worth hashing
+            if read_reply.info.header.cancel.is_cancelled() {
+                buffer.unmap();
+                buffer_pool.push(buffer);
+                continue;
+            }
+            stats.received(rx_read_replies.len());
+
+            let (deadline, offset) = {
+                let mut_bs = buffer.get_buffer();
+                #[cfg(feature = "async_io")]
+                let bs = crate::utils::lock_sync(&mut_bs);
+                #[cfg(not(feature = "async_io"))]
+                let bs = match mut_bs.lock() {
+                    Ok(guard) => guard,
+                    Err(poisoned) => {
+                        error!("GPU: buffer mutex poisoned, recovering...");
+                        poisoned.into_inner()
+                    }
+                };
+                let hashing = Instant::now();
+                let result = context.hash(&bs[..read_reply.info.len], &read_reply.info.header.gensig);
+                read_reply.info.header.stages.add(Stage::Hashing, hashing.elapsed());
+                stats.hashed((read_reply.info.len / SCOOP_SIZE) as u64, result.1, hashing.elapsed());
+                if verifier.sample() {
+                    verifier.verify(
+                        &stats,
+                        read_reply.info.header.height,
+                        &bs[..read_reply.info.len],
+                        &read_reply.info.header.gensig,
+                        result.0,
+                    );
+                }
+                if probe_compression {
+                    probe_compression = false;
+                    transfer_compression::report(stats.device(), &bs[..read_reply.info.len]);
+                }
+                result
+            };
+
+            let _ = read_reply.info.header.tx_nonce_data.blocking_send(NonceData {
+                height: read_reply.info.header.height,
+                block: read_reply.info.header.block,
+                base_target: read_reply.info.header.base_target,
+                deadline,
+                nonce: offset.saturating_add(read_reply.info.start_nonce),
+                reader_task_processed: read_reply.info.finished,
+                round_finished: false,
+                account_id: read_reply.info.account_id,
+                drive_id: read_reply.info.header.drive_id.clone(),
+            });
+
+            buffer_pool.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_min() {
+        assert_eq!(find_min([5, 3, 7, 3].into_iter()), (3, 1));
+        assert_eq!(find_min(std::iter::empty()), (u64::MAX, 0));
+    }
+}
@@ -0,0 +1,359 @@
+//! Startup hardware report.
+//!
+//! Collects what support requests and benchmark threads always ask for: CPU, SIMD extension in
+//! use, core topology, memory, GPUs with driver versions, drives and the capacity per account.
+//! The report is logged once at startup and serializable for tooling.
+
+use crate::poc_hashing::NONCE_SIZE;
+use crate::topology::topology;
+use crate::utils::{get_bus_type, get_sector_size};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Read;
+use std::time::Instant;
+
+/// Bytes read from the first plot of every drive to estimate its speed.
+const SAMPLE_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuReport {
+    pub name: String,
+    pub vendor: String,
+    pub driver: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DriveReport {
+    pub drive_id: String,
+    pub path: String,
+    pub bus_type: String,
+    pub sector_size: u64,
+    /// Buffered sequential read, can be inflated by the page cache.
+    pub sample_speed_mibs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HardwareReport {
+    pub cpu_model: String,
+    pub simd: &'static str,
+    pub logical_cores: usize,
+    pub physical_cores: usize,
+    pub performance_cores: usize,
+    pub efficiency_cores: usize,
+    pub memory_bytes: Option<u64>,
+    pub gpus: Vec<GpuReport>,
+    pub drives: Vec<DriveReport>,
+    pub capacity_by_account: BTreeMap<u64, u64>,
+}
+
+impl HardwareReport {
+    /// `drive_id_to_path` maps every drive to one of its plots, which is sampled for the speed.
+    pub fn collect(
+        drive_id_to_path: &HashMap<String, String>,
+        account_id_to_nonces: &HashMap<u64, u64>,
+        gpus: Vec<GpuReport>,
+    ) -> HardwareReport {
+        let topology = topology();
+        let mut drives: Vec<DriveReport> = drive_id_to_path
+            .iter()
+            .map(|(drive_id, path)| DriveReport {
+                drive_id: drive_id.clone(),
+                path: path.clone(),
+                bus_type: get_bus_type(path),
+                sector_size: get_sector_size(path),
+                sample_speed_mibs: sample_speed(path),
+            })
+            .collect();
+        drives.sort_by(|a, b| a.drive_id.cmp(&b.drive_id));
+
+        HardwareReport {
+            cpu_model: cpu_model(),
+            simd: simd_extension(),
+            logical_cores: num_cpus::get(),
+            physical_cores: num_cpus::get_physical(),
+            performance_cores: if topology.is_hybrid() {
+                topology.performance.len()
+            } else {
+                0
+            },
+            efficiency_cores: topology.efficiency.len(),
+            memory_bytes: total_memory(),
+            gpus,
+            drives,
+            capacity_by_account: account_id_to_nonces
+                .iter()
+                .map(|(&account_id, &nonces)| (account_id, nonces * NONCE_SIZE as u64))
+                .collect(),
+        }
+    }
+
+    pub fn log(&self) {
+        info!("hardware report:");
+        info!(
+            "  CPU: {}, SIMD={}, cores={} logical/{} physical{}",
+            self.cpu_model,
+            self.simd,
+            self.logical_cores,
+            self.physical_cores,
+            if self.efficiency_cores > 0 {
+                format!(
+                    " ({}P+{}E)",
+                    self.performance_cores, self.efficiency_cores
+                )
+            } else {
+                String::new()
+            }
+        );
+        match self.memory_bytes {
+            Some(bytes) => info!("  RAM: {} MiB", bytes / 1024 / 1024),
+            None => info!("  RAM: unknown"),
+        }
+        for gpu in &self.gpus {
+            info!("  GPU: {} - {}, driver {}", gpu.vendor, gpu.name, gpu.driver);
+        }
+        for drive in &self.drives {
+            info!(
+                "  drive {}: bus={}, sector size={}, sample speed={}",
+                drive.drive_id,
+                drive.bus_type,
+                drive.sector_size,
+                match drive.sample_speed_mibs {
+                    Some(speed) => format!("{:.0} MiB/s", speed),
+                    None => "n/a".to_owned(),
+                }
+            );
+        }
+        for (account_id, bytes) in &self.capacity_by_account {
+            info!(
+                "  account {}: {:.4} TiB",
+                account_id,
+                *bytes as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0
+            );
+        }
+    }
+}
+
+/// The shabal implementation selected at build time, see `init_cpu_extensions`.
+pub fn simd_extension() -> &'static str {
+    if cfg!(feature = "simd_avx512f") {
+        "AVX512F"
+    } else if cfg!(feature = "simd_avx2") {
+        "AVX2"
+    } else if cfg!(feature = "simd_avx") {
+        "AVX"
+    } else if cfg!(feature = "simd_sse2") {
+        "SSE2"
+    } else if cfg!(feature = "neon") {
+        "neon"
+    } else {
+        "none"
+    }
+}
+
+fn sample_speed(path: &str) -> Option<f64> {
+    let mut file = File::open(path).ok()?;
+    let mut buffer = vec![0u8; SAMPLE_SIZE];
+    let start = Instant::now();
+    file.read_exact(&mut buffer).ok()?;
+    let elapsed = start.elapsed().as_secs_f64();
+    if elapsed > 0.0 {
+        Some(SAMPLE_SIZE as f64 / 1024.0 / 1024.0 / elapsed)
+    } else {
+        None
+    }
+}
+
+fn cpu_model() -> String {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if let Some(brand) = cpu_brand() {
+        return brand;
+    }
+    if let Some(model) = fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| parse_cpuinfo_model(&cpuinfo))
+    {
+        return model;
+    }
+    #[cfg(target_os = "macos")]
+    if let Some(brand) = sysctl("machdep.cpu.brand_string") {
+        return brand;
+    }
+    "unknown".to_owned()
+}
+
+/// Brand string from the extended cpuid leaves 0x80000002-0x80000004.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+// the cpuid intrinsics are only unsafe on older toolchains
+#[allow(unused_unsafe)]
+fn cpu_brand() -> Option<String> {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::__cpuid;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::__cpuid;
+
+    if unsafe { __cpuid(0x8000_0000) }.eax < 0x8000_0004 {
+        return None;
+    }
+    let mut brand = Vec::with_capacity(48);
+    for leaf in 0x8000_0002..=0x8000_0004u32 {
+        let regs = unsafe { __cpuid(leaf) };
+        for reg in &[regs.eax, regs.ebx, regs.ecx, regs.edx] {
+            brand.extend_from_slice(&reg.to_le_bytes());
+        }
+    }
+    let brand = String::from_utf8_lossy(&brand)
+        .trim_matches(char::from(0))
+        .trim()
+        .to_owned();
+    if brand.is_empty() {
+        None
+    } else {
+        Some(brand)
+    }
+}
+
+/// x86 names the CPU in `model name`, some ARM kernels only have `Hardware`.
+fn parse_cpuinfo_model(cpuinfo: &str) -> Option<String> {
+    ["model name", "Hardware"].iter().find_map(|key| {
+        cpuinfo.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.trim() == *key && !value.trim().is_empty() {
+                Some(value.trim().to_owned())
+            } else {
+                None
+            }
+        })
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn sysctl(name: &str) -> Option<String> {
+    let output = std::process::Command::new("sysctl")
+        .args(["-n", name])
+        .output()
+        .ok()?;
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if output.status.success() && !value.is_empty() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn total_memory() -> Option<u64> {
+    sysctl("hw.memsize")?.parse().ok()
+}
+
+#[cfg(windows)]
+fn total_memory() -> Option<u64> {
+    use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+    unsafe {
+        let mut status: MEMORYSTATUSEX = std::mem::zeroed();
+        status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+        if GlobalMemoryStatusEx(&mut status) != 0 {
+            Some(status.ullTotalPhys)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn total_memory() -> Option<u64> {
+    fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| parse_meminfo_total(&meminfo))
+}
+
+#[cfg(windows)]
+pub fn available_memory() -> Option<u64> {
+    use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+    unsafe {
+        let mut status: MEMORYSTATUSEX = std::mem::zeroed();
+        status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+        if GlobalMemoryStatusEx(&mut status) != 0 {
+            Some(status.ullAvailPhys)
+        } else {
+            None
+        }
+    }
+}
+
+// vm_stat only has free pages, which leaves out the reclaimable page cache
+#[cfg(target_os = "macos")]
+pub fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Memory that can be used without swapping, including reclaimable page cache.
+#[cfg(not(any(target_os = "macos", windows)))]
+pub fn available_memory() -> Option<u64> {
+    fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| parse_meminfo(&meminfo, "MemAvailable:"))
+}
+
+/// Resident memory of the miner process.
+#[cfg(target_os = "linux")]
+pub fn resident_memory() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * page_size::get() as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resident_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(any(test, not(any(target_os = "macos", windows))))]
+fn parse_meminfo_total(meminfo: &str) -> Option<u64> {
+    parse_meminfo(meminfo, "MemTotal:")
+}
+
+#[cfg(any(test, not(any(target_os = "macos", windows))))]
+fn parse_meminfo(meminfo: &str, field: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with(field))?;
+    let kib: u64 = line[field.len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpuinfo_model() {
+        let cpuinfo = "processor\t: 0\nvendor_id\t: GenuineIntel\nmodel name\t: Intel(R) Core(TM) i9-12900K\n";
+        assert_eq!(
+            parse_cpuinfo_model(cpuinfo),
+            Some("Intel(R) Core(TM) i9-12900K".to_owned())
+        );
+        assert_eq!(
+            parse_cpuinfo_model("Processor\t: AArch64\nHardware\t: BCM2835\n"),
+            Some("BCM2835".to_owned())
+        );
+        assert_eq!(parse_cpuinfo_model("processor\t: 0\n"), None);
+    }
+
+    #[test]
+    fn test_parse_meminfo_total() {
+        assert_eq!(
+            parse_meminfo_total("MemTotal:       16314020 kB\nMemFree: 1 kB\n"),
+            Some(16314020 * 1024)
+        );
+        assert_eq!(parse_meminfo_total("MemFree: 1 kB\n"), None);
+        assert_eq!(
+            parse_meminfo("MemTotal: 4 kB\nMemAvailable:    2048 kB\n", "MemAvailable:"),
+            Some(2048 * 1024)
+        );
+    }
+}
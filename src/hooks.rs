@@ -0,0 +1,59 @@
+//! User defined commands run on miner events.
+//!
+//! Hooks are configured as a map of event name to command line, e.g.
+//! `hooks: { block_won: '/usr/local/bin/celebrate.sh' }`. Commands are run through the system
+//! shell without waiting for them, event details are passed as `SIGNUM_*` environment variables.
+
+use std::collections::HashMap;
+use tokio::process::Command;
+
+pub const BLOCK_WON: &str = "block_won";
+pub const CAPACITY_CHANGED: &str = "capacity_changed";
+
+pub struct Hooks {
+    event_to_command: HashMap<String, String>,
+}
+
+impl Hooks {
+    pub fn new(event_to_command: HashMap<String, String>) -> Self {
+        for event in event_to_command.keys() {
+            info!("hook configured: {}", event);
+        }
+        Hooks { event_to_command }
+    }
+
+    /// Runs the command configured for `event`, if any. Must be called from within the runtime.
+    pub fn fire(&self, event: &str, vars: &[(&str, String)]) {
+        let command = match self.event_to_command.get(event) {
+            Some(command) => command,
+            None => return,
+        };
+
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let mut cmd = Command::new(shell);
+        cmd.arg(flag).arg(command).env("SIGNUM_EVENT", event);
+        for (key, value) in vars {
+            cmd.env(format!("SIGNUM_{}", key), value);
+        }
+
+        match cmd.spawn() {
+            Ok(mut child) => {
+                let event = event.to_owned();
+                tokio::spawn(async move {
+                    match child.wait().await {
+                        Ok(status) if !status.success() => {
+                            warn!("hook {}: command exited with {}", event, status)
+                        }
+                        Err(e) => warn!("hook {}: {}", event, e),
+                        _ => {}
+                    }
+                });
+            }
+            Err(e) => error!("hook {}: can't run command: {}", event, e),
+        }
+    }
+}
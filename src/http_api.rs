@@ -0,0 +1,669 @@
+//! The miner's HTTP API, enabled with `api_listen`.
+//!
+//! JSON over HTTP/1.1, or HTTPS with `api_tls_cert` and `api_tls_key`. Requests carry their token
+//! as `Authorization: Bearer TOKEN`, the POST endpoints change what is mined and need an admin
+//! token, see `api_auth`. Base path, CORS and reverse proxies are covered in `api_proxy`:
+//!
+//! - `GET /api/round/current[?context=NAME]`: the running round of a mining context (the first
+//!   one by default), see `round_status`.
+//! - `GET /api/rotation[?context=NAME&blocks=N]`: the accounts of the running and the next
+//!   blocks, 10 by default, see `rotation`.
+//! - `GET /api/status[?context=NAME]`: the running round with its base target and the scan
+//!   progress of every drive, the buffer pool and the metrics, see `metrics::snapshot`.
+//! - `GET /api/config`: the effective config of every mining context, secrets redacted.
+//! - `GET /api/hardware[?context=NAME]`: the hardware report logged at startup, CPU, memory, GPUs
+//!   and the drives of the context, see `hardware`.
+//! - `GET /api/drives[?context=NAME]`: the drives disabled at runtime.
+//! - `POST /api/drive?drive=ID&enabled=BOOL[&context=NAME]`: takes a drive out of mining or back
+//!   in, see `drive_toggles`, and reports the plots this added or removed.
+//! - `POST /api/rescan[?context=NAME&dir=PATH|drive=ID]`: rescans the plots right away and
+//!   reports the plots added and removed within the directory or drive, see `rescan`.
+//! - `GET /metrics[?context=NAME]`: the metrics for Prometheus in the OpenMetrics text format,
+//!   with exemplars on the submission latencies, see `openmetrics`.
+
+use crate::api_auth::{ApiAuth, Scope};
+use crate::api_proxy::ApiProxy;
+use crate::drive_toggles::DriveToggles;
+use crate::hardware::HardwareReport;
+use crate::http_server::{self, Request, Response};
+use crate::metrics::{self, SharedMetrics};
+use crate::openmetrics;
+use crate::rescan::{RescanReport, RescanRequest, RescanScope, RescanSender};
+use crate::rotation::AccountRotation;
+use crate::round_status::{self, SharedRoundStatus};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio::sync::oneshot;
+
+pub struct Api {
+    /// Round status of every mining context, in config order.
+    pub rounds: Vec<SharedRoundStatus>,
+    /// Account rotation of every mining context, in the same order.
+    pub rotations: Vec<Arc<AccountRotation>>,
+    /// Metrics of every mining context, in the same order.
+    pub metrics: Vec<SharedMetrics>,
+    /// Rescan requests of every mining context, in the same order.
+    pub rescans: Vec<RescanSender>,
+    /// Drives disabled at runtime of every mining context, in the same order.
+    pub drives: Vec<Arc<DriveToggles>>,
+    /// Startup hardware report of every mining context, in the same order.
+    pub hardware: Vec<Arc<HardwareReport>>,
+    /// Redacted effective config of every mining context, see `diagnose::effective_config`.
+    pub config: serde_json::Value,
+    pub auth: ApiAuth,
+    pub proxy: ApiProxy,
+}
+
+const DEFAULT_ROTATION_BLOCKS: u64 = 10;
+const MAX_ROTATION_BLOCKS: u64 = 1000;
+
+impl Api {
+    /// Index of the `context` parameter's mining context, the first one without.
+    fn context(&self, params: &HashMap<String, String>) -> Option<usize> {
+        match params.get("context") {
+            Some(context) => self
+                .rounds
+                .iter()
+                .position(|status| round_status::lock(status).context() == context.as_str()),
+            None => (!self.rounds.is_empty()).then_some(0),
+        }
+    }
+
+    /// Rescans the plots of `context`, None if its miner isn't running.
+    async fn request_rescan(&self, context: usize, scope: RescanScope) -> Option<RescanReport> {
+        let (reply, report) = oneshot::channel();
+        // a request nobody takes drops the reply, which fails the wait
+        match self.rescans.get(context) {
+            Some(rescans) => {
+                let _ = rescans.send(RescanRequest { scope, reply });
+            }
+            None => drop(reply),
+        }
+        report.await.ok()
+    }
+
+    async fn rescan(&self, params: &HashMap<String, String>) -> Response {
+        let Some(context) = self.context(params) else {
+            return Response::error("404 Not Found", "unknown context");
+        };
+        let scope = match RescanScope::from_params(params) {
+            Ok(scope) => scope,
+            Err(e) => return Response::error("400 Bad Request", e),
+        };
+        match self.request_rescan(context, scope).await {
+            Some(report) => Response::json(serde_json::to_string(&report).unwrap_or_default()),
+            None => Response::error("503 Service Unavailable", "the miner isn't running"),
+        }
+    }
+
+    async fn toggle_drive(&self, params: &HashMap<String, String>) -> Response {
+        let Some(context) = self.context(params) else {
+            return Response::error("404 Not Found", "unknown context");
+        };
+        let Some(drive) = params.get("drive") else {
+            return Response::error("400 Bad Request", "drive is missing");
+        };
+        let enabled = match params.get("enabled").map(|enabled| enabled.as_str()) {
+            Some("true") => true,
+            Some("false") => false,
+            _ => return Response::error("400 Bad Request", "enabled must be true or false"),
+        };
+        let Some(toggles) = self.drives.get(context) else {
+            return Response::error("503 Service Unavailable", "the miner isn't running");
+        };
+        let changed = toggles.set(drive, enabled);
+        // the rescan takes the drive's plots in or out and reports the capacity to the pool
+        let report = if changed {
+            info!(
+                "api: drive {} {}",
+                drive,
+                if enabled { "enabled" } else { "disabled" }
+            );
+            match self
+                .request_rescan(context, RescanScope::Drive(drive.clone()))
+                .await
+            {
+                Some(report) => Some(report),
+                None => {
+                    return Response::error("503 Service Unavailable", "the miner isn't running")
+                }
+            }
+        } else {
+            None
+        };
+        Response::json(
+            serde_json::json!({
+                "drive": drive,
+                "enabled": enabled,
+                "changed": changed,
+                "rescan": report,
+            })
+            .to_string(),
+        )
+    }
+
+    /// Answers `request` from `peer` if its token allows it.
+    async fn respond(&self, peer: IpAddr, request: &Request) -> Response {
+        if let Some(response) = self.proxy.preflight(request) {
+            return response;
+        }
+        let response = match self.proxy.endpoint(&request.path) {
+            Some(path) => {
+                let needed = Scope::of(&request.method);
+                match self.auth.check(request.header("authorization"), needed) {
+                    Ok(()) => self.handle(&request.method, path, &request.params).await,
+                    Err(response) => {
+                        warn!(
+                            "api: {} {} from {} refused: {}",
+                            request.method,
+                            request.path,
+                            self.proxy.client(peer, request),
+                            response.status
+                        );
+                        response
+                    }
+                }
+            }
+            None => Response::error("404 Not Found", "unknown endpoint"),
+        };
+        self.proxy.share(request, response)
+    }
+
+    async fn handle(&self, method: &str, path: &str, params: &HashMap<String, String>) -> Response {
+        match (method, path) {
+            ("POST", "/api/rescan") => return self.rescan(params).await,
+            ("POST", "/api/drive") => return self.toggle_drive(params).await,
+            (_, "/api/rescan" | "/api/drive") => {
+                return Response::error("405 Method Not Allowed", "only POST is supported")
+            }
+            ("GET", _) => {}
+            _ => return Response::error("405 Method Not Allowed", "only GET is supported"),
+        }
+        if path == "/api/config" {
+            return Response::json(self.config.to_string());
+        }
+        if !matches!(
+            path,
+            "/api/round/current"
+                | "/api/rotation"
+                | "/api/status"
+                | "/api/drives"
+                | "/api/hardware"
+                | "/metrics"
+        ) {
+            return Response::error("404 Not Found", "unknown endpoint");
+        }
+        let Some(context) = self.context(params) else {
+            return Response::error("404 Not Found", "unknown context");
+        };
+        if path == "/api/drives" {
+            let disabled = self
+                .drives
+                .get(context)
+                .map(|toggles| toggles.disabled())
+                .unwrap_or_default();
+            return Response::json(serde_json::json!({ "disabled": disabled }).to_string());
+        }
+        if path == "/api/hardware" {
+            return match self.hardware.get(context) {
+                Some(report) => Response::json(serde_json::to_string(&**report).unwrap_or_default()),
+                None => Response::error("503 Service Unavailable", "the miner isn't running"),
+            };
+        }
+        if path == "/metrics" {
+            let Some(metrics) = self.metrics.get(context) else {
+                return Response::error("503 Service Unavailable", "the miner isn't running");
+            };
+            #[cfg(feature = "async_io")]
+            let metrics = metrics.read().await;
+            #[cfg(not(feature = "async_io"))]
+            let metrics = match metrics.read() {
+                Ok(guard) => guard,
+                Err(poisoned) => {
+                    error!("api: metrics mutex poisoned, recovering...");
+                    poisoned.into_inner()
+                }
+            };
+            return Response {
+                status: "200 OK",
+                content_type: openmetrics::CONTENT_TYPE,
+                headers: Vec::new(),
+                body: openmetrics::render(&metrics),
+            };
+        }
+        if path == "/api/status" {
+            let metrics = match self.metrics.get(context) {
+                Some(metrics) => Some(metrics::snapshot(metrics).await),
+                None => None,
+            };
+            let round = round_status::lock(&self.rounds[context]).snapshot();
+            return Response::json(
+                serde_json::json!({
+                    "round": round,
+                    "metrics": metrics,
+                })
+                .to_string(),
+            );
+        }
+        let status = round_status::lock(&self.rounds[context]);
+        match path {
+            "/api/round/current" => {
+                Response::json(serde_json::to_string(&status.snapshot()).unwrap_or_default())
+            }
+            _ => {
+                let blocks = match params.get("blocks").map(|blocks| blocks.parse::<u64>()) {
+                    Some(Ok(blocks)) => blocks.min(MAX_ROTATION_BLOCKS),
+                    Some(Err(_)) => {
+                        return Response::error("400 Bad Request", "blocks must be a number")
+                    }
+                    None => DEFAULT_ROTATION_BLOCKS,
+                };
+                let schedule = self
+                    .rotations
+                    .get(context)
+                    .map(|rotation| rotation.schedule(status.height(), blocks))
+                    .unwrap_or_default();
+                Response::json(
+                    serde_json::json!({
+                        "context": status.context(),
+                        "height": status.height(),
+                        "schedule": schedule,
+                    })
+                    .to_string(),
+                )
+            }
+        }
+    }
+}
+
+pub async fn run(listen: String, tls: Option<TlsAcceptor>, api: Api) {
+    let listener = match TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("api: can't listen on {}: {}", listen, e);
+            return;
+        }
+    };
+    info!(
+        "api: listening on {}://{}/",
+        if tls.is_some() { "https" } else { "http" },
+        listen
+    );
+    let api = std::sync::Arc::new(api);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("api: can't accept connection: {}", e);
+                continue;
+            }
+        };
+        let api = api.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            let handle = |request: Request| {
+                let api = api.clone();
+                async move { api.respond(peer.ip(), &request).await }
+            };
+            let served = match tls {
+                Some(tls) => match tls.accept(stream).await {
+                    Ok(stream) => http_server::serve(stream, handle).await,
+                    Err(e) => Err(e),
+                },
+                None => http_server::serve(stream, handle).await,
+            };
+            if let Err(e) = served {
+                debug!("api: connection from {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_pool::BufferPool;
+    use crate::metrics::new_shared_metrics;
+    use crate::poc_hashing::NONCE_SIZE;
+    use crate::rotation::RotationEntry;
+    use crate::round_status::new_shared_round_status;
+
+    #[tokio::test]
+    async fn test_round_current() {
+        let api = Api {
+            rounds: vec![
+                new_shared_round_status(String::new()),
+                new_shared_round_status("second".to_owned()),
+            ],
+            rotations: Vec::new(),
+            metrics: Vec::new(),
+            rescans: Vec::new(),
+            drives: Vec::new(),
+            hardware: Vec::new(),
+            config: serde_json::Value::Null,
+            auth: ApiAuth::default(),
+            proxy: ApiProxy::default(),
+        };
+        round_status::lock(&api.rounds[1]).start(5, 70_000, &[1u8; 32], 3, Default::default());
+
+        let response = api
+            .handle("GET", "/api/round/current", &HashMap::new())
+            .await;
+        assert_eq!(response.status, "200 OK");
+        assert!(response.body.contains("\"height\":0"));
+        let params = HashMap::from([("context".to_owned(), "second".to_owned())]);
+        let response = api.handle("GET", "/api/round/current", &params).await;
+        assert!(response.body.contains("\"height\":5"));
+
+        assert_eq!(
+            api.handle("GET", "/api/nope", &params).await.status,
+            "404 Not Found"
+        );
+        let params = HashMap::from([("context".to_owned(), "third".to_owned())]);
+        assert_eq!(
+            api.handle("GET", "/api/round/current", &params)
+                .await
+                .status,
+            "404 Not Found"
+        );
+        assert_eq!(
+            api.handle("POST", "/api/round/current", &params)
+                .await
+                .status,
+            "405 Method Not Allowed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation() {
+        let api = Api {
+            rounds: vec![new_shared_round_status(String::new())],
+            rotations: vec![Arc::new(AccountRotation::new(vec![
+                RotationEntry {
+                    account_id: 1,
+                    weight: 1,
+                },
+                RotationEntry {
+                    account_id: 2,
+                    weight: 1,
+                },
+            ]))],
+            metrics: Vec::new(),
+            rescans: Vec::new(),
+            drives: Vec::new(),
+            hardware: Vec::new(),
+            config: serde_json::Value::Null,
+            auth: ApiAuth::default(),
+            proxy: ApiProxy::default(),
+        };
+        round_status::lock(&api.rounds[0]).start(5, 70_000, &[1u8; 32], 3, Default::default());
+
+        let params = HashMap::from([("blocks".to_owned(), "2".to_owned())]);
+        let response = api.handle("GET", "/api/rotation", &params).await;
+        assert_eq!(response.status, "200 OK");
+        assert!(response
+            .body
+            .contains(r#""schedule":[{"account_id":2,"height":5},{"account_id":1,"height":6}]"#));
+        let params = HashMap::from([("blocks".to_owned(), "many".to_owned())]);
+        assert_eq!(
+            api.handle("GET", "/api/rotation", &params).await.status,
+            "400 Bad Request"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status() {
+        let buffer_pool = BufferPool::new(2, 1);
+        let api = Api {
+            rounds: vec![new_shared_round_status(String::new())],
+            rotations: Vec::new(),
+            metrics: vec![new_shared_metrics(
+                "rig".to_owned(),
+                Some(buffer_pool.counters()),
+                Vec::new(),
+                None,
+                None,
+                Vec::new(),
+                None,
+            )],
+            rescans: Vec::new(),
+            drives: Vec::new(),
+            hardware: Vec::new(),
+            config: serde_json::Value::Null,
+            auth: ApiAuth::default(),
+            proxy: ApiProxy::default(),
+        };
+        round_status::lock(&api.rounds[0]).start(5, 70_000, &[1u8; 32], 3, Default::default());
+
+        let response = api.handle("GET", "/api/status", &HashMap::new()).await;
+        assert_eq!(response.status, "200 OK");
+        let status: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(status["round"]["base_target"], 70_000);
+        assert_eq!(status["metrics"]["rounds_completed"], 0);
+        assert!(status["metrics"]["buffers"]["consumers"].is_array());
+        assert!(status["metrics"]["summary"]
+            .as_str()
+            .unwrap()
+            .contains("Miner ID: rig"));
+    }
+
+    #[tokio::test]
+    async fn test_hardware() {
+        let report = HardwareReport::collect(&HashMap::new(), &HashMap::from([(7, 4)]), Vec::new());
+        let api = Api {
+            rounds: vec![new_shared_round_status(String::new())],
+            rotations: Vec::new(),
+            metrics: Vec::new(),
+            rescans: Vec::new(),
+            drives: Vec::new(),
+            hardware: vec![Arc::new(report)],
+            config: serde_json::Value::Null,
+            auth: ApiAuth::new(vec!["reader".to_owned()], Vec::new()),
+            proxy: ApiProxy::default(),
+        };
+        let request = |token: Option<&str>| Request {
+            method: "GET".to_owned(),
+            path: "/api/hardware".to_owned(),
+            headers: token
+                .map(|token| ("authorization".to_owned(), format!("Bearer {}", token)))
+                .into_iter()
+                .collect(),
+            params: HashMap::new(),
+        };
+        let peer = IpAddr::from([127, 0, 0, 1]);
+
+        let response = api.respond(peer, &request(None)).await;
+        assert_eq!(response.status, "401 Unauthorized");
+        let response = api.respond(peer, &request(Some("reader"))).await;
+        assert_eq!(response.status, "200 OK");
+        let hardware: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(hardware["capacity_by_account"]["7"], 4 * NONCE_SIZE as u64);
+        assert!(hardware["drives"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rescan() {
+        let (tx_rescan, mut rx_rescan) = tokio::sync::mpsc::unbounded_channel::<RescanRequest>();
+        tokio::spawn(async move {
+            while let Some(request) = rx_rescan.recv().await {
+                let plots = HashMap::from([("/a/1".to_owned(), 4)]);
+                let drives = HashMap::from([("/a/1".to_owned(), "sda".to_owned())]);
+                let report = crate::rescan::RescanReport::new(
+                    "",
+                    &request.scope,
+                    (&HashMap::new(), &HashMap::new()),
+                    (&plots, &drives),
+                );
+                let _ = request.reply.send(report);
+            }
+        });
+        let api = Api {
+            rounds: vec![
+                new_shared_round_status(String::new()),
+                new_shared_round_status("stopped".to_owned()),
+            ],
+            rotations: Vec::new(),
+            metrics: Vec::new(),
+            rescans: vec![tx_rescan],
+            drives: vec![Arc::default()],
+            hardware: Vec::new(),
+            config: serde_json::Value::Null,
+            auth: ApiAuth::default(),
+            proxy: ApiProxy::default(),
+        };
+
+        let params = HashMap::from([("drive".to_owned(), "sda".to_owned())]);
+        assert_eq!(
+            api.handle("GET", "/api/rescan", &params).await.status,
+            "405 Method Not Allowed"
+        );
+        let response = api.handle("POST", "/api/rescan", &params).await;
+        assert_eq!(response.status, "200 OK");
+        assert!(response.body.contains(r#""added":["/a/1"]"#));
+        let params = HashMap::from([("drive".to_owned(), "sdb".to_owned())]);
+        let response = api.handle("POST", "/api/rescan", &params).await;
+        assert!(response.body.contains(r#""added":[]"#));
+
+        let params = HashMap::from([("context".to_owned(), "stopped".to_owned())]);
+        assert_eq!(
+            api.handle("POST", "/api/rescan", &params).await.status,
+            "503 Service Unavailable"
+        );
+
+        let params = HashMap::from([
+            ("drive".to_owned(), "sda".to_owned()),
+            ("enabled".to_owned(), "false".to_owned()),
+        ]);
+        let response = api.handle("POST", "/api/drive", &params).await;
+        assert_eq!(response.status, "200 OK");
+        assert!(response.body.contains(r#""changed":true"#));
+        assert!(response.body.contains(r#""scope":"drive sda""#));
+        let response = api.handle("POST", "/api/drive", &params).await;
+        assert!(response
+            .body
+            .contains(r#""changed":false,"drive":"sda","enabled":false,"rescan":null"#));
+        let response = api.handle("GET", "/api/drives", &HashMap::new()).await;
+        assert_eq!(response.body, r#"{"disabled":["sda"]}"#);
+        let params = HashMap::from([("drive".to_owned(), "sda".to_owned())]);
+        assert_eq!(
+            api.handle("POST", "/api/drive", &params).await.status,
+            "400 Bad Request"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth() {
+        let api = Api {
+            rounds: vec![new_shared_round_status(String::new())],
+            rotations: Vec::new(),
+            metrics: Vec::new(),
+            rescans: Vec::new(),
+            drives: vec![Arc::default()],
+            hardware: Vec::new(),
+            config: serde_json::Value::Null,
+            auth: ApiAuth::new(vec!["reader".to_owned()], vec!["admin".to_owned()]),
+            proxy: ApiProxy::default(),
+        };
+        let request = |method: &str, path: &str, token: Option<&str>| Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers: token
+                .map(|token| ("authorization".to_owned(), format!("Bearer {}", token)))
+                .into_iter()
+                .collect(),
+            params: HashMap::from([
+                ("drive".to_owned(), "sda".to_owned()),
+                ("enabled".to_owned(), "true".to_owned()),
+            ]),
+        };
+
+        let peer = IpAddr::from([127, 0, 0, 1]);
+        let status = |request: Request| {
+            let api = &api;
+            async move { api.respond(peer, &request).await.status }
+        };
+        assert_eq!(
+            status(request("GET", "/api/drives", None)).await,
+            "401 Unauthorized"
+        );
+        assert_eq!(
+            status(request("GET", "/api/drives", Some("reader"))).await,
+            "200 OK"
+        );
+        assert_eq!(
+            status(request("POST", "/api/drive", Some("reader"))).await,
+            "403 Forbidden"
+        );
+        // an unchanged drive needs no rescan, the miner needn't run
+        assert_eq!(
+            status(request("POST", "/api/drive", Some("admin"))).await,
+            "200 OK"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_behind_proxy() {
+        let api = Api {
+            rounds: vec![new_shared_round_status(String::new())],
+            rotations: Vec::new(),
+            metrics: Vec::new(),
+            rescans: Vec::new(),
+            drives: Vec::new(),
+            hardware: Vec::new(),
+            config: serde_json::Value::Null,
+            auth: ApiAuth::new(vec!["reader".to_owned()], Vec::new()),
+            proxy: ApiProxy::new("/miner", vec!["*".to_owned()], Vec::new()),
+        };
+        let request = |method: &str, path: &str, headers: &[(&str, &str)]| Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers: headers
+                .iter()
+                .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+            params: HashMap::new(),
+        };
+        let peer = IpAddr::from([127, 0, 0, 1]);
+
+        let response = api
+            .respond(
+                peer,
+                &request(
+                    "GET",
+                    "/miner/api/round/current",
+                    &[("authorization", "Bearer reader"), ("origin", "https://farm.example")],
+                ),
+            )
+            .await;
+        assert_eq!(response.status, "200 OK");
+        assert!(response
+            .headers
+            .contains(&("Access-Control-Allow-Origin", "https://farm.example".to_owned())));
+        let response = api
+            .respond(
+                peer,
+                &request("GET", "/api/round/current", &[("authorization", "Bearer reader")]),
+            )
+            .await;
+        assert_eq!(response.status, "404 Not Found");
+        // browsers ask without the token
+        let response = api
+            .respond(
+                peer,
+                &request(
+                    "OPTIONS",
+                    "/miner/api/round/current",
+                    &[
+                        ("origin", "https://farm.example"),
+                        ("access-control-request-method", "GET"),
+                        ("access-control-request-headers", "authorization"),
+                    ],
+                ),
+            )
+            .await;
+        assert_eq!(response.status, "200 OK");
+    }
+}
@@ -0,0 +1,240 @@
+//! The plain HTTP/1.1 server behind the API (`http_api`) and the `mockpool` command.
+//!
+//! Connections are kept alive, over TLS with an acceptor from `tls_acceptor`. The parameters of a request are those of the query, followed by
+//! those of a form body. Request lines, headers and bodies are capped, a request over a cap ends
+//! the connection.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader as StdBufReader};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use url::form_urlencoded;
+
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY: usize = 64 * 1024;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Names in lower case.
+    pub headers: HashMap<String, String>,
+    pub params: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    /// Sent in addition to the content type and length.
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(body: String) -> Response {
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    pub fn error(status: &'static str, message: &str) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// A line of at most `MAX_LINE` bytes, None at the end of the connection.
+async fn read_line<R>(reader: &mut R) -> io::Result<Option<String>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    if (&mut *reader).take(MAX_LINE).read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && line.len() as u64 >= MAX_LINE {
+        return Err(invalid("line too long"));
+    }
+    Ok(Some(line))
+}
+
+/// The next request of the connection, None once the client closed it.
+async fn read_request<R>(reader: &mut R) -> io::Result<Option<Request>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let Some(request_line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+    let mut headers = HashMap::new();
+    loop {
+        let Some(line) = read_line(reader).await? else {
+            return Ok(None);
+        };
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+        }
+    }
+
+    // the body is read even where nobody needs it, the next request follows it
+    let content_length = match headers.get("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| invalid("invalid content length"))?,
+        None => 0,
+    };
+    if content_length > MAX_BODY {
+        return Err(invalid("body too large"));
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_owned();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = form_urlencoded::parse(query.as_bytes())
+        .chain(form_urlencoded::parse(&body))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    Ok(Some(Request {
+        method,
+        path: path.to_owned(),
+        headers,
+        params,
+    }))
+}
+
+/// Answers the requests of `stream` with `handle` until the client closes the connection.
+pub async fn serve<S, F, Fut>(stream: S, handle: F) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    while let Some(request) = read_request(&mut reader).await? {
+        let response = handle(request).await;
+        let headers: String = response
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        writer
+            .write_all(
+                format!(
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\r\n{}",
+                    response.status,
+                    response.content_type,
+                    response.body.len(),
+                    headers,
+                    response.body
+                )
+                .as_bytes(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// TLS for the server from a PEM certificate chain and a PEM private key.
+pub fn tls_acceptor(cert: &Path, key: &Path) -> io::Result<TlsAcceptor> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(StdBufReader::new)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    };
+    let certs = CertificateDer::pem_reader_iter(open(cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(&format!("{}: {}", cert.display(), e)))?;
+    let key = PrivateKeyDer::from_pem_reader(open(key)?)
+        .map_err(|e| invalid(&format!("{}: {}", key.display(), e)))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(&e.to_string()))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serves `handle` on a free port of localhost for the rest of the test, returns the base URL.
+#[cfg(test)]
+pub async fn serve_local<F, Fut>(handle: F) -> url::Url
+where
+    F: Fn(Request) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, handle.clone()));
+        }
+    });
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let raw = b"POST /api/rescan?drive=sda HTTP/1.1\r\nContent-Length: 9\r\n\
+                    Authorization: Bearer x\r\n\r\ncontext=bGET /metrics HTTP/1.1\r\n\r\n";
+        let mut reader = &raw[..];
+        let request = read_request(&mut reader).await.unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/rescan");
+        assert_eq!(request.header("authorization"), Some("Bearer x"));
+        assert_eq!(request.params["drive"], "sda");
+        assert_eq!(request.params["context"], "b");
+        // the body didn't end up in the next request
+        let request = read_request(&mut reader).await.unwrap().unwrap();
+        assert_eq!(request.path, "/metrics");
+        assert!(read_request(&mut reader).await.unwrap().is_none());
+
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE as usize));
+        assert!(read_request(&mut long.as_bytes()).await.is_err());
+        let large = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert!(read_request(&mut large.as_bytes()).await.is_err());
+    }
+}
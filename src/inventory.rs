@@ -0,0 +1,277 @@
+//! Plot inventory: one row per discovered plot with everything operators ask about, shown by the
+//! `list-plots` command and included in the `diagnose` archive.
+
+use crate::config::Cfg;
+use crate::drive_toggles::DriveToggles;
+use crate::miner::{scan_plots, PlotScan};
+use crate::plot::{compressed_plot, open, Meta, Plot};
+use crate::plot_order::PlotOrdering;
+use crate::poc_hashing::NONCE_SIZE;
+use crate::retire::RetireList;
+use crate::sparse;
+use crate::utils::{get_bus_type, get_device_id};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(not(feature = "async_io"))]
+use std::sync::Mutex;
+#[cfg(feature = "async_io")]
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlotInfo {
+    pub path: String,
+    pub account_id: u64,
+    pub start_nonce: u64,
+    /// Exclusive.
+    pub end_nonce: u64,
+    pub size: u64,
+    pub drive_id: String,
+    pub bus_type: String,
+    pub sector_size: u64,
+    pub direct_io: bool,
+    /// `ok`, `sparse`, `unreadable` or `compressed xFACTOR` for plots that aren't mined.
+    pub health: String,
+    /// Names of plots of the same account sharing nonces with this one.
+    pub overlaps: Vec<String>,
+}
+
+/// Scans the configured plots the same way mining does.
+pub fn scan(cfg: &Cfg) -> PlotScan {
+    scan_plots(
+        &cfg.plot_dirs,
+        &cfg.raw_plots,
+        cfg.sparse_plots,
+        &PlotOrdering::from_cfg(cfg),
+        &cfg.quotas,
+        cfg.poc1_support,
+        cfg.hdd_use_direct_io,
+        false,
+        cfg.plot_encryption_key.as_deref(),
+        &RetireList::load(&cfg.retire_list),
+        &DriveToggles::default(),
+    )
+}
+
+pub fn plot_inventory(drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>) -> Vec<PlotInfo> {
+    let mut bus_types: HashMap<&str, String> = HashMap::new();
+    let mut plots: Vec<(PlotInfo, Meta)> = Vec::new();
+    for (drive_id, drive_plots) in drive_id_to_plots {
+        for plot in drive_plots.iter() {
+            let plot = lock(plot);
+            let bus_type = bus_types
+                .entry(drive_id.as_str())
+                .or_insert_with(|| get_bus_type(&plot.path))
+                .clone();
+            plots.push((
+                PlotInfo {
+                    path: plot.path.clone(),
+                    account_id: plot.meta.account_id,
+                    start_nonce: plot.meta.start_nonce,
+                    end_nonce: plot.meta.start_nonce + plot.meta.nonces,
+                    size: plot.meta.nonces * NONCE_SIZE as u64,
+                    drive_id: drive_id.clone(),
+                    bus_type,
+                    sector_size: plot.sector_size(),
+                    direct_io: plot.uses_direct_io(),
+                    health: health(&plot.path),
+                    overlaps: Vec::new(),
+                },
+                plot.meta.clone(),
+            ));
+        }
+    }
+
+    let metas: Vec<Meta> = plots.iter().map(|(_, meta)| meta.clone()).collect();
+    let mut plots: Vec<PlotInfo> = plots
+        .into_iter()
+        .zip(overlaps(&metas))
+        .map(|((info, _), overlaps)| PlotInfo { overlaps, ..info })
+        .collect();
+    plots.sort_by(|a, b| a.path.cmp(&b.path));
+    plots
+}
+
+/// The compressed plots in `plot_dirs`. Mining skips them, the size is what they take on disk.
+pub fn compressed_plots(plot_dirs: &[PathBuf]) -> Vec<PlotInfo> {
+    let mut plots = Vec::new();
+    for plot_dir in plot_dirs {
+        let Ok(entries) = fs::read_dir(plot_dir) else {
+            continue;
+        };
+        for file in entries.flatten().map(|entry| entry.path()) {
+            let Some((meta, factor)) = file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(compressed_plot)
+            else {
+                continue;
+            };
+            let path = file.to_string_lossy().into_owned();
+            plots.push(PlotInfo {
+                account_id: meta.account_id,
+                start_nonce: meta.start_nonce,
+                end_nonce: meta.start_nonce + meta.nonces,
+                size: fs::metadata(&file).map_or(0, |metadata| metadata.len()),
+                drive_id: get_device_id(&path),
+                bus_type: get_bus_type(&path),
+                sector_size: 0,
+                direct_io: false,
+                health: format!("compressed x{}", factor),
+                overlaps: Vec::new(),
+                path,
+            });
+        }
+    }
+    plots.sort_by(|a, b| a.path.cmp(&b.path));
+    plots
+}
+
+/// For every plot the names of the plots of the same account it shares nonces with.
+fn overlaps(metas: &[Meta]) -> Vec<Vec<String>> {
+    let mut overlaps = vec![Vec::new(); metas.len()];
+    for i in 0..metas.len() {
+        for j in i + 1..metas.len() {
+            if metas[i].account_id == metas[j].account_id && metas[i].overlaps_with(&metas[j]) {
+                overlaps[i].push(metas[j].name.clone());
+                overlaps[j].push(metas[i].name.clone());
+            }
+        }
+    }
+    overlaps
+}
+
+/// Reads the first bytes of the plot and looks for holes.
+fn health(path: &str) -> String {
+    let mut byte = [0u8; 1];
+    if open(path)
+        .and_then(|mut f| f.read_exact(&mut byte))
+        .is_err()
+    {
+        return "unreadable".to_owned();
+    }
+    match sparse::check(Path::new(path)) {
+        Ok(Some(report)) if !report.holes.is_empty() => "sparse".to_owned(),
+        _ => "ok".to_owned(),
+    }
+}
+
+/// `list-plots`: prints the inventory as a table or as one JSON document.
+pub fn list_plots(cfg: &Cfg, json: bool) {
+    let PlotScan {
+        drive_id_to_plots, ..
+    } = scan(cfg);
+    let plots = plot_inventory(&drive_id_to_plots);
+    let compressed = compressed_plots(&cfg.plot_dirs);
+
+    if json {
+        match serde_json::to_string(&[plots, compressed].concat()) {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("list-plots: can't serialize inventory: {}", e),
+        }
+        return;
+    }
+
+    println!(
+        "{:<60} {:>20} {:>25} {:>10} {:<12} {:<6} {:>6} {:<4} {:<10} OVERLAPS",
+        "PATH", "ACCOUNT", "NONCES", "SIZE", "DRIVE", "BUS", "SECTOR", "DIO", "HEALTH"
+    );
+    for plot in plots.iter().chain(&compressed) {
+        println!(
+            "{:<60} {:>20} {:>25} {:>10} {:<12} {:<6} {:>6} {:<4} {:<10} {}",
+            plot.path,
+            plot.account_id,
+            format!("{}-{}", plot.start_nonce, plot.end_nonce),
+            format!("{:.2} GiB", plot.size as f64 / 1024.0 / 1024.0 / 1024.0),
+            plot.drive_id,
+            plot.bus_type,
+            plot.sector_size,
+            if plot.direct_io { "yes" } else { "no" },
+            plot.health,
+            if plot.overlaps.is_empty() {
+                "-".to_owned()
+            } else {
+                plot.overlaps.join(",")
+            }
+        );
+    }
+    let total: u64 = plots.iter().map(|plot| plot.size).sum();
+    println!(
+        "{} plots, {:.4} TiB",
+        plots.len(),
+        total as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0
+    );
+    if !compressed.is_empty() {
+        let total: u64 = compressed.iter().map(|plot| plot.size).sum();
+        println!(
+            "{} compressed plots not mined, {:.4} TiB on disk",
+            compressed.len(),
+            total as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0
+        );
+    }
+}
+
+#[cfg(feature = "async_io")]
+fn lock<T>(mutex: &Mutex<T>) -> tokio::sync::MutexGuard<'_, T> {
+    crate::utils::lock_sync(mutex)
+}
+
+#[cfg(not(feature = "async_io"))]
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("inventory: mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(account_id: u64, start_nonce: u64, nonces: u64) -> Meta {
+        Meta {
+            account_id,
+            start_nonce,
+            nonces,
+            name: format!("{}_{}_{}", account_id, start_nonce, nonces),
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let overlaps = overlaps(&[
+            meta(1, 0, 100),
+            meta(1, 50, 100),
+            meta(2, 0, 100),
+            meta(1, 150, 10),
+        ]);
+        assert_eq!(overlaps[0], vec!["1_50_100".to_owned()]);
+        assert_eq!(overlaps[1], vec!["1_0_100".to_owned()]);
+        assert!(overlaps[2].is_empty());
+        // 50..150 ends where 150..160 starts
+        assert!(overlaps[3].is_empty());
+    }
+
+    #[test]
+    fn test_compressed_plots() {
+        let dir =
+            std::env::temp_dir().join(format!("signum-miner-compressed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1_0_64_x4"), [0u8; 1024]).unwrap();
+        fs::write(dir.join("1_64_64"), [0u8; 1024]).unwrap();
+        fs::write(dir.join("1_128_64_x1"), [0u8; 1024]).unwrap();
+        let plots = compressed_plots(std::slice::from_ref(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(plots.len(), 1);
+        assert_eq!(plots[0].account_id, 1);
+        assert_eq!(plots[0].end_nonce, 64);
+        assert_eq!(plots[0].size, 1024);
+        assert_eq!(plots[0].health, "compressed x4");
+    }
+}
@@ -0,0 +1,272 @@
+//! I/O priority of the reader threads per drive, see `io_priority`.
+//!
+//! On a disk shared with other services the mining reads can be marked idle, so they only get the
+//! disk when nobody else wants it. On Linux this is the ionice class and level of the reading
+//! thread, honoured by the BFQ and CFQ schedulers. On Windows `idle` puts the thread into
+//! background mode, which lowers its I/O priority, the other classes keep the default there.
+//! Reader threads are shared by drives, so the priority is set for a drive's read task and the
+//! previous one restored after it. With `async_io` the reads run on the runtime's threads and
+//! aren't prioritized.
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+/// Class and level as ionice takes them, a lower level is served first. Idle has no level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPriority {
+    pub class: IoClass,
+    pub level: u8,
+}
+
+/// The kernel's level for best effort threads without one.
+const DEFAULT_LEVEL: u8 = 4;
+
+impl FromStr for IoPriority {
+    type Err = String;
+
+    /// `idle`, `best_effort[:LEVEL]` or `realtime[:LEVEL]`, levels 0 to 7.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (s, None),
+        };
+        let class = match class.trim().to_lowercase().as_str() {
+            "realtime" | "rt" => IoClass::Realtime,
+            "best_effort" | "best-effort" | "be" => IoClass::BestEffort,
+            "idle" => IoClass::Idle,
+            _ => {
+                return Err(format!(
+                    "unknown io priority '{}' (idle, best_effort[:0-7], realtime[:0-7])",
+                    s
+                ))
+            }
+        };
+        let level = match level {
+            Some(_) if class == IoClass::Idle => {
+                return Err(format!("io priority '{}': idle has no level", s))
+            }
+            Some(level) => match level.trim().parse::<u8>() {
+                Ok(level) if level <= 7 => level,
+                _ => return Err(format!("io priority '{}': level must be 0 to 7", s)),
+            },
+            None if class == IoClass::Idle => 0,
+            None => DEFAULT_LEVEL,
+        };
+        Ok(IoPriority { class, level })
+    }
+}
+
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.class {
+            IoClass::Realtime => write!(f, "realtime:{}", self.level),
+            IoClass::BestEffort => write!(f, "best_effort:{}", self.level),
+            IoClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for IoPriority {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for IoPriority {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl IoPriority {
+    /// The value of ioprio_set, the class in the top bits.
+    #[cfg(any(target_os = "linux", test))]
+    fn ioprio(self) -> libc::c_int {
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        let class = match self.class {
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+        (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(self.level)
+    }
+}
+
+pub struct IoPriorities {
+    drives: HashMap<String, IoPriority>,
+}
+
+impl IoPriorities {
+    /// `None` without any configured priorities, so the reader threads are left alone.
+    pub fn new(drives: HashMap<String, IoPriority>) -> Option<IoPriorities> {
+        if drives.is_empty() {
+            return None;
+        }
+        if cfg!(not(any(target_os = "linux", windows))) || cfg!(feature = "async_io") {
+            warn!("io priority: not supported on this platform or with async_io, ignored");
+        }
+        Some(IoPriorities { drives })
+    }
+
+    /// A drive id wins over the longest matching plot path prefix, which wins over `*`.
+    pub fn lookup(&self, drive_id: &str, path: &str) -> Option<IoPriority> {
+        self.drives
+            .get(drive_id)
+            .or_else(|| {
+                self.drives
+                    .iter()
+                    .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+                    .max_by_key(|(prefix, _)| prefix.len())
+                    .map(|(_, priority)| priority)
+            })
+            .or_else(|| self.drives.get("*"))
+            .copied()
+    }
+}
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+fn warn_once(e: std::io::Error) {
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        warn!("io priority: can't set the reader thread's priority: {}", e);
+    }
+}
+
+/// Runs `task` with the calling thread's I/O priority set to `priority`.
+pub fn with<T>(priority: Option<IoPriority>, task: impl FnOnce() -> T) -> T {
+    match priority {
+        Some(priority) => {
+            let previous = set(priority);
+            let result = task();
+            restore(previous);
+            result
+        }
+        None => task(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+// with IOPRIO_WHO_PROCESS, 0 is the calling thread
+#[cfg(target_os = "linux")]
+fn set(priority: IoPriority) -> Option<libc::c_int> {
+    let previous = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+    if previous < 0 {
+        warn_once(std::io::Error::last_os_error());
+        return None;
+    }
+    if unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            priority.ioprio(),
+        )
+    } != 0
+    {
+        warn_once(std::io::Error::last_os_error());
+        return None;
+    }
+    Some(previous as libc::c_int)
+}
+
+#[cfg(target_os = "linux")]
+fn restore(previous: Option<libc::c_int>) {
+    if let Some(previous) = previous {
+        unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, previous) };
+    }
+}
+
+#[cfg(windows)]
+fn set(priority: IoPriority) -> Option<()> {
+    use winapi::um::processthreadsapi::{GetCurrentThread, SetThreadPriority};
+    use winapi::um::winbase::THREAD_MODE_BACKGROUND_BEGIN;
+
+    if priority.class != IoClass::Idle {
+        return None;
+    }
+    if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN as i32) } == 0 {
+        warn_once(std::io::Error::last_os_error());
+        return None;
+    }
+    Some(())
+}
+
+#[cfg(windows)]
+fn restore(previous: Option<()>) {
+    use winapi::um::processthreadsapi::{GetCurrentThread, SetThreadPriority};
+    use winapi::um::winbase::THREAD_MODE_BACKGROUND_END;
+
+    if previous.is_some() {
+        unsafe { SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_END as i32) };
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn set(_priority: IoPriority) -> Option<()> {
+    None
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn restore(_previous: Option<()>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let idle: IoPriority = "idle".parse().unwrap();
+        assert_eq!(idle.ioprio(), 3 << 13);
+        let be: IoPriority = "best_effort:7".parse().unwrap();
+        assert_eq!(be.ioprio(), (2 << 13) | 7);
+        assert_eq!(
+            "realtime".parse::<IoPriority>().unwrap().to_string(),
+            "realtime:4"
+        );
+        assert!("best_effort:8".parse::<IoPriority>().is_err());
+        assert!("idle:3".parse::<IoPriority>().is_err());
+        assert!("low".parse::<IoPriority>().is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let priorities = IoPriorities::new(HashMap::from([
+            ("*".to_owned(), "best_effort:2".parse().unwrap()),
+            ("/mnt/shared".to_owned(), "idle".parse().unwrap()),
+            ("sdc".to_owned(), "realtime:0".parse().unwrap()),
+        ]))
+        .unwrap();
+        let class = |drive, path| priorities.lookup(drive, path).map(|p| p.class);
+        assert_eq!(
+            class("sdb", "/mnt/shared/plots/1_0_10"),
+            Some(IoClass::Idle)
+        );
+        assert_eq!(
+            class("sdc", "/mnt/shared/plots/1_0_10"),
+            Some(IoClass::Realtime)
+        );
+        assert_eq!(class("sda", "/mnt/own/1_0_10"), Some(IoClass::BestEffort));
+        assert!(IoPriorities::new(HashMap::new()).is_none());
+    }
+}
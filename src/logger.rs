@@ -1,28 +1,200 @@
 use crate::config::Cfg;
 
-use log::LevelFilter;
+use log::{LevelFilter, Record};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
 use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
 use log4rs::append::rolling_file::RollingFileAppender;
 use log4rs::config::{Appender, Config, Root};
+use log4rs::encode::json::JsonEncoder;
 use log4rs::encode::pattern::PatternEncoder;
-use log4rs::filter::threshold::ThresholdFilter;
+use log4rs::encode::Encode;
+use log4rs::filter::{Filter, Response};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::Duration;
 
-fn to_log_level(s: &str, default: log::LevelFilter) -> log::LevelFilter {
+const LOG_LEVELS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Console output selected with `--output`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    /// Log lines and the progress bar.
+    Human,
+    /// One JSON object per log record, progress as records with target `progress`, no bar.
+    Json,
+}
+
+impl OutputMode {
+    pub fn parse(s: &str) -> Option<OutputMode> {
+        match s.to_lowercase().as_str() {
+            "human" => Some(OutputMode::Human),
+            "json" => Some(OutputMode::Json),
+            _ => None,
+        }
+    }
+}
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// True if the console is read by a program, human only output should be skipped.
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Target prefixes with their level from `log_levels`, the most specific first.
+static MODULE_LEVELS: RwLock<Vec<(String, LevelFilter)>> = RwLock::new(Vec::new());
+/// Highest of the console and log file level, the global maximum can't go below it.
+static BASE_LEVEL: RwLock<LevelFilter> = RwLock::new(LevelFilter::Info);
+
+fn module_levels() -> RwLockReadGuard<'static, Vec<(String, LevelFilter)>> {
+    match MODULE_LEVELS.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("logger: module levels lock poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+/// Source modules behind the names users know from the logs.
+fn module_targets(module: &str) -> Vec<String> {
+    let modules: &[&str] = match module {
+        "client" => &["com", "requests"],
+        "gpu" => &[
+            "gpu_worker",
+            "gpu_worker_async",
+            "gpu_worker_host",
+            "ocl",
+            "mtl",
+            "wgpu_backend",
+        ],
+        module => &[module],
+    };
+    modules
+        .iter()
+        .map(|module| format!("{}::{}", env!("CARGO_CRATE_NAME"), module))
+        .collect()
+}
+
+fn parse_module_levels(levels: &HashMap<String, String>) -> Vec<(String, LevelFilter)> {
+    let mut parsed: Vec<(String, LevelFilter)> = Vec::new();
+    for (module, level) in levels {
+        let level = match to_level(level) {
+            Some(level) => level,
+            None => {
+                warn!("logger: unknown level '{}' for module {}, ignored", level, module);
+                continue;
+            }
+        };
+        parsed.extend(module_targets(module).into_iter().map(|target| (target, level)));
+    }
+    // longest prefix first, so `com::client` wins over `com`
+    parsed.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+    parsed
+}
+
+/// Applies `log_levels`, can be called at any time.
+pub fn set_module_levels(levels: &HashMap<String, String>) {
+    let parsed = parse_module_levels(levels);
+    let base = match BASE_LEVEL.read() {
+        Ok(guard) => *guard,
+        Err(poisoned) => *poisoned.into_inner(),
+    };
+    let max = parsed.iter().map(|&(_, level)| level).fold(base, Ord::max);
+    match MODULE_LEVELS.write() {
+        Ok(mut guard) => *guard = parsed,
+        Err(poisoned) => *poisoned.into_inner() = parsed,
+    }
+    // records above the global maximum are dropped before they reach the filters
+    log::set_max_level(max);
+}
+
+fn module_level(target: &str) -> Option<LevelFilter> {
+    module_levels()
+        .iter()
+        .find(|(prefix, _)| {
+            target.starts_with(prefix.as_str())
+                && target[prefix.len()..].chars().next().is_none_or(|c| c == ':')
+        })
+        .map(|&(_, level)| level)
+}
+
+/// Threshold of an appender, unless the record's module has its own level.
+#[derive(Debug)]
+struct ModuleFilter {
+    level: LevelFilter,
+}
+
+impl Filter for ModuleFilter {
+    fn filter(&self, record: &Record) -> Response {
+        let level = module_level(record.target()).unwrap_or(self.level);
+        if record.level() <= level {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// Polls the config file for changed `log_levels`, so a drive can be debugged without a restart.
+pub fn watch_log_levels(config: String, mut current: HashMap<String, String>) {
+    #[derive(Deserialize)]
+    struct LogLevels {
+        #[serde(default)]
+        log_levels: HashMap<String, String>,
+    }
+
+    thread::spawn(move || loop {
+        thread::sleep(LOG_LEVELS_POLL_INTERVAL);
+        let levels = match fs::read_to_string(&config)
+            .ok()
+            .and_then(|raw| serde_yaml::from_str::<LogLevels>(&raw).ok())
+        {
+            Some(levels) => levels.log_levels,
+            None => continue,
+        };
+        if levels != current {
+            let mut changes: Vec<String> = levels
+                .iter()
+                .map(|(module, level)| format!("{}={}", module, level))
+                .collect();
+            changes.sort();
+            info!("logger: module levels changed: [{}]", changes.join(", "));
+            set_module_levels(&levels);
+            current = levels;
+        }
+    });
+}
+
+fn to_level(s: &str) -> Option<log::LevelFilter> {
     match s.to_lowercase().as_str() {
-        "trace" => log::LevelFilter::Trace,
-        "debug" => log::LevelFilter::Debug,
-        "info" => log::LevelFilter::Info,
-        "warn" => log::LevelFilter::Warn,
-        "error" => log::LevelFilter::Error,
-        "off" => log::LevelFilter::Off,
-        _ => default,
+        "trace" => Some(log::LevelFilter::Trace),
+        "debug" => Some(log::LevelFilter::Debug),
+        "info" => Some(log::LevelFilter::Info),
+        "warn" => Some(log::LevelFilter::Warn),
+        "error" => Some(log::LevelFilter::Error),
+        "off" => Some(log::LevelFilter::Off),
+        _ => None,
     }
 }
 
-pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
+fn to_log_level(s: &str, default: log::LevelFilter) -> log::LevelFilter {
+    to_level(s).unwrap_or(default)
+}
+
+pub fn init_logger(cfg: &Cfg, output: OutputMode) -> log4rs::Handle {
+    init_logger_in(Path::new("log"), cfg, output)
+}
+
+/// Like `init_logger`, with the log files in `dir`.
+fn init_logger_in(dir: &Path, cfg: &Cfg, output: OutputMode) -> log4rs::Handle {
+    JSON_OUTPUT.store(output == OutputMode::Json, Ordering::Relaxed);
     let level_console = to_log_level(&cfg.console_log_level, log::LevelFilter::Info);
     let level_logfile = to_log_level(&cfg.logfile_log_level, log::LevelFilter::Warn);
     let mut console_log_pattern = if cfg.show_progress {
@@ -38,13 +210,18 @@ pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
     };
     logfile_log_pattern.push_str(&cfg.logfile_log_pattern);
 
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(&console_log_pattern)))
-        .build();
+    let console_encoder: Box<dyn Encode> = match output {
+        OutputMode::Human => Box::new(PatternEncoder::new(&console_log_pattern)),
+        OutputMode::Json => Box::new(JsonEncoder::new()),
+    };
+    let stdout = ConsoleAppender::builder().encoder(console_encoder).build();
 
     let roller = FixedWindowRoller::builder()
         .base(1)
-        .build("log/signum-miner.{}.log", cfg.logfile_max_count)
+        .build(
+            &dir.join("signum-miner.{}.log").to_string_lossy(),
+            cfg.logfile_max_count,
+        )
         .unwrap();
     let trigger = SizeTrigger::new(&cfg.logfile_max_size * 1024 * 1024);
     let policy = Box::new(CompoundPolicy::new(Box::new(trigger), Box::new(roller)));
@@ -53,25 +230,25 @@ pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
         Config::builder()
             .appender(
                 Appender::builder()
-                    .filter(Box::new(ThresholdFilter::new(level_console)))
+                    .filter(Box::new(ModuleFilter { level: level_console }))
                     .build("stdout", Box::new(stdout)),
             )
-            .build(Root::builder().appender("stdout").build(LevelFilter::Info))
+            .build(Root::builder().appender("stdout").build(LevelFilter::Trace))
             .unwrap()
     } else {
         let logfile = RollingFileAppender::builder()
             .encoder(Box::new(PatternEncoder::new(&logfile_log_pattern)))
-            .build("log/signum-miner.1.log", policy)
+            .build(dir.join("signum-miner.1.log"), policy)
             .unwrap();
         Config::builder()
             .appender(
                 Appender::builder()
-                    .filter(Box::new(ThresholdFilter::new(level_console)))
+                    .filter(Box::new(ModuleFilter { level: level_console }))
                     .build("stdout", Box::new(stdout)),
             )
             .appender(
                 Appender::builder()
-                    .filter(Box::new(ThresholdFilter::new(level_logfile)))
+                    .filter(Box::new(ModuleFilter { level: level_logfile }))
                     .build("logfile", Box::new(logfile)),
             )
             .build(
@@ -82,7 +259,13 @@ pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
             )
             .unwrap()
     };
-    log4rs::init_config(config).unwrap()
+    let handle = log4rs::init_config(config).unwrap();
+    match BASE_LEVEL.write() {
+        Ok(mut guard) => *guard = level_console.max(level_logfile),
+        Err(poisoned) => *poisoned.into_inner() = level_console.max(level_logfile),
+    }
+    set_module_levels(&cfg.log_levels);
+    handle
 }
 
 #[cfg(test)]
@@ -121,6 +304,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_module_levels() {
+        let mut levels = HashMap::new();
+        levels.insert("client".to_owned(), "debug".to_owned());
+        levels.insert("com::client".to_owned(), "trace".to_owned());
+        levels.insert("plot".to_owned(), "loud".to_owned());
+        let parsed = parse_module_levels(&levels);
+        let crate_name = env!("CARGO_CRATE_NAME");
+        assert_eq!(
+            parsed,
+            vec![
+                (format!("{}::com::client", crate_name), LevelFilter::Trace),
+                (format!("{}::requests", crate_name), LevelFilter::Debug),
+                (format!("{}::com", crate_name), LevelFilter::Debug),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_mode() {
+        assert_eq!(OutputMode::parse("JSON"), Some(OutputMode::Json));
+        assert_eq!(OutputMode::parse("human"), Some(OutputMode::Human));
+        assert_eq!(OutputMode::parse("xml"), None);
+    }
+
     #[test]
     fn test_init_logger() {
         use crate::config::load_cfg;
@@ -128,10 +336,12 @@ mod tests {
 
         cfg.console_log_level = log::LevelFilter::Error.to_string();
 
-        let _ = init_logger(&cfg);
+        let dir = std::env::temp_dir().join(format!("signum-miner-log-{}", std::process::id()));
+        let _ = init_logger_in(&dir, &cfg, OutputMode::Human);
 
         trace!("TRACE");
         debug!("DEBUG");
         info!("INFO");
+        let _ = fs::remove_dir_all(&dir);
     }
 }
@@ -0,0 +1,86 @@
+//! Constrained mode for single board computers.
+//!
+//! `low_memory` sets the miner up for boards with 512 MB of RAM mining a couple of USB drives: one
+//! hashing thread with a single small buffer, no GPU and metrics histories of a few entries.
+//! `memory_limit_mb` caps the memory: the read buffers are shrunk at startup to take at most half
+//! of it, and the resident memory is checked every minute, over the limit the metrics histories
+//! are dropped.
+
+use crate::config::Cfg;
+use crate::hardware;
+use crate::metrics::MinerMetrics;
+
+/// Read buffer in low memory mode, 1024 nonces.
+const SMALL_BUFFER: usize = 256 * 1024;
+/// Buffers stay a multiple of 64 nonces for direct I/O.
+const BUFFER_GRANULE: usize = 64 * 64;
+/// Entries kept per metrics history in low memory mode.
+const HISTORY: usize = 32;
+
+/// Applies the preset and fits the read buffers into the memory limit.
+pub fn apply(cfg: &mut Cfg) {
+    if cfg.low_memory {
+        cfg.cpu_threads = 1;
+        cfg.cpu_worker_task_count = 1;
+        cfg.io_buffer_size = cfg.io_buffer_size.min(SMALL_BUFFER);
+        cfg.gpu_threads = 0;
+        cfg.gpu_worker_task_count = 0;
+        if cfg.tokio_worker_threads == 0 {
+            cfg.tokio_worker_threads = 2;
+        }
+    }
+    if cfg.memory_limit_mb > 0 {
+        let budget = cfg.memory_limit_mb as usize * 1024 * 1024 / 2;
+        let buffers = (cfg.cpu_worker_task_count + cfg.cpu_threads).max(1);
+        let max = (budget / buffers / BUFFER_GRANULE * BUFFER_GRANULE).max(BUFFER_GRANULE);
+        if cfg.io_buffer_size > max {
+            warn!(
+                "{} read buffers of {} bytes exceed half of memory_limit_mb, using {} bytes",
+                buffers, cfg.io_buffer_size, max
+            );
+            cfg.io_buffer_size = max;
+        }
+    }
+}
+
+/// Trims the metrics histories in low memory mode and drops them over the memory limit.
+pub fn enforce(metrics: &mut MinerMetrics, low_memory: bool, limit_mb: u64) {
+    if low_memory {
+        metrics.truncate_histories(HISTORY);
+    }
+    if limit_mb == 0 {
+        return;
+    }
+    if let Some(resident) = hardware::resident_memory() {
+        if resident > limit_mb * 1024 * 1024 {
+            warn!(
+                "memory: {} MiB resident exceeds memory_limit_mb {}, dropping metrics histories",
+                resident / 1024 / 1024,
+                limit_mb
+            );
+            metrics.truncate_histories(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::validate_cfg;
+
+    #[test]
+    fn test_apply() {
+        let mut cfg: Cfg =
+            serde_yaml::from_str("low_memory: true\nurl: 'http://localhost'").unwrap();
+        apply(&mut cfg);
+        assert_eq!((cfg.cpu_threads, cfg.cpu_worker_task_count), (1, 1));
+        assert_eq!(cfg.io_buffer_size, SMALL_BUFFER);
+
+        let cfg: Cfg = serde_yaml::from_str(
+            "memory_limit_mb: 8\ncpu_threads: 1\ncpu_worker_task_count: 3\nurl: 'http://localhost'",
+        )
+        .unwrap();
+        let cfg = validate_cfg(cfg);
+        assert_eq!(cfg.io_buffer_size, 1024 * 1024);
+    }
+}
@@ -8,30 +8,107 @@ extern crate cfg_if;
 #[macro_use]
 extern crate log;
 
+mod account_stats;
+mod api_auth;
+mod api_proxy;
+mod audit;
+mod bench_hash;
+mod buffer_pool;
+mod cache_stats;
+mod cancel;
+mod capacity;
+mod chains;
+mod circuit_breaker;
 mod com;
 mod config;
 mod cpu_worker;
+mod deadline_format;
+mod deadline_stats;
+mod diagnose;
+mod difficulty;
+mod drive_labels;
+mod drive_toggles;
+mod energy;
+mod explorer;
+mod fault_injection;
+mod fd_pool;
+mod fleet;
 mod future;
+mod gpu_stats;
+mod gpu_verify;
+mod hardware;
+mod hooks;
+mod http_api;
+mod http_server;
+mod inventory;
+mod io_priority;
 mod logger;
+mod low_memory;
 mod metrics;
 mod miner;
+mod mlock;
+mod mockpool;
+mod openmetrics;
+mod page_cache;
+mod payouts;
 mod plot;
+mod plot_cipher;
+mod plot_order;
 mod poc_hashing;
+mod pool_slo;
+mod preflight;
+mod quota;
+mod read_verify;
 mod reader;
+mod remote_config;
 mod requests;
+mod rescan;
+mod retire;
+mod rotation;
+mod round_barrier;
+mod round_jitter;
+mod round_status;
+mod scheduler;
+mod scoops;
+mod seeded;
 mod shabal256;
+mod sparse;
+mod split_pools;
+mod stages;
+mod test_plot;
+mod topology;
+mod transfer_compression;
+mod upgrade;
 mod utils;
+mod winner;
 
 #[cfg(feature = "opencl")]
 mod gpu_worker;
 #[cfg(feature = "opencl")]
 mod gpu_worker_async;
+#[cfg(any(feature = "metal", feature = "wgpu"))]
+mod gpu_worker_host;
+#[cfg(feature = "metal")]
+mod mtl;
 #[cfg(feature = "opencl")]
 mod ocl;
+#[cfg(feature = "wgpu")]
+mod wgpu_backend;
 
-use crate::config::load_cfg;
-use crate::miner::Miner;
+#[cfg(any(
+    all(feature = "opencl", feature = "metal"),
+    all(feature = "opencl", feature = "wgpu"),
+    all(feature = "metal", feature = "wgpu")
+))]
+compile_error!("only one of the opencl, metal and wgpu features can be enabled");
+#[cfg(all(feature = "metal", not(target_os = "macos")))]
+compile_error!("the metal feature is only available on macOS");
+
+use crate::config::{build_runtime, load_cfgs, Cfg};
+use crate::logger::OutputMode;
+use crate::miner::{Miner, Workers};
 use clap::{Arg, Command};
+use std::collections::HashSet;
 #[cfg(feature = "opencl")]
 use std::process;
 
@@ -111,8 +188,7 @@ fn print_simd_support() {
 }
 
 
-#[tokio::main(flavor = "multi_thread")]
-async fn main() {
+fn main() {
     let cmd = Command::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
@@ -125,6 +201,257 @@ async fn main() {
                 .help("Location of the config file")
                 .default_value("config.yaml")
                 .required(false),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .value_name("MODE")
+                .help("Console output: human or json (one event per line, for front-ends)")
+                .value_parser(["human", "json"])
+                .default_value("human"),
+        )
+        .arg(
+            Arg::new("upgrade")
+                .long("upgrade")
+                .help("Resume the round saved by the previous process on a rolling upgrade (SIGUSR2)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Scan and compute deadlines as usual, but log and audit submissions instead of sending them")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .subcommand(
+            Command::new("diagnose")
+                .about("Collect hardware, config, logs and plots into an archive for bug reports")
+                .arg(
+                    Arg::new("archive")
+                        .value_name("FILE")
+                        .help("Location of the archive")
+                        .default_value("signum-miner-diagnose.tar"),
+                ),
+        )
+        .subcommand(
+            Command::new("rescan")
+                .about("Have a running miner rescan its plots now and print what changed")
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .value_name("PATH")
+                        .help("Only report the plots in this directory")
+                        .conflicts_with("drive"),
+                )
+                .arg(
+                    Arg::new("drive")
+                        .long("drive")
+                        .value_name("ID")
+                        .help("Only report the plots on this drive"),
+                )
+                .arg(
+                    Arg::new("context")
+                        .long("context")
+                        .value_name("NAME")
+                        .help("Mining context to rescan, the first one by default"),
+                ),
+        )
+        .subcommand(
+            Command::new("retire-plot")
+                .about("Take a plot out of a running miner, optionally deleting it after the round")
+                .arg(
+                    Arg::new("plot")
+                        .value_name("FILE")
+                        .help("Plot file or raw plot device")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("delete")
+                        .long("delete")
+                        .help("Delete the file once the running round is over")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("fetch-config")
+                .about("Fetch and verify the remote config overlay, used from the next start on"),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the effective config of every mining context as JSON, secrets redacted"),
+                ),
+        )
+        .subcommand(
+            Command::new("list-plots")
+                .about("Print every discovered plot with its drive, health and overlaps")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the inventory as JSON")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("bench-hash")
+                .about("Benchmark every hashing backend of this build in nonces per second")
+                .arg(
+                    Arg::new("secs")
+                        .long("secs")
+                        .value_name("SECONDS")
+                        .help("How long every backend is measured")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("3"),
+                )
+                .arg(
+                    Arg::new("pin")
+                        .long("pin")
+                        .help("Write the faster CPU backend to the config as cpu_hasher")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the results as JSON")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-reads")
+                .about("Compare direct I/O reads of random scoops with buffered reads")
+                .arg(
+                    Arg::new("scoops")
+                        .long("scoops")
+                        .value_name("SCOOPS")
+                        .help("Random scoops checked per plot")
+                        .value_parser(clap::value_parser!(u32))
+                        .default_value("4"),
+                ),
+        )
+        .subcommand(
+            Command::new("make-test-plot")
+                .about("Write a small plot with a golden file of its scoops, for reproducing read bugs")
+                .hide(true)
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Directory to write the plot to")
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::new("account")
+                        .long("account")
+                        .value_name("ACCOUNT")
+                        .help("Numeric account id")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("start-nonce")
+                        .long("start-nonce")
+                        .value_name("NONCE")
+                        .help("First nonce of the plot")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("nonces")
+                        .long("nonces")
+                        .value_name("NONCES")
+                        .help("Size of the plot in nonces (max 256)")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("8"),
+                )
+                .arg(
+                    Arg::new("poc1")
+                        .long("poc1")
+                        .help("Write an optimized PoC1 plot")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("encrypt")
+                        .long("encrypt")
+                        .help("Encrypt the plot with plot_encryption_key")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .help("Read the plot back through the miner's reader and compare it with the golden file")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("selftest")
+                .about("Mine a generated plot with a known best deadline and check the result")
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("Seed for the generation signature, base target and plot")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("nonces")
+                        .long("nonces")
+                        .value_name("NONCES")
+                        .help("Size of the generated plot in nonces (max 64)")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("8"),
+                ),
+        )
+        .subcommand(
+            Command::new("mockpool")
+                .about("Run a local pool serving a seeded round, for testing the miner")
+                .arg(
+                    Arg::new("listen")
+                        .long("listen")
+                        .value_name("ADDRESS")
+                        .help("Address to listen on")
+                        .default_value("127.0.0.1:8080"),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("Seed for the generation signature, base target and plot")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("nonces")
+                        .long("nonces")
+                        .value_name("NONCES")
+                        .help("Size of the generated plot in nonces (max 64)")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("8"),
+                )
+                .arg(
+                    Arg::new("script")
+                        .long("script")
+                        .value_name("SCRIPT")
+                        .help("Misbehavior to simulate")
+                        .value_parser(["normal", "slow", "reject", "gensig-change"])
+                        .default_value("normal"),
+                )
+                .arg(
+                    Arg::new("round-time")
+                        .long("round-time")
+                        .value_name("SECONDS")
+                        .help("Seconds until the next block")
+                        .value_parser(clap::value_parser!(u64).range(2..))
+                        .default_value("240"),
+                )
+                .arg(
+                    Arg::new("plot-dir")
+                        .long("plot-dir")
+                        .value_name("DIR")
+                        .help("Write the seeded plot here, so the expected best deadline is known"),
+                ),
         );
 
     #[cfg(feature = "opencl")]
@@ -136,33 +463,202 @@ async fn main() {
             .action(clap::ArgAction::SetTrue),
     );
 
+    #[cfg(feature = "metal")]
+    let cmd = cmd.arg(
+        Arg::new("metal")
+            .long("metal")
+            .help("Display Metal devices")
+            .action(clap::ArgAction::SetTrue),
+    );
+
+    #[cfg(feature = "wgpu")]
+    let cmd = cmd.arg(
+        Arg::new("wgpu")
+            .long("wgpu")
+            .help("Display wgpu adapters")
+            .action(clap::ArgAction::SetTrue),
+    );
+
     let matches = cmd.get_matches();
+    // resolved before the binary can be replaced for an upgrade
+    let exe = std::env::current_exe();
     let config = matches
         .get_one::<String>("config")
         .map(|s| s.as_str())
         .unwrap_or("config.yaml");
 
-    let cfg_loaded = match load_cfg(config) {
-        Ok(cfg) => cfg,
+    let output = matches
+        .get_one::<String>("output")
+        .and_then(|s| OutputMode::parse(s))
+        .unwrap_or(OutputMode::Human);
+
+    // the overlay has to be in the cache before the config is loaded. Only mining and
+    // fetch-config go to the network for it, the other subcommands use the cached one
+    let remote_config = if matches!(matches.subcommand_name(), None | Some("fetch-config")) {
+        remote_config::refresh(config)
+    } else {
+        Ok(None)
+    };
+
+    let mut cfgs = match load_cfgs(config) {
+        Ok(cfgs) => cfgs,
         Err(e) => {
             eprintln!("❌ Configuration Error: {}", e);
             eprintln!("\nPlease create a valid config.yaml file or specify a different config file with --config");
             std::process::exit(1);
         }
     };
-    logger::init_logger(&cfg_loaded);
+    if output == OutputMode::Json {
+        // the bar would corrupt the event stream
+        for cfg in &mut cfgs {
+            cfg.show_progress = false;
+        }
+    }
+    let dry_run = matches.get_flag("dry-run");
+    if dry_run {
+        for cfg in &mut cfgs {
+            cfg.dry_run = true;
+            // what would have been submitted is the point of a dry run
+            if cfg.audit_log_dir.is_none() {
+                cfg.audit_log_dir = Some(std::path::PathBuf::from("dry-run"));
+            }
+        }
+    }
+    // one id per machine, shared by all mining contexts. Only mining stores a new one, the
+    // subcommands make do with a temporary id until then
+    let miner_id = if matches.subcommand_name().is_none() {
+        fleet::load_or_create_id(&cfgs[0].miner_id_file).map(Some)
+    } else {
+        fleet::load_id(&cfgs[0].miner_id_file).map(|id| id.map(|id| (id, false)))
+    };
+    let id = match &miner_id {
+        Ok(Some((id, _))) => id.clone(),
+        _ => fleet::new_id(),
+    };
+    for cfg in &mut cfgs {
+        cfg.miner_id = id.clone();
+    }
+    // stdout is only the JSON, before the logger writes to it
+    if let Some(config_cmd) = matches.subcommand_matches("config") {
+        if config_cmd.subcommand_matches("show").is_some() {
+            let contexts: Vec<_> = cfgs.iter().map(diagnose::effective_config).collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&contexts).unwrap_or_default()
+            );
+            std::process::exit(0);
+        }
+    }
+    let cfg_loaded = &cfgs[0];
+    logger::init_logger(cfg_loaded, output);
+    deadline_format::set_deadline_format(cfg_loaded.deadline_format);
+    if let Some(chain) = chains::resolve(&cfg_loaded.chains, &cfg_loaded.chain) {
+        deadline_format::set_block_time(chain.block_time);
+    }
+    fd_pool::set_limit(cfg_loaded.max_open_files);
+    page_cache::set_mode(cfg_loaded.page_cache);
+    cpu_worker::set_cpu_hasher(cfg_loaded.cpu_hasher);
 
     info!(
         "{} v{}",
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION")
     );
+    match miner_id {
+        Ok(Some((id, true))) => info!("miner id: {} (new)", id),
+        Ok(Some((id, false))) => info!("miner id: {}", id),
+        Ok(None) => {}
+        Err(e) => warn!(
+            "can't keep the miner id in {}: {}, using {} until the next start",
+            cfg_loaded.miner_id_file.display(),
+            e,
+            cfg_loaded.miner_id
+        ),
+    }
     
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    print_simd_support();
+    if output == OutputMode::Human {
+        print_simd_support();
+    }
+
+    if dry_run {
+        info!(
+            "dry run: nothing is submitted, would-be submissions go to {}",
+            cfg_loaded
+                .audit_log_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default()
+        );
+    }
+
+    match &remote_config {
+        Ok(Some(url)) => info!("remote config: overlay from {} verified and applied", url),
+        Ok(None) => {}
+        Err(e) => warn!("remote config: {}, keeping the last verified overlay, if any", e),
+    }
+    if matches.subcommand_matches("fetch-config").is_some() {
+        std::process::exit(match remote_config {
+            Ok(Some(_)) => 0,
+            Ok(None) => {
+                error!("fetch-config: no remote_config section in {}", config);
+                1
+            }
+            Err(_) => 1,
+        });
+    }
+
+    if let Some(retire_plot) = matches.subcommand_matches("retire-plot") {
+        let plot = retire_plot
+            .get_one::<String>("plot")
+            .map(|s| s.as_str())
+            .unwrap_or_default();
+        let delete = retire_plot.get_flag("delete");
+        match retire::append(&cfg_loaded.retire_list, std::path::Path::new(plot), delete) {
+            Ok(()) => {
+                info!(
+                    "{} added to {}, a running miner drops it within seconds{}",
+                    plot,
+                    cfg_loaded.retire_list.display(),
+                    if delete { " and deletes it after the round" } else { "" }
+                );
+                std::process::exit(0);
+            }
+            Err(e) => {
+                error!("retire-plot: can't retire {}: {}", plot, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(list_plots) = matches.subcommand_matches("list-plots") {
+        inventory::list_plots(
+            cfg_loaded,
+            list_plots.get_flag("json") || output == OutputMode::Json,
+        );
+        std::process::exit(0);
+    }
+
+    if let Some(diagnose) = matches.subcommand_matches("diagnose") {
+        let output = diagnose
+            .get_one::<String>("archive")
+            .map(|s| s.as_str())
+            .unwrap_or("signum-miner-diagnose.tar");
+        match diagnose::run(config, cfg_loaded, std::path::Path::new(output)) {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                error!("diagnose: can't write {}: {}", output, e);
+                std::process::exit(1);
+            }
+        }
+    }
 
     #[cfg(feature = "opencl")]
     info!("GPU extensions: OpenCL");
+    #[cfg(feature = "metal")]
+    info!("GPU extensions: Metal");
+    #[cfg(feature = "wgpu")]
+    info!("GPU extensions: wgpu");
 
     #[cfg(feature = "opencl")]
     if matches.contains_id("opencl") {
@@ -170,6 +666,18 @@ async fn main() {
         process::exit(0);
     }
 
+    #[cfg(feature = "metal")]
+    if matches.get_flag("metal") {
+        mtl::platform_info();
+        std::process::exit(0);
+    }
+
+    #[cfg(feature = "wgpu")]
+    if matches.get_flag("wgpu") {
+        wgpu_backend::platform_info();
+        std::process::exit(0);
+    }
+
     #[cfg(any(
         feature = "simd_avx512f",
         feature = "simd_avx2",
@@ -179,10 +687,283 @@ async fn main() {
     ))]
     init_cpu_extensions();
 
+    if let Some(bench) = matches.subcommand_matches("bench-hash") {
+        let secs = bench.get_one::<u64>("secs").copied().unwrap_or(3);
+        let passed = bench_hash::bench_hash(
+            cfg_loaded,
+            config,
+            std::time::Duration::from_secs(secs.max(1)),
+            bench.get_flag("pin"),
+            bench.get_flag("json") || output == OutputMode::Json,
+        );
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     #[cfg(feature = "opencl")]
-    ocl::gpu_info(&cfg_loaded);
+    ocl::gpu_info(cfg_loaded);
+    #[cfg(feature = "metal")]
+    mtl::gpu_info(cfg_loaded);
+    #[cfg(feature = "wgpu")]
+    wgpu_backend::gpu_info(cfg_loaded);
+
+    logger::watch_log_levels(config.to_owned(), cfg_loaded.log_levels.clone());
 
+    // the runtime is shared by all mining contexts
+    let runtime = match build_runtime(&cfgs) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("can't start tokio runtime: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(selftest) = matches.subcommand_matches("selftest") {
+        let seed = selftest.get_one::<u64>("seed").copied().unwrap_or(1);
+        let nonces = selftest.get_one::<u64>("nonces").copied().unwrap_or(8);
+        let dir = std::env::temp_dir().join("signum-miner-selftest");
+        let passed = runtime.block_on(seeded::selftest(
+            seed,
+            nonces,
+            &dir,
+            cfg_loaded.cpu_threads,
+        ));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(make_test_plot) = matches.subcommand_matches("make-test-plot") {
+        let encryption_key = if make_test_plot.get_flag("encrypt") {
+            match &cfg_loaded.plot_encryption_key {
+                Some(key) => Some(key.clone()),
+                None => {
+                    error!("make-test-plot: --encrypt needs plot_encryption_key in the config");
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            None
+        };
+        let plot = test_plot::TestPlot {
+            account_id: make_test_plot.get_one::<u64>("account").copied().unwrap_or(1),
+            start_nonce: make_test_plot
+                .get_one::<u64>("start-nonce")
+                .copied()
+                .unwrap_or(0),
+            nonces: make_test_plot
+                .get_one::<u64>("nonces")
+                .copied()
+                .unwrap_or(8)
+                .clamp(1, test_plot::MAX_NONCES),
+            layout: if make_test_plot.get_flag("poc1") {
+                test_plot::Layout::Poc1
+            } else {
+                test_plot::Layout::Poc2
+            },
+            encryption_key,
+        };
+        let dir = make_test_plot
+            .get_one::<String>("dir")
+            .map(|s| s.as_str())
+            .unwrap_or(".");
+        let path = match plot.write(std::path::Path::new(dir)) {
+            Ok(path) => path,
+            Err(e) => {
+                error!("make-test-plot: can't write to {}: {}", dir, e);
+                std::process::exit(1);
+            }
+        };
+        info!("make-test-plot: wrote {}", path.display());
+        if make_test_plot.get_flag("verify") {
+            match runtime.block_on(test_plot::verify_golden(
+                &path,
+                cfg_loaded.hdd_use_direct_io,
+                plot.encryption_key.as_deref(),
+            )) {
+                Ok(differing) if differing.is_empty() => {
+                    info!("make-test-plot: every scoop reads as expected")
+                }
+                Ok(differing) => {
+                    error!(
+                        "make-test-plot: {} scoops read wrong, first {:?}",
+                        differing.len(),
+                        &differing[..differing.len().min(10)]
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    error!("make-test-plot: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        std::process::exit(0);
+    }
+    if let Some(rescan_cmd) = matches.subcommand_matches("rescan") {
+        let Some(api_listen) = cfg_loaded.api_listen.as_deref() else {
+            error!("rescan: the running miner is reached through its api, set api_listen");
+            std::process::exit(1);
+        };
+        let scope = match (
+            rescan_cmd.get_one::<String>("dir"),
+            rescan_cmd.get_one::<String>("drive"),
+        ) {
+            (Some(dir), _) => rescan::RescanScope::Dir(dir.into()),
+            (None, Some(drive)) => rescan::RescanScope::Drive(drive.clone()),
+            (None, None) => rescan::RescanScope::All,
+        };
+        let context = rescan_cmd.get_one::<String>("context").map(|s| s.as_str());
+        match runtime.block_on(rescan::request(cfg_loaded, api_listen, context, &scope)) {
+            Ok(report) => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).unwrap_or_default()
+                );
+                std::process::exit(0);
+            }
+            Err(e) => {
+                error!("rescan: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(verify_reads) = matches.subcommand_matches("verify-reads") {
+        let scoops = verify_reads.get_one::<u32>("scoops").copied().unwrap_or(4);
+        let passed = runtime.block_on(read_verify::run(cfg_loaded, scoops.max(1)));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    if let Some(mockpool) = matches.subcommand_matches("mockpool") {
+        let settings = mockpool::Settings {
+            listen: mockpool
+                .get_one::<String>("listen")
+                .cloned()
+                .unwrap_or_default(),
+            seed: mockpool.get_one::<u64>("seed").copied().unwrap_or(1),
+            nonces: mockpool.get_one::<u64>("nonces").copied().unwrap_or(8),
+            script: mockpool
+                .get_one::<String>("script")
+                .and_then(|s| mockpool::Script::parse(s))
+                .unwrap_or(mockpool::Script::Normal),
+            round_time: std::time::Duration::from_secs(
+                mockpool.get_one::<u64>("round-time").copied().unwrap_or(240),
+            ),
+            plot_dir: mockpool
+                .get_one::<String>("plot-dir")
+                .map(std::path::PathBuf::from),
+        };
+        if let Err(e) = runtime.block_on(mockpool::run(settings)) {
+            error!("mockpool: {}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+    if matches.get_flag("upgrade") {
+        upgrade::resume(&cfgs[0].upgrade_state_file);
+    }
+    match exe {
+        Ok(exe) => {
+            runtime.spawn(upgrade::listen(exe, cfgs[0].upgrade_state_file.clone()));
+        }
+        Err(e) => warn!("upgrade: can't locate the executable, SIGUSR2 disabled: {}", e),
+    }
+    // USB enclosures may still be enumerating after a reboot
+    let wait_for: Vec<String> = cfgs
+        .iter()
+        .flat_map(|cfg| cfg.wait_for_drives.iter().cloned())
+        .collect();
+    let wait_timeout = cfgs
+        .iter()
+        .map(|cfg| cfg.wait_for_drives_timeout)
+        .max()
+        .unwrap_or(0);
+    drive_labels::wait_for_drives(&wait_for, std::time::Duration::from_secs(wait_timeout));
+    // the other contexts had their plot dirs checked while loading
+    for cfg in cfgs.iter_mut().filter(|cfg| !cfg.wait_for_drives.is_empty()) {
+        config::filter_plot_dirs(cfg);
+    }
+    runtime.block_on(run(cfgs));
+}
+
+async fn run(cfgs: Vec<Cfg>) {
+    for cfg in &cfgs {
+        if let Err(e) = chains::validate_chains(cfg).await {
+            error!("❌ {}", e);
+            std::process::exit(1);
+        }
+        if let Err(failures) = preflight::preflight(cfg).await {
+            error!(
+                "❌ preflight: {} problem(s) with the pools of {}, not starting (preflight: strict)",
+                failures, cfg.name
+            );
+            std::process::exit(1);
+        }
+    }
+
+    if cfgs.len() > 1 {
+        info!("running {} mining contexts", cfgs.len());
+    }
+    if let Some(fleet_cfg) = cfgs[0].fleet.clone() {
+        let registration = fleet::Registration {
+            miner_id: cfgs[0].miner_id.clone(),
+            hostname: hostname::get()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            version: env!("CARGO_PKG_VERSION"),
+            contexts: cfgs.iter().map(|cfg| cfg.name.clone()).collect(),
+            uptime: 0,
+        };
+        tokio::spawn(fleet::register(fleet_cfg, cfgs[0].timeout, registration));
+    }
+    let configs: Vec<_> = cfgs.iter().map(diagnose::effective_config).collect();
+    for config in &configs {
+        info!("effective config: {}", config);
+    }
     let handle = tokio::runtime::Handle::current();
-    let miner = Miner::new(cfg_loaded, handle);
-    miner.run().await;
+    let api_listen = cfgs[0].api_listen.clone();
+    let api_auth = api_auth::ApiAuth::new(
+        cfgs[0].api_read_tokens.clone(),
+        cfgs[0].api_admin_tokens.clone(),
+    );
+    let api_proxy = api_proxy::ApiProxy::new(
+        cfgs[0].api_base_path.as_deref().unwrap_or_default(),
+        cfgs[0].api_cors_origins.clone(),
+        cfgs[0].api_trusted_proxies.clone(),
+    );
+    let api_tls = match (&cfgs[0].api_tls_cert, &cfgs[0].api_tls_key) {
+        (Some(cert), Some(key)) => Some(http_server::tls_acceptor(cert, key)),
+        (None, None) => None,
+        _ => Some(Err(std::io::Error::other(
+            "api_tls_cert and api_tls_key are set together",
+        ))),
+    };
+    let scans: Vec<_> = cfgs.iter().map(miner::scan).collect();
+    let drive_count = scans
+        .iter()
+        .flat_map(|scan| scan.drive_id_to_plots.keys())
+        .collect::<HashSet<_>>()
+        .len();
+    // one set of buffers, readers and hash workers for all contexts, set up from the top level
+    let workers = Workers::new(&cfgs[0], drive_count);
+    let miners: Vec<Miner> = cfgs
+        .into_iter()
+        .zip(scans)
+        .map(|(cfg, scan)| Miner::new(cfg, scan, &workers, handle.clone()))
+        .collect();
+    if let Some(listen) = api_listen {
+        let api = http_api::Api {
+            rounds: miners.iter().map(|miner| miner.round_status()).collect(),
+            rotations: miners.iter().map(|miner| miner.rotation()).collect(),
+            metrics: miners.iter().map(|miner| miner.metrics()).collect(),
+            rescans: miners.iter().map(|miner| miner.rescans()).collect(),
+            drives: miners.iter().map(|miner| miner.drive_toggles()).collect(),
+            hardware: miners.iter().map(|miner| miner.hardware()).collect(),
+            config: serde_json::Value::Array(configs),
+            auth: api_auth,
+            proxy: api_proxy,
+        };
+        match api_tls.transpose() {
+            Ok(tls) => {
+                tokio::spawn(http_api::run(listen, tls, api));
+            }
+            // never fall back to plain HTTP for an API meant to be served over HTTPS
+            Err(e) => error!("api: can't set up TLS, not serving the api: {}", e),
+        }
+    }
+    futures::future::join_all(miners.into_iter().map(|miner| miner.run())).await;
 }
@@ -1,11 +1,32 @@
-use std::collections::HashMap;
+use crate::account_stats::{AccountHealth, AccountStats, AccountSummary};
+use crate::audit::{backfill, now_ms};
+use crate::buffer_pool::{BufferCounters, BufferPoolStats};
+use crate::cache_stats::{self, DriveCache};
+use crate::circuit_breaker::{BreakerCfg, CircuitBreaker, Transition};
+use crate::com::api::RejectionReason;
+use crate::deadline_format::format_deadline;
+use crate::difficulty::DifficultyHistory;
+use crate::energy::{EnergyCfg, EnergyMeter};
+use crate::gpu_stats::GpuStats;
+use crate::payouts::PoolBalance;
+use crate::pool_slo::PoolSlo;
+use crate::scoops::ScoopHistory;
+use crate::stages::StageBreakdown;
+use crate::winner::WinnerStats;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 #[cfg(feature = "async_io")]
 use tokio::sync::RwLock;
 #[cfg(not(feature = "async_io"))]
 use std::sync::RwLock;
 
+/// Number of latency samples kept per pool endpoint
+const POOL_LATENCY_HISTORY: usize = 100;
+/// Columns of the scoop heatmap, 64 scoops each
+const SCOOP_HEATMAP_WIDTH: usize = 64;
+
 /// Comprehensive metrics tracking for the miner
 /// Some fields and methods are intentionally kept for future monitoring/debugging use
 #[allow(dead_code)]
@@ -13,14 +34,22 @@ use std::sync::RwLock;
 pub struct MinerMetrics {
     /// Time when miner started
     start_time: Instant,
+    /// Stable id of this machine, see `fleet`
+    pub miner_id: String,
     /// Total number of nonce submissions attempted
     pub total_submissions: u64,
     /// Total number of successful submissions
     pub successful_submissions: u64,
     /// Total number of failed submissions
     pub failed_submissions: u64,
+    /// Submissions refused by the pool per reason
+    pub rejections_by_reason: BTreeMap<RejectionReason, u64>,
+    /// Submissions dropped because their round was already over
+    pub stale_submissions: u64,
     /// Best deadline ever achieved (per account)
     pub best_deadlines: HashMap<u64, u64>,
+    /// Submissions and estimated capacity per account
+    pub accounts: BTreeMap<u64, AccountStats>,
     /// Total rounds completed
     pub rounds_completed: u64,
     /// Total rounds failed
@@ -37,8 +66,34 @@ pub struct MinerMetrics {
     pub last_submission: Option<Instant>,
     /// Average round time in milliseconds
     pub avg_round_time_ms: f64,
+    /// Where the last completed round spent its time
+    pub last_round_stages: Option<StageBreakdown>,
+    /// Scoops of the recently completed rounds
+    pub scoops: ScoopHistory,
+    /// Base targets of the recent blocks
+    pub difficulty: DifficultyHistory,
     /// Total bytes read
     pub total_bytes_read: u64,
+    /// Recent latency samples per pool endpoint in milliseconds
+    pub pool_latencies_ms: HashMap<String, VecDeque<u64>>,
+    /// Failed latency probes per pool endpoint
+    pub pool_probe_failures: HashMap<String, u64>,
+    /// Capacity in GB each pool endpoint last saw in the headers
+    pub pool_declared_gb: HashMap<String, usize>,
+    /// Submission round trips and availability per pool
+    pub pool_slo: PoolSlo,
+    /// Balances and shares reported by the pool per account
+    pub pool_balances: HashMap<u64, PoolBalance>,
+    /// Blocks forged by one of the mined accounts
+    pub blocks_won: u64,
+    /// Best deadlines against the winners' of the blocks lost
+    pub winners: WinnerStats,
+    /// Live buffer pool counters
+    pub buffers: Option<Arc<BufferCounters>>,
+    /// Live counters of every GPU worker
+    pub gpus: Vec<Arc<GpuStats>>,
+    /// Estimated energy cost, if a price schedule is configured
+    pub energy: Option<EnergyMeter>,
 }
 
 #[allow(dead_code)]
@@ -46,10 +101,14 @@ impl MinerMetrics {
     pub fn new() -> Self {
         Self {
             start_time: Instant::now(),
+            miner_id: String::new(),
             total_submissions: 0,
             successful_submissions: 0,
             failed_submissions: 0,
+            rejections_by_reason: BTreeMap::new(),
+            stale_submissions: 0,
             best_deadlines: HashMap::new(),
+            accounts: BTreeMap::new(),
             rounds_completed: 0,
             rounds_failed: 0,
             io_errors_by_drive: HashMap::new(),
@@ -58,7 +117,20 @@ impl MinerMetrics {
             network_errors: 0,
             last_submission: None,
             avg_round_time_ms: 0.0,
+            last_round_stages: None,
+            scoops: ScoopHistory::default(),
+            difficulty: DifficultyHistory::default(),
             total_bytes_read: 0,
+            pool_latencies_ms: HashMap::new(),
+            pool_probe_failures: HashMap::new(),
+            pool_declared_gb: HashMap::new(),
+            pool_slo: PoolSlo::default(),
+            pool_balances: HashMap::new(),
+            blocks_won: 0,
+            winners: WinnerStats::default(),
+            buffers: None,
+            gpus: Vec::new(),
+            energy: None,
         }
     }
 
@@ -87,12 +159,46 @@ impl MinerMetrics {
         if deadline < *current_best {
             *current_best = deadline;
         }
+        self.accounts.entry(account_id).or_default().record_accepted(deadline);
     }
 
-    /// Record a failed submission
-    pub fn record_submission_failure(&mut self) {
+    /// Record a submission the pool didn't answer
+    pub fn record_submission_failure(&mut self, account_id: u64) {
         self.total_submissions += 1;
         self.failed_submissions += 1;
+        self.accounts.entry(account_id).or_default().record_failed();
+    }
+
+    /// Record a submission refused by the pool
+    pub fn record_rejection(&mut self, account_id: u64, reason: RejectionReason) {
+        self.total_submissions += 1;
+        self.failed_submissions += 1;
+        *self.rejections_by_reason.entry(reason).or_insert(0) += 1;
+        // a busy pool says nothing about the account, it's retried
+        let account = self.accounts.entry(account_id).or_default();
+        if reason == RejectionReason::RateLimited {
+            account.record_failed();
+        } else {
+            account.record_rejected();
+        }
+    }
+
+    /// Record a submission dropped before sending because a new round started
+    pub fn record_stale_submission(&mut self, account_id: u64) {
+        self.stale_submissions += 1;
+        self.accounts.entry(account_id).or_default().record_stale();
+    }
+
+    /// Record the best raw deadline of every account in a completed round
+    pub fn record_account_rounds(
+        &mut self,
+        account_id_to_round_best: &HashMap<u64, u64>,
+        account_id_to_nonces: &HashMap<u64, u64>,
+    ) {
+        for (account_id, &best) in account_id_to_round_best {
+            let nonces = account_id_to_nonces.get(account_id).copied().unwrap_or(0);
+            self.accounts.entry(*account_id).or_default().record_round(best, nonces);
+        }
     }
 
     /// Record a completed round
@@ -104,6 +210,16 @@ impl MinerMetrics {
         self.avg_round_time_ms = alpha * duration_ms as f64 + (1.0 - alpha) * self.avg_round_time_ms;
     }
 
+    /// Record the stage times of a completed round
+    pub fn record_round_stages(&mut self, stages: StageBreakdown) {
+        self.last_round_stages = Some(stages);
+    }
+
+    /// Record the scoop a completed round was mined on
+    pub fn record_scoop(&mut self, scoop: u32) {
+        self.scoops.record(scoop);
+    }
+
     /// Record a failed round
     pub fn record_round_failure(&mut self) {
         self.rounds_failed += 1;
@@ -130,6 +246,70 @@ impl MinerMetrics {
         self.total_bytes_read += bytes;
     }
 
+    /// Record the result of a latency probe against a pool endpoint
+    pub fn record_pool_latency(&mut self, url: &str, latency_ms: Option<u64>, declared_gb: Option<usize>) {
+        if let Some(declared_gb) = declared_gb {
+            self.pool_declared_gb.insert(url.to_string(), declared_gb);
+        }
+        match latency_ms {
+            Some(latency_ms) => {
+                let history = self.pool_latencies_ms.entry(url.to_string()).or_default();
+                history.push_back(latency_ms);
+                while history.len() > POOL_LATENCY_HISTORY {
+                    history.pop_front();
+                }
+            }
+            None => *self.pool_probe_failures.entry(url.to_string()).or_insert(0) += 1,
+        }
+    }
+
+    /// Record the round trip of a submission, `available` if the pool answered it
+    pub fn record_submission_latency(
+        &mut self,
+        pool: &str,
+        latency_ms: u64,
+        available: bool,
+        submission: (u64, String),
+    ) {
+        self.pool_slo
+            .record(pool, unix_secs(), latency_ms, available, Some(submission));
+    }
+
+    /// Record a block forged by one of the mined accounts
+    pub fn record_block_won(&mut self) {
+        self.blocks_won += 1;
+    }
+
+    /// Cut every history down to its `keep` most recent entries
+    pub fn truncate_histories(&mut self, keep: usize) {
+        self.scoops.truncate(keep);
+        self.difficulty.truncate(keep);
+        self.winners.truncate(keep);
+        self.pool_slo.truncate(keep);
+        for history in self.pool_latencies_ms.values_mut() {
+            while history.len() > keep {
+                history.pop_front();
+            }
+        }
+    }
+
+    /// Record the base target of a new block
+    pub fn record_block(&mut self, height: u64, base_target: u64) {
+        self.difficulty.record(height, unix_secs(), base_target);
+    }
+
+    /// Charge the energy used since the last completed round
+    pub fn record_energy(&mut self) {
+        if let Some(energy) = &mut self.energy {
+            energy.charge(unix_secs());
+        }
+    }
+
+    /// Record the balance the pool reports for an account
+    pub fn record_pool_balance(&mut self, account_id: u64, balance: PoolBalance) {
+        self.pool_balances.insert(account_id, balance);
+    }
+
     /// Get submission success rate
     pub fn submission_success_rate(&self) -> f64 {
         if self.total_submissions == 0 {
@@ -159,27 +339,180 @@ impl MinerMetrics {
         }
     }
 
+    /// Counters and the summary for `/api/status`.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            uptime_secs: self.start_time.elapsed().as_secs(),
+            rounds_completed: self.rounds_completed,
+            rounds_failed: self.rounds_failed,
+            total_submissions: self.total_submissions,
+            successful_submissions: self.successful_submissions,
+            failed_submissions: self.failed_submissions,
+            stale_submissions: self.stale_submissions,
+            total_io_errors: self.total_io_errors,
+            network_errors: self.network_errors,
+            blocks_won: self.blocks_won,
+            avg_round_time_ms: self.avg_round_time_ms,
+            avg_read_speed_mibs: self.avg_read_speed_mibs(),
+            buffers: self.buffers.as_ref().map(|buffers| buffers.stats()),
+            accounts: self
+                .accounts
+                .iter()
+                .map(|(&account_id, stats)| (account_id, stats.summary()))
+                .collect(),
+            page_cache: cache_stats::snapshot(),
+            summary: self.summary(),
+        }
+    }
+
     /// Get formatted metrics summary
     pub fn summary(&self) -> String {
         let mut summary = String::new();
         summary.push_str("=== MINER METRICS ===\n");
+        if !self.miner_id.is_empty() {
+            summary.push_str(&format!("Miner ID: {}\n", self.miner_id));
+        }
         summary.push_str(&format!("Uptime: {}\n", self.uptime_formatted()));
         summary.push_str(&format!("Rounds: {} completed, {} failed ({:.1}% success)\n",
             self.rounds_completed, self.rounds_failed, self.round_success_rate()));
         summary.push_str(&format!("Avg Round Time: {:.0}ms\n", self.avg_round_time_ms));
+        if let Some(stages) = &self.last_round_stages {
+            summary.push_str(&format!("Last Round Stages: {}\n", stages));
+            if let Some(bottleneck) = stages.bottleneck() {
+                summary.push_str(&format!("Bottleneck: {}\n", bottleneck.as_str()));
+            }
+        }
+        if !self.scoops.is_empty() {
+            summary.push_str(&format!(
+                "Scoop Heatmap (last {} rounds): |{}|\n",
+                self.scoops.len(),
+                self.scoops.heatmap(SCOOP_HEATMAP_WIDTH)
+            ));
+        }
+        if let Some(winners) = self.winners.summary() {
+            summary.push_str(&format!("Distance to Winner: {}\n", winners));
+        }
+        if let Some(difficulty) = self.difficulty.summary(unix_secs()) {
+            summary.push_str(&format!("Network Difficulty: {}\n", difficulty));
+        }
         summary.push_str(&format!("Submissions: {} total, {} successful, {} failed ({:.1}% success)\n",
             self.total_submissions, self.successful_submissions, self.failed_submissions,
             self.submission_success_rate()));
+        if !self.rejections_by_reason.is_empty() {
+            let rejections: Vec<String> = self
+                .rejections_by_reason
+                .iter()
+                .map(|(reason, count)| format!("{}={}", reason.as_str(), count))
+                .collect();
+            summary.push_str(&format!("Rejections: {}\n", rejections.join(", ")));
+        }
+        if self.stale_submissions > 0 {
+            summary.push_str(&format!("Stale Submissions Dropped: {}\n", self.stale_submissions));
+        }
+        if !self.accounts.is_empty() {
+            summary.push_str("Accounts:\n");
+            for (account_id, stats) in &self.accounts {
+                summary.push_str(&format!("  Account {}: {}\n", account_id, account_line(stats)));
+            }
+        }
+        if let Some(buffers) = &self.buffers {
+            summary.push_str(&format!("Buffers: {}\n", buffers.stats()));
+        }
+        for gpu in self.gpus.iter().map(|gpu| gpu.snapshot()) {
+            summary.push_str(&format!(
+                "GPU {}: {:.0} nonces/s, queue {}, {} kernel errors, {}/{} verified chunks wrong, \
+                 last batch {}{}\n",
+                gpu.device,
+                gpu.nonces_per_sec,
+                gpu.queue_depth,
+                gpu.kernel_errors,
+                gpu.mismatches,
+                gpu.verified,
+                gpu.last_batch_secs_ago
+                    .map_or_else(|| "never".to_owned(), |secs| format!("{}s ago", secs)),
+                if gpu.healthy { "" } else { " (UNHEALTHY, no results)" }
+            ));
+        }
+        let page_cache = cache_stats::summary();
+        if !page_cache.is_empty() {
+            summary.push_str("Page Cache Reads (speeds overstate the disks):\n");
+            summary.push_str(&page_cache);
+        }
         summary.push_str(&format!("Data Read: {:.2} TiB (avg {:.2} MiB/s)\n",
             self.total_bytes_read as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0,
             self.avg_read_speed_mibs()));
         summary.push_str(&format!("I/O Errors: {} total\n", self.total_io_errors));
         summary.push_str(&format!("Network Errors: {}\n", self.network_errors));
+        summary.push_str(&format!("Blocks Won: {}\n", self.blocks_won));
+
+        let mut pools: Vec<&String> = self
+            .pool_latencies_ms
+            .keys()
+            .chain(self.pool_probe_failures.keys())
+            .collect();
+        pools.sort();
+        pools.dedup();
+        if !pools.is_empty() {
+            summary.push_str("Pool Latency:\n");
+            for url in pools {
+                let failures = self.pool_probe_failures.get(url).copied().unwrap_or(0);
+                let declared = self
+                    .pool_declared_gb
+                    .get(url)
+                    .map(|gb| format!(", declared {}GB", gb))
+                    .unwrap_or_default();
+                match self.pool_latencies_ms.get(url).filter(|h| !h.is_empty()) {
+                    Some(history) => summary.push_str(&format!(
+                        "  {}: avg {}ms, last {}ms, {} failed probes{}\n",
+                        url,
+                        history.iter().sum::<u64>() / history.len() as u64,
+                        history.back().unwrap(),
+                        failures,
+                        declared
+                    )),
+                    None => summary.push_str(&format!(
+                        "  {}: unreachable, {} failed probes{}\n",
+                        url, failures, declared
+                    )),
+                }
+            }
+        }
+
+        let slo = self.pool_slo.summary(unix_secs());
+        if !slo.is_empty() {
+            summary.push_str("Pool Submissions:\n");
+            summary.push_str(&slo);
+        }
+
+        if !self.pool_balances.is_empty() {
+            let fmt = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_owned());
+            summary.push_str("Pool Balances:\n");
+            let mut account_ids: Vec<&u64> = self.pool_balances.keys().collect();
+            account_ids.sort();
+            for account_id in account_ids {
+                let balance = &self.pool_balances[account_id];
+                summary.push_str(&format!(
+                    "  Account {}: pending {}, paid {}, shares {}\n",
+                    account_id,
+                    fmt(balance.pending),
+                    fmt(balance.paid),
+                    fmt(balance.shares)
+                ));
+            }
+        }
+
+        if let Some(energy) = &self.energy {
+            summary.push_str(&format!("Energy Cost: {}\n", energy.summary(unix_secs())));
+        }
 
         if !self.best_deadlines.is_empty() {
             summary.push_str("Best Deadlines:\n");
             for (account_id, deadline) in &self.best_deadlines {
-                summary.push_str(&format!("  Account {}: {} seconds\n", account_id, deadline));
+                summary.push_str(&format!(
+                    "  Account {}: {}\n",
+                    account_id,
+                    format_deadline(*deadline)
+                ));
             }
         }
 
@@ -195,10 +528,15 @@ impl MinerMetrics {
         };
 
         let round_failure_rate = 100.0 - self.round_success_rate();
+        let stalled_gpu = self.gpus.iter().any(|gpu| !gpu.snapshot().healthy);
 
         if io_error_rate > 5.0 || round_failure_rate > 20.0 || self.network_errors > 100 {
             HealthStatus::Critical
-        } else if io_error_rate > 1.0 || round_failure_rate > 10.0 || self.network_errors > 50 {
+        } else if io_error_rate > 1.0
+            || round_failure_rate > 10.0
+            || self.network_errors > 50
+            || stalled_gpu
+        {
             HealthStatus::Warning
         } else {
             HealthStatus::Healthy
@@ -220,12 +558,111 @@ pub enum HealthStatus {
     Critical,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    pub uptime_secs: u64,
+    pub rounds_completed: u64,
+    pub rounds_failed: u64,
+    pub total_submissions: u64,
+    pub successful_submissions: u64,
+    pub failed_submissions: u64,
+    pub stale_submissions: u64,
+    pub total_io_errors: u64,
+    pub network_errors: u64,
+    pub blocks_won: u64,
+    pub avg_round_time_ms: f64,
+    pub avg_read_speed_mibs: f64,
+    /// Free and in flight buffers per consumer, None before the pool is set up.
+    pub buffers: Option<BufferPoolStats>,
+    pub accounts: BTreeMap<u64, AccountSummary>,
+    /// Plot data read per drive and how much of it came from the page cache.
+    pub page_cache: BTreeMap<String, DriveCache>,
+    /// The summary logged every 5 minutes.
+    pub summary: String,
+}
+
+/// Snapshot of shared metrics from async code, whichever lock `async_io` picked.
+pub async fn snapshot(metrics: &SharedMetrics) -> MetricsSnapshot {
+    #[cfg(feature = "async_io")]
+    let metrics = metrics.read().await;
+    #[cfg(not(feature = "async_io"))]
+    let metrics = match metrics.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("metrics snapshot: mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    metrics.snapshot()
+}
+
 /// Thread-safe metrics wrapper
 pub type SharedMetrics = Arc<RwLock<MinerMetrics>>;
 
 /// Create a new shared metrics instance
-pub fn new_shared_metrics() -> SharedMetrics {
-    Arc::new(RwLock::new(MinerMetrics::new()))
+pub fn new_shared_metrics(
+    miner_id: String,
+    buffers: Option<Arc<BufferCounters>>,
+    gpus: Vec<Arc<GpuStats>>,
+    energy: Option<EnergyCfg>,
+    difficulty_history_file: Option<PathBuf>,
+    slo_windows: Vec<u64>,
+    audit_backfill: Option<(&Path, u64)>,
+) -> SharedMetrics {
+    let mut metrics = MinerMetrics {
+        miner_id,
+        buffers,
+        gpus,
+        energy: energy.map(EnergyMeter::new),
+        difficulty: DifficultyHistory::load(difficulty_history_file),
+        pool_slo: PoolSlo::new(slo_windows),
+        ..MinerMetrics::new()
+    };
+    if let Some((dir, hours)) = audit_backfill.filter(|&(_, hours)| hours > 0) {
+        let replayed = backfill(dir, now_ms().saturating_sub(hours * 3_600_000), &mut metrics);
+        info!(
+            "metrics: replayed {} submissions of the last {}h from the audit log",
+            replayed, hours
+        );
+    }
+    Arc::new(RwLock::new(metrics))
+}
+
+fn unix_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn account_line(stats: &AccountStats) -> String {
+    let summary = stats.summary();
+    let mut line = format!(
+        "{} submissions, {} rejected, {} failed",
+        summary.submissions, summary.rejected, summary.failed
+    );
+    if let Some(rate) = summary.acceptance_rate {
+        line.push_str(&format!(" ({:.1}% accepted)", rate));
+    }
+    if let Some(best) = summary.best_deadline {
+        line.push_str(&format!(", best {}", format_deadline(best)));
+    }
+    match summary.effective_tib {
+        Some(effective) => line.push_str(&format!(
+            ", {:.2} of {:.2} TiB effective",
+            effective, summary.plotted_tib
+        )),
+        None if summary.plotted_tib > 0.0 => {
+            line.push_str(&format!(", {:.2} TiB plotted", summary.plotted_tib))
+        }
+        None => {}
+    }
+    match summary.health {
+        AccountHealth::Healthy => {}
+        AccountHealth::Rejected => line.push_str(" - mostly rejected, check the pool's reward assignment"),
+        AccountHealth::Underperforming => line.push_str(" - deadlines worse than its plots predict"),
+    }
+    line
 }
 
 /// Disk health monitor
@@ -238,6 +675,7 @@ pub struct DiskHealthInfo {
     pub failed_reads: u64,
     pub last_error: Option<Instant>,
     pub consecutive_errors: u32,
+    pub breaker: CircuitBreaker,
 }
 
 #[allow(dead_code)]
@@ -249,6 +687,7 @@ impl DiskHealthInfo {
             failed_reads: 0,
             last_error: None,
             consecutive_errors: 0,
+            breaker: CircuitBreaker::new(),
         }
     }
 
@@ -296,14 +735,58 @@ impl DiskHealthInfo {
 #[allow(dead_code)]
 pub struct DiskHealthMonitor {
     drives: HashMap<String, DiskHealthInfo>,
+    breaker_cfg: BreakerCfg,
 }
 
 #[allow(dead_code)]
 impl DiskHealthMonitor {
     pub fn new() -> Self {
+        Self::with_breaker(BreakerCfg::default())
+    }
+
+    pub fn with_breaker(breaker_cfg: BreakerCfg) -> Self {
         Self {
             drives: HashMap::new(),
+            breaker_cfg,
+        }
+    }
+
+    /// Whether the reader should touch the drive this round, false while its circuit is open.
+    pub fn allow_read(&mut self, drive_id: &str) -> bool {
+        let info = self.get_or_create(drive_id);
+        let was_open = info.breaker.is_open();
+        let allowed = info.breaker.allow(Instant::now());
+        if !allowed {
+            debug!("drive {}: circuit open, not reading", drive_id);
+        } else if was_open {
+            info!("drive {}: cooldown over, probing with one round", drive_id);
+        }
+        allowed
+    }
+
+    /// Records the outcome of a read, returns true if the drive's circuit just opened.
+    pub fn record_read(&mut self, drive_id: &str, ok: bool) -> bool {
+        let cfg = self.breaker_cfg;
+        let info = self.get_or_create(drive_id);
+        let transition = if ok {
+            info.record_success();
+            info.breaker.record_success()
+        } else {
+            info.record_failure();
+            info.breaker.record_error(&cfg, Instant::now())
+        };
+        match transition {
+            Transition::Opened => warn!(
+                "drive {}: error budget of {} in {}s exhausted, no reads for {}s",
+                drive_id,
+                cfg.error_budget,
+                cfg.window.as_secs(),
+                cfg.cooldown.as_secs()
+            ),
+            Transition::Closed => info!("drive {}: probe succeeded, reading again", drive_id),
+            Transition::None => {}
         }
+        transition == Transition::Opened
     }
 
     /// Get or create disk health info
@@ -326,9 +809,9 @@ impl DiskHealthMonitor {
             };
 
             summary.push_str(&format!(
-                "Drive {}: {} (errors: {}/{}, rate: {:.2}%, consecutive: {})\n",
+                "Drive {}: {} (errors: {}/{}, rate: {:.2}%, consecutive: {}, circuit: {})\n",
                 drive_id, status, info.failed_reads, info.total_reads,
-                info.error_rate(), info.consecutive_errors
+                info.error_rate(), info.consecutive_errors, info.breaker.as_str()
             ));
         }
 
@@ -349,6 +832,6 @@ impl Default for DiskHealthMonitor {
 
 pub type SharedDiskHealth = Arc<RwLock<DiskHealthMonitor>>;
 
-pub fn new_shared_disk_health() -> SharedDiskHealth {
-    Arc::new(RwLock::new(DiskHealthMonitor::new()))
+pub fn new_shared_disk_health(breaker_cfg: BreakerCfg) -> SharedDiskHealth {
+    Arc::new(RwLock::new(DiskHealthMonitor::with_breaker(breaker_cfg)))
 }
@@ -1,7 +1,23 @@
+use crate::buffer_pool::BufferPool;
+use crate::capacity::CapacityDelta;
+use crate::com::api::BlockResponse;
 use crate::com::api::MiningInfoResponse as MiningInfo;
-use crate::config::Cfg;
-use crate::cpu_worker::create_cpu_worker_task;
+use crate::com::client::{Client, ConnectionSettings, ProxyDetails};
+use crate::config::{Cfg, RawPlotCfg};
+use crate::cpu_worker::{create_cpu_worker_task, create_pooled_cpu_worker_task};
+use crate::deadline_format::format_deadline;
+use crate::deadline_stats::DeadlineOutlierDetector;
+use crate::drive_labels;
+use crate::drive_toggles::DriveToggles;
+use crate::explorer::Explorer;
+use crate::fault_injection::FaultInjector;
+use crate::io_priority::IoPriorities;
 use crate::future::interval::Interval;
+use crate::gpu_stats::GpuStats;
+#[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
+use crate::gpu_verify::GpuVerifier;
+use crate::hardware::HardwareReport;
+use crate::hooks::{self, Hooks};
 #[cfg(feature = "opencl")]
 use crate::gpu_worker::create_gpu_worker_task;
 #[cfg(feature = "opencl")]
@@ -9,23 +25,45 @@ use crate::gpu_worker_async::create_gpu_worker_task_async;
 #[cfg(feature = "opencl")]
 use crate::ocl::GpuBuffer;
 #[cfg(feature = "opencl")]
-use crate::ocl::GpuContext;
+use crate::ocl::{check_direct_storage, watch_kernel, GpuContext, KernelSource};
+#[cfg(any(feature = "metal", feature = "wgpu"))]
+use crate::gpu_worker_host::{create_gpu_worker_task_host, HostGpuBuffer};
+#[cfg(feature = "metal")]
+use crate::mtl::MetalContext;
+#[cfg(feature = "wgpu")]
+use crate::wgpu_backend::WgpuContext;
+use crate::logger::json_output;
+use crate::low_memory;
 use crate::metrics::{SharedMetrics, SharedDiskHealth, new_shared_metrics, new_shared_disk_health};
+use crate::mlock;
+use crate::page_cache;
+use crate::payouts::PayoutTracker;
 use crate::plot::{Plot, SCOOP_SIZE};
-use crate::poc_hashing;
-use crate::reader::Reader;
+use crate::plot_order::PlotOrdering;
+use crate::quota::{self, Candidate, QuotaCfg};
+use crate::poc_hashing::{self, NONCE_SIZE};
+use crate::reader::{ReadReply, Reader, RoundHandles};
+use crate::rescan::{RescanReport, RescanRequest, RescanScope, RescanSender};
+use crate::retire::{delete_plot, RetireList};
+use crate::rotation::AccountRotation;
+use crate::scheduler::ReaderPool;
+use crate::round_status::{self, new_shared_round_status, SharedRoundStatus};
+use crate::stages::RoundStages;
+use crate::upgrade;
+use crate::sparse::{self, SparsePlotAction};
+use crate::split_pools;
 use crate::requests::RequestHandler;
 use crate::utils::{get_bus_type, get_device_id, new_thread_pool};
-use filetime::FileTime;
+use crossbeam_channel::Sender;
 use futures_util::{stream::StreamExt};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 #[cfg(feature = "opencl")]
 use ocl_core::Mem;
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::read_dir;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::Arc;
 #[cfg(feature = "async_io")]
@@ -38,13 +76,27 @@ use std::thread;
 use stopwatch::Stopwatch;
 use tokio::runtime::Handle;
 
-
+/// Seconds between checks of the retire list.
+const RETIRE_CHECK_INTERVAL: u64 = 10;
+/// Seconds between checks of the memory limit.
+const MEMORY_CHECK_INTERVAL: u64 = 60;
 
 pub struct Miner {
+    name: String,
     plot_dirs: Vec<PathBuf>,
+    raw_plots: Vec<RawPlotCfg>,
+    sparse_plots: SparsePlotAction,
+    plot_ordering: PlotOrdering,
+    quotas: Vec<QuotaCfg>,
+    low_memory: bool,
+    memory_limit_mb: u64,
+    poc1_support: bool,
+    plot_encryption_key: Option<String>,
     hdd_use_direct_io: bool,
     benchmark_cpu: bool,
     capacity_check_interval: u64,
+    retire_list: PathBuf,
+    latency_check_interval: u64,
     reader: Arc<Mutex<Reader>>,
     request_handler: Arc<Mutex<RequestHandler>>,
     rx_nonce_data: mpsc::Receiver<NonceData>,
@@ -58,9 +110,30 @@ pub struct Miner {
     submit_only_best: bool,
     metrics: SharedMetrics,
     disk_health: SharedDiskHealth,
+    payout_tracker: Option<Arc<PayoutTracker>>,
+    node: Option<Client>,
+    hooks: Arc<Hooks>,
+    explorer: Option<Explorer>,
+    round_status: SharedRoundStatus,
+    rotation: Arc<AccountRotation>,
+    drive_toggles: Arc<DriveToggles>,
+    hardware: Arc<HardwareReport>,
+    tx_rescan: RescanSender,
+    rx_rescan: mpsc::UnboundedReceiver<RescanRequest>,
+}
+
+/// The buffers, reader threads and hash workers, shared by every mining context.
+pub struct Workers {
+    buffer_pool: Arc<BufferPool>,
+    buffer_count: usize,
+    reader_pool: Arc<ReaderPool>,
+    tx_read_replies_cpu: Sender<ReadReply>,
+    tx_read_replies_gpu: Option<Vec<Sender<ReadReply>>>,
+    gpu_stats: Vec<Arc<GpuStats>>,
 }
 
 pub struct State {
+    label: String,
     generation_signature: String,
     generation_signature_bytes: [u8; 32],
     height: u64,
@@ -69,16 +142,38 @@ pub struct State {
     server_target_deadline: u64,
     base_target: u64,
     sw: Stopwatch,
+    // where the running round spends its time, shared with reader, workers and submitter
+    stages: Arc<RoundStages>,
     scanning: bool,
     processed_reader_tasks: usize,
     scoop: u32,
     first: bool,
     outage: bool,
+    best_nonce_data: Option<NonceData>,
+    // best deadline of the running round in seconds, submitted or not
+    round_best_deadline: u64,
+    drive_id_to_best_deadline: HashMap<Arc<str>, u64>,
+    // best raw deadline of the running round per account, for the capacity estimate
+    account_id_to_round_best: HashMap<u64, u64>,
+    deadline_outliers: DeadlineOutlierDetector,
+    account_id_to_nonces: HashMap<u64, u64>,
+    // plots of the last scan, to log what a capacity refresh changed
+    path_to_nonces: HashMap<String, u64>,
+    path_to_drive_id: HashMap<String, String>,
+    // retired plots to delete once the running round is over
+    pending_deletes: Vec<String>,
 }
 
 impl State {
-    fn new() -> Self {
+    fn new(
+        label: String,
+        deadline_outliers: DeadlineOutlierDetector,
+        account_id_to_nonces: HashMap<u64, u64>,
+        path_to_nonces: HashMap<String, u64>,
+        path_to_drive_id: HashMap<String, String>,
+    ) -> Self {
         Self {
+            label,
             generation_signature: "".to_owned(),
             height: 0,
             block: 0,
@@ -88,14 +183,67 @@ impl State {
             base_target: 1,
             processed_reader_tasks: 0,
             sw: Stopwatch::new(),
+            stages: Arc::default(),
             generation_signature_bytes: [0; 32],
             scanning: false,
             first: true,
             outage: false,
+            best_nonce_data: None,
+            round_best_deadline: u64::MAX,
+            drive_id_to_best_deadline: HashMap::new(),
+            account_id_to_round_best: HashMap::new(),
+            deadline_outliers,
+            account_id_to_nonces,
+            path_to_nonces,
+            path_to_drive_id,
+            pending_deletes: Vec::new(),
+        }
+    }
+
+    /// Takes over the best deadline the pool holds for `account_id`, true if it beats ours.
+    fn resume_pool_best(&mut self, account_id: u64, deadline: u64) -> bool {
+        let best = self
+            .account_id_to_best_deadline
+            .entry(account_id)
+            .or_insert(u64::MAX);
+        if deadline < *best {
+            *best = deadline;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether plots of `account_id` are mined.
+    fn mines(&self, account_id: u64) -> bool {
+        self.account_id_to_nonces.contains_key(&account_id)
+    }
+
+    /// Whether `deadline` beats the best one of `account_id` in this round, only those are
+    /// submitted.
+    fn improves(&self, account_id: u64, deadline: u64) -> bool {
+        deadline
+            < *self
+                .account_id_to_best_deadline
+                .get(&account_id)
+                .unwrap_or(&u64::MAX)
+    }
+
+    /// `[name] ` if this is one of several mining contexts.
+    fn log_prefix(&self) -> String {
+        if self.label.is_empty() {
+            String::new()
+        } else {
+            format!("[{}] ", self.label)
         }
     }
 
-    fn update_mining_info(&mut self, mining_info: &MiningInfo) {
+    /// Starts the round of `mining_info`, returns the height and best deadline of the one before.
+    fn update_mining_info(&mut self, mining_info: &MiningInfo) -> Option<(u64, u64)> {
+        let finished = (self.round_best_deadline < u64::MAX)
+            .then_some((self.height, self.round_best_deadline));
+        self.round_best_deadline = u64::MAX;
+        self.account_id_to_round_best.clear();
         for best_deadlines in self.account_id_to_best_deadline.values_mut() {
             *best_deadlines = u64::MAX;
         }
@@ -112,17 +260,25 @@ impl State {
             poc_hashing::calculate_scoop(mining_info.height, &self.generation_signature_bytes);
         info!(
             "{: <80}",
-            format!("new block: height={}, scoop={}", mining_info.height, scoop)
+            format!(
+                "{}new block: height={}, scoop={}",
+                self.log_prefix(),
+                mining_info.height,
+                scoop
+            )
         );
         self.scoop = scoop;
 
         self.sw.restart();
         self.processed_reader_tasks = 0;
         self.scanning = true;
+        self.best_nonce_data = None;
+        self.drive_id_to_best_deadline.clear();
+        finished
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct NonceData {
     pub height: u64,
     pub block: u64,
@@ -130,7 +286,10 @@ pub struct NonceData {
     pub deadline: u64,
     pub nonce: u64,
     pub reader_task_processed: bool,
+    // marker of the drive that completed the round, carries no nonce
+    pub round_finished: bool,
     pub account_id: u64,
+    pub drive_id: Arc<str>,
 }
 
 #[allow(dead_code)]
@@ -145,6 +304,48 @@ pub trait Buffer {
     fn get_id(&self) -> usize;
 }
 
+/// A buffer mapped for writing. Readers only map buffers through this guard: dropping it or
+/// `unmap` releases the mapping, so an early return or error path can't leak it, and only
+/// `into_mapped` hands the still mapped buffer on to the worker that transfers the data.
+pub struct MappedBuffer {
+    buffer: Option<Box<dyn Buffer + Send>>,
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MappedBuffer {
+    pub fn map(mut buffer: Box<dyn Buffer + Send>) -> MappedBuffer {
+        let data = buffer.get_buffer_for_writing();
+        MappedBuffer {
+            buffer: Some(buffer),
+            data,
+        }
+    }
+
+    pub fn data(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.data.clone()
+    }
+
+    /// The buffer without its mapping, for empty chunks and for returning it to the pool.
+    pub fn unmap(mut self) -> Box<dyn Buffer + Send> {
+        let buffer = self.buffer.take().unwrap();
+        buffer.unmap();
+        buffer
+    }
+
+    /// The buffer with its mapping, whoever receives it takes care of unmapping.
+    pub fn into_mapped(mut self) -> Box<dyn Buffer + Send> {
+        self.buffer.take().unwrap()
+    }
+}
+
+impl Drop for MappedBuffer {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            buffer.unmap();
+        }
+    }
+}
+
 pub struct CpuBuffer {
     data: Arc<Mutex<Vec<u8>>>,
 }
@@ -157,6 +358,16 @@ impl CpuBuffer {
             data: Arc::new(Mutex::new(data)),
         }
     }
+
+    /// A buffer that can't be swapped out, see `mlock`.
+    pub fn new_locked(buffer_size: usize) -> std::io::Result<Self> {
+        let data = vec![0u8; buffer_size];
+        mlock::lock(&data)?;
+
+        Ok(CpuBuffer {
+            data: Arc::new(Mutex::new(data)),
+        })
+    }
 }
 
 impl Buffer for CpuBuffer {
@@ -180,16 +391,101 @@ impl Buffer for CpuBuffer {
     }
 }
 
-#[allow(clippy::type_complexity)]
-fn scan_plots(
+/// Reports holes in a plot file, returns false if the plot shouldn't be mined.
+fn check_sparse(file: &Path, nonces: u64, action: SparsePlotAction) -> bool {
+    if action == SparsePlotAction::Ignore {
+        return true;
+    }
+    let report = match sparse::check(file) {
+        Ok(Some(report)) => report,
+        Ok(None) => return true,
+        Err(e) => {
+            warn!("sparse check failed for {}: {}", file.to_string_lossy(), e);
+            return true;
+        }
+    };
+
+    if report.holes.is_empty() {
+        info!(
+            "{} allocates {} of {} bytes but has no holes (compressed file system?)",
+            file.to_string_lossy(),
+            report.allocated,
+            report.size
+        );
+        return true;
+    }
+
+    let missing: u64 = report.holes.iter().map(|(start, end)| end - start).sum();
+    warn!(
+        "sparse plot {}: {:.2}% missing in {} holes, these nonces produce garbage deadlines{}",
+        file.to_string_lossy(),
+        missing as f64 * 100.0 / report.size as f64,
+        report.holes.len(),
+        if action == SparsePlotAction::Skip { ", skipping" } else { "" }
+    );
+    for hole in report.holes.iter().take(10) {
+        warn!("  missing: {}", sparse::describe_hole(*hole, nonces));
+    }
+    if report.holes.len() > 10 {
+        warn!("  ... and {} more", report.holes.len() - 10);
+    }
+
+    action != SparsePlotAction::Skip
+}
+
+pub struct PlotScan {
+    pub drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>>,
+    pub total_size: u64,
+    pub drive_id_to_nonces: HashMap<String, u64>,
+    pub account_id_to_nonces: HashMap<u64, u64>,
+    // one plot per drive, sampled for the hardware report
+    pub drive_id_to_path: HashMap<String, String>,
+    pub path_to_nonces: HashMap<String, u64>,
+    pub path_to_drive_id: HashMap<String, String>,
+}
+
+/// Scans the plots of a mining context.
+pub fn scan(cfg: &Cfg) -> PlotScan {
+    scan_plots(
+        &cfg.plot_dirs,
+        &cfg.raw_plots,
+        cfg.sparse_plots,
+        &PlotOrdering::from_cfg(cfg),
+        &cfg.quotas,
+        cfg.poc1_support,
+        cfg.hdd_use_direct_io,
+        cfg.benchmark_cpu(),
+        cfg.plot_encryption_key.as_deref(),
+        &RetireList::load(&cfg.retire_list),
+        &DriveToggles::default(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn scan_plots(
     plot_dirs: &[PathBuf],
+    raw_plots: &[RawPlotCfg],
+    sparse_plots: SparsePlotAction,
+    ordering: &PlotOrdering,
+    quotas: &[QuotaCfg],
+    poc1_support: bool,
     use_direct_io: bool,
     dummy: bool,
-) -> (HashMap<String, Arc<Vec<Mutex<Plot>>>>, u64) {
+    encryption_key: Option<&str>,
+    retired: &RetireList,
+    toggles: &DriveToggles,
+) -> PlotScan {
     let mut drive_id_to_plots: HashMap<String, Vec<Mutex<Plot>>> = HashMap::new();
+    let mut drive_id_to_nonces: HashMap<String, u64> = HashMap::new();
+    let mut account_id_to_nonces: HashMap<u64, u64> = HashMap::new();
+    let mut drive_id_to_path: HashMap<String, String> = HashMap::new();
+    let mut path_to_nonces: HashMap<String, u64> = HashMap::new();
+    let mut path_to_drive_id: HashMap<String, String> = HashMap::new();
     let mut global_capacity: u64 = 0;
+    // plots with their drive, before quotas
+    let mut loaded: Vec<(String, Plot)> = Vec::new();
 
-    for plot_dir in plot_dirs {
+    for plot_dir in &drive_labels::resolve_plot_dirs(plot_dirs) {
         let bus_type = get_bus_type(plot_dir.to_str().unwrap_or_default());
         let is_usb = bus_type.to_lowercase() == "usb" || bus_type.to_lowercase() == "removable";
         let mut num_plots = 0;
@@ -200,15 +496,29 @@ fn scan_plots(
                     match entry {
                         Ok(entry) => {
                             let file = entry.path();
-                            if let Ok(p) = Plot::new(&file, use_direct_io && !is_usb, dummy) {
-                                let drive_id = get_device_id(file.to_str().unwrap_or_default());
-                                let plots = drive_id_to_plots.entry(drive_id).or_default();
-
-                                local_capacity += p.meta.nonces;
-                                plots.push(Mutex::new(p));
-                                num_plots += 1;
-                            } else {
-                                warn!("failed to load plot {}", file.to_string_lossy());
+                            if retired.contains(&file) {
+                                debug!("skipping retired plot {}", file.to_string_lossy());
+                                continue;
+                            }
+                            match Plot::new(
+                                &file,
+                                use_direct_io && !is_usb,
+                                dummy,
+                                encryption_key,
+                                poc1_support,
+                            ) {
+                                Ok(p) => {
+                                    if !check_sparse(&file, p.meta.nonces, sparse_plots) {
+                                        continue;
+                                    }
+                                    let drive_id = get_device_id(file.to_str().unwrap_or_default());
+                                    local_capacity += p.meta.nonces;
+                                    loaded.push((drive_id, p));
+                                    num_plots += 1;
+                                }
+                                Err(e) => {
+                                    warn!("failed to load plot {}: {}", file.to_string_lossy(), e);
+                                }
                             }
                         }
                         Err(e) => {
@@ -231,19 +541,93 @@ fn scan_plots(
             if is_usb { " (USB)" } else { "" }
         );
 
-        global_capacity += local_capacity;
         if num_plots == 0 {
             warn!("no plots in {}", plot_dir.to_string_lossy());
         }
     }
 
-    // sort plots by filetime and get them into an arc
+    for raw in raw_plots {
+        if retired.contains(&raw.device) {
+            debug!("skipping retired raw plot {}", raw.device.to_string_lossy());
+            continue;
+        }
+        match Plot::new_raw(raw, use_direct_io, dummy) {
+            Ok(p) => {
+                let drive_id = get_device_id(raw.device.to_str().unwrap_or_default());
+                info!(
+                    "device={}, offset={}, size={:.4} TiB",
+                    raw.device.to_string_lossy(),
+                    raw.offset,
+                    p.meta.nonces as f64 / 4.0 / 1024.0 / 1024.0
+                );
+                loaded.push((drive_id, p));
+            }
+            Err(e) => {
+                warn!("failed to load raw plot {}: {}", raw.device.to_string_lossy(), e);
+            }
+        }
+    }
+
+    // disabled drives are left out before the quotas, their plots mustn't use up a quota
+    let mut disabled: BTreeMap<String, u64> = BTreeMap::new();
+    loaded.retain(|(drive_id, p)| {
+        if toggles.is_disabled(drive_id) {
+            *disabled.entry(drive_id.clone()).or_insert(0) += p.meta.nonces;
+            return false;
+        }
+        true
+    });
+
+    let candidates: Vec<Candidate> = loaded
+        .iter()
+        .map(|(_, p)| Candidate {
+            path: &p.path,
+            account_id: p.meta.account_id,
+            nonces: p.meta.nonces,
+        })
+        .collect();
+    let selected = quota::select(quotas, &candidates);
+    let (mut over_quota, mut over_quota_nonces) = (0, 0);
+    for ((drive_id, p), selected) in loaded.into_iter().zip(selected) {
+        if !selected {
+            debug!("skipping {}, over quota", p.path);
+            over_quota += 1;
+            over_quota_nonces += p.meta.nonces;
+            continue;
+        }
+        *drive_id_to_nonces.entry(drive_id.clone()).or_insert(0) += p.meta.nonces;
+        *account_id_to_nonces.entry(p.meta.account_id).or_insert(0) += p.meta.nonces;
+        drive_id_to_path
+            .entry(drive_id.clone())
+            .or_insert_with(|| p.path.clone());
+        path_to_nonces.insert(p.path.clone(), p.meta.nonces);
+        path_to_drive_id.insert(p.path.clone(), drive_id.clone());
+        global_capacity += p.meta.nonces;
+        drive_id_to_plots.entry(drive_id).or_default().push(Mutex::new(p));
+    }
+
+    for (drive_id, nonces) in &disabled {
+        info!(
+            "drive {} disabled, {:.4} TiB left out",
+            drive_id,
+            *nonces as f64 / 4.0 / 1024.0 / 1024.0
+        );
+    }
+    if over_quota > 0 {
+        info!(
+            "quotas: {} plot files ({:.4} TiB) left out",
+            over_quota,
+            over_quota_nonces as f64 / 4.0 / 1024.0 / 1024.0
+        );
+    }
+
+    // sort plots into the configured read order and get them into an arc
     let drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>> = drive_id_to_plots
         .drain()
         .map(|(drive_id, mut plots)| {
-            plots.sort_by_key(|p| {
+            plots.sort_by_cached_key(|p| {
                 #[cfg(feature = "async_io")]
-                let p = p.blocking_lock();
+                let p = crate::utils::lock_sync(p);
                 #[cfg(not(feature = "async_io"))]
                 let p = match p.lock() {
                     Ok(guard) => guard,
@@ -252,13 +636,7 @@ fn scan_plots(
                         poisoned.into_inner()
                     }
                 };
-                match std::fs::metadata(&p.path) {
-                    Ok(m) => -FileTime::from_last_modification_time(&m).unix_seconds(),
-                    Err(e) => {
-                        warn!("failed to get metadata for {}: {}", p.path, e);
-                        0
-                    }
-                }
+                ordering.key(&p.path, p.meta.start_nonce)
             });
             (drive_id, Arc::new(plots))
         })
@@ -270,14 +648,204 @@ fn scan_plots(
         global_capacity as f64 / 4.0 / 1024.0 / 1024.0
     );
 
-    (drive_id_to_plots, global_capacity * 64)
+    PlotScan {
+        drive_id_to_plots,
+        total_size: global_capacity * 64,
+        drive_id_to_nonces,
+        account_id_to_nonces,
+        drive_id_to_path,
+        path_to_nonces,
+        path_to_drive_id,
+    }
 }
 
-impl Miner {
-    pub fn new(cfg: Cfg, executor: Handle) -> Miner {
-        let (drive_id_to_plots, total_size) =
-            scan_plots(&cfg.plot_dirs, cfg.hdd_use_direct_io, cfg.benchmark_cpu());
+/// Takes over the deadlines the pool already holds for the running round, so a miner started in
+/// the middle of a block only submits improvements.
+async fn resume_pool_best_deadlines(miner: Arc<Miner>, height: u64) {
+    let payout_tracker = match &miner.payout_tracker {
+        Some(payout_tracker) => payout_tracker,
+        None => return,
+    };
+    let account_ids: Vec<u64> = {
+        #[cfg(feature = "async_io")]
+        let state = miner.state.lock().await;
+        #[cfg(not(feature = "async_io"))]
+        let state = match miner.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("late join: state mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        };
+        state.account_id_to_nonces.keys().copied().collect()
+    };
+
+    let mut pool_best = Vec::new();
+    for account_id in account_ids {
+        match payout_tracker.fetch_best_deadline(account_id).await {
+            Ok(Some(deadline)) => pool_best.push((account_id, deadline)),
+            Ok(None) => {}
+            Err(e) => debug!("late join: account={}, can't fetch best deadline: {}", account_id, e),
+        }
+    }
+    if pool_best.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "async_io")]
+    let mut state = miner.state.lock().await;
+    #[cfg(not(feature = "async_io"))]
+    let mut state = match miner.state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("late join: state mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    if state.height != height {
+        return;
+    }
+    for (account_id, deadline) in pool_best {
+        if state.resume_pool_best(account_id, deadline) {
+            info!(
+                "{: <80}",
+                format!(
+                    "late join: account={} already has deadline {} at the pool, submitting only better ones",
+                    account_id,
+                    format_deadline(deadline)
+                )
+            );
+        }
+    }
+}
+
+/// Looks up the generator of the block at `height` and fires the block won hook if it was forged
+/// by one of the mined accounts.
+async fn check_block_won(miner: Arc<Miner>, height: u64, best_deadline: Option<u64>) {
+    let node = match &miner.node {
+        Some(node) => node,
+        None => return,
+    };
+    let block = match node.get_block(height).await {
+        Ok(block) => block,
+        Err(e) => {
+            debug!("block check: can't get block {}: {:?}", height, e);
+            return;
+        }
+    };
+
+    #[cfg(feature = "async_io")]
+    let won = miner.state.lock().await.mines(block.generator);
+    #[cfg(not(feature = "async_io"))]
+    let won = match miner.state.lock() {
+        Ok(state) => state.mines(block.generator),
+        Err(poisoned) => {
+            error!("block check: state mutex poisoned, recovering...");
+            poisoned.into_inner().mines(block.generator)
+        }
+    };
+    if !won {
+        if let Some(best_deadline) = best_deadline {
+            compare_with_winner(&miner, node, &block, best_deadline).await;
+        }
+        return;
+    }
+
+    info!(
+        "{: <80}",
+        format!(
+            "block won! height={}, block={}, account={}",
+            block.height, block.block, block.generator
+        )
+    );
+    if let Some(explorer) = &miner.explorer {
+        info!("block: {}", explorer.block(&block.block));
+        info!("account: {}", explorer.account(block.generator));
+    }
+
+    {
+        #[cfg(feature = "async_io")]
+        let mut metrics = miner.metrics.write().await;
+        #[cfg(not(feature = "async_io"))]
+        let mut metrics = match miner.metrics.write() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("metrics: mutex poisoned during block won, recovering...");
+                poisoned.into_inner()
+            }
+        };
+        metrics.record_block_won();
+    }
+
+    fire_block_won(&miner.hooks, &block);
+}
+
+fn fire_block_won(hooks: &Hooks, block: &BlockResponse) {
+    hooks.fire(
+        hooks::BLOCK_WON,
+        &[
+            ("HEIGHT", block.height.to_string()),
+            ("BLOCK", block.block.clone()),
+            ("ACCOUNT_ID", block.generator.to_string()),
+        ],
+    );
+}
+
+/// Records how the round's best deadline compares with the deadline of the block's winner.
+async fn compare_with_winner(miner: &Miner, node: &Client, block: &BlockResponse, best_deadline: u64) {
+    if block.timestamp == 0 || block.height == 0 {
+        return;
+    }
+    let previous = match node.get_block(block.height - 1).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            debug!("winner analysis: can't get block {}: {:?}", block.height - 1, e);
+            return;
+        }
+    };
+    let winner_deadline = block.timestamp.saturating_sub(previous.timestamp);
+
+    let ratio = {
+        #[cfg(feature = "async_io")]
+        let mut metrics = miner.metrics.write().await;
+        #[cfg(not(feature = "async_io"))]
+        let mut metrics = match miner.metrics.write() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("metrics: mutex poisoned during winner analysis, recovering...");
+                poisoned.into_inner()
+            }
+        };
+        metrics.winners.record(best_deadline, winner_deadline)
+    };
+    if best_deadline < winner_deadline {
+        info!(
+            "{: <80}",
+            format!(
+                "winner analysis: height={}, best deadline {} beat the winner's {}, but wasn't \
+                 submitted in time",
+                block.height,
+                format_deadline(best_deadline),
+                format_deadline(winner_deadline)
+            )
+        );
+    } else {
+        debug!(
+            "winner analysis: height={}, base target={}, winner={} with {}, best deadline {} ({:.1}x)",
+            block.height,
+            block.base_target,
+            block.generator,
+            format_deadline(winner_deadline),
+            format_deadline(best_deadline),
+            ratio
+        );
+    }
+}
 
+impl Workers {
+    /// Sets up the buffers, reader threads and hash workers from the top level of `cfg`.
+    /// `drive_count` is the number of drives of all contexts, the default reader thread count.
+    pub fn new(cfg: &Cfg, drive_count: usize) -> Workers {
         let cpu_threads = cfg.cpu_threads.max(1);
         info!("🖥️  Using {} CPU thread(s)", cpu_threads);
         let cpu_worker_task_count = cfg.cpu_worker_task_count;
@@ -290,16 +858,16 @@ impl Miner {
             };
 
         let reader_thread_count = if cfg.hdd_reader_thread_count == 0 {
-            drive_id_to_plots.len()
+            drive_count
         } else {
             cfg.hdd_reader_thread_count
         };
 
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let gpu_worker_task_count = cfg.gpu_worker_task_count;
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let gpu_threads = cfg.gpu_threads;
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let gpu_buffer_count = if gpu_worker_task_count > 0 {
             if cfg.gpu_async {
                 gpu_worker_task_count + 2 * gpu_threads
@@ -309,7 +877,7 @@ impl Miner {
         } else {
             0
         };
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         {
             info!(
                 "reader-threads={}, CPU-threads={}, GPU-threads={}",
@@ -344,7 +912,7 @@ impl Miner {
             }
         }
 
-        #[cfg(not(feature = "opencl"))]
+        #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
         {
             info!(
                 "reader-threads={} CPU-threads={}",
@@ -361,32 +929,57 @@ impl Miner {
             }
         }
 
-        #[cfg(not(feature = "opencl"))]
+        #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
         let buffer_count = cpu_buffer_count;
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let buffer_count = cpu_buffer_count + gpu_buffer_count;
 
         let cpu_nonces_per_cache = cfg.io_buffer_size / SCOOP_SIZE as usize;
         let buffer_size_cpu = cpu_nonces_per_cache * SCOOP_SIZE as usize;
-        let (tx_empty_buffers, rx_empty_buffers) =
-            crossbeam_channel::bounded(buffer_count);
+        // readers and workers all take and return buffers, one shard per reader and hashing thread
+        let buffer_pool = Arc::new(BufferPool::new(
+            buffer_count,
+            reader_thread_count + cpu_threads,
+        ));
         let (tx_read_replies_cpu, rx_read_replies_cpu) =
             crossbeam_channel::bounded(cpu_buffer_count);
 
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let mut tx_read_replies_gpu = Vec::new();
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let mut rx_read_replies_gpu = Vec::new();
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
+        for _ in 0..gpu_threads {
+            let (tx, rx) = crossbeam_channel::unbounded();
+            tx_read_replies_gpu.push(tx);
+            rx_read_replies_gpu.push(rx);
+        }
+
         #[cfg(feature = "opencl")]
         let mut gpu_contexts = Vec::new();
         #[cfg(feature = "opencl")]
         {
-            for _ in 0..gpu_threads {
-                let (tx, rx) = crossbeam_channel::unbounded();
-                tx_read_replies_gpu.push(tx);
-                rx_read_replies_gpu.push(rx);
+            let kernel_source = KernelSource::from_cfg(&cfg);
+            let kernel_src = match kernel_source.load() {
+                Ok(src) => src,
+                Err(e) => {
+                    error!("OCL: {}. Shutting down...", e);
+                    std::process::exit(0);
+                }
+            };
+            let mut gpu_mem_mapping = cfg.gpu_mem_mapping;
+            if cfg.gpu_direct_storage && gpu_threads > 0 {
+                match check_direct_storage(cfg.gpu_platform, cfg.gpu_device) {
+                    Ok(()) => {
+                        info!("GPU: direct storage (experimental): reading plots into device memory");
+                        if !cfg.hdd_use_direct_io {
+                            warn!("GPU: direct storage without hdd_use_direct_io still reads through the page cache");
+                        }
+                        gpu_mem_mapping = true;
+                    }
+                    Err(e) => warn!("GPU: direct storage not available ({}), using regular transfers", e),
+                }
             }
-
             for _ in 0..gpu_threads {
                 gpu_contexts.push(Arc::new(GpuContext::new(
                     cfg.gpu_platform,
@@ -395,17 +988,49 @@ impl Miner {
                     if cfg.benchmark_io() {
                         false
                     } else {
-                        cfg.gpu_mem_mapping
+                        gpu_mem_mapping
                     },
+                    &kernel_src,
                 )));
             }
+            if gpu_threads > 0 {
+                watch_kernel(kernel_source, gpu_contexts.clone());
+            }
         }
 
+        let mut lock_buffers = cfg.lock_buffers
+            && match mlock::check((cpu_buffer_count * buffer_size_cpu) as u64) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("lock_buffers: {}, the buffers stay swappable", e);
+                    false
+                }
+            };
+        let mut locked = 0;
         for _ in 0..cpu_buffer_count {
-            let cpu_buffer = CpuBuffer::new(buffer_size_cpu);
-            tx_empty_buffers
-                .send(Box::new(cpu_buffer) as Box<dyn Buffer + Send>)
-                .unwrap();
+            let cpu_buffer = if lock_buffers {
+                match CpuBuffer::new_locked(buffer_size_cpu) {
+                    Ok(cpu_buffer) => {
+                        locked += 1;
+                        cpu_buffer
+                    }
+                    Err(e) => {
+                        error!("lock_buffers: can't lock a buffer: {}, the rest stay swappable", e);
+                        lock_buffers = false;
+                        CpuBuffer::new(buffer_size_cpu)
+                    }
+                }
+            } else {
+                CpuBuffer::new(buffer_size_cpu)
+            };
+            buffer_pool.add(Box::new(cpu_buffer) as Box<dyn Buffer + Send>);
+        }
+        if locked > 0 {
+            info!(
+                "lock_buffers: {} buffers ({:.1} MiB) locked in memory",
+                locked,
+                (locked * buffer_size_cpu) as f64 / 1024.0 / 1024.0
+            );
         }
 
         #[cfg(feature = "opencl")]
@@ -418,23 +1043,76 @@ impl Miner {
                 })
             {
                 let gpu_buffer = GpuBuffer::new(&context.clone(), i + 1);
-                tx_empty_buffers
-                    .send(Box::new(gpu_buffer) as Box<dyn Buffer + Send>)
-                    .unwrap();
+                buffer_pool.add(Box::new(gpu_buffer) as Box<dyn Buffer + Send>);
             }
         }
 
-        let (tx_nonce_data, rx_nonce_data) = mpsc::channel(buffer_count);
+        #[cfg(any(feature = "metal", feature = "wgpu"))]
+        for i in 0..gpu_threads {
+            for _ in 0..(gpu_buffer_count / gpu_threads
+                + if i == 0 {
+                    gpu_buffer_count % gpu_threads
+                } else {
+                    0
+                })
+            {
+                let gpu_buffer = HostGpuBuffer::new(cfg.gpu_nonces_per_cache, i + 1);
+                buffer_pool.add(Box::new(gpu_buffer) as Box<dyn Buffer + Send>);
+            }
+        }
 
-        thread::spawn({
-            create_cpu_worker_task(
-                cfg.benchmark_io(),
-                new_thread_pool(cpu_threads, cfg.cpu_thread_pinning),
-                rx_read_replies_cpu.clone(),
-                tx_empty_buffers.clone(),
-                tx_nonce_data.clone(),
-            )
-        });
+        // counters of every GPU worker, labelled device:worker
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
+        let gpu_stats: Vec<Arc<GpuStats>> = (0..gpu_threads)
+            .map(|i| GpuStats::new(format!("{}:{}", cfg.gpu_device, i)))
+            .collect();
+        #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
+        let gpu_stats: Vec<Arc<GpuStats>> = Vec::new();
+
+        // mapped OpenCL buffers leave no host copy to verify against
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
+        let gpu_verifier = Arc::new(GpuVerifier::new(
+            if cfg!(feature = "opencl") && cfg.gpu_mem_mapping {
+                0.0
+            } else {
+                cfg.gpu_verify_fraction
+            },
+            cfg.gpu_verify_max_mismatches,
+        ));
+
+        let split_pools = if cfg.cpu_split_pools && cpu_threads > 0 {
+            split_pools::pools(cpu_threads)
+        } else {
+            None
+        };
+        match split_pools {
+            Some((big, little)) => {
+                for hashing_pool in [big, little] {
+                    thread::spawn({
+                        create_pooled_cpu_worker_task(
+                            cfg.benchmark_io(),
+                            hashing_pool,
+                            rx_read_replies_cpu.clone(),
+                            buffer_pool.clone(),
+                        )
+                    });
+                }
+            }
+            None => {
+                thread::spawn({
+                    create_cpu_worker_task(
+                        cfg.benchmark_io(),
+                        new_thread_pool(
+                            cpu_threads,
+                            cfg.cpu_thread_pinning,
+                            cfg.cpu_thread_cores,
+                        ),
+                        rx_read_replies_cpu.clone(),
+                        buffer_pool.clone(),
+                    )
+                });
+            }
+        }
 
         #[cfg(feature = "opencl")]
         for i in 0..gpu_threads {
@@ -443,10 +1121,9 @@ impl Miner {
                     create_gpu_worker_task_async(
                         cfg.benchmark_io(),
                         rx_read_replies_gpu[i].clone(),
-                        tx_empty_buffers.clone(),
-                        tx_nonce_data.clone(),
+                        buffer_pool.clone(),
                         gpu_contexts[i].clone(),
-                        drive_id_to_plots.len(),
+                        gpu_stats[i].clone(),
                     )
                 });
             } else {
@@ -455,54 +1132,222 @@ impl Miner {
                     create_gpu_worker_task(
                         cfg.benchmark_io(),
                         rx_read_replies_gpu[i].clone(),
-                        tx_empty_buffers.clone(),
-                        tx_nonce_data.clone(),
+                        buffer_pool.clone(),
                         gpu_contexts[i].clone(),
+                        gpu_stats[i].clone(),
+                        gpu_verifier.clone(),
+                        cfg.gpu_transfer_compression && !cfg.gpu_mem_mapping,
                     )
                 });
             }
         }
 
-        #[cfg(feature = "opencl")]
+        #[cfg(feature = "metal")]
+        for (rx, stats) in rx_read_replies_gpu.iter().zip(&gpu_stats) {
+            thread::spawn({
+                create_gpu_worker_task_host(
+                    cfg.benchmark_io(),
+                    rx.clone(),
+                    buffer_pool.clone(),
+                    MetalContext::new(cfg.gpu_device, cfg.gpu_nonces_per_cache),
+                    stats.clone(),
+                    gpu_verifier.clone(),
+                    cfg.gpu_transfer_compression,
+                )
+            });
+        }
+
+        #[cfg(feature = "wgpu")]
+        for (rx, stats) in rx_read_replies_gpu.iter().zip(&gpu_stats) {
+            let context = match WgpuContext::new(
+                cfg.gpu_device,
+                cfg.gpu_nonces_per_cache,
+                cfg.gpu_workgroup_size,
+            ) {
+                Ok(context) => context,
+                Err(e) => {
+                    error!("GPU: {}. Shutting down...", e);
+                    process::exit(0);
+                }
+            };
+            thread::spawn({
+                create_gpu_worker_task_host(
+                    cfg.benchmark_io(),
+                    rx.clone(),
+                    buffer_pool.clone(),
+                    context,
+                    stats.clone(),
+                    gpu_verifier.clone(),
+                    cfg.gpu_transfer_compression,
+                )
+            });
+        }
+
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         let tx_read_replies_gpu = Some(tx_read_replies_gpu);
-        #[cfg(not(feature = "opencl"))]
+        #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
         let tx_read_replies_gpu = None;
 
-        let metrics = new_shared_metrics();
-        let disk_health = new_shared_disk_health();
+        Workers {
+            buffer_pool,
+            buffer_count,
+            reader_pool: Arc::new(ReaderPool::new(
+                cfg.reader_scheduler,
+                reader_thread_count,
+                cfg.cpu_thread_pinning,
+                cfg.reader_thread_cores,
+            )),
+            tx_read_replies_cpu,
+            tx_read_replies_gpu,
+            gpu_stats,
+        }
+    }
+}
+
+impl Miner {
+    pub fn new(cfg: Cfg, scan: PlotScan, workers: &Workers, executor: Handle) -> Miner {
+        let PlotScan {
+            drive_id_to_plots,
+            total_size,
+            drive_id_to_nonces,
+            account_id_to_nonces,
+            drive_id_to_path,
+            path_to_nonces,
+            path_to_drive_id,
+        } = scan;
+        page_cache::settle(total_size / SCOOP_SIZE * NONCE_SIZE as u64, cfg.hdd_use_direct_io);
+
+        #[cfg(feature = "opencl")]
+        let gpus = crate::ocl::gpu_report();
+        #[cfg(feature = "metal")]
+        let gpus = crate::mtl::gpu_report();
+        #[cfg(feature = "wgpu")]
+        let gpus = crate::wgpu_backend::gpu_report();
+        #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
+        let gpus = Vec::new();
+        let hardware = Arc::new(HardwareReport::collect(
+            &drive_id_to_path,
+            &account_id_to_nonces,
+            gpus,
+        ));
+        hardware.log();
+
+        let (tx_nonce_data, rx_nonce_data) = mpsc::channel(workers.buffer_count);
+        let (tx_rescan, rx_rescan) = mpsc::unbounded_channel();
+
+        let metrics = new_shared_metrics(
+            cfg.miner_id.clone(),
+            Some(workers.buffer_pool.counters()),
+            workers.gpu_stats.clone(),
+            cfg.energy.clone(),
+            cfg.difficulty_history_file.clone(),
+            cfg.pool_slo_windows.clone(),
+            cfg.audit_log_dir
+                .as_deref()
+                .map(|dir| (dir, cfg.audit_backfill_hours)),
+        );
+        let disk_health = new_shared_disk_health(cfg.breaker_cfg());
+
+        let node = cfg.node_url.clone().map(|url| {
+            Client::new(
+                url,
+                HashMap::new(),
+                ConnectionSettings {
+                    timeout: cfg.timeout,
+                    pool_max_idle_per_host: 1,
+                    keep_alive: cfg.http_keep_alive,
+                    http2: cfg.http2,
+                }
+                .build(),
+                0,
+                ProxyDetails::Disabled,
+                HashMap::new(),
+            )
+        });
+
+        let mut deadline_outliers = DeadlineOutlierDetector::new(
+            cfg.deadline_outlier_window,
+            cfg.deadline_outlier_threshold,
+        );
+        deadline_outliers.set_capacities(drive_id_to_nonces);
+
+        let rotation = Arc::new(AccountRotation::new(cfg.account_rotation.clone()));
+        if !rotation.is_empty() {
+            info!(
+                "account rotation: blocks alternate between {} accounts",
+                cfg.account_rotation.len()
+            );
+        }
+        let request_handler = RequestHandler::new(
+            cfg.pools.clone(),
+            cfg.account_id_to_secret_phrase.clone(),
+            cfg.node_url.clone(),
+            ConnectionSettings {
+                timeout: cfg.timeout,
+                pool_max_idle_per_host: cfg.http_pool_max_idle_per_host,
+                keep_alive: cfg.http_keep_alive,
+                http2: cfg.http2,
+            },
+            (total_size * 4 / 1024 / 1024) as usize,
+            cfg.send_proxy_details,
+            cfg.additional_headers.clone(),
+            rotation.clone(),
+            cfg.pool_failover_after,
+            cfg.fallback_node.clone(),
+            cfg.mining_info_sources.clone(),
+            cfg.audit_log_dir.clone(),
+            cfg.dry_run,
+            metrics.clone(),
+            executor.clone(),
+        );
+        upgrade::register(&cfg.name, request_handler.clone());
 
         Miner {
+            name: cfg.name.clone(),
             plot_dirs: cfg.plot_dirs.clone(),
+            raw_plots: cfg.raw_plots.clone(),
+            sparse_plots: cfg.sparse_plots,
+            plot_ordering: PlotOrdering::from_cfg(&cfg),
+            quotas: cfg.quotas.clone(),
+            low_memory: cfg.low_memory,
+            memory_limit_mb: cfg.memory_limit_mb,
+            poc1_support: cfg.poc1_support,
+            plot_encryption_key: cfg.plot_encryption_key.clone(),
             hdd_use_direct_io: cfg.hdd_use_direct_io,
             benchmark_cpu: cfg.benchmark_cpu(),
             capacity_check_interval: cfg.capacity_check_interval,
+            retire_list: cfg.retire_list.clone(),
+            latency_check_interval: cfg.latency_check_interval,
             reader_task_count: drive_id_to_plots.len(),
             reader: Arc::new(Mutex::new(Reader::new(
                 drive_id_to_plots,
                 total_size,
-                reader_thread_count,
-                rx_empty_buffers,
-                tx_empty_buffers,
-                tx_read_replies_cpu,
-                tx_read_replies_gpu,
+                workers.reader_pool.clone(),
+                workers.buffer_pool.clone(),
+                workers.tx_read_replies_cpu.clone(),
+                workers.tx_read_replies_gpu.clone(),
+                tx_nonce_data,
                 cfg.show_progress,
                 cfg.show_drive_stats,
-                cfg.cpu_thread_pinning,
                 cfg.benchmark_cpu(),
+                if cfg.pre_seek { cfg.io_buffer_size as u64 } else { 0 },
+                cfg.round_start_jitter_ms,
+                disk_health.clone(),
+                FaultInjector::new(cfg.fault_injection.clone()).map(Arc::new),
+                IoPriorities::new(cfg.io_priority.clone()),
+                executor.clone(),
             ))), // three closing parens
             rx_nonce_data,
             target_deadline: cfg.target_deadline,
             account_id_to_target_deadline: cfg.account_id_to_target_deadline,
-            request_handler: Arc::new(Mutex::new(RequestHandler::new(
-                cfg.url,
-                cfg.account_id_to_secret_phrase,
-                cfg.timeout,
-                (total_size * 4 / 1024 / 1024) as usize,
-                cfg.send_proxy_details,
-                cfg.additional_headers,
-                executor.clone(),
-            ))), // three closing parens
-            state: Arc::new(Mutex::new(State::new())),
+            request_handler: Arc::new(Mutex::new(request_handler)),
+            state: Arc::new(Mutex::new(State::new(
+                cfg.name.clone(),
+                deadline_outliers,
+                account_id_to_nonces,
+                path_to_nonces,
+                path_to_drive_id,
+            ))),
             // floor at 1s to protect servers
             get_mining_info_interval: max(1000, cfg.get_mining_info_interval),
             executor,
@@ -510,12 +1355,75 @@ impl Miner {
             submit_only_best : cfg.submit_only_best,
             metrics,
             disk_health,
+            payout_tracker: cfg
+                .payout_tracking
+                .map(|payout_cfg| Arc::new(PayoutTracker::new(payout_cfg, cfg.timeout))),
+            node,
+            hooks: Arc::new(Hooks::new(cfg.hooks)),
+            explorer: cfg.explorer_url.map(Explorer::new),
+            round_status: new_shared_round_status(cfg.name.clone()),
+            rotation,
+            drive_toggles: Arc::default(),
+            hardware,
+            tx_rescan,
+            rx_rescan,
         }
     }
 
+    pub fn round_status(&self) -> SharedRoundStatus {
+        self.round_status.clone()
+    }
+
+    pub fn rotation(&self) -> Arc<AccountRotation> {
+        self.rotation.clone()
+    }
+
+    pub fn metrics(&self) -> SharedMetrics {
+        self.metrics.clone()
+    }
+
+    pub fn drive_toggles(&self) -> Arc<DriveToggles> {
+        self.drive_toggles.clone()
+    }
+
+    /// The hardware report logged at startup.
+    pub fn hardware(&self) -> Arc<HardwareReport> {
+        self.hardware.clone()
+    }
+
+    /// Where the API sends rescans, answered once `run` is going.
+    pub fn rescans(&self) -> RescanSender {
+        self.tx_rescan.clone()
+    }
+
     pub async fn refresh_capacity(&self) {
-        let (drive_id_to_plots, total_size) =
-            scan_plots(&self.plot_dirs, self.hdd_use_direct_io, self.benchmark_cpu);
+        self.rescan(&RescanScope::All).await;
+    }
+
+    /// Scans the plots for changes, reporting those within `scope`.
+    pub async fn rescan(&self, scope: &RescanScope) -> RescanReport {
+        let PlotScan {
+            drive_id_to_plots,
+            total_size,
+            drive_id_to_nonces,
+            account_id_to_nonces,
+            path_to_nonces,
+            path_to_drive_id,
+            ..
+        } = scan_plots(
+                &self.plot_dirs,
+                &self.raw_plots,
+                self.sparse_plots,
+                &self.plot_ordering,
+                &self.quotas,
+                self.poc1_support,
+                self.hdd_use_direct_io,
+                self.benchmark_cpu,
+                self.plot_encryption_key.as_deref(),
+                &RetireList::load(&self.retire_list),
+                &self.drive_toggles,
+            );
+        page_cache::settle(total_size / SCOOP_SIZE * NONCE_SIZE as u64, self.hdd_use_direct_io);
 
         #[cfg(feature = "async_io")]
         let mut reader = self.reader.lock().await;
@@ -527,10 +1435,33 @@ impl Miner {
                 poisoned.into_inner()
             }
         };
-        let old_size = reader.total_size;
         reader.update_plots(drive_id_to_plots, total_size, self.benchmark_cpu);
         drop(reader);
 
+        let (delta, report) = {
+            #[cfg(feature = "async_io")]
+            let mut state = self.state.lock().await;
+            #[cfg(not(feature = "async_io"))]
+            let mut state = match self.state.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => {
+                    error!("refresh_capacity: state mutex poisoned, recovering...");
+                    poisoned.into_inner()
+                }
+            };
+            state.deadline_outliers.set_capacities(drive_id_to_nonces);
+            state.account_id_to_nonces = account_id_to_nonces;
+            let delta = CapacityDelta::between(&state.path_to_nonces, &path_to_nonces);
+            let report = RescanReport::new(
+                &self.name,
+                scope,
+                (&state.path_to_nonces, &state.path_to_drive_id),
+                (&path_to_nonces, &path_to_drive_id),
+            );
+            state.path_to_nonces = path_to_nonces;
+            state.path_to_drive_id = path_to_drive_id;
+            (delta.map(|delta| (delta, state.log_prefix())), report)
+        };
         let total_size_gb = (total_size * 4 / 1024 / 1024) as usize;
         #[cfg(feature = "async_io")]
         {
@@ -549,12 +1480,81 @@ impl Miner {
             rh.update_capacity(total_size_gb);
         }
 
-        if old_size != total_size {
-            info!(
-                "updated total capacity: {:.4} TiB",
-                (total_size / 64) as f64 / 4.0 / 1024.0 / 1024.0
+        if let Some((delta, log_prefix)) = delta {
+            delta.log(&log_prefix);
+            self.hooks.fire(
+                hooks::CAPACITY_CHANGED,
+                &[
+                    ("FILES_ADDED", delta.added.len().to_string()),
+                    ("FILES_REMOVED", delta.removed.len().to_string()),
+                    ("DELTA_BYTES", delta.delta_bytes().to_string()),
+                    ("TOTAL_BYTES", delta.new_bytes.to_string()),
+                ],
             );
         }
+        report
+    }
+
+    /// Takes plots added to the retire list out of mining.
+    pub async fn check_retire_list(&self) {
+        let retired = RetireList::load(&self.retire_list);
+        if retired.is_empty() {
+            return;
+        }
+
+        let newly_retired: Vec<String> = {
+            #[cfg(feature = "async_io")]
+            let state = self.state.lock().await;
+            #[cfg(not(feature = "async_io"))]
+            let state = match self.state.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => {
+                    error!("check_retire_list: state mutex poisoned, recovering...");
+                    poisoned.into_inner()
+                }
+            };
+            state
+                .path_to_nonces
+                .keys()
+                .filter(|path| retired.contains(Path::new(path)))
+                .cloned()
+                .collect()
+        };
+        if newly_retired.is_empty() {
+            return;
+        }
+        for path in &newly_retired {
+            info!("retiring plot {}", path);
+        }
+
+        // the rescan leaves retired plots out, running read tasks keep their own reference
+        self.refresh_capacity().await;
+
+        let to_delete: Vec<String> = newly_retired
+            .into_iter()
+            .filter(|path| retired.should_delete(Path::new(path)))
+            .collect();
+        if to_delete.is_empty() {
+            return;
+        }
+        #[cfg(feature = "async_io")]
+        let mut state = self.state.lock().await;
+        #[cfg(not(feature = "async_io"))]
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("check_retire_list: state mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        };
+        if state.scanning {
+            state.pending_deletes.extend(to_delete);
+        } else {
+            drop(state);
+            for path in &to_delete {
+                delete_plot(path);
+            }
+        }
     }
 
     pub async fn run(self) {
@@ -568,6 +1568,11 @@ impl Miner {
             let inner = Arc::get_mut(&mut miner).expect("unique reference");
             std::mem::replace(&mut inner.rx_nonce_data, dummy_rx)
         };
+        let mut rx_rescan = {
+            let (_tx, dummy_rx) = mpsc::unbounded_channel();
+            let inner = Arc::get_mut(&mut miner).expect("unique reference");
+            std::mem::replace(&mut inner.rx_rescan, dummy_rx)
+        };
 
         let request_handler = miner.request_handler.clone();
         #[cfg(feature = "async_io")]
@@ -603,7 +1608,11 @@ impl Miner {
                         #[cfg(feature = "async_io")]
                         let mining_info_fut = {
                             let rh = request_handler.lock().await.clone();
-                            async move { rh.get_mining_info().await }
+                            async move {
+                                rh.get_mining_info()
+                                    .await
+                                    .map(|mining_info| (mining_info, rh.round_stages(), rh.round_cancel()))
+                            }
                         };
                         #[cfg(not(feature = "async_io"))]
                         let mining_info_fut = {
@@ -614,10 +1623,14 @@ impl Miner {
                                     poisoned.into_inner().clone()
                                 }
                             };
-                            async move { rh.get_mining_info().await }
+                            async move {
+                                rh.get_mining_info()
+                                    .await
+                                    .map(|mining_info| (mining_info, rh.round_stages(), rh.round_cancel()))
+                            }
                         };
                         match mining_info_fut.await {
-                            Ok(mining_info) => {
+                            Ok((mining_info, stages, cancel)) => {
                                 #[cfg(feature = "async_io")]
                                 let mut state = state.lock().await;
                                 #[cfg(not(feature = "async_io"))]
@@ -628,41 +1641,105 @@ impl Miner {
                                         poisoned.into_inner()
                                     }
                                 };
+                                // the first round after a start may be half over
+                                let late_join = state.first;
                                 state.first = false;
                                 if state.outage {
                                     error!("{: <80}", "outage resolved.");
                                     state.outage = false;
                                 }
                                 if mining_info.generation_signature != state.generation_signature {
-                                    state.update_mining_info(&mining_info);
+                                    let finished = state.update_mining_info(&mining_info);
+                                    state.stages = stages;
+                                    if let Some(best) = upgrade::take_resumed(
+                                        &state.label,
+                                        &mining_info.generation_signature,
+                                    ) {
+                                        state.account_id_to_best_deadline.extend(best);
+                                    }
+                                    if late_join {
+                                        tokio::spawn(resume_pool_best_deadlines(
+                                            miner_for_interval.clone(),
+                                            mining_info.height,
+                                        ));
+                                    }
+                                    let miner_ref = miner_for_interval.clone();
+                                    let (height, base_target) =
+                                        (mining_info.height, mining_info.base_target);
+                                    tokio::spawn(async move {
+                                        #[cfg(feature = "async_io")]
+                                        let mut metrics = miner_ref.metrics.write().await;
+                                        #[cfg(not(feature = "async_io"))]
+                                        let mut metrics = match miner_ref.metrics.write() {
+                                            Ok(guard) => guard,
+                                            Err(poisoned) => {
+                                                error!("metrics: mutex poisoned during new block, recovering...");
+                                                poisoned.into_inner()
+                                            }
+                                        };
+                                        metrics.record_block(height, base_target);
+                                    });
+                                    if mining_info.height > 1 {
+                                        let forged = mining_info.height - 1;
+                                        tokio::spawn(check_block_won(
+                                            miner_for_interval.clone(),
+                                            forged,
+                                            finished
+                                                .filter(|&(height, _)| height == forged)
+                                                .map(|(_, best)| best),
+                                        ));
+                                    }
+                                    let round = RoundHandles {
+                                        stages: state.stages.clone(),
+                                        cancel,
+                                    };
                                     #[cfg(feature = "async_io")]
-                                    reader.lock().await.start_reading(
-                                        mining_info.height,
-                                        state.block,
-                                        mining_info.base_target,
-                                        state.scoop,
-                                        &Arc::new(state.generation_signature_bytes),
-                                    );
-                                    #[cfg(not(feature = "async_io"))]
-                                    match reader.lock() {
-                                        Ok(mut reader) => reader.start_reading(
+                                    let progress = {
+                                        let mut reader = reader.lock().await;
+                                        reader.start_reading(
                                             mining_info.height,
                                             state.block,
                                             mining_info.base_target,
                                             state.scoop,
                                             &Arc::new(state.generation_signature_bytes),
-                                        ),
+                                            &round,
+                                        );
+                                        reader.progress()
+                                    };
+                                    #[cfg(not(feature = "async_io"))]
+                                    let progress = match reader.lock() {
+                                        Ok(mut reader) => {
+                                            reader.start_reading(
+                                                mining_info.height,
+                                                state.block,
+                                                mining_info.base_target,
+                                                state.scoop,
+                                                &Arc::new(state.generation_signature_bytes),
+                                                &round,
+                                            );
+                                            reader.progress()
+                                        }
                                         Err(poisoned) => {
                                             error!("run: reader mutex poisoned during start_reading, recovering...");
-                                            poisoned.into_inner().start_reading(
+                                            let mut reader = poisoned.into_inner();
+                                            reader.start_reading(
                                                 mining_info.height,
                                                 state.block,
                                                 mining_info.base_target,
                                                 state.scoop,
                                                 &Arc::new(state.generation_signature_bytes),
+                                                &round,
                                             );
+                                            reader.progress()
                                         }
-                                    }
+                                    };
+                                    round_status::lock(&miner_for_interval.round_status).start(
+                                        mining_info.height,
+                                        mining_info.base_target,
+                                        &state.generation_signature_bytes,
+                                        state.scoop,
+                                        progress,
+                                    );
                                     drop(state);
                                 } else if !state.scanning
                                     && wakeup_after != 0
@@ -742,6 +1819,160 @@ impl Miner {
                 .await;
         });
 
+        let miner_rescan = miner.clone();
+        tokio::spawn(async move {
+            while let Some(request) = rx_rescan.recv().await {
+                info!("rescan of {} requested", request.scope);
+                let report = miner_rescan.rescan(&request.scope).await;
+                // the requester may have given up waiting
+                let _ = request.reply.send(report);
+            }
+        });
+
+        let miner_retire = miner.clone();
+        tokio::spawn(async move {
+            Interval::new_interval(Duration::from_secs(RETIRE_CHECK_INTERVAL))
+                .for_each(move |_| {
+                    let miner_retire = miner_retire.clone();
+                    async move {
+                        miner_retire.check_retire_list().await;
+                    }
+                })
+                .await;
+        });
+
+        if miner.low_memory || miner.memory_limit_mb > 0 {
+            let miner_memory = miner.clone();
+            tokio::spawn(async move {
+                Interval::new_interval(Duration::from_secs(MEMORY_CHECK_INTERVAL))
+                    .for_each(move |_| {
+                        let miner_memory = miner_memory.clone();
+                        async move {
+                            #[cfg(feature = "async_io")]
+                            let mut metrics = miner_memory.metrics.write().await;
+                            #[cfg(not(feature = "async_io"))]
+                            let mut metrics = match miner_memory.metrics.write() {
+                                Ok(guard) => guard,
+                                Err(poisoned) => {
+                                    error!("metrics: mutex poisoned during memory check, recovering...");
+                                    poisoned.into_inner()
+                                }
+                            };
+                            low_memory::enforce(
+                                &mut metrics,
+                                miner_memory.low_memory,
+                                miner_memory.memory_limit_mb,
+                            );
+                        }
+                    })
+                    .await;
+            });
+        }
+
+        // Pool latency probes, only useful with more than one endpoint
+        #[cfg(feature = "async_io")]
+        let pool_endpoint_count = miner.request_handler.lock().await.pool_endpoint_count();
+        #[cfg(not(feature = "async_io"))]
+        let pool_endpoint_count = match miner.request_handler.lock() {
+            Ok(guard) => guard.pool_endpoint_count(),
+            Err(poisoned) => {
+                error!("run: request_handler mutex poisoned during init, recovering...");
+                poisoned.into_inner().pool_endpoint_count()
+            }
+        };
+        if miner.latency_check_interval > 0 && pool_endpoint_count > 1 {
+            let miner_latency = miner.clone();
+            tokio::spawn(async move {
+                Interval::new_interval(Duration::from_secs(miner_latency.latency_check_interval))
+                    .for_each(move |_| {
+                        let miner_latency = miner_latency.clone();
+                        async move {
+                            #[cfg(feature = "async_io")]
+                            let rh = miner_latency.request_handler.lock().await.clone();
+                            #[cfg(not(feature = "async_io"))]
+                            let rh = match miner_latency.request_handler.lock() {
+                                Ok(guard) => guard.clone(),
+                                Err(poisoned) => {
+                                    error!("latency probe: request_handler mutex poisoned, recovering...");
+                                    poisoned.into_inner().clone()
+                                }
+                            };
+                            let probes = rh.probe_latencies().await;
+
+                            #[cfg(feature = "async_io")]
+                            let mut metrics = miner_latency.metrics.write().await;
+                            #[cfg(not(feature = "async_io"))]
+                            let mut metrics = match miner_latency.metrics.write() {
+                                Ok(guard) => guard,
+                                Err(poisoned) => {
+                                    error!("metrics: mutex poisoned during latency probe, recovering...");
+                                    poisoned.into_inner()
+                                }
+                            };
+                            for probe in probes {
+                                match probe.latency_ms {
+                                    Some(latency_ms) => {
+                                        debug!("pool latency: {} {}ms", probe.url, latency_ms)
+                                    }
+                                    None => warn!("pool latency: {} unreachable", probe.url),
+                                }
+                                metrics.record_pool_latency(&probe.url, probe.latency_ms, probe.declared_gb);
+                            }
+                        }
+                    })
+                    .await;
+            });
+        }
+
+        if let Some(payout_tracker) = miner.payout_tracker.clone() {
+            let miner_payouts = miner.clone();
+            tokio::spawn(async move {
+                Interval::new_interval(Duration::from_secs(payout_tracker.interval().max(60)))
+                    .for_each(move |_| {
+                        let miner_payouts = miner_payouts.clone();
+                        let payout_tracker = payout_tracker.clone();
+                        async move {
+                            let account_ids: Vec<u64> = {
+                                #[cfg(feature = "async_io")]
+                                let state = miner_payouts.state.lock().await;
+                                #[cfg(not(feature = "async_io"))]
+                                let state = match miner_payouts.state.lock() {
+                                    Ok(guard) => guard,
+                                    Err(poisoned) => {
+                                        error!("payouts: state mutex poisoned, recovering...");
+                                        poisoned.into_inner()
+                                    }
+                                };
+                                state.account_id_to_nonces.keys().copied().collect()
+                            };
+
+                            for account_id in account_ids {
+                                let balance = match payout_tracker.fetch(account_id).await {
+                                    Ok(balance) => balance,
+                                    Err(e) => {
+                                        warn!("payouts: account={}, can't fetch balance: {}", account_id, e);
+                                        continue;
+                                    }
+                                };
+
+                                #[cfg(feature = "async_io")]
+                                let mut metrics = miner_payouts.metrics.write().await;
+                                #[cfg(not(feature = "async_io"))]
+                                let mut metrics = match miner_payouts.metrics.write() {
+                                    Ok(guard) => guard,
+                                    Err(poisoned) => {
+                                        error!("metrics: mutex poisoned during payout update, recovering...");
+                                        poisoned.into_inner()
+                                    }
+                                };
+                                metrics.record_pool_balance(account_id, balance);
+                            }
+                        }
+                    })
+                    .await;
+            });
+        }
+
         // Metrics reporting task (every 5 minutes)
         let miner_metrics = miner.clone();
         tokio::spawn(async move {
@@ -760,7 +1991,11 @@ impl Miner {
                             }
                         };
 
-                        info!("\n{}", metrics.summary());
+                        if miner_metrics.name.is_empty() {
+                            info!("\n{}", metrics.summary());
+                        } else {
+                            info!("\n[{}]\n{}", miner_metrics.name, metrics.summary());
+                        }
 
                         #[cfg(feature = "async_io")]
                         let disk_health = miner_metrics.disk_health.read().await;
@@ -783,17 +2018,6 @@ impl Miner {
                 .await;
         });
 
-        // only start submitting nonces after a while
-        let mut best_nonce_data = NonceData {
-            height: 0,
-            block: 0,
-            base_target: 0,
-            deadline: 0,
-            nonce: 0,
-            reader_task_processed: false,
-            account_id: 0,
-        };
-
         let target_deadline = miner.target_deadline;
         let account_id_to_target_deadline = miner.account_id_to_target_deadline.clone();
         let request_handler = miner.request_handler.clone();
@@ -821,11 +2045,31 @@ impl Miner {
 
                         let deadline = nonce_data.deadline / nonce_data.base_target;
                         if state.height == nonce_data.height {
-                            let best_deadline = *state
-                                .account_id_to_best_deadline
-                                .get(&nonce_data.account_id)
-                                .unwrap_or(&u64::MAX);
-                            if best_deadline > deadline
+                            if nonce_data.deadline < u64::MAX && !nonce_data.round_finished {
+                                round_status::lock(&miner.round_status).record(
+                                    nonce_data.height,
+                                    nonce_data.account_id,
+                                    deadline,
+                                );
+                            }
+                            if nonce_data.deadline < u64::MAX {
+                                let drive_best = state
+                                    .drive_id_to_best_deadline
+                                    .entry(nonce_data.drive_id.clone())
+                                    .or_insert(u64::MAX);
+                                if nonce_data.deadline < *drive_best {
+                                    *drive_best = nonce_data.deadline;
+                                    state.round_best_deadline = state.round_best_deadline.min(deadline);
+                                }
+                                let account_best = state
+                                    .account_id_to_round_best
+                                    .entry(nonce_data.account_id)
+                                    .or_insert(u64::MAX);
+                                *account_best = (*account_best).min(nonce_data.deadline);
+                            }
+
+                            if !nonce_data.round_finished
+                                && state.improves(nonce_data.account_id, deadline)
                                 && deadline
                                     < min(
                                         state.server_target_deadline,
@@ -839,7 +2083,7 @@ impl Miner {
                                     .insert(nonce_data.account_id, deadline);
 
                                 if inner_submit_only_best {
-                                    best_nonce_data = nonce_data;
+                                    state.best_nonce_data = Some(nonce_data.clone());
                                 } else {
                                     #[cfg(feature = "async_io")]
                                     request_handler.lock().await.submit_nonce(
@@ -880,42 +2124,95 @@ impl Miner {
 
                             if nonce_data.reader_task_processed {
                                 state.processed_reader_tasks += 1;
-                                if state.processed_reader_tasks == reader_task_count {
-                                    let round_time_ms = state.sw.elapsed_ms();
-                                    let speed_mibs = total_size as f64 * 1000.0 / 1024.0 / 1024.0 / round_time_ms as f64;
-
+                                if json_output() {
                                     info!(
-                                        "{: <80}",
-                                        format!(
-                                            "round finished: roundtime={}ms, speed={:.2}MiB/s",
-                                            round_time_ms, speed_mibs
-                                        )
+                                        target: "progress",
+                                        "height={} drives={}/{}",
+                                        state.height,
+                                        state.processed_reader_tasks,
+                                        reader_task_count
+                                    );
+                                }
+                            }
+                            // the drive that completed the round sent its marker
+                            if nonce_data.round_finished {
+                                let round_time_ms = state.sw.elapsed_ms();
+                                let speed_mibs = total_size as f64 * 1000.0 / 1024.0 / 1024.0 / round_time_ms as f64;
+
+                                info!(
+                                    "{: <80}",
+                                    format!(
+                                        "round finished: roundtime={}ms, speed={:.2}MiB/s",
+                                        round_time_ms, speed_mibs
+                                    )
+                                );
+                                let stages = state.stages.breakdown();
+                                let scoop = state.scoop;
+                                info!("{: <80}", format!("round stages: {}", stages));
+
+                                // Record metrics for completed round
+                                let miner_ref = miner.clone();
+                                let bytes_read = total_size;
+                                let account_id_to_round_best =
+                                    std::mem::take(&mut state.account_id_to_round_best);
+                                let account_id_to_nonces = state.account_id_to_nonces.clone();
+                                tokio::spawn(async move {
+                                    #[cfg(feature = "async_io")]
+                                    let mut metrics = miner_ref.metrics.write().await;
+                                    #[cfg(not(feature = "async_io"))]
+                                    let mut metrics = match miner_ref.metrics.write() {
+                                        Ok(guard) => guard,
+                                        Err(poisoned) => {
+                                            error!("metrics: mutex poisoned during round completion, recovering...");
+                                            poisoned.into_inner()
+                                        }
+                                    };
+                                    metrics.record_round_complete(round_time_ms);
+                                    metrics.record_energy();
+                                    metrics.record_round_stages(stages);
+                                    metrics.record_scoop(scoop);
+                                    metrics.record_bytes_read(bytes_read);
+                                    metrics.record_account_rounds(
+                                        &account_id_to_round_best,
+                                        &account_id_to_nonces,
                                     );
+                                });
 
-                                    // Record metrics for completed round
-                                    let miner_ref = miner.clone();
-                                    let bytes_read = total_size;
-                                    tokio::spawn(async move {
-                                        #[cfg(feature = "async_io")]
-                                        let mut metrics = miner_ref.metrics.write().await;
-                                        #[cfg(not(feature = "async_io"))]
-                                        let mut metrics = match miner_ref.metrics.write() {
-                                            Ok(guard) => guard,
-                                            Err(poisoned) => {
-                                                error!("metrics: mutex poisoned during round completion, recovering...");
-                                                poisoned.into_inner()
-                                            }
-                                        };
-                                        metrics.record_round_complete(round_time_ms);
-                                        metrics.record_bytes_read(bytes_read);
-                                    });
+                                let drive_id_to_best_deadline =
+                                    std::mem::take(&mut state.drive_id_to_best_deadline);
+                                for report in state
+                                    .deadline_outliers
+                                    .record_round(&drive_id_to_best_deadline)
+                                {
+                                    warn!(
+                                        "deadline stats: drive {} finds deadlines {:.1}x worse than its \
+                                         capacity predicts over the last {} rounds, check its plots for \
+                                         corruption or wrong account/nonce metadata",
+                                        report.drive_id, report.mean_score, report.rounds
+                                    );
+                                }
 
-                                    // Submit now our best one, if configured that way
-                                    if best_nonce_data.height == state.height {
-                                        let deadline =
-                                            best_nonce_data.deadline / best_nonce_data.base_target;
-                                        #[cfg(feature = "async_io")]
-                                        request_handler.lock().await.submit_nonce(
+                                // Submit now our best one, if configured that way
+                                if let Some(best_nonce_data) = state
+                                    .best_nonce_data
+                                    .take()
+                                    .filter(|best| best.height == state.height)
+                                {
+                                    let deadline =
+                                        best_nonce_data.deadline / best_nonce_data.base_target;
+                                    #[cfg(feature = "async_io")]
+                                    request_handler.lock().await.submit_nonce(
+                                        best_nonce_data.account_id,
+                                        best_nonce_data.nonce,
+                                        best_nonce_data.height,
+                                        best_nonce_data.block,
+                                        best_nonce_data.deadline,
+                                        deadline,
+                                        state.generation_signature_bytes,
+                                    );
+                                    #[cfg(not(feature = "async_io"))]
+                                    match request_handler.lock() {
+                                        Ok(rh) => rh.submit_nonce(
                                             best_nonce_data.account_id,
                                             best_nonce_data.nonce,
                                             best_nonce_data.height,
@@ -923,10 +2220,10 @@ impl Miner {
                                             best_nonce_data.deadline,
                                             deadline,
                                             state.generation_signature_bytes,
-                                        );
-                                        #[cfg(not(feature = "async_io"))]
-                                        match request_handler.lock() {
-                                            Ok(rh) => rh.submit_nonce(
+                                        ),
+                                        Err(poisoned) => {
+                                            error!("run: request_handler mutex poisoned during best nonce submit, recovering...");
+                                            poisoned.into_inner().submit_nonce(
                                                 best_nonce_data.account_id,
                                                 best_nonce_data.nonce,
                                                 best_nonce_data.height,
@@ -934,24 +2231,15 @@ impl Miner {
                                                 best_nonce_data.deadline,
                                                 deadline,
                                                 state.generation_signature_bytes,
-                                            ),
-                                            Err(poisoned) => {
-                                                error!("run: request_handler mutex poisoned during best nonce submit, recovering...");
-                                                poisoned.into_inner().submit_nonce(
-                                                    best_nonce_data.account_id,
-                                                    best_nonce_data.nonce,
-                                                    best_nonce_data.height,
-                                                    best_nonce_data.block,
-                                                    best_nonce_data.deadline,
-                                                    deadline,
-                                                    state.generation_signature_bytes,
-                                                );
-                                            }
+                                            );
                                         }
                                     }
+                                }
 
-                                    state.sw.restart();
-                                    state.scanning = false;
+                                state.sw.restart();
+                                state.scanning = false;
+                                for path in std::mem::take(&mut state.pending_deletes) {
+                                    delete_plot(&path);
                                 }
                             }
                         }
@@ -964,3 +2252,75 @@ impl Miner {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PayoutTrackingCfg;
+
+    #[test]
+    fn test_resume_pool_best_deadlines() {
+        let cfg: PayoutTrackingCfg =
+            serde_yaml::from_str("url: 'http://pool/api/getMiner/{account_id}'").unwrap();
+        let tracker = PayoutTracker::new(cfg, 1000);
+        let mut state = State::new(
+            String::new(),
+            DeadlineOutlierDetector::new(0, 0.0),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+
+        let reply = serde_json::json!({ "currentRoundBestDeadline": "300" });
+        let pool_best = tracker.extract_best_deadline(&reply).unwrap();
+        assert!(state.resume_pool_best(1, pool_best));
+        // the pool's deadline isn't submitted again, neither is anything worse
+        assert!(!state.improves(1, 300));
+        assert!(!state.improves(1, 500));
+        assert!(state.improves(1, 299));
+        // other accounts start from scratch
+        assert!(state.improves(2, 500));
+
+        // a worse reply doesn't undo a better deadline found meanwhile
+        assert!(state.resume_pool_best(1, 100));
+        assert!(!state.resume_pool_best(1, pool_best));
+        assert!(!state.improves(1, 200));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_block_won_fires_hook() {
+        let file = std::env::temp_dir().join(format!("signum-miner-block-won-{}", process::id()));
+        let hooks = Hooks::new(HashMap::from([(
+            hooks::BLOCK_WON.to_owned(),
+            format!("echo $SIGNUM_HEIGHT $SIGNUM_ACCOUNT_ID > {}", file.display()),
+        )]));
+        let state = State::new(
+            String::new(),
+            DeadlineOutlierDetector::new(0, 0.0),
+            HashMap::from([(7, 4)]),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let block: BlockResponse = serde_json::from_value(serde_json::json!({
+            "block": "123",
+            "height": "5",
+            "generator": "7",
+        }))
+        .unwrap();
+        assert!(!state.mines(8));
+        assert!(state.mines(block.generator));
+
+        fire_block_won(&hooks, &block);
+        let mut fired = String::new();
+        for _ in 0..100 {
+            fired = std::fs::read_to_string(&file).unwrap_or_default();
+            if fired.ends_with('\n') {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let _ = std::fs::remove_file(&file);
+        assert_eq!(fired, "5 7\n");
+    }
+}
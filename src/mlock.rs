@@ -0,0 +1,97 @@
+//! `lock_buffers`: keeps the read buffers in RAM.
+//!
+//! On a machine short of memory the kernel swaps out read buffers that sat idle between rounds,
+//! and hashing stalls for seconds while they are paged back in. Locked buffers can't be swapped.
+//! Locked memory counts against RLIMIT_MEMLOCK unless the process has CAP_IPC_LOCK, so the limit
+//! is checked before the first buffer is locked and a too low one is reported with how to raise
+//! it, instead of failing buffer by buffer.
+
+use std::io;
+
+/// Whether `bytes` can be locked, the reason and a remedy if not.
+#[cfg(unix)]
+pub fn check(bytes: u64) -> Result<(), String> {
+    if has_ipc_lock() {
+        return Ok(());
+    }
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+        return Err(format!(
+            "can't read RLIMIT_MEMLOCK: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    if limit.rlim_cur == libc::RLIM_INFINITY || limit.rlim_cur >= bytes {
+        return Ok(());
+    }
+    Err(format!(
+        "RLIMIT_MEMLOCK is {} KiB but the buffers need {} KiB, raise it with `ulimit -l`, \
+         LimitMEMLOCK= in the systemd unit or memlock in /etc/security/limits.conf, or grant \
+         CAP_IPC_LOCK",
+        limit.rlim_cur / 1024,
+        bytes.div_ceil(1024)
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn check(_bytes: u64) -> Result<(), String> {
+    Err("locking memory is only supported on unix".to_owned())
+}
+
+#[cfg(unix)]
+pub fn lock(data: &[u8]) -> io::Result<()> {
+    if unsafe { libc::mlock(data.as_ptr() as *const libc::c_void, data.len()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lock(_data: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "locking memory is only supported on unix",
+    ))
+}
+
+/// CAP_IPC_LOCK lifts the limit, root has it.
+#[cfg(target_os = "linux")]
+fn has_ipc_lock() -> bool {
+    std::fs::read_to_string("/proc/self/status")
+        .map(|status| effective_ipc_lock(&status))
+        .unwrap_or(false)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn has_ipc_lock() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn effective_ipc_lock(status: &str) -> bool {
+    const CAP_IPC_LOCK: u32 = 14;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_IPC_LOCK) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_ipc_lock() {
+        assert!(effective_ipc_lock(
+            "Name:\tminer\nCapEff:\t000001ffffffffff\n"
+        ));
+        assert!(!effective_ipc_lock("CapEff:\t0000000000000000\n"));
+        assert!(effective_ipc_lock("CapEff:\t0000000000004000\n"));
+        assert!(!effective_ipc_lock("Name:\tminer\n"));
+    }
+}
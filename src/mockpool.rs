@@ -0,0 +1,270 @@
+//! `mockpool` command: a minimal pool for testing the miner without a live network.
+//!
+//! Serves `getMiningInfo` and `submitNonce` for the seeded scenario (see `seeded`) and verifies
+//! every submission like a real pool. Scripts make the pool misbehave on purpose: slow responses,
+//! rejections with the messages real pools send, or a generation signature that changes in the
+//! middle of a round.
+
+use crate::http_server::{self, Request, Response};
+use crate::seeded::Scenario;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+
+const SLOW_DELAY: Duration = Duration::from_secs(3);
+/// Rotated through by the `reject` script, each maps to a different `RejectionReason`.
+const REJECTIONS: [&str; 4] = [
+    "Deadline exceeds the pool's target deadline",
+    "Unknown account",
+    "Submitted on wrong height",
+    "limit exceeded",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Script {
+    Normal,
+    /// Every response is delayed by `SLOW_DELAY`.
+    Slow,
+    /// Every second submission is rejected.
+    Reject,
+    /// Halfway through each round the generation signature changes at the same height, as after
+    /// a fork.
+    GensigChange,
+}
+
+impl Script {
+    pub fn parse(s: &str) -> Option<Script> {
+        match s {
+            "normal" => Some(Script::Normal),
+            "slow" => Some(Script::Slow),
+            "reject" => Some(Script::Reject),
+            "gensig-change" => Some(Script::GensigChange),
+            _ => None,
+        }
+    }
+}
+
+pub struct Settings {
+    pub listen: String,
+    pub seed: u64,
+    pub nonces: u64,
+    pub script: Script,
+    pub round_time: Duration,
+    /// Writes the scenario's plot here, so a miner pointed at it knows the best deadline.
+    pub plot_dir: Option<PathBuf>,
+}
+
+struct MockPool {
+    script: Script,
+    round: Mutex<Scenario>,
+    submissions: AtomicU64,
+}
+
+impl MockPool {
+    fn round(&self) -> MutexGuard<'_, Scenario> {
+        match self.round.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("mockpool: round mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+
+    fn handle(&self, params: &HashMap<String, String>, reported_deadline: Option<u64>) -> String {
+        match params.get("requestType").map(String::as_str) {
+            Some("getMiningInfo") => {
+                let round = self.round();
+                serde_json::json!({
+                    "generationSignature": round.gensig_hex(),
+                    "baseTarget": round.base_target.to_string(),
+                    "height": round.height.to_string(),
+                })
+                .to_string()
+            }
+            Some("submitNonce") => self.submit_nonce(params, reported_deadline),
+            _ => pool_error(1, "Incorrect request"),
+        }
+    }
+
+    fn submit_nonce(
+        &self,
+        params: &HashMap<String, String>,
+        reported_deadline: Option<u64>,
+    ) -> String {
+        let param = |name: &str| params.get(name).and_then(|v| v.parse::<u64>().ok());
+        let (account_id, nonce, height) =
+            match (param("accountId"), param("nonce"), param("blockheight")) {
+                (Some(account_id), Some(nonce), Some(height)) => (account_id, nonce, height),
+                _ => return pool_error(3, "Missing or invalid parameters"),
+            };
+        let submission = self.submissions.fetch_add(1, Ordering::Relaxed) + 1;
+        let round = self.round().clone();
+
+        if height != round.height {
+            warn!(
+                "mockpool: account={} nonce={} submitted for height {}, round is at {}",
+                account_id, nonce, height, round.height
+            );
+            return pool_error(1005, "Submitted on wrong height");
+        }
+        if self.script == Script::Reject && submission.is_multiple_of(2) {
+            let message = REJECTIONS[(submission / 2) as usize % REJECTIONS.len()];
+            info!("mockpool: rejecting submission {}: {}", submission, message);
+            return pool_error(1008, message);
+        }
+
+        let deadline = round.deadline(account_id, nonce);
+        if let Some(reported) = reported_deadline.filter(|&reported| reported != deadline) {
+            warn!(
+                "mockpool: account={} nonce={}: miner reported deadline {}, verified {}",
+                account_id, nonce, reported, deadline
+            );
+        }
+        info!(
+            "mockpool: accepted account={} nonce={} height={} deadline={}",
+            account_id, nonce, height, deadline
+        );
+        serde_json::json!({ "result": "success", "deadline": deadline }).to_string()
+    }
+}
+
+pub async fn run(settings: Settings) -> io::Result<()> {
+    let scenario = Scenario::from_seed(settings.seed, settings.nonces);
+    if let Some(dir) = &settings.plot_dir {
+        let (path, best) = scenario.write_plot(dir)?;
+        info!(
+            "mockpool: plot {} written, expected best nonce={}, deadline={} at height {}",
+            path.display(),
+            best.nonce,
+            best.deadline,
+            scenario.height
+        );
+    }
+
+    let listener = TcpListener::bind(&settings.listen).await?;
+    info!(
+        "mockpool: listening on http://{}/, script={:?}, round time={}s",
+        listener.local_addr()?,
+        settings.script,
+        settings.round_time.as_secs()
+    );
+    let pool = Arc::new(MockPool {
+        script: settings.script,
+        round: Mutex::new(scenario),
+        submissions: AtomicU64::new(0),
+    });
+    tokio::spawn(advance_rounds(pool.clone(), settings.round_time));
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let handle = |request| respond(pool.clone(), request);
+            if let Err(e) = http_server::serve(stream, handle).await {
+                debug!("mockpool: connection from {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn advance_rounds(pool: Arc<MockPool>, round_time: Duration) {
+    loop {
+        if pool.script == Script::GensigChange {
+            sleep(round_time / 2).await;
+            {
+                let mut round = pool.round();
+                let mut forked = round.next_round(!round.account_id);
+                forked.height = round.height;
+                info!(
+                    "mockpool: generation signature changed at height {}: {}",
+                    forked.height,
+                    forked.gensig_hex()
+                );
+                *round = forked;
+            }
+            sleep(round_time - round_time / 2).await;
+        } else {
+            sleep(round_time).await;
+        }
+
+        let mut round = pool.round();
+        *round = round.next_round(round.account_id);
+        info!(
+            "mockpool: new round, height={} gensig={}",
+            round.height,
+            round.gensig_hex()
+        );
+    }
+}
+
+/// Answers a request to `/burst` like a pool, the parameters come from the query or a form body.
+async fn respond(pool: Arc<MockPool>, request: Request) -> Response {
+    if request.path != "/burst" {
+        return Response::error("404 Not Found", "unknown endpoint");
+    }
+    if !matches!(request.method.as_str(), "GET" | "POST") {
+        return Response::error("405 Method Not Allowed", "only GET and POST are supported");
+    }
+    if pool.script == Script::Slow {
+        sleep(SLOW_DELAY).await;
+    }
+    let reported_deadline = request
+        .header("x-deadline")
+        .and_then(|deadline| deadline.parse().ok());
+    Response::json(pool.handle(&request.params, reported_deadline))
+}
+
+/// The error format `parse_json_result` understands.
+fn pool_error(code: i32, message: &str) -> String {
+    serde_json::json!({ "error": { "code": code, "message": message } }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(script: Script) -> MockPool {
+        MockPool {
+            script,
+            round: Mutex::new(Scenario::from_seed(3, 1)),
+            submissions: AtomicU64::new(0),
+        }
+    }
+
+    fn submit(pool: &MockPool, height: u64) -> String {
+        let round = pool.round().clone();
+        let mut params = HashMap::new();
+        params.insert("requestType".to_owned(), "submitNonce".to_owned());
+        params.insert("accountId".to_owned(), round.account_id.to_string());
+        params.insert("nonce".to_owned(), round.start_nonce.to_string());
+        params.insert("blockheight".to_owned(), height.to_string());
+        pool.handle(&params, None)
+    }
+
+    #[test]
+    fn test_submit_nonce() {
+        let pool = pool(Script::Normal);
+        let round = pool.round().clone();
+        let expected = round.deadline(round.account_id, round.start_nonce);
+        assert_eq!(
+            submit(&pool, round.height),
+            format!("{{\"deadline\":{},\"result\":\"success\"}}", expected)
+        );
+        assert!(submit(&pool, round.height + 1).contains("wrong height"));
+    }
+
+    #[test]
+    fn test_reject_script() {
+        let pool = pool(Script::Reject);
+        let height = pool.round().height;
+        assert!(submit(&pool, height).contains("success"));
+        assert!(submit(&pool, height).contains("\"error\""));
+        assert!(submit(&pool, height).contains("success"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Metal compute backend for Apple GPUs.
+//!
+//! Mirrors the OpenCL backend: every GPU thread owns a context with its own command queue and
+//! shared-memory buffers, read buffers are routed to it over the GPU reply channels. Apple
+//! Silicon has unified memory, so scoops are copied into a shared buffer instead of being
+//! transferred, and the deadlines are scanned for the minimum on the CPU.
+
+use crate::config::Cfg;
+use crate::hardware::GpuReport;
+use crate::gpu_worker_host::{find_min, HostGpu};
+use metal::{
+    Buffer as MtlBuffer, CommandQueue, CompileOptions, ComputePipelineState, Device,
+    MTLResourceOptions, MTLSize,
+};
+use objc::rc::autoreleasepool;
+use std::ffi::c_void;
+use std::process;
+use std::slice::from_raw_parts;
+
+static SRC: &str = include_str!("mtl/kernel.metal");
+const SCOOP_SIZE: u64 = 64;
+
+fn device(index: usize) -> Option<Device> {
+    Device::all().into_iter().nth(index)
+}
+
+pub fn platform_info() {
+    for (i, device) in Device::all().iter().enumerate() {
+        info!(
+            "Metal: device {}, {}, working set={}MiB{}",
+            i,
+            device.name(),
+            device.recommended_max_working_set_size() / 1024 / 1024,
+            if device.has_unified_memory() {
+                ", unified memory"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+/// Metal has no driver version of its own, it ships with the OS.
+pub fn gpu_report() -> Vec<GpuReport> {
+    Device::all()
+        .iter()
+        .map(|device| GpuReport {
+            name: device.name().to_owned(),
+            vendor: "Apple".to_owned(),
+            driver: "Metal".to_owned(),
+        })
+        .collect()
+}
+
+pub fn gpu_info(cfg: &Cfg) {
+    if cfg.gpu_worker_task_count > 0 {
+        let device = match device(cfg.gpu_device) {
+            Some(device) => device,
+            None => {
+                error!("Metal: Selected device doesn't exist. Shutting down...");
+                process::exit(0);
+            }
+        };
+        info!("GPU: {} (Metal)", device.name());
+
+        let gpu_num_buffers = cfg.gpu_worker_task_count + cfg.gpu_threads;
+        let mem = device.recommended_max_working_set_size() as usize / 1024 / 1024;
+        // per thread: scoop buffer + deadlines
+        let usage = (cfg.gpu_nonces_per_cache * 64 * gpu_num_buffers
+            + cfg.gpu_nonces_per_cache * (64 + 8) * cfg.gpu_threads)
+            / 1024
+            / 1024;
+        info!("GPU: working set={}MiB, usage (estimated)={}MiB", mem, usage);
+        if usage > mem {
+            error!(
+                "GPU: Insufficient GPU memory. Please reduce gpu_worker_task_count \
+                 and/or gpu_nonces_per_cache. Shutting down..."
+            );
+            process::exit(0);
+        }
+    } else if cfg.cpu_worker_task_count == 0 {
+        error!("CPU, GPU: no workers configured. Shutting down...");
+        process::exit(0);
+    }
+}
+
+pub struct MetalContext {
+    queue: CommandQueue,
+    pipeline: ComputePipelineState,
+    scoops: MtlBuffer,
+    deadlines: MtlBuffer,
+    nonces_per_cache: usize,
+    threadgroup_width: u64,
+}
+
+impl MetalContext {
+    pub fn new(gpu_device: usize, nonces_per_cache: usize) -> MetalContext {
+        let device = device(gpu_device).expect("Metal device vanished");
+        let library = match device.new_library_with_source(SRC, &CompileOptions::new()) {
+            Ok(library) => library,
+            Err(e) => {
+                error!("Metal: can't build kernel: {}. Shutting down...", e);
+                process::exit(0);
+            }
+        };
+        let function = library
+            .get_function("calculate_deadlines", None)
+            .expect("Metal: kernel function missing");
+        let pipeline = device
+            .new_compute_pipeline_state_with_function(&function)
+            .expect("Metal: can't create pipeline");
+        let threadgroup_width = pipeline
+            .max_total_threads_per_threadgroup()
+            .min(pipeline.thread_execution_width() * 8);
+
+        let scoops = device.new_buffer(
+            SCOOP_SIZE * nonces_per_cache as u64,
+            MTLResourceOptions::StorageModeShared,
+        );
+        let deadlines = device.new_buffer(
+            8 * nonces_per_cache as u64,
+            MTLResourceOptions::StorageModeShared,
+        );
+
+        MetalContext {
+            queue: device.new_command_queue(),
+            pipeline,
+            scoops,
+            deadlines,
+            nonces_per_cache,
+            threadgroup_width,
+        }
+    }
+}
+
+impl HostGpu for MetalContext {
+    fn hash(&self, data: &[u8], gensig: &[u8; 32]) -> (u64, u64) {
+        let nonce_count = (data.len() / SCOOP_SIZE as usize).min(self.nonces_per_cache);
+        if nonce_count == 0 {
+            return (u64::MAX, 0);
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.scoops.contents() as *mut u8,
+                nonce_count * SCOOP_SIZE as usize,
+            );
+        }
+
+        let count = nonce_count as u32;
+        autoreleasepool(|| {
+            let command_buffer = self.queue.new_command_buffer();
+            let encoder = command_buffer.new_compute_command_encoder();
+            encoder.set_compute_pipeline_state(&self.pipeline);
+            encoder.set_bytes(0, 32, gensig.as_ptr() as *const c_void);
+            encoder.set_buffer(1, Some(&self.scoops), 0);
+            encoder.set_buffer(2, Some(&self.deadlines), 0);
+            encoder.set_bytes(3, 4, &count as *const u32 as *const c_void);
+            encoder.dispatch_threads(
+                MTLSize::new(nonce_count as u64, 1, 1),
+                MTLSize::new(self.threadgroup_width.min(nonce_count as u64), 1, 1),
+            );
+            encoder.end_encoding();
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+        });
+
+        let deadlines =
+            unsafe { from_raw_parts(self.deadlines.contents() as *const u64, nonce_count) };
+        find_min(deadlines.iter().copied())
+    }
+}
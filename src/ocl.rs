@@ -4,12 +4,19 @@ use self::core::{
 use ocl_core as core;
 
 use crate::config::Cfg;
+use crate::hardware::GpuReport;
 use crate::miner::Buffer;
+use sha2::{Digest, Sha256};
 use std::cmp::{max, min};
 use std::ffi::CString;
+use std::fs;
+use std::path::PathBuf;
 use std::process;
 use std::slice::from_raw_parts_mut;
 use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use std::thread;
+use std::time::Duration;
 #[cfg(feature = "async_io")]
 use tokio::sync::Mutex;
 #[cfg(not(feature = "async_io"))]
@@ -17,6 +24,7 @@ use std::sync::Mutex;
 
 static SRC: &'static str = include_str!("ocl/kernel.cl");
 const SCOOP_SIZE: u64 = 64;
+const KERNEL_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 // convert the info or error to a string for printing:
 macro_rules! to_string {
@@ -84,6 +92,26 @@ pub fn platform_info() {
     }
 }
 
+/// All OpenCL devices of all platforms for the hardware report.
+pub fn gpu_report() -> Vec<GpuReport> {
+    let platform_ids = match core::get_platform_ids() {
+        Ok(ids) => ids,
+        Err(e) => {
+            warn!("OCL: can't list platforms: {}", e);
+            return Vec::new();
+        }
+    };
+    platform_ids
+        .iter()
+        .flat_map(|platform_id| core::get_device_ids(platform_id, None, None).unwrap_or_default())
+        .map(|device_id| GpuReport {
+            name: to_string!(core::get_device_info(&device_id, DeviceInfo::Name)),
+            vendor: to_string!(core::get_device_info(&device_id, DeviceInfo::Vendor)),
+            driver: to_string!(core::get_device_info(&device_id, DeviceInfo::DriverVersion)),
+        })
+        .collect()
+}
+
 pub fn gpu_info(cfg: &Cfg) {
     if cfg.gpu_worker_task_count > 0 {
         let platform_ids = core::get_platform_ids().unwrap();
@@ -109,6 +137,11 @@ pub fn gpu_info(cfg: &Cfg) {
             to_string!(core::get_device_info(&device, DeviceInfo::Name))
         );
 
+        if let Err(e) = KernelSource::from_cfg(cfg).load() {
+            error!("OCL: {}. Shutting down...", e);
+            process::exit(0);
+        }
+
         let gpu_num_buffers = if cfg.gpu_worker_task_count > 0 {
             if cfg.gpu_async {
                 cfg.gpu_worker_task_count + 2 * cfg.gpu_threads
@@ -160,12 +193,135 @@ pub fn gpu_info(cfg: &Cfg) {
     }
 }
 
+/// Experimental direct storage path: on a device that shares its memory with the host, zero-copy
+/// buffers are the memory the kernel reads, so plot reads (with direct io: DMA from the drive)
+/// land in GPU memory without a host copy or transfer. Returns why the path can't be used.
+pub fn check_direct_storage(gpu_platform: usize, gpu_device: usize) -> Result<(), String> {
+    let platform_ids = core::get_platform_ids().map_err(|e| e.to_string())?;
+    let platform = *platform_ids
+        .get(gpu_platform)
+        .ok_or_else(|| "platform doesn't exist".to_owned())?;
+    let device_ids = core::get_device_ids(&platform, None, None).map_err(|e| e.to_string())?;
+    let device = device_ids
+        .get(gpu_device)
+        .ok_or_else(|| "device doesn't exist".to_owned())?;
+    match core::get_device_info(device, DeviceInfo::HostUnifiedMemory) {
+        Ok(core::DeviceInfoResult::HostUnifiedMemory(true)) => Ok(()),
+        Ok(_) => Err("device has no host unified memory".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Where the OpenCL kernel comes from: the source embedded at build time or an external file,
+/// optionally pinned to a SHA-256 hash.
+#[derive(Clone, Default)]
+pub struct KernelSource {
+    path: Option<PathBuf>,
+    sha256: Option<String>,
+}
+
+impl KernelSource {
+    pub fn from_cfg(cfg: &Cfg) -> Self {
+        KernelSource {
+            path: cfg.gpu_kernel_path.clone(),
+            sha256: cfg.gpu_kernel_sha256.clone(),
+        }
+    }
+
+    pub fn load(&self) -> Result<String, String> {
+        match &self.path {
+            Some(path) => {
+                let src = fs::read_to_string(path)
+                    .map_err(|e| format!("can't read kernel {}: {}", path.display(), e))?;
+                self.verify(&src)?;
+                Ok(src)
+            }
+            None => Ok(SRC.to_owned()),
+        }
+    }
+
+    fn verify(&self, src: &str) -> Result<(), String> {
+        if let Some(expected) = &self.sha256 {
+            let actual = hex::encode(Sha256::digest(src.as_bytes()));
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!(
+                    "kernel sha256 {} doesn't match the configured {}",
+                    actual, expected
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Polls an external kernel file and recompiles the kernels of all contexts when it changes.
+/// A kernel that fails the hash check or doesn't build leaves the running kernels in place.
+pub fn watch_kernel(source: KernelSource, contexts: Vec<Arc<GpuContext>>) {
+    let path = match &source.path {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    info!("OCL: watching {} for kernel changes", path.display());
+    thread::spawn(move || {
+        let mut current = source.load().ok();
+        loop {
+            thread::sleep(KERNEL_POLL_INTERVAL);
+            let src = match fs::read_to_string(&path) {
+                Ok(src) => src,
+                Err(_) => continue,
+            };
+            if current.as_deref() == Some(src.as_str()) {
+                continue;
+            }
+            current = Some(src.clone());
+
+            if let Err(e) = source.verify(&src) {
+                warn!("OCL: not reloading kernel: {}", e);
+                continue;
+            }
+            for (i, context) in contexts.iter().enumerate() {
+                match context.reload_kernels(&src) {
+                    Ok(()) => info!("OCL: kernel reloaded, gpu thread {}", i),
+                    Err(e) => warn!("OCL: kernel reload failed, gpu thread {}: {}", i, e),
+                }
+            }
+        }
+    });
+}
+
+struct Kernels {
+    kernel1: core::Kernel,
+    kernel2: core::Kernel,
+}
+
+fn build_kernels(
+    context: &core::Context,
+    device_id: core::DeviceId,
+    src: &str,
+) -> Result<Kernels, String> {
+    let src_cstring = CString::new(src).map_err(|e| e.to_string())?;
+    let program =
+        core::create_program_with_source(context, &[src_cstring]).map_err(|e| e.to_string())?;
+    core::build_program(
+        &program,
+        Some(&[device_id]),
+        &CString::new("").unwrap(),
+        None,
+        None,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(Kernels {
+        kernel1: core::create_kernel(&program, "calculate_deadlines").map_err(|e| e.to_string())?,
+        kernel2: core::create_kernel(&program, "find_min").map_err(|e| e.to_string())?,
+    })
+}
+
 pub struct GpuContext {
     pub context: core::Context,
+    device_id: core::DeviceId,
     queue_compute: core::CommandQueue,
     pub queue_transfer: core::CommandQueue,
-    kernel1: core::Kernel,
-    kernel2: core::Kernel,
+    kernels: StdMutex<Kernels>,
     ldim1: [usize; 3],
     gdim1: [usize; 3],
     ldim2: [usize; 3],
@@ -194,6 +350,7 @@ impl GpuContext {
         gpu_id: usize,
         nonces_per_cache: usize,
         mapping: bool,
+        kernel_src: &str,
     ) -> GpuContext {
         let platform_ids = core::get_platform_ids().unwrap();
         let platform_id = platform_ids[gpu_platform];
@@ -207,24 +364,18 @@ impl GpuContext {
         let context_properties = ContextProperties::new().platform(platform_id);
         let context =
             core::create_context(Some(&context_properties), &[device_id], None, None).unwrap();
-        let src_cstring = CString::new(SRC).unwrap();
-        let program = core::create_program_with_source(&context, &[src_cstring]).unwrap();
-        core::build_program(
-            &program,
-            None::<&[()]>,
-            &CString::new("").unwrap(),
-            None,
-            None,
-        )
-        .unwrap();
+        let kernels = match build_kernels(&context, device_id, kernel_src) {
+            Ok(kernels) => kernels,
+            Err(e) => {
+                error!("OCL: can't build kernel: {}. Shutting down...", e);
+                process::exit(0);
+            }
+        };
         let queue_compute = core::create_command_queue(&context, &device_id, None).unwrap();
         let queue_transfer = core::create_command_queue(&context, &device_id, None).unwrap();
 
-        let kernel1 = core::create_kernel(&program, "calculate_deadlines").unwrap();
-        let kernel2 = core::create_kernel(&program, "find_min").unwrap();
-
-        let kernel1_workgroup_size = get_kernel_work_group_size(&kernel1, device_id);
-        let kernel2_workgroup_size = get_kernel_work_group_size(&kernel2, device_id);
+        let kernel1_workgroup_size = get_kernel_work_group_size(&kernels.kernel1, device_id);
+        let kernel2_workgroup_size = get_kernel_work_group_size(&kernels.kernel2, device_id);
         let mut workgroup_count = nonces_per_cache / kernel1_workgroup_size;
         if nonces_per_cache % kernel1_workgroup_size != 0 {
             workgroup_count += 1;
@@ -253,10 +404,10 @@ impl GpuContext {
 
         GpuContext {
             context,
+            device_id,
             queue_compute,
             queue_transfer,
-            kernel1,
-            kernel2,
+            kernels: StdMutex::new(kernels),
             ldim1,
             gdim1,
             ldim2,
@@ -269,6 +420,22 @@ impl GpuContext {
             nvidia,
         }
     }
+
+    /// Builds `src` and swaps it in for the running kernels. Buffers are sized for the work
+    /// group sizes of the initial build, a kernel that can't run with them is rejected.
+    pub fn reload_kernels(&self, src: &str) -> Result<(), String> {
+        let kernels = build_kernels(&self.context, self.device_id, src)?;
+        let kernel1_workgroup_size = get_kernel_work_group_size(&kernels.kernel1, self.device_id);
+        let kernel2_workgroup_size = get_kernel_work_group_size(&kernels.kernel2, self.device_id);
+        if kernel1_workgroup_size < self.ldim1[0] || kernel2_workgroup_size < self.ldim2[0] {
+            return Err(format!(
+                "work group sizes {}/{} are smaller than the running {}/{}, restart required",
+                kernel1_workgroup_size, kernel2_workgroup_size, self.ldim1[0], self.ldim2[0]
+            ));
+        }
+        *self.kernels.lock().unwrap_or_else(|e| e.into_inner()) = kernels;
+        Ok(())
+    }
 }
 
 impl GpuBuffer {
@@ -466,7 +633,7 @@ fn upload_gensig(gpu_context: &Arc<GpuContext>, gensig: [u8; 32], blocking: bool
 fn transfer_buffer_to_gpu(gpu_context: &Arc<GpuContext>, buffer: &GpuBuffer, blocking: bool) {
     let data = buffer.data.clone();
 #[cfg(feature = "async_io")]
-    let data2 = (*data).blocking_lock();
+    let data2 = crate::utils::lock_sync(&data);
 #[cfg(not(feature = "async_io"))]
     let data2 = (*data).lock().unwrap();
     if gpu_context.mapping {
@@ -517,15 +684,20 @@ pub fn gpu_hash(
 }
 
 fn hash(gpu_context: &Arc<GpuContext>, nonce_count: usize, data_gpu: &core::Mem) {
+    // held until both kernels are enqueued, a reload must not swap them in between
+    let kernels = gpu_context
+        .kernels
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
     core::set_kernel_arg(
-        &gpu_context.kernel1,
+        &kernels.kernel1,
         0,
         ArgVal::mem(&gpu_context.gensig_gpu),
     )
     .unwrap();
-    core::set_kernel_arg(&gpu_context.kernel1, 1, ArgVal::mem(&data_gpu)).unwrap();
+    core::set_kernel_arg(&kernels.kernel1, 1, ArgVal::mem(&data_gpu)).unwrap();
     core::set_kernel_arg(
-        &gpu_context.kernel1,
+        &kernels.kernel1,
         2,
         ArgVal::mem(&gpu_context.deadlines_gpu),
     )
@@ -534,7 +706,7 @@ fn hash(gpu_context: &Arc<GpuContext>, nonce_count: usize, data_gpu: &core::Mem)
     unsafe {
         core::enqueue_kernel(
             &gpu_context.queue_compute,
-            &gpu_context.kernel1,
+            &kernels.kernel1,
             1,
             None,
             &gpu_context.gdim1,
@@ -546,31 +718,31 @@ fn hash(gpu_context: &Arc<GpuContext>, nonce_count: usize, data_gpu: &core::Mem)
     }
 
     core::set_kernel_arg(
-        &gpu_context.kernel2,
+        &kernels.kernel2,
         0,
         ArgVal::mem(&gpu_context.deadlines_gpu),
     )
     .unwrap();
     core::set_kernel_arg(
-        &gpu_context.kernel2,
+        &kernels.kernel2,
         1,
         ArgVal::primitive(&(nonce_count as u64)),
     )
     .unwrap();
     core::set_kernel_arg(
-        &gpu_context.kernel2,
+        &kernels.kernel2,
         2,
         ArgVal::local::<u32>(&gpu_context.ldim2[0]),
     )
     .unwrap();
     core::set_kernel_arg(
-        &gpu_context.kernel2,
+        &kernels.kernel2,
         3,
         ArgVal::mem(&gpu_context.best_offset_gpu),
     )
     .unwrap();
     core::set_kernel_arg(
-        &gpu_context.kernel2,
+        &kernels.kernel2,
         4,
         ArgVal::mem(&gpu_context.best_deadline_gpu),
     )
@@ -579,7 +751,7 @@ fn hash(gpu_context: &Arc<GpuContext>, nonce_count: usize, data_gpu: &core::Mem)
     unsafe {
         core::enqueue_kernel(
             &gpu_context.queue_compute,
-            &gpu_context.kernel2,
+            &kernels.kernel2,
             1,
             None,
             &gpu_context.gdim2,
@@ -654,3 +826,21 @@ fn gcd(a: usize, b: usize) -> usize {
 fn lcm(a: usize, b: usize) -> usize {
     a * b / gcd(a, b)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernel_hash_check() {
+        let source = KernelSource {
+            path: None,
+            // sha256 of "abc"
+            sha256: Some(
+                "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD".to_owned(),
+            ),
+        };
+        assert!(source.verify("abc").is_ok());
+        assert!(source.verify("abd").is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! The metrics in the OpenMetrics text format, `GET /metrics` of the API.
+//!
+//! Besides the counters of the summary, the submission latency is exported as a histogram per
+//! pool. Every bucket carries the latest submission that fell into it as an exemplar, labelled
+//! with the round's `height` and a `submission_id` of `<account_id>:<nonce>:<attempt>`. These are
+//! the fields of the audit log, so a latency spike in Grafana leads straight to the round and its
+//! records.
+
+use crate::cache_stats;
+use crate::metrics::MinerMetrics;
+use crate::pool_slo::Exemplar;
+use std::fmt::Write;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+const PREFIX: &str = "signum_miner";
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind);
+    let _ = writeln!(out, "# HELP {}_{} {}", PREFIX, name, help);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    let _ = write!(out, "{}_{}", PREFIX, name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn exemplar(exemplar: &Exemplar) -> String {
+    format!(
+        " # {{height=\"{}\",submission_id=\"{}\"}} {} {}",
+        exemplar.height,
+        escape(&exemplar.submission_id),
+        exemplar.latency_ms as f64 / 1000.0,
+        exemplar.time
+    )
+}
+
+pub fn render(metrics: &MinerMetrics) -> String {
+    let mut out = String::new();
+
+    family(
+        &mut out,
+        "uptime_seconds",
+        "gauge",
+        "Time since the miner started.",
+    );
+    sample(&mut out, "uptime_seconds", &[], metrics.uptime_secs());
+
+    family(&mut out, "rounds", "counter", "Rounds by outcome.");
+    sample(
+        &mut out,
+        "rounds_total",
+        &[("result", "completed")],
+        metrics.rounds_completed,
+    );
+    sample(
+        &mut out,
+        "rounds_total",
+        &[("result", "failed")],
+        metrics.rounds_failed,
+    );
+
+    family(
+        &mut out,
+        "submissions",
+        "counter",
+        "Submissions by outcome.",
+    );
+    for (result, count) in [
+        ("accepted", metrics.successful_submissions),
+        ("failed", metrics.failed_submissions),
+        ("stale", metrics.stale_submissions),
+    ] {
+        sample(&mut out, "submissions_total", &[("result", result)], count);
+    }
+
+    family(
+        &mut out,
+        "rejections",
+        "counter",
+        "Submissions refused by the pool by reason.",
+    );
+    for (reason, count) in &metrics.rejections_by_reason {
+        sample(
+            &mut out,
+            "rejections_total",
+            &[("reason", reason.as_str())],
+            count,
+        );
+    }
+
+    family(
+        &mut out,
+        "account_submissions",
+        "counter",
+        "Submissions per account by outcome.",
+    );
+    for (account_id, stats) in &metrics.accounts {
+        let account_id = account_id.to_string();
+        for (result, count) in [
+            ("accepted", stats.accepted),
+            ("rejected", stats.rejected),
+            ("failed", stats.failed),
+            ("stale", stats.stale),
+        ] {
+            sample(
+                &mut out,
+                "account_submissions_total",
+                &[("account", account_id.as_str()), ("result", result)],
+                count,
+            );
+        }
+    }
+
+    for (name, help, count) in [
+        ("io_errors", "Failed plot reads.", metrics.total_io_errors),
+        (
+            "network_errors",
+            "Failed requests to pools and nodes.",
+            metrics.network_errors,
+        ),
+        (
+            "blocks_won",
+            "Blocks forged by a mined account.",
+            metrics.blocks_won,
+        ),
+        ("read_bytes", "Plot data read.", metrics.total_bytes_read),
+    ] {
+        family(&mut out, name, "counter", help);
+        sample(&mut out, &format!("{}_total", name), &[], count);
+    }
+
+    family(
+        &mut out,
+        "drive_read_bytes",
+        "counter",
+        "Plot data read per drive, from the disk or the page cache.",
+    );
+    for (drive, stats) in cache_stats::snapshot() {
+        for (source, bytes) in [
+            ("disk", stats.read_bytes.saturating_sub(stats.cached_bytes)),
+            ("cache", stats.cached_bytes),
+        ] {
+            sample(
+                &mut out,
+                "drive_read_bytes_total",
+                &[("drive", drive.as_str()), ("source", source)],
+                bytes,
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "pool_declared_capacity_gb",
+        "gauge",
+        "Capacity each pool endpoint last saw in the headers.",
+    );
+    let mut declared: Vec<(&String, &usize)> = metrics.pool_declared_gb.iter().collect();
+    declared.sort();
+    for (pool, gb) in declared {
+        sample(
+            &mut out,
+            "pool_declared_capacity_gb",
+            &[("pool", pool.as_str())],
+            gb,
+        );
+    }
+
+    family(
+        &mut out,
+        "submission_latency_seconds",
+        "histogram",
+        "Round trip of the submissions per pool.",
+    );
+    let _ = writeln!(out, "# UNIT {}_submission_latency_seconds seconds", PREFIX);
+    for histogram in metrics.pool_slo.histograms() {
+        for (bound, count, bucket_exemplar) in &histogram.buckets {
+            let le = match bound {
+                Some(bound) => (*bound as f64 / 1000.0).to_string(),
+                None => "+Inf".to_owned(),
+            };
+            let _ = write!(
+                out,
+                "{}_submission_latency_seconds_bucket{{pool=\"{}\",le=\"{}\"}} {}",
+                PREFIX,
+                escape(histogram.pool),
+                le,
+                count
+            );
+            let _ = writeln!(out, "{}", bucket_exemplar.map(exemplar).unwrap_or_default());
+        }
+        let pool = [("pool", histogram.pool)];
+        sample(
+            &mut out,
+            "submission_latency_seconds_count",
+            &pool,
+            histogram.count,
+        );
+        sample(
+            &mut out,
+            "submission_latency_seconds_sum",
+            &pool,
+            histogram.sum_ms as f64 / 1000.0,
+        );
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = MinerMetrics::new();
+        metrics.record_submission_success(7, 300);
+        metrics.record_submission_latency("http://pool/", 40, true, (100, "7:12:1".to_owned()));
+        metrics.record_submission_latency("http://pool/", 3000, true, (101, "7:13:2".to_owned()));
+
+        let text = render(&metrics);
+        assert!(text.ends_with("# EOF\n"));
+        assert!(text.contains("signum_miner_submissions_total{result=\"accepted\"} 1\n"));
+        assert!(text.contains(
+            "signum_miner_account_submissions_total{account=\"7\",result=\"accepted\"} 1\n"
+        ));
+        assert!(text.contains(
+            "signum_miner_submission_latency_seconds_bucket{pool=\"http://pool/\",le=\"0.05\"} 1 \
+             # {height=\"100\",submission_id=\"7:12:1\"} 0.04 "
+        ));
+        assert!(text.contains(
+            "signum_miner_submission_latency_seconds_bucket{pool=\"http://pool/\",le=\"2.5\"} 1\n"
+        ));
+        assert!(text.contains(
+            "signum_miner_submission_latency_seconds_bucket{pool=\"http://pool/\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(text
+            .contains("signum_miner_submission_latency_seconds_sum{pool=\"http://pool/\"} 3.04\n"));
+    }
+}
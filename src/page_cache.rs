@@ -0,0 +1,183 @@
+//! What happens to the page cache after a scoop read.
+//!
+//! Without direct I/O every scoop read goes through the page cache. Each round reads a different
+//! scoop, so those pages are never read again, but the kernel still evicts other processes' data
+//! to keep them. `drop` tells the kernel, via `posix_fadvise(DONTNEED)`, that the region just read
+//! can go. Only Linux and Android have the hint, elsewhere `drop` behaves like `keep`.
+//!
+//! Small farms, test rigs for example, can go the other way: `warm` reads every plot once in the
+//! background, after that all scoops come from memory. `auto` picks `warm` when the plots fit in
+//! the available memory and `keep` otherwise, it's decided again after every plot rescan.
+
+use crate::hardware::available_memory;
+use crate::plot::PreSeekTarget;
+use serde::de::{self, Deserialize, Deserializer};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+
+/// Part of the available memory the plots may take in `auto`, the rest stays for everything else.
+const WARM_SHARE: f64 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum PageCache {
+    Keep,
+    Drop,
+    Warm,
+    Auto,
+}
+
+impl<'de> Deserialize<'de> for PageCache {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(PageCache::Keep),
+            "drop" => Ok(PageCache::Drop),
+            "warm" => Ok(PageCache::Warm),
+            "auto" => Ok(PageCache::Auto),
+            _ => Err(de::Error::custom(format!(
+                "unknown page_cache mode '{}' (keep, drop, warm, auto)",
+                s
+            ))),
+        }
+    }
+}
+
+impl PageCache {
+    fn from_u8(m: u8) -> PageCache {
+        match m {
+            1 => PageCache::Drop,
+            2 => PageCache::Warm,
+            3 => PageCache::Auto,
+            _ => PageCache::Keep,
+        }
+    }
+}
+
+// as configured, and what `auto` was resolved to
+static REQUESTED: AtomicU8 = AtomicU8::new(PageCache::Keep as u8);
+static MODE: AtomicU8 = AtomicU8::new(PageCache::Keep as u8);
+
+pub fn set_mode(mode: PageCache) {
+    REQUESTED.store(mode as u8, Ordering::Relaxed);
+    MODE.store(resolve(mode, 0, None) as u8, Ordering::Relaxed);
+}
+
+/// The mode in effect, never `auto`.
+pub fn mode() -> PageCache {
+    PageCache::from_u8(MODE.load(Ordering::Relaxed))
+}
+
+/// Resolves `auto` for `plot_bytes` of plots. Warming needs buffered reads, with direct I/O
+/// `auto` stays at `keep`.
+pub fn settle(plot_bytes: u64, direct_io: bool) -> PageCache {
+    let requested = PageCache::from_u8(REQUESTED.load(Ordering::Relaxed));
+    let mode = if requested == PageCache::Auto && direct_io {
+        PageCache::Keep
+    } else {
+        resolve(requested, plot_bytes, available_memory())
+    };
+    let previous = PageCache::from_u8(MODE.swap(mode as u8, Ordering::Relaxed));
+    if requested == PageCache::Auto && previous != mode {
+        info!(
+            "page cache: {:.1} GiB of plots, {}",
+            plot_bytes as f64 / 1024.0 / 1024.0 / 1024.0,
+            if mode == PageCache::Warm {
+                "fits in memory, keeping it warm"
+            } else {
+                "not warming (no room or direct io)"
+            }
+        );
+    }
+    mode
+}
+
+fn resolve(requested: PageCache, plot_bytes: u64, available: Option<u64>) -> PageCache {
+    match requested {
+        PageCache::Auto => match available {
+            Some(available)
+                if plot_bytes > 0 && plot_bytes as f64 <= available as f64 * WARM_SHARE =>
+            {
+                PageCache::Warm
+            }
+            _ => PageCache::Keep,
+        },
+        mode => mode,
+    }
+}
+
+/// Reads all of `targets` once from a background thread, so the page cache holds every scoop.
+pub fn warm(targets: Vec<PreSeekTarget>) {
+    if let Err(e) = thread::Builder::new()
+        .name("page-cache-warm".to_owned())
+        .spawn(move || {
+            for target in &targets {
+                if let Err(e) = target.warm() {
+                    warn!("page cache: can't warm {}: {}", target.path(), e);
+                }
+            }
+            info!("page cache: {} plot(s) warmed", targets.len());
+        })
+    {
+        warn!("page cache: can't spawn thread: {}", e);
+    }
+}
+
+/// Called after `len` bytes at `start` were read from `fh` without direct I/O.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn after_read<F: std::os::unix::io::AsRawFd>(fh: &F, start: u64, len: usize) {
+    if mode() != PageCache::Drop {
+        return;
+    }
+    // only a hint, skipped where the offsets don't fit
+    let offsets = (
+        crate::plot::to_off_t(start),
+        crate::plot::to_off_t(len as u64),
+    );
+    if let (Some(start), Some(len)) = offsets {
+        unsafe {
+            libc::posix_fadvise(fh.as_raw_fd(), start, len, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn after_read<F>(_fh: &F, _start: u64, _len: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_round_trip() {
+        let mode: PageCache = serde_yaml::from_str("Drop").unwrap();
+        assert_eq!(mode, PageCache::Drop);
+        assert!(serde_yaml::from_str::<PageCache>("forget").is_err());
+
+        set_mode(PageCache::Drop);
+        assert_eq!(super::mode(), PageCache::Drop);
+        set_mode(PageCache::Keep);
+        assert_eq!(super::mode(), PageCache::Keep);
+    }
+
+    #[test]
+    fn test_resolve_auto() {
+        let gib = 1024 * 1024 * 1024;
+        assert_eq!(
+            resolve(PageCache::Auto, 4 * gib, Some(16 * gib)),
+            PageCache::Warm
+        );
+        assert_eq!(
+            resolve(PageCache::Auto, 15 * gib, Some(16 * gib)),
+            PageCache::Keep
+        );
+        assert_eq!(resolve(PageCache::Auto, 4 * gib, None), PageCache::Keep);
+        assert_eq!(resolve(PageCache::Auto, 0, Some(16 * gib)), PageCache::Keep);
+        assert_eq!(
+            resolve(PageCache::Drop, 4 * gib, Some(16 * gib)),
+            PageCache::Drop
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Polls the pool's web API for the balance and shares of every mined account.
+//!
+//! Pool software differs in routes and field names, so both are configurable: the url contains an
+//! `{account_id}` placeholder and every value is addressed with a JSON pointer. The defaults match
+//! the miner route of signum-pool (`/api/getMiner/{account_id}`).
+
+use crate::config::PayoutTrackingCfg;
+use reqwest::Client as InnerClient;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolBalance {
+    pub pending: Option<f64>,
+    pub paid: Option<f64>,
+    pub shares: Option<f64>,
+}
+
+pub struct PayoutTracker {
+    inner: InnerClient,
+    cfg: PayoutTrackingCfg,
+}
+
+impl PayoutTracker {
+    pub fn new(cfg: PayoutTrackingCfg, timeout: u64) -> Self {
+        let inner = InnerClient::builder()
+            .timeout(Duration::from_millis(timeout))
+            .build()
+            .unwrap();
+        PayoutTracker { inner, cfg }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.cfg.interval
+    }
+
+    pub async fn fetch(&self, account_id: u64) -> Result<PoolBalance, String> {
+        let json = self.fetch_json(account_id).await?;
+        Ok(self.extract(&json))
+    }
+
+    /// The account's best deadline in the running round, `None` if it has none yet or the pool
+    /// doesn't report it.
+    pub async fn fetch_best_deadline(&self, account_id: u64) -> Result<Option<u64>, String> {
+        if self.cfg.best_deadline_field.is_none() {
+            return Ok(None);
+        }
+        let json = self.fetch_json(account_id).await?;
+        Ok(self.extract_best_deadline(&json))
+    }
+
+    async fn fetch_json(&self, account_id: u64) -> Result<serde_json::Value, String> {
+        let url = self
+            .cfg
+            .url
+            .replace("{account_id}", &account_id.to_string());
+        let body = self
+            .inner
+            .get(&url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| e.to_string())?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?;
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+
+    fn extract(&self, json: &serde_json::Value) -> PoolBalance {
+        let field = |pointer: &Option<String>| {
+            pointer
+                .as_deref()
+                .and_then(|pointer| json.pointer(pointer))
+                .and_then(parse_amount)
+        };
+        PoolBalance {
+            pending: field(&self.cfg.pending_field),
+            paid: field(&self.cfg.paid_field),
+            shares: field(&self.cfg.shares_field),
+        }
+    }
+
+    /// The round's best deadline the pool holds in a reply, with `best_deadline_field` set.
+    pub fn extract_best_deadline(&self, json: &serde_json::Value) -> Option<u64> {
+        self.cfg
+            .best_deadline_field
+            .as_deref()
+            .and_then(|pointer| json.pointer(pointer))
+            .and_then(parse_amount)
+            .filter(|deadline| *deadline >= 0.0)
+            .map(|deadline| deadline as u64)
+    }
+}
+
+/// Accepts plain numbers as well as strings like `"12.5 SIGNA"`.
+fn parse_amount(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.split_whitespace().next()?.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount(&serde_json::json!(1.5)), Some(1.5));
+        assert_eq!(parse_amount(&serde_json::json!("12.5 SIGNA")), Some(12.5));
+        assert_eq!(parse_amount(&serde_json::json!("n/a")), None);
+        assert_eq!(parse_amount(&serde_json::json!(null)), None);
+    }
+
+    #[test]
+    fn test_extract() {
+        let cfg: PayoutTrackingCfg =
+            serde_yaml::from_str("url: 'http://pool/api/getMiner/{account_id}'").unwrap();
+        let tracker = PayoutTracker::new(cfg, 1000);
+        let json = serde_json::json!({
+            "pendingBalance": "3.25 SIGNA",
+            "nConf": 17,
+        });
+        assert_eq!(
+            tracker.extract(&json),
+            PoolBalance {
+                pending: Some(3.25),
+                paid: None,
+                shares: Some(17.0),
+            }
+        );
+        assert_eq!(tracker.extract_best_deadline(&json), None);
+        assert_eq!(
+            tracker.extract_best_deadline(&serde_json::json!({ "currentRoundBestDeadline": "86400" })),
+            Some(86400)
+        );
+    }
+}
@@ -1,3 +1,9 @@
+use crate::cache_stats;
+use crate::config::RawPlotCfg;
+use crate::fd_pool;
+use crate::page_cache;
+use crate::plot_cipher::{strip_encrypted_suffix, PlotCipher};
+use crate::poc_hashing::generate_nonce;
 use crate::utils::get_sector_size;
 use rand::prelude::*;
 use std::cmp::{max, min};
@@ -17,6 +23,7 @@ use std::io::{SeekFrom};
 #[cfg(not(feature = "async_io"))]
 use std::io::{Read, Seek};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 const SCOOPS_IN_NONCE: u64 = 4096;
 const SHABAL256_HASH_SIZE: u64 = 32;
@@ -51,16 +58,94 @@ impl Meta {
     }
 }
 
+/// Location of a plot's scoop regions, used to move the drive's head before the round starts.
+#[derive(Clone)]
+pub struct PreSeekTarget {
+    path: String,
+    base_offset: u64,
+    nonces: u64,
+}
+
+impl PreSeekTarget {
+    /// Offset and length of the part of the scoop's region a pre-seek of `len` bytes touches.
+    fn region(&self, scoop: u32, len: u64) -> (u64, u64) {
+        (
+            self.base_offset + u64::from(scoop) * self.nonces * SCOOP_SIZE,
+            len.min(self.nonces * SCOOP_SIZE),
+        )
+    }
+
+    /// Hints the kernel to read ahead the first `len` bytes of the scoop's region and touches its
+    /// first byte, so the seek is already done when the reader starts.
+    pub fn pre_seek(&self, scoop: u32, len: u64) {
+        let (start, len) = self.region(scoop, len);
+        let mut fh = match open(&self.path) {
+            Ok(fh) => fh,
+            Err(e) => {
+                debug!("pre-seek: can't open {}: {}", self.path, e);
+                return;
+            }
+        };
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::unix::io::AsRawFd;
+            if let (Some(start), Some(len)) = (to_off_t(start), to_off_t(len)) {
+                unsafe {
+                    libc::posix_fadvise(fh.as_raw_fd(), start, len, libc::POSIX_FADV_WILLNEED);
+                }
+            }
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let _ = len;
+
+        let mut byte = [0u8; 1];
+        let res = std::io::Seek::seek(&mut fh, SeekFrom::Start(start))
+            .and_then(|_| std::io::Read::read(&mut fh, &mut byte));
+        if let Err(e) = res {
+            debug!("pre-seek: {}: {}", self.path, e);
+        }
+    }
+
+    /// Reads the whole plot once so it ends up in the page cache, see `page_cache`.
+    pub fn warm(&self) -> io::Result<()> {
+        let mut fh = open(&self.path)?;
+        std::io::Seek::seek(&mut fh, SeekFrom::Start(self.base_offset))?;
+        let mut buf = vec![0u8; 1024 * 1024];
+        let mut left = self.nonces * NONCE_SIZE;
+        while left > 0 {
+            let len = min(left, buf.len() as u64) as usize;
+            std::io::Read::read_exact(&mut fh, &mut buf[..len])?;
+            left -= len as u64;
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
 pub struct Plot {
     pub meta: Meta,
     pub path: String,
-    pub fh: TokioFile,
+    // open while the plot is read, parked in the fd pool in between
+    fh: Option<TokioFile>,
     read_offset: u64,
     align_offset: u64,
     seek_base: u64,
+    // position of the plot on a raw device, 0 for plot files
+    base_offset: u64,
     use_direct_io: bool,
     sector_size: u64,
     dummy: bool,
+    cipher: Option<PlotCipher>,
+    // legacy PoC1 layout, the second half of each scoop is read from the mirrored scoop
+    poc1: bool,
+    // read from the page cache since the last `take_cached_bytes`
+    cached_bytes: u64,
+    // keeps the parked handle apart from that of a plot rescanned from the same file
+    fd_owner: u64,
 }
 
 cfg_if! {
@@ -106,7 +191,13 @@ cfg_if! {
 }
 
 impl Plot {
-    pub fn new(path: &PathBuf, mut use_direct_io: bool, dummy: bool) -> Result<Plot, Box<dyn Error>> {
+    pub fn new(
+        path: &PathBuf,
+        use_direct_io: bool,
+        dummy: bool,
+        encryption_key: Option<&str>,
+        poc1_support: bool,
+    ) -> Result<Plot, Box<dyn Error>> {
         if !path.is_file() {
             return Err(From::from(format!(
                 "{} is not a file",
@@ -114,15 +205,44 @@ impl Plot {
             )));
         }
 
-        let plot_file = path.file_name().unwrap().to_str().unwrap();
+        let (plot_file, encrypted) =
+            strip_encrypted_suffix(path.file_name().unwrap().to_str().unwrap());
+        let cipher = if encrypted {
+            match encryption_key {
+                Some(key) => Some(PlotCipher::new(key, plot_file)),
+                None => {
+                    return Err(From::from(
+                        "plot file is encrypted but no plot_encryption_key is configured",
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+
+        // PoC1 plots are named account_startnonce_nonces_stagger
         let parts: Vec<&str> = plot_file.split('_').collect();
-        if parts.len() != 3 {
-            return Err(From::from("plot file has wrong format"));
+        if let Some(factor) = parts.get(3).and_then(|part| compression_factor(part)) {
+            return Err(From::from(format!(
+                "compressed plot (x{}) isn't supported, PoC+ mines regular PoC2 plots, \
+                 replot without compression",
+                factor
+            )));
         }
+        let poc1 = match parts.len() {
+            3 => false,
+            4 if poc1_support => true,
+            _ => return Err(From::from("plot file has wrong format")),
+        };
 
         let account_id = parts[0].parse::<u64>()?;
         let start_nonce = parts[1].parse::<u64>()?;
         let nonces = parts[2].parse::<u64>()?;
+        if poc1 && parts[3].parse::<u64>()? != nonces {
+            return Err(From::from(
+                "unoptimized PoC1 plot (stagger != nonces), convert it to PoC2",
+            ));
+        }
 
         let size = fs::metadata(path)?.len();
         let exp_size = nonces * NONCE_SIZE;
@@ -133,134 +253,300 @@ impl Plot {
             )));
         }
 
-        let fh_std = if use_direct_io {
-            open_using_direct_io(path)?
-        } else {
-            open(path)?
+        let meta = Meta {
+            account_id,
+            start_nonce,
+            nonces,
+            name: plot_file.to_string(),
         };
-        let fh = {
-            #[cfg(feature = "async_io")]
-            { TokioFile::from_std(fh_std) }
-            #[cfg(not(feature = "async_io"))]
-            { fh_std }
+        let mut plot = Plot::open_plot(path, meta, 0, use_direct_io, dummy, cipher)?;
+        if poc1_support && !dummy {
+            plot.detect_format(poc1)?;
+        }
+        Ok(plot)
+    }
+
+    /// A plot written directly to a block device (or any file) at `offset`, without a file
+    /// system. The plot geometry can't be derived from a name, so it comes from the config.
+    pub fn new_raw(
+        raw: &RawPlotCfg,
+        mut use_direct_io: bool,
+        dummy: bool,
+    ) -> Result<Plot, Box<dyn Error>> {
+        let path = &raw.device;
+        // block devices report a length of 0 in their metadata, seeking to the end works for both
+        let size = open(path)?.seek(SeekFrom::End(0))?;
+        let exp_size = raw.nonces * NONCE_SIZE;
+        if raw.offset + exp_size > size {
+            return Err(From::from(format!(
+                "plot ends at byte {} but device has only {}",
+                raw.offset + exp_size,
+                size
+            )));
+        }
+
+        let sector_size = get_sector_size(path.to_str().unwrap_or_default());
+        if use_direct_io && !raw.offset.is_multiple_of(sector_size) {
+            warn!(
+                "offset {} of {} isn't aligned to the sector size {}, not using direct io",
+                raw.offset,
+                path.display(),
+                sector_size
+            );
+            use_direct_io = false;
+        }
+
+        let meta = Meta {
+            account_id: raw.account_id,
+            start_nonce: raw.start_nonce,
+            nonces: raw.nonces,
+            name: format!("{}_{}_{}", raw.account_id, raw.start_nonce, raw.nonces),
         };
+        Plot::open_plot(path, meta, raw.offset, use_direct_io, dummy, None)
+    }
 
-        let plot_file_name = plot_file.to_string();
+    fn open_plot(
+        path: &Path,
+        meta: Meta,
+        base_offset: u64,
+        mut use_direct_io: bool,
+        dummy: bool,
+        cipher: Option<PlotCipher>,
+    ) -> Result<Plot, Box<dyn Error>> {
         let sector_size = get_sector_size(path.to_str().unwrap());
-        if use_direct_io && sector_size / 64 > nonces {
+        if use_direct_io && sector_size / 64 > meta.nonces {
             warn!(
                 "not enough nonces for using direct io: plot={}",
-                plot_file_name
+                meta.name
             );
             use_direct_io = false;
         }
 
-        let file_path = path.clone().into_os_string().into_string().unwrap();
+        // opened in the mode it's read with, a direct I/O handle can't do unaligned reads
+        let fh = if use_direct_io {
+            open_using_direct_io(path)?
+        } else {
+            open(path)?
+        };
+
+        let file_path = path.to_path_buf().into_os_string().into_string().unwrap();
+        let fd_owner = fd_pool::new_owner();
+        fd_pool::put((file_path.clone(), base_offset, fd_owner), fh);
         Ok(Plot {
-            meta: Meta {
-                account_id,
-                start_nonce,
-                nonces,
-                name: plot_file_name,
-            },
-            fh,
+            meta,
+            fh: None,
             path: file_path,
             read_offset: 0,
             align_offset: 0,
             seek_base: 0,
+            base_offset,
             use_direct_io,
             sector_size,
             dummy,
+            cipher,
+            poc1: false,
+            cached_bytes: 0,
+            fd_owner,
         })
     }
 
+    /// Determines the plot's layout by comparing the first nonce's first scoop against a freshly
+    /// generated one. If neither layout matches, `name_suggests_poc1` decides.
+    fn detect_format(&mut self, name_suggests_poc1: bool) -> io::Result<()> {
+        let last_scoop = (SCOOPS_IN_NONCE - 1) * self.meta.nonces * SCOOP_SIZE;
+        let first = self.read_raw_scoop(0)?;
+        let mirrored = self.read_raw_scoop(last_scoop)?;
+
+        let nonce = generate_nonce(self.meta.account_id, self.meta.start_nonce);
+        let half = SHABAL256_HASH_SIZE as usize;
+        let scoop_size = SCOOP_SIZE as usize;
+        let poc2_first = &nonce[..scoop_size];
+        let poc2_last = &nonce[nonce.len() - scoop_size..];
+
+        if first[..] == poc2_first[..] {
+            self.poc1 = false;
+        } else if first[..half] == poc2_first[..half]
+            && first[half..] == poc2_last[half..]
+            && mirrored[half..] == poc2_first[half..]
+        {
+            self.poc1 = true;
+        } else {
+            warn!(
+                "plot {} doesn't match its account and nonces, assuming {}",
+                self.meta.name,
+                if name_suggests_poc1 { "PoC1" } else { "PoC2" }
+            );
+            self.poc1 = name_suggests_poc1;
+        }
+
+        if self.poc1 {
+            info!("plot {}: PoC1 layout, mirroring second scoop halves", self.meta.name);
+            // the mirrored reads aren't sector aligned, the next round opens a buffered handle
+            self.use_direct_io = false;
+            fd_pool::take(&self.fd_key());
+        }
+        Ok(())
+    }
+
+    /// Reads the 64 bytes at `offset` (relative to the plot's start) through a buffered handle.
+    fn read_raw_scoop(&self, offset: u64) -> io::Result<[u8; SCOOP_SIZE as usize]> {
+        let mut scoop = [0u8; SCOOP_SIZE as usize];
+        let mut fh = open(&self.path)?;
+        std::io::Seek::seek(&mut fh, SeekFrom::Start(self.base_offset + offset))?;
+        std::io::Read::read_exact(&mut fh, &mut scoop)?;
+        if let Some(cipher) = &self.cipher {
+            cipher.apply(&mut scoop, offset);
+        }
+        Ok(scoop)
+    }
+
+    /// Address of the region holding the second hashes for `scoop` in a PoC1 plot.
+    fn mirror_addr(&self, scoop: u32, offset: u64) -> u64 {
+        self.base_offset
+            + (SCOOPS_IN_NONCE - 1 - u64::from(scoop)) * self.meta.nonces * SCOOP_SIZE
+            + offset
+    }
+
+    /// Replaces the second half of every scoop in `bs` with the one read from the mirror region.
+    fn merge_mirror(&self, bs: &mut [u8], mut mirror: Vec<u8>, mirror_addr: u64) {
+        if let Some(cipher) = &self.cipher {
+            cipher.apply(&mut mirror, mirror_addr - self.base_offset);
+        }
+        let half = SHABAL256_HASH_SIZE as usize;
+        for (scoop, mirrored) in bs
+            .chunks_exact_mut(SCOOP_SIZE as usize)
+            .zip(mirror.chunks_exact(SCOOP_SIZE as usize))
+        {
+            scoop[half..].copy_from_slice(&mirrored[half..]);
+        }
+    }
+
 #[cfg(not(feature = "async_io"))]
 pub fn prepare(&mut self, scoop: u32) -> io::Result<u64> {
         self.read_offset = 0;
         self.align_offset = 0;
-        let nonces = self.meta.nonces;
-        let mut seek_addr = u64::from(scoop) * nonces * SCOOP_SIZE;
+        let mut seek_addr = self.scoop_addr(scoop);
 
-        // reopening file handles
-        if !self.use_direct_io {
-            self.fh = open(&self.path)?;
-        } else {
-            self.fh = open_using_direct_io(&self.path)?;
-        };
+        if self.fh.is_none() {
+            self.fh = Some(match fd_pool::take(&self.fd_key()) {
+                Some(fh) => fh,
+                None => self.open_handle()?,
+            });
+        }
 
         if self.use_direct_io {
             self.align_offset = self.round_seek_addr(&mut seek_addr);
         }
         self.seek_base = seek_addr;
 
-        self.fh.seek(SeekFrom::Start(seek_addr))
+        let result = self.handle()?.seek(SeekFrom::Start(seek_addr));
+        if result.is_err() {
+            // a failing handle isn't kept, the next round opens the file again
+            self.fh = None;
+        }
+        result
     }
 
     #[cfg(feature = "async_io")]
     pub async fn prepare_async(&mut self, scoop: u32) -> io::Result<u64> {
         self.read_offset = 0;
         self.align_offset = 0;
-        let nonces = self.meta.nonces;
-        let mut seek_addr = u64::from(scoop) * nonces * SCOOP_SIZE;
+        let mut seek_addr = self.scoop_addr(scoop);
 
-        if !self.use_direct_io {
-            let f = open(&self.path)?;
-            self.fh = TokioFile::from_std(f);
-        } else {
-            let f = open_using_direct_io(&self.path)?;
-            self.fh = TokioFile::from_std(f);
-        };
+        if self.fh.is_none() {
+            let fh = match fd_pool::take(&self.fd_key()) {
+                Some(fh) => fh,
+                None => self.open_handle()?,
+            };
+            self.fh = Some(TokioFile::from_std(fh));
+        }
 
         if self.use_direct_io {
             self.align_offset = self.round_seek_addr(&mut seek_addr);
         }
         self.seek_base = seek_addr;
 
-        self.fh.seek(SeekFrom::Start(seek_addr)).await
+        let result = self.handle()?.seek(SeekFrom::Start(seek_addr)).await;
+        if result.is_err() {
+            // a failing handle isn't kept, the next round opens the file again
+            self.fh = None;
+        }
+        result
     }
 
 #[cfg(not(feature = "async_io"))]
     pub fn read(&mut self, bs: &mut Vec<u8>, scoop: u32) -> Result<(usize, u64, bool), io::Error> {
         let read_offset = self.read_offset;
         let buffer_cap = bs.capacity();
-        let start_nonce = self.meta.start_nonce
-            + u64::from(scoop) * self.meta.nonces
-            + self.read_offset / 64;
-
-        let (bytes_to_read, finished) =
-            if read_offset as usize + buffer_cap >= (SCOOP_SIZE * self.meta.nonces) as usize {
-                let mut bytes_to_read =
-                    (SCOOP_SIZE * self.meta.nonces) as usize - self.read_offset as usize;
-                if self.use_direct_io {
-                    let r = bytes_to_read % self.sector_size as usize;
-                    if r != 0 {
-                        bytes_to_read -= r;
-                    }
-                }
+        let start_nonce = self.meta.start_nonce + self.read_offset / 64;
 
-                (bytes_to_read, true)
-            } else {
-                (buffer_cap, false)
-            };
+        let (bytes_to_read, finished) = next_chunk(
+            read_offset,
+            SCOOP_SIZE * self.meta.nonces,
+            buffer_cap,
+            self.use_direct_io.then_some(self.sector_size),
+        );
 
         let offset = self.read_offset;
-        let seek_addr = SeekFrom::Start(self.seek_base + self.align_offset + offset);
         if !self.dummy {
-            self.fh.seek(seek_addr)?;
-            self.fh.read_exact(&mut bs[0..bytes_to_read])?;
-            // interrupt avoider (not implemented)
-            // let read_chunk_size_in_nonces = 65536;
-            // for i in (0..bytes_to_read).step_by(read_chunk_size_in_nonces) {
-            //     self.fh.read_exact(
-            //         &mut bs[i..(i + min(read_chunk_size_in_nonces, bytes_to_read - i))],
-            //     )?;
-            // }
+            if let Err(e) = self.read_at(&mut bs[0..bytes_to_read], scoop, offset) {
+                self.fh = None;
+                return Err(e);
+            }
         }
         self.read_offset += bytes_to_read as u64;
+        if finished {
+            self.release();
+        }
 
         Ok((bytes_to_read, start_nonce, finished))
     }
 
+    #[cfg(not(feature = "async_io"))]
+    fn read_at(&mut self, bs: &mut [u8], scoop: u32, offset: u64) -> io::Result<()> {
+        let start = self.seek_base + self.align_offset + offset;
+        let buffered = !self.use_direct_io;
+        let fh = self.handle()?;
+        fh.seek(SeekFrom::Start(start))?;
+        let cached = buffered.then(|| cache_stats::cached_bytes(&*fh, start, bs.len()));
+        let reading = Instant::now();
+        fh.read_exact(bs)?;
+        if buffered {
+            page_cache::after_read(&*fh, start, bs.len());
+            self.cached_bytes += cached
+                .flatten()
+                .unwrap_or_else(|| cache_stats::by_timing(bs.len(), reading.elapsed()));
+        }
+        if let Some(cipher) = &self.cipher {
+            cipher.apply(bs, start - self.base_offset);
+        }
+        if self.poc1 {
+            let mirror_addr = self.mirror_addr(scoop, offset);
+            let mut mirror = vec![0u8; bs.len()];
+            let fh = self.handle()?;
+            fh.seek(SeekFrom::Start(mirror_addr))?;
+            fh.read_exact(&mut mirror)?;
+            if buffered {
+                page_cache::after_read(&*fh, mirror_addr, mirror.len());
+            }
+            self.merge_mirror(bs, mirror, mirror_addr);
+        }
+        Ok(())
+    }
+
+    /// Bytes read from the page cache since the last call.
+    pub fn take_cached_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.cached_bytes)
+    }
+
+    /// Parks the handle of a completely read plot in the fd pool.
+    #[cfg(not(feature = "async_io"))]
+    fn release(&mut self) {
+        if let Some(fh) = self.fh.take() {
+            fd_pool::put(self.fd_key(), fh);
+        }
+    }
+
     #[cfg(feature = "async_io")]
     pub async fn read_async(
         &mut self,
@@ -269,48 +555,84 @@ pub fn prepare(&mut self, scoop: u32) -> io::Result<u64> {
     ) -> Result<(usize, u64, bool), io::Error> {
         let read_offset = self.read_offset;
         let buffer_cap = bs.capacity();
-        let start_nonce = self.meta.start_nonce
-            + u64::from(scoop) * self.meta.nonces
-            + self.read_offset / 64;
+        let start_nonce = self.meta.start_nonce + self.read_offset / 64;
 
-        let (bytes_to_read, finished) = if read_offset as usize + buffer_cap
-            >= (SCOOP_SIZE * self.meta.nonces) as usize
-        {
-            let mut bytes_to_read = (SCOOP_SIZE * self.meta.nonces) as usize
-                - self.read_offset as usize;
-            if self.use_direct_io {
-                let r = bytes_to_read % self.sector_size as usize;
-                if r != 0 {
-                    bytes_to_read -= r;
-                }
-            }
-            (bytes_to_read, true)
-        } else {
-            (buffer_cap, false)
-        };
+        let (bytes_to_read, finished) = next_chunk(
+            read_offset,
+            SCOOP_SIZE * self.meta.nonces,
+            buffer_cap,
+            self.use_direct_io.then_some(self.sector_size),
+        );
 
         let offset = self.read_offset;
-        let seek_addr = SeekFrom::Start(self.seek_base + self.align_offset + offset);
         if !self.dummy {
-            self.fh.seek(seek_addr).await?;
-            self.fh.read_exact(&mut bs[0..bytes_to_read]).await?;
+            if let Err(e) = self.read_at_async(&mut bs[0..bytes_to_read], scoop, offset).await {
+                self.fh = None;
+                return Err(e);
+            }
         }
         self.read_offset += bytes_to_read as u64;
+        if finished {
+            self.release_async().await;
+        }
 
         Ok((bytes_to_read, start_nonce, finished))
     }
 
+    #[cfg(feature = "async_io")]
+    async fn read_at_async(&mut self, bs: &mut [u8], scoop: u32, offset: u64) -> io::Result<()> {
+        let start = self.seek_base + self.align_offset + offset;
+        let buffered = !self.use_direct_io;
+        let fh = self.handle()?;
+        fh.seek(SeekFrom::Start(start)).await?;
+        let cached = buffered.then(|| cache_stats::cached_bytes(&*fh, start, bs.len()));
+        let reading = Instant::now();
+        fh.read_exact(bs).await?;
+        if buffered {
+            page_cache::after_read(&*fh, start, bs.len());
+            self.cached_bytes += cached
+                .flatten()
+                .unwrap_or_else(|| cache_stats::by_timing(bs.len(), reading.elapsed()));
+        }
+        if let Some(cipher) = &self.cipher {
+            cipher.apply(bs, start - self.base_offset);
+        }
+        if self.poc1 {
+            let mirror_addr = self.mirror_addr(scoop, offset);
+            let mut mirror = vec![0u8; bs.len()];
+            let fh = self.handle()?;
+            fh.seek(SeekFrom::Start(mirror_addr)).await?;
+            fh.read_exact(&mut mirror).await?;
+            if buffered {
+                page_cache::after_read(&*fh, mirror_addr, mirror.len());
+            }
+            self.merge_mirror(bs, mirror, mirror_addr);
+        }
+        Ok(())
+    }
+
+    /// Parks the handle of a completely read plot in the fd pool.
+    #[cfg(feature = "async_io")]
+    async fn release_async(&mut self) {
+        if let Some(fh) = self.fh.take() {
+            fd_pool::put(self.fd_key(), fh.into_std().await);
+        }
+    }
+
 #[cfg(not(feature = "async_io"))]
     pub fn seek_random(&mut self) -> io::Result<u64> {
         let mut rng = thread_rng();
         let rand_scoop = rng.gen_range(0, SCOOPS_IN_NONCE);
 
-        let mut seek_addr = rand_scoop as u64 * self.meta.nonces * SCOOP_SIZE;
+        let mut seek_addr = self.base_offset + rand_scoop * self.meta.nonces * SCOOP_SIZE;
         if self.use_direct_io {
             self.round_seek_addr(&mut seek_addr);
         }
 
-        self.fh.seek(SeekFrom::Start(seek_addr))
+        match self.fh.as_mut() {
+            Some(fh) => fh.seek(SeekFrom::Start(seek_addr)),
+            None => self.open_handle()?.seek(SeekFrom::Start(seek_addr)),
+        }
     }
 
     #[cfg(feature = "async_io")]
@@ -318,7 +640,7 @@ pub fn prepare(&mut self, scoop: u32) -> io::Result<u64> {
         let mut rng = thread_rng();
         let rand_scoop = rng.gen_range(0, SCOOPS_IN_NONCE);
 
-        let mut seek_addr = rand_scoop as u64 * self.meta.nonces * SCOOP_SIZE;
+        let mut seek_addr = self.base_offset + rand_scoop * self.meta.nonces * SCOOP_SIZE;
         if self.use_direct_io {
             self.round_seek_addr(&mut seek_addr);
         }
@@ -332,6 +654,51 @@ pub fn prepare(&mut self, scoop: u32) -> io::Result<u64> {
         f.seek(SeekFrom::Start(seek_addr))
     }
 
+    fn fd_key(&self) -> fd_pool::Key {
+        (self.path.clone(), self.base_offset, self.fd_owner)
+    }
+
+    fn open_handle(&self) -> io::Result<File> {
+        if self.use_direct_io {
+            open_using_direct_io(&self.path)
+        } else {
+            open(&self.path)
+        }
+    }
+
+    fn handle(&mut self) -> io::Result<&mut TokioFile> {
+        self.fh
+            .as_mut()
+            .ok_or_else(|| io::Error::other("plot isn't prepared for reading"))
+    }
+
+    pub fn pre_seek_target(&self) -> PreSeekTarget {
+        PreSeekTarget {
+            path: self.path.clone(),
+            base_offset: self.base_offset,
+            nonces: self.meta.nonces,
+        }
+    }
+
+    /// Position of `scoop` in the file or on the device.
+    pub fn scoop_addr(&self, scoop: u32) -> u64 {
+        self.base_offset + u64::from(scoop) * self.meta.nonces * SCOOP_SIZE
+    }
+
+    /// True if reads decrypt or reassemble the data, it differs from the bytes on disk.
+    pub fn transforms_data(&self) -> bool {
+        self.cipher.is_some() || self.poc1
+    }
+
+    /// False if direct io was requested but the plot can't use it.
+    pub fn uses_direct_io(&self) -> bool {
+        self.use_direct_io
+    }
+
+    pub fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
     fn round_seek_addr(&mut self, seek_addr: &mut u64) -> u64 {
         // Align file offset to the underlying sector size without skipping
         // the beginning of the scoop.  Older logic aligned upwards which
@@ -347,3 +714,185 @@ pub fn prepare(&mut self, scoop: u32) -> io::Result<u64> {
         r
     }
 }
+
+/// Length of the next read of a scoop's `scoop_bytes` at `read_offset` into a buffer of
+/// `buffer_cap` bytes, and whether it's the last one. With direct I/O the last read is cut to
+/// whole sectors. The offsets stay u64, on 32-bit targets they don't fit a `usize`.
+fn next_chunk(
+    read_offset: u64,
+    scoop_bytes: u64,
+    buffer_cap: usize,
+    sector_size: Option<u64>,
+) -> (usize, bool) {
+    let left = scoop_bytes.saturating_sub(read_offset);
+    if left > buffer_cap as u64 {
+        return (buffer_cap, false);
+    }
+    let len = match sector_size {
+        Some(sector_size) => left - left % sector_size,
+        None => left,
+    };
+    // at most `buffer_cap`
+    (len as usize, true)
+}
+
+/// The x-factor of a compressed plot's name, `account_startnonce_nonces_xFACTOR`. Such a plot
+/// would need part of every nonce hashed while mining, which no Signum plotter specifies, and
+/// Signum's PoC+ weighs commitment against capacity with regular PoC2 plots. The factor is only
+/// recognized to tell these plots apart from broken names.
+fn compression_factor(part: &str) -> Option<u32> {
+    part.strip_prefix(['x', 'X'])?
+        .parse()
+        .ok()
+        .filter(|&factor| factor > 1)
+}
+
+/// Meta and x-factor of a compressed plot, None for any other file name. These plots are never
+/// loaded, the inventory still lists them with the capacity on disk.
+pub fn compressed_plot(file_name: &str) -> Option<(Meta, u32)> {
+    let (plot_file, _) = strip_encrypted_suffix(file_name);
+    let parts: Vec<&str> = plot_file.split('_').collect();
+    let [account_id, start_nonce, nonces, factor] = parts[..] else {
+        return None;
+    };
+    let factor = compression_factor(factor)?;
+    let meta = Meta {
+        account_id: account_id.parse().ok()?,
+        start_nonce: start_nonce.parse().ok()?,
+        nonces: nonces.parse().ok()?,
+        name: plot_file.to_owned(),
+    };
+    Some((meta, factor))
+}
+
+/// `offset` for the libc calls taking an `off_t`, None if it doesn't fit like beyond 2 GiB on
+/// 32-bit targets without large file offsets.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn to_off_t(offset: u64) -> Option<libc::off_t> {
+    libc::off_t::try_from(offset).ok()
+}
+
+// a plot that's gone (rescan, retired) doesn't keep its file open
+impl Drop for Plot {
+    fn drop(&mut self) {
+        fd_pool::take(&self.fd_key());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_chunk() {
+        let buffer_cap = 1 << 20;
+        assert_eq!(next_chunk(0, 3 << 20, buffer_cap, None), (buffer_cap, false));
+        assert_eq!(next_chunk(2 << 20, 3 << 20, buffer_cap, None), (buffer_cap, true));
+        // the last read is short, and cut to sectors with direct I/O
+        assert_eq!(next_chunk(0, 4096 + 64, buffer_cap, None), (4096 + 64, true));
+        assert_eq!(next_chunk(0, 4096 + 64, buffer_cap, Some(4096)), (4096, true));
+        assert_eq!(next_chunk(64, 64, buffer_cap, None), (0, true));
+    }
+
+    #[test]
+    fn test_next_chunk_beyond_4_gib() {
+        // offsets a 32-bit usize would wrap
+        let scoop_bytes = (1u64 << 32) + 3 * 4096;
+        let buffer_cap = 8192;
+        assert_eq!(
+            next_chunk(1 << 32, scoop_bytes, buffer_cap, Some(4096)),
+            (buffer_cap, false)
+        );
+        assert_eq!(
+            next_chunk((1 << 32) + 8192, scoop_bytes, buffer_cap, Some(4096)),
+            (4096, true)
+        );
+        assert_eq!(
+            next_chunk(u64::MAX - 100, u64::MAX, buffer_cap, None),
+            (100, true)
+        );
+    }
+
+    #[cfg(not(feature = "async_io"))]
+    #[test]
+    fn test_read_start_nonce() {
+        let dir = std::env::temp_dir().join(format!("signum-miner-plot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("7_1000_2");
+        fs::write(&path, vec![0u8; 2 * NONCE_SIZE as usize]).unwrap();
+
+        let mut plot = Plot::new(&path, false, false, None, false).unwrap();
+        // one nonce per read, scoop 5 reports the plot's own nonces like scoop 0
+        let mut bs = vec![0u8; SCOOP_SIZE as usize];
+        plot.prepare(5).unwrap();
+        assert_eq!(plot.read(&mut bs, 5).unwrap(), (64, 1000, false));
+        assert_eq!(plot.read(&mut bs, 5).unwrap(), (64, 1001, true));
+        drop(plot);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pre_seek_region() {
+        let target = PreSeekTarget {
+            path: String::new(),
+            base_offset: 4096,
+            nonces: 8,
+        };
+        assert_eq!(target.region(0, 1 << 20), (4096, 8 * SCOOP_SIZE));
+        assert_eq!(target.region(3, 64), (4096 + 3 * 8 * SCOOP_SIZE, 64));
+        assert_eq!(
+            target.region(4095, u64::MAX),
+            (4096 + 4095 * 8 * SCOOP_SIZE, 8 * SCOOP_SIZE)
+        );
+
+        // the reader starts where the pre-seek went
+        let dir = std::env::temp_dir().join(format!("signum-miner-pre-seek-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("7_0_2");
+        fs::write(&path, vec![0u8; 2 * NONCE_SIZE as usize]).unwrap();
+        let plot = Plot::new(&path, false, false, None, false).unwrap();
+        for scoop in [0, 1, 4095] {
+            assert_eq!(
+                plot.pre_seek_target().region(scoop, 1 << 20),
+                (plot.scoop_addr(scoop), 2 * SCOOP_SIZE)
+            );
+        }
+        drop(plot);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rescanned_plot_keeps_its_handle() {
+        let dir = std::env::temp_dir().join(format!("signum-miner-rescan-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("7_0_1");
+        fs::write(&path, vec![0u8; NONCE_SIZE as usize]).unwrap();
+
+        let old = Plot::new(&path, false, false, None, false).unwrap();
+        let new = Plot::new(&path, false, false, None, false).unwrap();
+        drop(old);
+        assert!(fd_pool::take(&new.fd_key()).is_some());
+        drop(new);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compression_factor() {
+        assert_eq!(compression_factor("x4"), Some(4));
+        assert_eq!(compression_factor("X2"), Some(2));
+        assert_eq!(compression_factor("x1"), None);
+        assert_eq!(compression_factor("1024"), None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    #[test]
+    fn test_to_off_t() {
+        assert_eq!(to_off_t(4096), Some(4096));
+        let beyond_2_gib = 1u64 << 31;
+        assert_eq!(
+            to_off_t(beyond_2_gib).is_some(),
+            std::mem::size_of::<libc::off_t>() == 8
+        );
+        assert_eq!(to_off_t(u64::MAX), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Order in which the plots of a drive are read.
+//!
+//! A drive reads its plots one after the other. By default the most recently modified plot comes
+//! first, so a fresh plot is checked right away, but file times change and every rescan can shuffle
+//! the sequence. `path` and `start_nonce` read the plots in the same order every round, which makes
+//! rounds comparable when debugging a drive. `custom` reads the plots listed in
+//! `plot_order_custom` first, in that order, and the rest by path. Ties are always broken by path.
+
+use crate::config::Cfg;
+use filetime::FileTime;
+use serde::de::{self, Deserialize, Deserializer};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum PlotOrder {
+    Modified,
+    Path,
+    StartNonce,
+    Custom,
+}
+
+impl<'de> Deserialize<'de> for PlotOrder {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "modified" => Ok(PlotOrder::Modified),
+            "path" => Ok(PlotOrder::Path),
+            "start_nonce" => Ok(PlotOrder::StartNonce),
+            "custom" => Ok(PlotOrder::Custom),
+            _ => Err(de::Error::custom(format!(
+                "unknown plot_order '{}' (modified, path, start_nonce, custom)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Sort key of a plot, compared field by field: position in the custom list, negated modification
+/// time, start nonce and path.
+pub type SortKey = (usize, i64, u64, String);
+
+#[derive(Debug, Clone)]
+pub struct PlotOrdering {
+    order: PlotOrder,
+    // file names or full paths
+    custom: Vec<String>,
+}
+
+impl PlotOrdering {
+    pub fn new(order: PlotOrder, custom: Vec<String>) -> PlotOrdering {
+        PlotOrdering { order, custom }
+    }
+
+    pub fn from_cfg(cfg: &Cfg) -> PlotOrdering {
+        PlotOrdering::new(cfg.plot_order, cfg.plot_order_custom.clone())
+    }
+
+    pub fn key(&self, path: &str, start_nonce: u64) -> SortKey {
+        let by_path = path.to_owned();
+        match self.order {
+            PlotOrder::Modified => (0, -modified(path), 0, by_path),
+            PlotOrder::Path => (0, 0, 0, by_path),
+            PlotOrder::StartNonce => (0, 0, start_nonce, by_path),
+            PlotOrder::Custom => (self.position(path), 0, 0, by_path),
+        }
+    }
+
+    /// Position in the custom list, plots that aren't listed go last.
+    fn position(&self, path: &str) -> usize {
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy());
+        self.custom
+            .iter()
+            .position(|entry| entry == path || Some(entry.as_str()) == name.as_deref())
+            .unwrap_or(usize::MAX)
+    }
+}
+
+fn modified(path: &str) -> i64 {
+    match std::fs::metadata(path) {
+        Ok(m) => FileTime::from_last_modification_time(&m).unix_seconds(),
+        Err(e) => {
+            warn!("failed to get metadata for {}: {}", path, e);
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(ordering: &PlotOrdering, plots: &[(&str, u64)]) -> Vec<String> {
+        let mut plots = plots.to_vec();
+        plots.sort_by_cached_key(|&(path, start_nonce)| ordering.key(path, start_nonce));
+        plots.into_iter().map(|(path, _)| path.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_plot_order() {
+        let plots = [
+            ("/mnt/a/1_300_10", 300),
+            ("/mnt/a/1_1000_10", 1000),
+            ("/mnt/a/1_0_10", 0),
+        ];
+        assert_eq!(
+            sorted(&PlotOrdering::new(PlotOrder::Path, Vec::new()), &plots),
+            ["/mnt/a/1_0_10", "/mnt/a/1_1000_10", "/mnt/a/1_300_10"]
+        );
+        assert_eq!(
+            sorted(
+                &PlotOrdering::new(PlotOrder::StartNonce, Vec::new()),
+                &plots
+            ),
+            ["/mnt/a/1_0_10", "/mnt/a/1_300_10", "/mnt/a/1_1000_10"]
+        );
+
+        let custom = PlotOrdering::new(
+            PlotOrder::Custom,
+            vec!["1_300_10".to_owned(), "/mnt/a/1_1000_10".to_owned()],
+        );
+        assert_eq!(
+            sorted(&custom, &plots),
+            ["/mnt/a/1_300_10", "/mnt/a/1_1000_10", "/mnt/a/1_0_10"]
+        );
+        assert!(serde_yaml::from_str::<PlotOrder>("newest").is_err());
+    }
+}
@@ -1,7 +1,11 @@
-use crate::shabal256::{shabal256_deadline_fast, shabal256_hash_fast};
+use crate::shabal256::{shabal256, shabal256_deadline_fast, shabal256_hash_fast};
 
 #[allow(dead_code)]
 const SCOOP_SIZE: usize = 64;
+const HASH_SIZE: usize = 32;
+const HASH_CAP: usize = 4096;
+const SCOOPS_IN_NONCE: usize = 4096;
+pub const NONCE_SIZE: usize = SCOOP_SIZE * SCOOPS_IN_NONCE;
 
 pub fn decode_gensig(gensig: &str) -> [u8; 32] {
     let mut gensig_bytes = [0; 32];
@@ -42,3 +46,61 @@ pub fn find_best_deadline_rust(
     }
     (best_deadline, best_offset as u64)
 }
+
+/// Generates a single nonce in PoC2 layout (scoop-wise, second hash of scoop `i` swapped with the
+/// second hash of scoop `4095 - i`). Slow, only meant for verifying small samples of plots.
+pub fn generate_nonce(account_id: u64, nonce: u64) -> Vec<u8> {
+    let mut buf = vec![0u8; NONCE_SIZE + 16];
+    buf[NONCE_SIZE..NONCE_SIZE + 8].copy_from_slice(&account_id.to_be_bytes());
+    buf[NONCE_SIZE + 8..].copy_from_slice(&nonce.to_be_bytes());
+
+    for i in (0..NONCE_SIZE).step_by(HASH_SIZE).rev() {
+        let end = (i + HASH_SIZE + HASH_CAP).min(NONCE_SIZE + 16);
+        let hash = shabal256(&buf[i + HASH_SIZE..end]);
+        buf[i..i + HASH_SIZE].copy_from_slice(&hash);
+    }
+
+    let final_hash = shabal256(&buf);
+    buf.truncate(NONCE_SIZE);
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte ^= final_hash[i % HASH_SIZE];
+    }
+
+    // PoC1 -> PoC2
+    for scoop in 0..SCOOPS_IN_NONCE / 2 {
+        let a = scoop * SCOOP_SIZE + HASH_SIZE;
+        let b = (SCOOPS_IN_NONCE - 1 - scoop) * SCOOP_SIZE + HASH_SIZE;
+        let (head, tail) = buf.split_at_mut(b);
+        head[a..a + HASH_SIZE].swap_with_slice(&mut tail[..HASH_SIZE]);
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_nonce_matches_test_plot() {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data")
+            .join("10282355196851764065_0_8");
+        let plot = std::fs::read(path).unwrap();
+        let nonces = 8;
+
+        // the last 8 bytes of the test plot are zeroed, so the final scoop of nonce 7 is skipped
+        for (nonce, scoops) in [(0usize, &[0usize, 1, 2048, 4095][..]), (7, &[0, 1, 2048][..])] {
+            let generated = generate_nonce(10282355196851764065, nonce as u64);
+            for &scoop in scoops {
+                let in_plot = (scoop * nonces + nonce) * SCOOP_SIZE;
+                assert_eq!(
+                    &plot[in_plot..in_plot + SCOOP_SIZE],
+                    &generated[scoop * SCOOP_SIZE..(scoop + 1) * SCOOP_SIZE],
+                    "nonce {} scoop {}",
+                    nonce,
+                    scoop
+                );
+            }
+        }
+    }
+}
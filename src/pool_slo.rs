@@ -0,0 +1,258 @@
+//! Submission latency and availability per pool.
+//!
+//! Every submission's round trip is recorded with its outcome. A submission the pool answered,
+//! accepted or rejected, counts as available. One that failed on the connection or was turned away
+//! as busy does not. Latencies go into a histogram per pool, and the p50, p95 and p99 are taken
+//! from the samples of each configured window, so a pool's claim that slow submissions are the
+//! miner's connection can be held against the numbers. Each bucket keeps its latest submission as
+//! an exemplar for the OpenMetrics export, see `openmetrics`.
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// Upper bounds of the histogram buckets in milliseconds, the last one takes everything above.
+const BUCKETS_MS: [u64; 8] = [50, 100, 250, 500, 1000, 2500, 5000, 10000];
+/// Samples kept per pool, whatever the windows.
+const MAX_SAMPLES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: i64,
+    latency_ms: u64,
+    available: bool,
+}
+
+/// The submission behind a latency sample, as the audit log records it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub height: u64,
+    /// `<account_id>:<nonce>:<attempt>`
+    pub submission_id: String,
+    pub latency_ms: u64,
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct PoolSamples {
+    samples: VecDeque<Sample>,
+    // since the start, the last bucket counts everything above the bounds
+    histogram: [u64; BUCKETS_MS.len() + 1],
+    sum_ms: u64,
+    exemplars: [Option<Exemplar>; BUCKETS_MS.len() + 1],
+}
+
+/// The histogram of a pool since the start, buckets are cumulative as OpenMetrics has them.
+pub struct LatencyHistogram<'a> {
+    pub pool: &'a str,
+    /// Upper bound in milliseconds, None for the last bucket, with the count up to it.
+    pub buckets: Vec<(Option<u64>, u64, Option<&'a Exemplar>)>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowStats {
+    pub submissions: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    /// Percent of the submissions the pool answered.
+    pub availability: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PoolSlo {
+    /// Window lengths in seconds.
+    windows: Vec<u64>,
+    pools: BTreeMap<String, PoolSamples>,
+}
+
+impl PoolSlo {
+    pub fn new(mut windows: Vec<u64>) -> PoolSlo {
+        windows.retain(|&window| window > 0);
+        windows.sort_unstable();
+        windows.dedup();
+        PoolSlo {
+            windows,
+            pools: BTreeMap::new(),
+        }
+    }
+
+    /// Records a submission to `pool` at unix time `now`, `submission` is its height and id.
+    pub fn record(
+        &mut self,
+        pool: &str,
+        now: i64,
+        latency_ms: u64,
+        available: bool,
+        submission: Option<(u64, String)>,
+    ) {
+        let longest = self.windows.last().copied().unwrap_or(0) as i64;
+        let pool = self.pools.entry(pool.to_owned()).or_default();
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|&bound| latency_ms <= bound)
+            .unwrap_or(BUCKETS_MS.len());
+        pool.histogram[bucket] += 1;
+        pool.sum_ms += latency_ms;
+        if let Some((height, submission_id)) = submission {
+            pool.exemplars[bucket] = Some(Exemplar {
+                height,
+                submission_id,
+                latency_ms,
+                time: now,
+            });
+        }
+        pool.samples.push_back(Sample {
+            time: now,
+            latency_ms,
+            available,
+        });
+        while pool.samples.len() > MAX_SAMPLES
+            || pool
+                .samples
+                .front()
+                .is_some_and(|sample| sample.time < now - longest)
+        {
+            pool.samples.pop_front();
+        }
+    }
+
+    /// Keeps the `keep` most recent samples per pool, the histograms stay.
+    pub fn truncate(&mut self, keep: usize) {
+        for pool in self.pools.values_mut() {
+            while pool.samples.len() > keep {
+                pool.samples.pop_front();
+            }
+        }
+    }
+
+    pub fn window_stats(&self, pool: &str, now: i64, window: u64) -> Option<WindowStats> {
+        let samples: Vec<&Sample> = self
+            .pools
+            .get(pool)?
+            .samples
+            .iter()
+            .filter(|sample| sample.time >= now - window as i64)
+            .collect();
+        if samples.is_empty() {
+            return None;
+        }
+        let mut latencies: Vec<u64> = samples.iter().map(|sample| sample.latency_ms).collect();
+        latencies.sort_unstable();
+        let percentile = |p: f64| {
+            let rank = (p / 100.0 * latencies.len() as f64).ceil() as usize;
+            latencies[rank.clamp(1, latencies.len()) - 1]
+        };
+        let available = samples.iter().filter(|sample| sample.available).count();
+        Some(WindowStats {
+            submissions: samples.len(),
+            p50_ms: percentile(50.0),
+            p95_ms: percentile(95.0),
+            p99_ms: percentile(99.0),
+            availability: available as f64 * 100.0 / samples.len() as f64,
+        })
+    }
+
+    fn histogram(pool: &PoolSamples) -> String {
+        BUCKETS_MS
+            .iter()
+            .map(|bound| format!("<={}ms", bound))
+            .chain(std::iter::once(format!(
+                ">{}ms",
+                BUCKETS_MS[BUCKETS_MS.len() - 1]
+            )))
+            .zip(pool.histogram.iter())
+            .filter(|(_, &count)| count > 0)
+            .map(|(bucket, count)| format!("{} {}", bucket, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn histograms(&self) -> Vec<LatencyHistogram<'_>> {
+        self.pools
+            .iter()
+            .map(|(url, pool)| {
+                let mut count = 0;
+                let buckets = BUCKETS_MS
+                    .iter()
+                    .map(|&bound| Some(bound))
+                    .chain(std::iter::once(None))
+                    .zip(pool.histogram.iter().zip(pool.exemplars.iter()))
+                    .map(|(bound, (&n, exemplar))| {
+                        count += n;
+                        (bound, count, exemplar.as_ref())
+                    })
+                    .collect();
+                LatencyHistogram {
+                    pool: url,
+                    buckets,
+                    count,
+                    sum_ms: pool.sum_ms,
+                }
+            })
+            .collect()
+    }
+
+    /// Lines for the metrics summary, empty before the first submission.
+    pub fn summary(&self, now: i64) -> String {
+        let mut summary = String::new();
+        for (url, pool) in &self.pools {
+            summary.push_str(&format!("  {}: {}\n", url, PoolSlo::histogram(pool)));
+            for &window in &self.windows {
+                if let Some(stats) = self.window_stats(url, now, window) {
+                    summary.push_str(&format!(
+                        "    last {}: {} submissions, p50 {}ms, p95 {}ms, p99 {}ms, {:.2}% available\n",
+                        format_window(window),
+                        stats.submissions,
+                        stats.p50_ms,
+                        stats.p95_ms,
+                        stats.p99_ms,
+                        stats.availability
+                    ));
+                }
+            }
+        }
+        summary
+    }
+}
+
+fn format_window(secs: u64) -> String {
+    if secs.is_multiple_of(86_400) {
+        format!("{}d", secs / 86_400)
+    } else if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_stats() {
+        let mut slo = PoolSlo::new(vec![86_400, 3600, 0]);
+        let now = 1_000_000;
+        // an old slow submission only the day sees
+        slo.record("pool", now - 7200, 9000, false, None);
+        for latency in 1..=100 {
+            slo.record("pool", now - 60, latency * 10, latency != 100, None);
+        }
+
+        let hour = slo.window_stats("pool", now, 3600).unwrap();
+        assert_eq!(hour.submissions, 100);
+        assert_eq!((hour.p50_ms, hour.p95_ms, hour.p99_ms), (500, 950, 990));
+        assert_eq!(hour.availability, 99.0);
+        let day = slo.window_stats("pool", now, 86_400).unwrap();
+        assert_eq!((day.submissions, day.p99_ms), (101, 1000));
+        assert_eq!(slo.window_stats("other", now, 3600), None);
+
+        let summary = slo.summary(now);
+        assert!(summary.contains("<=50ms 5, <=100ms 5,"));
+        assert!(summary.contains("last 1h: 100 submissions"));
+        assert!(summary.contains("last 1d: 101 submissions"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Connectivity check before the first round.
+//!
+//! For every pool the host has to resolve, `getMiningInfo` has to answer, and every account the
+//! miner knows of is sent a test submission of nonce 0, which a pool rejects with a deadline
+//! error for accounts it knows and with an account error for the others. Failures are explained
+//! (DNS, firewall, TLS, pool errors) instead of surfacing as a silent miner that never starts a
+//! round. `strict` refuses to start on any failure, `warn` only logs them.
+
+use crate::com::api::{FetchError, RejectionReason};
+use crate::com::client::{Client, ConnectionSettings, ProxyDetails, SubmissionParameters};
+use crate::config::Cfg;
+use crate::drive_labels;
+use crate::plot_cipher::strip_encrypted_suffix;
+use serde::de::{self, Deserialize, Deserializer};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Preflight {
+    Off,
+    Warn,
+    Strict,
+}
+
+impl<'de> Deserialize<'de> for Preflight {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "off" => Ok(Preflight::Off),
+            "warn" => Ok(Preflight::Warn),
+            "strict" => Ok(Preflight::Strict),
+            _ => Err(de::Error::custom(format!(
+                "unknown preflight mode '{}' (off, warn, strict)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Runs the checks and logs every failure. Err with the number of failures if the miner must
+/// not start.
+pub async fn preflight(cfg: &Cfg) -> Result<(), usize> {
+    if cfg.preflight == Preflight::Off {
+        return Ok(());
+    }
+    let inner = ConnectionSettings {
+        timeout: cfg.timeout,
+        pool_max_idle_per_host: 1,
+        keep_alive: 0,
+        http2: cfg.http2,
+    }
+    .build();
+    let accounts = accounts(cfg);
+
+    let mut failures = 0;
+    for pool in &cfg.pools {
+        let client = Client::new(
+            pool.url.clone(),
+            cfg.account_id_to_secret_phrase.clone(),
+            inner.clone(),
+            0,
+            ProxyDetails::Disabled,
+            cfg.additional_headers.clone(),
+        );
+        let problems = check_pool(&client, &pool.url, &accounts, cfg.dry_run).await;
+        if problems.is_empty() {
+            info!(
+                "preflight: {} ok, {} account(s) accepted",
+                pool.url,
+                accounts.len()
+            );
+        }
+        for problem in &problems {
+            if cfg.preflight == Preflight::Strict {
+                error!("preflight: {}: {}", pool.url, problem);
+            } else {
+                warn!("preflight: {}: {}", pool.url, problem);
+            }
+        }
+        failures += problems.len();
+    }
+
+    if failures > 0 && cfg.preflight == Preflight::Strict {
+        Err(failures)
+    } else {
+        Ok(())
+    }
+}
+
+async fn check_pool(
+    client: &Client,
+    url: &Url,
+    accounts: &BTreeSet<u64>,
+    dry_run: bool,
+) -> Vec<String> {
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return vec!["url has no host".to_owned()],
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+    if let Err(e) = tokio::net::lookup_host((host, port)).await {
+        return vec![format!("can't resolve {}: {}", host, e)];
+    }
+
+    let mining_info = match client.get_mining_info().await {
+        Ok(mining_info) => mining_info,
+        Err(e) => return vec![format!("getMiningInfo failed: {}", describe(&e))],
+    };
+
+    // dry runs never send a submission, not even a test one
+    if dry_run {
+        return Vec::new();
+    }
+    let mut problems = Vec::new();
+    for &account_id in accounts {
+        let submission = SubmissionParameters {
+            account_id,
+            nonce: 0,
+            height: mining_info.height,
+            block: 0,
+            deadline_unadjusted: u64::MAX,
+            deadline: u64::MAX,
+            gen_sig: [0; 32],
+        };
+        match client.submit_nonce(&submission).await {
+            Ok(_) => {}
+            Err(FetchError::Pool(e)) if e.reason() == RejectionReason::UnknownAccount => {
+                problems.push(format!("account {} rejected: {}", account_id, e.message))
+            }
+            Err(FetchError::Pool(e)) if e.code == 0 => problems.push(format!(
+                "account {}: unexpected answer to the test submission: {}",
+                account_id,
+                e.message.chars().take(200).collect::<String>()
+            )),
+            // refused for its deadline or height, so the account itself is known
+            Err(FetchError::Pool(_)) => {}
+            Err(e) => problems.push(format!(
+                "test submission for account {} failed: {}",
+                account_id,
+                describe(&e)
+            )),
+        }
+    }
+    problems
+}
+
+/// Names the likely cause of a failed request.
+fn describe(e: &FetchError) -> String {
+    match e {
+        FetchError::Http(e) => {
+            let mut chain = e.to_string();
+            let mut source = e.source();
+            while let Some(e) = source {
+                chain += &format!(": {}", e);
+                source = e.source();
+            }
+            classify(&chain, e.is_timeout(), e.is_connect())
+        }
+        FetchError::Pool(_) | FetchError::TooLarge(_) => e.to_string(),
+    }
+}
+
+fn classify(chain: &str, timeout: bool, connect: bool) -> String {
+    let lower = chain.to_lowercase();
+    if ["certificate", "tls", "ssl", "handshake"]
+        .iter()
+        .any(|p| lower.contains(p))
+    {
+        format!(
+            "TLS error, check the pool's certificate and the system time ({})",
+            chain
+        )
+    } else if timeout {
+        format!(
+            "timed out, a firewall may be dropping the connection ({})",
+            chain
+        )
+    } else if connect {
+        format!("can't connect, check the port and the firewall ({})", chain)
+    } else {
+        chain.to_owned()
+    }
+}
+
+/// Accounts of the configured plots, from their file names, and those with a secret phrase.
+fn accounts(cfg: &Cfg) -> BTreeSet<u64> {
+    let mut accounts: BTreeSet<u64> = cfg.account_id_to_secret_phrase.keys().copied().collect();
+    accounts.extend(cfg.raw_plots.iter().map(|raw| raw.account_id));
+    for dir in &drive_labels::resolve_plot_dirs(&cfg.plot_dirs) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let (name, _) = strip_encrypted_suffix(&name);
+            if let Some(account_id) = name.split('_').next().and_then(|a| a.parse().ok()) {
+                accounts.insert(account_id);
+            }
+        }
+    }
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert!(classify(
+            "error trying to connect: invalid peer certificate",
+            false,
+            true
+        )
+        .starts_with("TLS error"));
+        assert!(classify("operation timed out", true, false).starts_with("timed out"));
+        assert!(classify("Connection refused", false, true).starts_with("can't connect"));
+        assert_eq!(classify("HTTP 502", false, false), "HTTP 502");
+    }
+}
@@ -0,0 +1,151 @@
+//! Capacity quotas.
+//!
+//! A quota caps the capacity mined from a directory (a whole drive with its mount point or
+//! `LABEL=`), from an account, or from an account within a directory, e.g. to mine only 10 TiB of
+//! a rented disk billed by usage. The plots are taken by path until the next one would exceed a
+//! quota it falls under, so every rescan selects the same plots. Plots left out aren't read.
+
+use crate::drive_labels;
+use std::path::{Path, PathBuf};
+
+/// Nonces per TiB, a nonce is 256 KiB.
+const NONCES_PER_TIB: f64 = 4.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaCfg {
+    /// Plots below this directory, `LABEL=` and `UUID=` work like in `plot_dirs`.
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+
+    #[serde(default)]
+    pub account_id: Option<u64>,
+
+    pub max_tib: f64,
+}
+
+/// A plot as far as quotas care.
+pub struct Candidate<'a> {
+    pub path: &'a str,
+    pub account_id: u64,
+    pub nonces: u64,
+}
+
+struct Quota {
+    dir: Option<PathBuf>,
+    account_id: Option<u64>,
+    max_nonces: u64,
+    used: u64,
+}
+
+impl Quota {
+    fn matches(&self, plot: &Candidate) -> bool {
+        let in_dir = match &self.dir {
+            Some(dir) => Path::new(plot.path).starts_with(dir),
+            None => true,
+        };
+        let of_account = match self.account_id {
+            Some(id) => id == plot.account_id,
+            None => true,
+        };
+        in_dir && of_account
+    }
+}
+
+/// Which of `plots` to mine, in the order given.
+pub fn select(quotas: &[QuotaCfg], plots: &[Candidate]) -> Vec<bool> {
+    let mut quotas: Vec<Quota> = quotas
+        .iter()
+        .filter_map(|cfg| {
+            let dir = match cfg.dir.as_deref().map(drive_labels::resolve_dir) {
+                Some(Some(dir)) => Some(dir),
+                // the drive isn't mounted, there's nothing to cap
+                Some(None) => return None,
+                None => None,
+            };
+            Some(Quota {
+                dir,
+                account_id: cfg.account_id,
+                max_nonces: (cfg.max_tib.max(0.0) * NONCES_PER_TIB) as u64,
+                used: 0,
+            })
+        })
+        .collect();
+
+    let mut by_path: Vec<usize> = (0..plots.len()).collect();
+    by_path.sort_by_key(|&i| plots[i].path);
+
+    let mut selected = vec![true; plots.len()];
+    for i in by_path {
+        let plot = &plots[i];
+        let fits = quotas
+            .iter()
+            .filter(|quota| quota.matches(plot))
+            .all(|quota| quota.used.saturating_add(plot.nonces) <= quota.max_nonces);
+        if !fits {
+            selected[i] = false;
+            continue;
+        }
+        for quota in quotas.iter_mut().filter(|quota| quota.matches(plot)) {
+            quota.used += plot.nonces;
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, account_id: u64, tib: u64) -> Candidate<'_> {
+        Candidate {
+            path,
+            account_id,
+            nonces: tib * NONCES_PER_TIB as u64,
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let plots = [
+            candidate("/mnt/shared/1_2_0", 1, 4),
+            candidate("/mnt/shared/1_0_0", 1, 4),
+            candidate("/mnt/shared/1_1_0", 1, 4),
+            candidate("/mnt/own/2_0_0", 2, 8),
+            candidate("/mnt/own/1_3_0", 1, 8),
+        ];
+        assert_eq!(select(&[], &plots), [true; 5]);
+
+        let dir = QuotaCfg {
+            dir: Some(PathBuf::from("/mnt/shared")),
+            account_id: None,
+            max_tib: 10.0,
+        };
+        // by path: 1_0_0 and 1_1_0 fit, 1_2_0 doesn't
+        assert_eq!(
+            select(std::slice::from_ref(&dir), &plots),
+            [false, true, true, true, true]
+        );
+
+        let account = QuotaCfg {
+            dir: None,
+            account_id: Some(1),
+            max_tib: 12.0,
+        };
+        // /mnt/own/1_3_0 comes first by path and takes 8 of account 1's 12 TiB
+        assert_eq!(
+            select(&[dir, account], &plots),
+            [false, true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn test_quota_on_missing_drive() {
+        let plots = [candidate("/mnt/shared/1_0_0", 1, 4)];
+        let missing = QuotaCfg {
+            dir: Some(PathBuf::from("LABEL=signum-miner-test-missing")),
+            account_id: None,
+            max_tib: 0.0,
+        };
+        assert_eq!(select(&[missing], &plots), [true]);
+    }
+}
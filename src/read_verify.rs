@@ -0,0 +1,200 @@
+//! `verify-reads`: the direct I/O read path checked against buffered reads.
+//!
+//! Direct I/O needs sector aligned offsets and lengths, so the plot reader aligns a scoop's start
+//! down and reads from the shifted position. A mistake there fails no read, the miner just hashes
+//! the bytes of neighbouring nonces. This command reads random scoops of every plot chunk by chunk
+//! through the miner's direct I/O path and compares every chunk with the same nonces read by a
+//! plain buffered read. For a mismatch it looks for the shift, in nonces, at which the chunk does
+//! match the file.
+
+use crate::config::Cfg;
+use crate::drive_toggles::DriveToggles;
+use crate::miner::{scan_plots, PlotScan};
+use crate::plot::Plot;
+use crate::plot_order::PlotOrdering;
+use crate::retire::RetireList;
+use rand::Rng;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+const SCOOP_SIZE: u64 = 64;
+const SCOOPS_PER_NONCE: u32 = 4096;
+/// Shifts searched in both directions, beyond the largest sectors.
+const MAX_SHIFT: u64 = 256;
+
+#[derive(Debug)]
+pub struct Mismatch {
+    pub scoop: u32,
+    /// First nonce of the chunk.
+    pub start_nonce: u64,
+    /// Offset of the first differing byte in the chunk.
+    pub first_byte: usize,
+    /// Nonces the direct read is off by, if it matches the file elsewhere.
+    pub shift: Option<i64>,
+}
+
+/// Shift in nonces at which `chunk` is found in `window`, which starts `before` bytes ahead of
+/// the chunk's expected position.
+fn find_shift(window: &[u8], before: u64, chunk: &[u8]) -> Option<i64> {
+    let max = MAX_SHIFT * SCOOP_SIZE;
+    (0..=2 * MAX_SHIFT)
+        .map(|i| i * SCOOP_SIZE)
+        .filter(|&pos| pos + max >= before && pos <= before + max)
+        .find(|&pos| {
+            window
+                .get(pos as usize..pos as usize + chunk.len())
+                .is_some_and(|candidate| candidate == chunk)
+        })
+        .map(|pos| (pos as i64 - before as i64) / SCOOP_SIZE as i64)
+}
+
+/// Reads `len` bytes at `addr` and up to `MAX_SHIFT` nonces around them, buffered.
+fn read_window(file: &mut File, addr: u64, len: usize) -> io::Result<(Vec<u8>, u64)> {
+    let before = addr.min(MAX_SHIFT * SCOOP_SIZE);
+    let mut window = Vec::new();
+    file.seek(SeekFrom::Start(addr - before))?;
+    file.take(before + len as u64 + MAX_SHIFT * SCOOP_SIZE)
+        .read_to_end(&mut window)?;
+    Ok((window, before))
+}
+
+/// Reads `scoop` of the plot through its own read path and compares every chunk.
+async fn verify_scoop(
+    plot: &mut Plot,
+    scoop: u32,
+    buffer_size: usize,
+) -> io::Result<Option<Mismatch>> {
+    let mut file = File::open(&plot.path)?;
+    let scoop_addr = plot.scoop_addr(scoop);
+    #[cfg(feature = "async_io")]
+    plot.prepare_async(scoop).await?;
+    #[cfg(not(feature = "async_io"))]
+    plot.prepare(scoop)?;
+
+    let mut bs = vec![0u8; buffer_size];
+    loop {
+        #[cfg(feature = "async_io")]
+        let (len, start_nonce, finished) = plot.read_async(&mut bs, scoop).await?;
+        #[cfg(not(feature = "async_io"))]
+        let (len, start_nonce, finished) = plot.read(&mut bs, scoop)?;
+
+        let addr = scoop_addr + (start_nonce - plot.meta.start_nonce) * SCOOP_SIZE;
+        let (window, before) = read_window(&mut file, addr, len)?;
+        let expected = window.get(before as usize..before as usize + len);
+        let first_byte = match expected {
+            Some(expected) => bs[..len].iter().zip(expected).position(|(a, b)| a != b),
+            None => Some(0),
+        };
+        if let Some(first_byte) = first_byte {
+            return Ok(Some(Mismatch {
+                scoop,
+                start_nonce,
+                first_byte,
+                shift: find_shift(&window, before, &bs[..len]),
+            }));
+        }
+        if finished {
+            return Ok(None);
+        }
+    }
+}
+
+/// Checks `scoops` random scoops of every plot, true if all of them read the same.
+pub async fn run(cfg: &Cfg, scoops: u32) -> bool {
+    let PlotScan {
+        drive_id_to_plots, ..
+    } = scan_plots(
+        &cfg.plot_dirs,
+        &cfg.raw_plots,
+        cfg.sparse_plots,
+        &PlotOrdering::from_cfg(cfg),
+        &cfg.quotas,
+        cfg.poc1_support,
+        true,
+        false,
+        cfg.plot_encryption_key.as_deref(),
+        &RetireList::load(&cfg.retire_list),
+        &DriveToggles::default(),
+    );
+
+    let (mut checked, mut failed) = (0, 0);
+    // the scan is this command's own, nothing else holds its plots and they need no locking
+    for plot in drive_id_to_plots
+        .into_values()
+        .filter_map(Arc::into_inner)
+        .flatten()
+    {
+        #[cfg(feature = "async_io")]
+        let mut plot = plot.into_inner();
+        #[cfg(not(feature = "async_io"))]
+        let mut plot = plot
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if plot.transforms_data() {
+            info!("{}: encrypted or PoC1, skipped", plot.path);
+            continue;
+        }
+        if !plot.uses_direct_io() {
+            info!(
+                "{}: no direct I/O for this plot, checking buffered reads",
+                plot.path
+            );
+        }
+        checked += 1;
+        for _ in 0..scoops {
+            let scoop = rand::thread_rng().gen_range(0, SCOOPS_PER_NONCE);
+            match verify_scoop(&mut plot, scoop, cfg.io_buffer_size).await {
+                Ok(None) => {}
+                Ok(Some(mismatch)) => {
+                    error!(
+                        "{}: scoop {}, chunk from nonce {} differs from byte {}{}",
+                        plot.path,
+                        mismatch.scoop,
+                        mismatch.start_nonce,
+                        mismatch.first_byte,
+                        match mismatch.shift {
+                            Some(shift) => format!(", it's the data {} nonces off", shift),
+                            None => String::new(),
+                        }
+                    );
+                    failed += 1;
+                    break;
+                }
+                Err(e) => {
+                    error!("{}: can't read scoop {}: {}", plot.path, scoop, e);
+                    failed += 1;
+                    break;
+                }
+            }
+        }
+    }
+    if failed == 0 {
+        info!(
+            "verify-reads: {} plots read the same with direct and buffered I/O",
+            checked
+        );
+    } else {
+        error!(
+            "verify-reads: {} of {} plots read differently",
+            failed, checked
+        );
+    }
+    failed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_shift() {
+        let window: Vec<u8> = (0..64 * 20).map(|i| (i / 64) as u8).collect();
+        // the chunk is expected at nonce 10 of the window but holds nonces 12 and 13
+        let chunk = &window[12 * 64..14 * 64];
+        assert_eq!(find_shift(&window, 10 * 64, chunk), Some(2));
+        assert_eq!(find_shift(&window, 12 * 64, chunk), Some(0));
+        assert_eq!(find_shift(&window, 15 * 64, chunk), Some(-3));
+        assert_eq!(find_shift(&window, 10 * 64, &[0xff; 64]), None);
+    }
+}
@@ -1,30 +1,69 @@
-use crate::miner::Buffer;
-#[cfg(feature = "opencl")]
+use crate::buffer_pool::BufferPool;
+use crate::cache_stats;
+use crate::cancel::CancelToken;
+use crate::fault_injection::{short_read, FaultInjector};
+use crate::io_priority::{self, IoPriorities, IoPriority};
+use crate::metrics::SharedDiskHealth;
+use crate::miner::{Buffer, MappedBuffer, NonceData};
+use crate::page_cache::{self, PageCache};
 use crate::miner::CpuBuffer;
-use crate::plot::{Meta, Plot};
-use crate::utils::new_thread_pool;
-use crossbeam_channel::{Receiver, Sender};
+use crate::plot::{Meta, Plot, PreSeekTarget};
+use crate::round_barrier::{Hashing, RoundBarrier};
+use crate::round_jitter;
+use crate::round_status::RoundProgress;
+use crate::scheduler::ReaderPool;
+use crate::stages::{RoundStages, Stage};
+use crossbeam_channel::Sender;
 use pbr::{ProgressBar, Units};
 use rayon::prelude::*;
 use std::collections::HashMap;
-use std::io::Stdout;
+use std::io::{self, Stdout};
 use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 #[cfg(feature = "async_io")]
 use tokio::sync::Mutex;
 #[cfg(not(feature = "async_io"))]
 use std::sync::Mutex;
 use stopwatch::Stopwatch;
+use tokio::sync::mpsc;
 
-pub struct BufferInfo {
-    pub len: usize,
+/// Everything a chunk shares with the rest of its drive's round. Built once per drive and round,
+/// so handing a chunk to a worker costs a single reference count instead of copies and clones.
+pub struct RoundHeader {
     pub height: u64,
     pub block: u64,
     pub base_target: u64,
-    pub gensig: Arc<[u8; 32]>,
+    pub gensig: [u8; 32],
+    pub drive_id: Arc<str>,
+    pub stages: Arc<RoundStages>,
+    pub cancel: CancelToken,
+    /// Shared by all drives of the round.
+    pub barrier: Arc<RoundBarrier>,
+    /// Where the workers send the round's deadlines, the hash workers serve every mining context.
+    pub tx_nonce_data: mpsc::Sender<NonceData>,
+}
+
+/// Handles the miner keeps for a running round, shared by the headers of all its drives.
+pub struct RoundHandles {
+    /// Where the round's stage times are summed up.
+    pub stages: Arc<RoundStages>,
+    /// Cancelled when the next round starts.
+    pub cancel: CancelToken,
+}
+
+pub struct BufferInfo {
+    pub header: Arc<RoundHeader>,
+    pub len: usize,
     pub start_nonce: u64,
     pub finished: bool,
+    /// Set on the marker the last drive of a round sends, it carries no data.
+    pub round_finished: bool,
     pub account_id: u64,
     pub gpu_signal: u64,
+    /// Counts the chunk on the round's barrier until the worker is done with it, None for signals.
+    #[allow(dead_code)] // only held to be dropped
+    pub hashing: Option<Hashing>,
 }
 pub struct ReadReply {
     pub buffer: Box<dyn Buffer + Send>,
@@ -35,14 +74,31 @@ pub struct ReadReply {
 pub struct Reader {
     drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>>,
     pub total_size: u64,
-    pool: rayon::ThreadPool,
-    rx_empty_buffers: Receiver<Box<dyn Buffer + Send>>,
-    tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
+    pool: Arc<ReaderPool>,
+    buffer_pool: Arc<BufferPool>,
     tx_read_replies_cpu: Sender<ReadReply>,
     tx_read_replies_gpu: Option<Vec<Sender<ReadReply>>>,
-    interupts: Vec<Sender<()>>,
+    tx_nonce_data: mpsc::Sender<NonceData>,
+    // cancelled when the next round starts
+    round: CancelToken,
     show_progress: bool,
     show_drive_stats: bool,
+    // first plot of every drive, 0 bytes disables pre-seeking
+    pre_seek_targets: Vec<PreSeekTarget>,
+    pre_seek_bytes: u64,
+    // bytes of the scoop per drive, and what the running round has read of them
+    drive_scoop_bytes: Vec<(Arc<str>, u64)>,
+    progress: Arc<RoundProgress>,
+    // upper bound of the random delay before a round's reads, 0 starts right away
+    start_jitter_ms: u64,
+    // read outcomes per drive, drives with an open circuit aren't read
+    disk_health: SharedDiskHealth,
+    faults: Option<Arc<FaultInjector>>,
+    io_priorities: Option<IoPriorities>,
+    // I/O priority of every drive's read task, resolved from `io_priorities`
+    drive_io_priority: HashMap<String, IoPriority>,
+    // the async read tasks run here, reader threads aren't part of any runtime
+    runtime: tokio::runtime::Handle,
 }
 
 impl Reader {
@@ -50,31 +106,49 @@ impl Reader {
     pub fn new(
         drive_id_to_plots: HashMap<String, Arc<Vec<Mutex<Plot>>>>,
         total_size: u64,
-        num_threads: usize,
-        rx_empty_buffers: Receiver<Box<dyn Buffer + Send>>,
-        tx_empty_buffers: Sender<Box<dyn Buffer + Send>>,
+        pool: Arc<ReaderPool>,
+        buffer_pool: Arc<BufferPool>,
         tx_read_replies_cpu: Sender<ReadReply>,
         tx_read_replies_gpu: Option<Vec<Sender<ReadReply>>>,
+        tx_nonce_data: mpsc::Sender<NonceData>,
         show_progress: bool,
         show_drive_stats: bool,
-        thread_pinning: bool,
         benchmark: bool,
+        pre_seek_bytes: u64,
+        start_jitter_ms: u64,
+        disk_health: SharedDiskHealth,
+        faults: Option<Arc<FaultInjector>>,
+        io_priorities: Option<IoPriorities>,
+        runtime: tokio::runtime::Handle,
     ) -> Reader {
         if !benchmark {
             check_overlap(&drive_id_to_plots);
         }
+        if page_cache::mode() == PageCache::Warm {
+            page_cache::warm(warm_targets(&drive_id_to_plots));
+        }
 
         Reader {
+            pre_seek_targets: pre_seek_targets(&drive_id_to_plots),
+            drive_scoop_bytes: drive_scoop_bytes(&drive_id_to_plots),
+            progress: Arc::default(),
+            drive_io_priority: drive_io_priority(io_priorities.as_ref(), &drive_id_to_plots),
+            io_priorities,
+            pre_seek_bytes,
+            start_jitter_ms,
             drive_id_to_plots,
             total_size,
-            pool: new_thread_pool(num_threads, thread_pinning),
-            rx_empty_buffers,
-            tx_empty_buffers,
+            pool,
+            buffer_pool,
             tx_read_replies_cpu,
             tx_read_replies_gpu,
-            interupts: Vec::new(),
+            tx_nonce_data,
+            round: CancelToken::default(),
             show_progress,
             show_drive_stats,
+            disk_health,
+            faults,
+            runtime,
         }
     }
 
@@ -85,80 +159,131 @@ impl Reader {
         base_target: u64,
         scoop: u32,
         gensig: &Arc<[u8; 32]>,
+        round: &RoundHandles,
     ) {
-        for interupt in &self.interupts {
-            interupt.send(()).ok();
+        let jitter = round_jitter::draw(self.start_jitter_ms);
+        if !jitter.is_zero() {
+            debug!("reader: starting round {} in {} ms", height, jitter.as_millis());
         }
+        let start = Instant::now() + jitter;
+        // get the heads moving while the previous round is interrupted and buffers come back,
+        // unless the reads are delayed on purpose
+        if self.pre_seek_bytes > 0 && jitter.is_zero() {
+            self.pre_seek(scoop);
+        }
+        self.round.cancel();
+        self.round = round.cancel.clone();
+        self.progress = Arc::new(RoundProgress::new(&self.drive_scoop_bytes));
         let mut pb = ProgressBar::new(self.total_size);
         pb.format("│██░│");
         pb.set_width(Some(80));
         pb.set_units(Units::Bytes);
         pb.message("Searching your hashes: ");
         let pb = Arc::new(Mutex::new(pb));
+        let barrier = Arc::new(RoundBarrier::new(self.drive_id_to_plots.len()));
+        let new_header = |drive_id: Arc<str>| {
+            Arc::new(RoundHeader {
+                height,
+                block,
+                base_target,
+                gensig: **gensig,
+                drive_id,
+                stages: round.stages.clone(),
+                cancel: round.cancel.clone(),
+                barrier: barrier.clone(),
+                tx_nonce_data: self.tx_nonce_data.clone(),
+            })
+        };
 
         // send start signals (dummy buffer) to gpu threads
-        #[cfg(feature = "opencl")]
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
+        let header = new_header(Arc::from(""));
+        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
         for i in 0..self.tx_read_replies_gpu.as_ref().unwrap().len() {
             if let Err(e) = self.tx_read_replies_gpu.as_ref().unwrap()[i].send(ReadReply {
                 buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
                 info: BufferInfo {
+                    header: header.clone(),
                     len: 1,
-                    height,
-                    block,
-                    base_target,
-                    gensig: gensig.clone(),
                     start_nonce: 0,
                     finished: false,
+                    round_finished: false,
                     account_id: 0,
                     gpu_signal: 1,
+                    hashing: None,
                 },
             }) {
                 error!("reader: failed to send 'round start' signal to GPU thread: {}", e);
             }
         }
 
-        self.interupts = self
-            .drive_id_to_plots
-            .iter()
-            .map(|(drive, plots)| {
-                let (interupt, task) = if self.show_progress {
-                    self.create_read_task(
-                        Some(pb.clone()),
-                        drive.clone(),
-                        plots.clone(),
-                        height,
-                        block,
-                        base_target,
-                        scoop,
-                        gensig.clone(),
-                        self.show_drive_stats,
-                    )
-                } else {
-                    self.create_read_task(
-                        None,
-                        drive.clone(),
-                        plots.clone(),
-                        height,
-                        block,
-                        base_target,
-                        scoop,
-                        gensig.clone(),
-                        self.show_drive_stats,
-                    )
-                };
+        // without drives nobody arrives at the barrier, the round is over right away
+        if self.drive_id_to_plots.is_empty() {
+            let header = new_header(Arc::from(""));
+            finish_round(
+                &header,
+                &self.tx_read_replies_cpu,
+                self.tx_read_replies_gpu.as_deref(),
+            );
+            return;
+        }
+        for (drive, plots) in &self.drive_id_to_plots {
+            let header = new_header(Arc::from(drive.as_str()));
+            let task = if self.show_progress {
+                self.create_read_task(
+                    Some(pb.clone()),
+                    drive.clone(),
+                    plots.clone(),
+                    scoop,
+                    header,
+                    self.show_drive_stats,
+                )
+            } else {
+                self.create_read_task(
+                    None,
+                    drive.clone(),
+                    plots.clone(),
+                    scoop,
+                    header,
+                    self.show_drive_stats,
+                )
+            };
 
-                self.pool.spawn(task);
-                interupt
-            })
-            .collect();
+            let cancel = round.cancel.clone();
+            let priority = self.drive_io_priority.get(drive).copied();
+            self.pool.spawn(drive, move || {
+                round_jitter::wait(start, &cancel);
+                io_priority::with(priority, task)
+            });
+        }
+    }
+
+    /// Issues the first seek of the round on every drive from a dedicated thread, the reader
+    /// pool may still be busy with the interrupted previous round.
+    fn pre_seek(&self, scoop: u32) {
+        for target in &self.pre_seek_targets {
+            let target = target.clone();
+            let len = self.pre_seek_bytes;
+            if let Err(e) = thread::Builder::new()
+                .name("pre-seek".to_owned())
+                .spawn(move || target.pre_seek(scoop, len))
+            {
+                debug!("pre-seek: can't spawn thread: {}", e);
+            }
+        }
+    }
+
+    /// What the running round has read per drive.
+    pub fn progress(&self) -> Arc<RoundProgress> {
+        self.progress.clone()
     }
 
     pub fn wakeup(&mut self) {
-        for plots in self.drive_id_to_plots.values() {
+        for (drive, plots) in &self.drive_id_to_plots {
             let plots = plots.clone();
-            self.pool.spawn(move || {
+            self.pool.spawn(drive, move || {
 #[cfg(feature = "async_io")]
-                let mut p = plots[0].blocking_lock();
+                let mut p = crate::utils::lock_sync(&plots[0]);
 #[cfg(not(feature = "async_io"))]
                 let mut p = match plots[0].lock() {
                     Ok(guard) => guard,
@@ -187,6 +312,12 @@ impl Reader {
         if !benchmark {
             check_overlap(&drive_id_to_plots);
         }
+        if page_cache::mode() == PageCache::Warm {
+            page_cache::warm(warm_targets(&drive_id_to_plots));
+        }
+        self.pre_seek_targets = pre_seek_targets(&drive_id_to_plots);
+        self.drive_scoop_bytes = drive_scoop_bytes(&drive_id_to_plots);
+        self.drive_io_priority = drive_io_priority(self.io_priorities.as_ref(), &drive_id_to_plots);
         self.drive_id_to_plots = drive_id_to_plots;
         self.total_size = total_size;
     }
@@ -198,26 +329,35 @@ impl Reader {
         pb: Option<Arc<Mutex<pbr::ProgressBar<Stdout>>>>,
         drive: String,
         plots: Arc<Vec<Mutex<Plot>>>,
-        height: u64,
-        block: u64,
-        base_target: u64,
         scoop: u32,
-        gensig: Arc<[u8; 32]>,
+        header: Arc<RoundHeader>,
         show_drive_stats: bool,
-    ) -> (Sender<()>, impl FnOnce()) {
-        let (tx_interupt, rx_interupt) = crossbeam_channel::unbounded();
-        let rx_empty_buffers = self.rx_empty_buffers.clone();
-        let tx_empty_buffers = self.tx_empty_buffers.clone();
+    ) -> impl FnOnce() {
+        let buffer_pool = self.buffer_pool.clone();
         let tx_read_replies_cpu = self.tx_read_replies_cpu.clone();
-        #[cfg(feature = "opencl")]
         let tx_read_replies_gpu = self.tx_read_replies_gpu.clone();
+        let disk_health = self.disk_health.clone();
+        let faults = self.faults.clone();
+        let progress = self.progress.clone();
 
-        (tx_interupt, move || {
+        move || {
+            // completed, cancelled, without workers or panicking, the drive is done with this round
+            let _arrival = Arrival {
+                header: header.clone(),
+                tx_read_replies_cpu: tx_read_replies_cpu.clone(),
+                tx_read_replies_gpu: tx_read_replies_gpu.clone(),
+            };
             let mut sw = Stopwatch::new();
             let mut elapsed = 0i64;
             let mut nonces_processed = 0u64;
+            let mut cached_bytes = 0u64;
             let plot_count = plots.len();
+            // the round still needs the drive to finish, a skipped drive sends one empty chunk
+            let mut skip = !disk_health_lock(&disk_health).allow_read(&drive);
             'outer: for (i_p, p) in plots.iter().enumerate() {
+                if skip && i_p + 1 < plot_count {
+                    continue 'outer;
+                }
                 let mut p = match p.lock() {
                     Ok(guard) => guard,
                     Err(poisoned) => {
@@ -225,19 +365,25 @@ impl Reader {
                         poisoned.into_inner()
                     }
                 };
-                if let Err(e) = p.prepare(scoop) {
-                    error!(
-                        "reader: error preparing {} for reading: {} -> skip one round",
-                        p.meta.name, e
-                    );
-                    continue 'outer;
+                if !skip {
+                    if let Err(e) = p.prepare(scoop) {
+                        error!(
+                            "reader: error preparing {} for reading: {} -> skip one round",
+                            p.meta.name, e
+                        );
+                        skip = disk_health_lock(&disk_health).record_read(&drive, false);
+                        continue 'outer;
+                    }
                 }
 
-                'inner: for mut buffer in rx_empty_buffers.clone() {
+                'inner: loop {
+                    let waited = Instant::now();
+                    let mapped = MappedBuffer::map(buffer_pool.pop());
+                    header.stages.add(Stage::Buffers, waited.elapsed());
                     if show_drive_stats {
                         sw.restart();
                     }
-                    let mut_bs = buffer.get_buffer_for_writing();
+                    let mut_bs = mapped.data();
                     let mut bs = match mut_bs.lock() {
                         Ok(guard) => guard,
                         Err(poisoned) => {
@@ -245,89 +391,76 @@ impl Reader {
                             poisoned.into_inner()
                         }
                     };
-                    let (bytes_read, start_nonce, next_plot) = match p.read(&mut bs, scoop) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            error!(
-                                "reader: error reading chunk from {}: {} -> skip one round",
-                                p.meta.name, e
-                            );
-                            buffer.unmap();
-                            (0, 0, true)
+                    let read = if skip {
+                        None
+                    } else {
+                        let reading = Instant::now();
+                        let result = read_chunk(&mut p, &mut bs, scoop, faults.as_deref(), &drive);
+                        header.stages.add(Stage::Disk, reading.elapsed());
+                        cached_bytes += p.take_cached_bytes();
+                        match result {
+                            Ok(x) => {
+                                disk_health_lock(&disk_health).record_read(&drive, true);
+                                Some(x)
+                            }
+                            Err(e) => {
+                                error!(
+                                    "reader: error reading chunk from {}: {} -> skip one round",
+                                    p.meta.name, e
+                                );
+                                skip = disk_health_lock(&disk_health).record_read(&drive, false);
+                                None
+                            }
                         }
                     };
+                    drop(bs);
 
-                    if rx_interupt.try_recv().is_ok() {
-                        buffer.unmap();
-                        if let Err(e) = tx_empty_buffers.send(buffer) {
-                            error!("reader: failed to return buffer to pool: {} -> stopping", e);
-                        }
+                    if header.cancel.is_cancelled() {
+                        buffer_pool.push(mapped.unmap());
                         break 'outer;
                     }
 
+                    // only data that was read goes to the workers still mapped
+                    let (buffer, (bytes_read, start_nonce, next_plot)) = match read {
+                        Some(read) => (mapped.into_mapped(), read),
+                        None => (mapped.unmap(), (0, 0, true)),
+                    };
+
                     let finished = i_p == (plot_count - 1) && next_plot;
+                    let info = BufferInfo {
+                        header: header.clone(),
+                        len: bytes_read,
+                        start_nonce,
+                        finished,
+                        round_finished: false,
+                        account_id: p.meta.account_id,
+                        gpu_signal: 0,
+                        hashing: Some(header.barrier.sent()),
+                    };
                     // buffer routing
-                    #[cfg(feature = "opencl")]
+                    #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
                     match buffer.get_id() {
                         0 => {
-                            if let Err(e) = tx_read_replies_cpu.send(ReadReply {
-                                buffer,
-                                info: BufferInfo {
-                                    len: bytes_read,
-                                    height,
-                                    block,
-                                    base_target,
-                                    gensig: gensig.clone(),
-                                    start_nonce,
-                                    finished,
-                                    account_id: p.meta.account_id,
-                                    gpu_signal: 0,
-                                },
-                            }) {
+                            if let Err(e) = tx_read_replies_cpu.send(ReadReply { buffer, info }) {
                                 error!("reader: failed to send read data to CPU thread: {} -> stopping", e);
                                 break 'outer;
                             }
                         }
                         i => {
-                            if let Err(e) = tx_read_replies_gpu.as_ref().unwrap()[i - 1].send(ReadReply {
-                                buffer,
-                                info: BufferInfo {
-                                    len: bytes_read,
-                                    height,
-                                    block,
-                                    base_target,
-                                    gensig: gensig.clone(),
-                                    start_nonce,
-                                    finished,
-                                    account_id: p.meta.account_id,
-                                    gpu_signal: 0,
-                                },
-                            }) {
+                            if let Err(e) = tx_read_replies_gpu.as_ref().unwrap()[i - 1].send(ReadReply { buffer, info }) {
                                 error!("reader: failed to send read data to GPU thread: {} -> stopping", e);
                                 break 'outer;
                             }
                         }
                     }
-                    #[cfg(not(feature = "opencl"))]
-                    if let Err(e) = tx_read_replies_cpu.send(ReadReply {
-                        buffer,
-                        info: BufferInfo {
-                            len: bytes_read,
-                            height,
-                            block,
-                            base_target,
-                            gensig: gensig.clone(),
-                            start_nonce,
-                            finished,
-                            account_id: p.meta.account_id,
-                            gpu_signal: 0,
-                        },
-                    }) {
+                    #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
+                    if let Err(e) = tx_read_replies_cpu.send(ReadReply { buffer, info }) {
                         error!("reader: failed to send read data to CPU thread: {} -> stopping", e);
                         break 'outer;
                     }
 
                     nonces_processed += bytes_read as u64 / 64;
+                    progress.add(&drive, bytes_read as u64);
 
                     if let Some(pb) = &pb {
                         match pb.lock() {
@@ -344,36 +477,14 @@ impl Reader {
                         elapsed += sw.elapsed_ms();
                     }
 
-                    // send termination signal (dummy buffer) to gpu
-                    if finished {
-                        #[cfg(feature = "opencl")]
-                        for i in 0..tx_read_replies_gpu.as_ref().unwrap().len() {
-                            if let Err(e) = tx_read_replies_gpu.as_ref().unwrap()[i].send(ReadReply {
-                                buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
-                                info: BufferInfo {
-                                    len: 1,
-                                    height,
-                                    block,
-                                    base_target,
-                                    gensig: gensig.clone(),
-                                    start_nonce: 0,
-                                    finished: false,
-                                    account_id: 0,
-                                    gpu_signal: 2,
-                                },
-                            }) {
-                                error!("reader: failed to send 'drive finished' signal to GPU thread: {}", e);
-                            }
-                        }
-                    }
-
                     if finished && show_drive_stats {
                         info!(
                             "{: <80}",
                             format!(
-                                "drive {} finished, speed={} MiB/s",
+                                "drive {} finished, speed={} MiB/s{}",
                                 drive,
                                 nonces_processed * 1000 / (elapsed + 1) as u64 * 64 / 1024 / 1024,
+                                cached_note(cached_bytes, nonces_processed * 64),
                             )
                         );
                     }
@@ -383,141 +494,149 @@ impl Reader {
                     }
                 }
             }
-        })
+
+            cache_stats::record(&drive, nonces_processed * 64, cached_bytes);
+        }
     }
 
     #[cfg(feature = "async_io")]
+    #[allow(clippy::too_many_arguments)]
     fn create_read_task(
         &self,
         pb: Option<Arc<Mutex<pbr::ProgressBar<Stdout>>>>,
         drive: String,
         plots: Arc<Vec<Mutex<Plot>>>,
-        height: u64,
-        block: u64,
-        base_target: u64,
         scoop: u32,
-        gensig: Arc<[u8; 32]>,
+        header: Arc<RoundHeader>,
         show_drive_stats: bool,
-    ) -> (Sender<()>, impl FnOnce()) {
-        let (tx_interupt, rx_interupt) = crossbeam_channel::unbounded();
-        let rx_empty_buffers = self.rx_empty_buffers.clone();
-        let tx_empty_buffers = self.tx_empty_buffers.clone();
+    ) -> impl FnOnce() {
+        let buffer_pool = self.buffer_pool.clone();
         let tx_read_replies_cpu = self.tx_read_replies_cpu.clone();
-        #[cfg(feature = "opencl")]
         let tx_read_replies_gpu = self.tx_read_replies_gpu.clone();
 
-        (tx_interupt, move || {
-            tokio::spawn(async move {
+        let disk_health = self.disk_health.clone();
+        let faults = self.faults.clone();
+        let progress = self.progress.clone();
+        let runtime = self.runtime.clone();
+
+        move || {
+            runtime.spawn(async move {
+                // completed, cancelled, without workers or panicking, the drive is done with this round
+                let _arrival = Arrival {
+                    header: header.clone(),
+                    tx_read_replies_cpu: tx_read_replies_cpu.clone(),
+                    tx_read_replies_gpu: tx_read_replies_gpu.clone(),
+                };
                 let mut sw = Stopwatch::new();
                 let mut elapsed = 0i64;
                 let mut nonces_processed = 0u64;
+                let mut cached_bytes = 0u64;
                 let plot_count = plots.len();
+                // the round still needs the drive to finish, a skipped drive sends one empty chunk
+                let mut skip = !disk_health.write().await.allow_read(&drive);
                 'outer: for (i_p, p) in plots.iter().enumerate() {
+                    if skip && i_p + 1 < plot_count {
+                        continue 'outer;
+                    }
 #[cfg(feature = "async_io")]
                     let mut p = p.lock().await;
 #[cfg(not(feature = "async_io"))]
                     let mut p = p.lock().unwrap();
-                    if let Err(e) = p.prepare_async(scoop).await {
-                        error!(
-                            "reader: error preparing {} for reading: {} -> skip one round",
-                            p.meta.name,
-                            e
-                        );
-                        continue 'outer;
+                    if !skip {
+                        if let Err(e) = p.prepare_async(scoop).await {
+                            error!(
+                                "reader: error preparing {} for reading: {} -> skip one round",
+                                p.meta.name,
+                                e
+                            );
+                            skip = disk_health.write().await.record_read(&drive, false);
+                            continue 'outer;
+                        }
                     }
 
-                    'inner: for mut buffer in rx_empty_buffers.clone() {
+                    'inner: loop {
+                        let waited = Instant::now();
+                        let mapped = MappedBuffer::map(buffer_pool.pop());
+                        header.stages.add(Stage::Buffers, waited.elapsed());
                         if show_drive_stats {
                             sw.restart();
                         }
-                        let mut_bs = buffer.get_buffer_for_writing();
+                        let mut_bs = mapped.data();
 #[cfg(feature = "async_io")]
                         let mut bs = mut_bs.lock().await;
 #[cfg(not(feature = "async_io"))]
                         let mut bs = mut_bs.lock().unwrap();
-                        let (bytes_read, start_nonce, next_plot) = match p.read_async(&mut bs, scoop).await {
-                            Ok(x) => x,
-                            Err(e) => {
-                                error!(
-                                    "reader: error reading chunk from {}: {} -> skip one round",
-                                    p.meta.name,
-                                    e
-                                );
-                                buffer.unmap();
-                                (0, 0, true)
+                        let read = if skip {
+                            None
+                        } else {
+                            let reading = Instant::now();
+                            let result =
+                                read_chunk_async(&mut p, &mut bs, scoop, faults.as_deref(), &drive).await;
+                            header.stages.add(Stage::Disk, reading.elapsed());
+                            cached_bytes += p.take_cached_bytes();
+                            match result {
+                                Ok(x) => {
+                                    disk_health.write().await.record_read(&drive, true);
+                                    Some(x)
+                                }
+                                Err(e) => {
+                                    error!(
+                                        "reader: error reading chunk from {}: {} -> skip one round",
+                                        p.meta.name,
+                                        e
+                                    );
+                                    skip = disk_health.write().await.record_read(&drive, false);
+                                    None
+                                }
                             }
                         };
+                        drop(bs);
 
-                        if rx_interupt.try_recv().is_ok() {
-                            buffer.unmap();
-                            if let Err(e) = tx_empty_buffers.send(buffer) {
-                                error!("reader: failed to return buffer to pool (async): {} -> stopping", e);
-                            }
+                        if header.cancel.is_cancelled() {
+                            buffer_pool.push(mapped.unmap());
                             break 'outer;
                         }
 
+                        // only data that was read goes to the workers still mapped
+                        let (buffer, (bytes_read, start_nonce, next_plot)) = match read {
+                            Some(read) => (mapped.into_mapped(), read),
+                            None => (mapped.unmap(), (0, 0, true)),
+                        };
+
                         let finished = i_p == (plot_count - 1) && next_plot;
-                        #[cfg(feature = "opencl")]
+                        let info = BufferInfo {
+                            header: header.clone(),
+                            len: bytes_read,
+                            start_nonce,
+                            finished,
+                            round_finished: false,
+                            account_id: p.meta.account_id,
+                            gpu_signal: 0,
+                            hashing: Some(header.barrier.sent()),
+                        };
+                        #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
                         match buffer.get_id() {
                             0 => {
-                                if let Err(e) = tx_read_replies_cpu.send(ReadReply {
-                                    buffer,
-                                    info: BufferInfo {
-                                        len: bytes_read,
-                                        height,
-                                        block,
-                                        base_target,
-                                        gensig: gensig.clone(),
-                                        start_nonce,
-                                        finished,
-                                        account_id: p.meta.account_id,
-                                        gpu_signal: 0,
-                                    },
-                                }) {
+                                if let Err(e) = tx_read_replies_cpu.send(ReadReply { buffer, info }) {
                                     error!("reader: failed to send read data to CPU thread (async): {} -> stopping", e);
                                     break 'outer;
                                 }
                             }
                             i => {
-                                if let Err(e) = tx_read_replies_gpu.as_ref().unwrap()[i - 1].send(ReadReply {
-                                    buffer,
-                                    info: BufferInfo {
-                                        len: bytes_read,
-                                        height,
-                                        block,
-                                        base_target,
-                                        gensig: gensig.clone(),
-                                        start_nonce,
-                                        finished,
-                                        account_id: p.meta.account_id,
-                                        gpu_signal: 0,
-                                    },
-                                }) {
+                                if let Err(e) = tx_read_replies_gpu.as_ref().unwrap()[i - 1].send(ReadReply { buffer, info }) {
                                     error!("reader: failed to send read data to GPU thread (async): {} -> stopping", e);
                                     break 'outer;
                                 }
                             }
                         }
-                        #[cfg(not(feature = "opencl"))]
-                        if let Err(e) = tx_read_replies_cpu.send(ReadReply {
-                            buffer,
-                            info: BufferInfo {
-                                len: bytes_read,
-                                height,
-                                block,
-                                base_target,
-                                gensig: gensig.clone(),
-                                start_nonce,
-                                finished,
-                                account_id: p.meta.account_id,
-                                gpu_signal: 0,
-                            },
-                        }) {
+                        #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
+                        if let Err(e) = tx_read_replies_cpu.send(ReadReply { buffer, info }) {
                             error!("reader: failed to send read data to CPU thread (async): {} -> stopping", e);
                             break 'outer;
                         }
 
                         nonces_processed += bytes_read as u64 / 64;
+                        progress.add(&drive, bytes_read as u64);
 
                         match &pb {
                             Some(pb) => {
@@ -534,35 +653,14 @@ impl Reader {
                             elapsed += sw.elapsed_ms();
                         }
 
-                        if finished {
-                            #[cfg(feature = "opencl")]
-                            for i in 0..tx_read_replies_gpu.as_ref().unwrap().len() {
-                                if let Err(e) = tx_read_replies_gpu.as_ref().unwrap()[i].send(ReadReply {
-                                    buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
-                                    info: BufferInfo {
-                                        len: 1,
-                                        height,
-                                        block,
-                                        base_target,
-                                        gensig: gensig.clone(),
-                                        start_nonce: 0,
-                                        finished: false,
-                                        account_id: 0,
-                                        gpu_signal: 2,
-                                    },
-                                }) {
-                                    error!("reader: failed to send 'drive finished' signal to GPU thread (async): {}", e);
-                                }
-                            }
-                        }
-
                         if finished && show_drive_stats {
                             info!(
                                 "{: <80}",
                                 format!(
-                                    "drive {} finished, speed={} MiB/s",
+                                    "drive {} finished, speed={} MiB/s{}",
                                     drive,
                                     nonces_processed * 1000 / (elapsed + 1) as u64 * 64 / 1024 / 1024,
+                                    cached_note(cached_bytes, nonces_processed * 64),
                                 )
                             );
                         }
@@ -572,11 +670,201 @@ impl Reader {
                         }
                     }
                 }
+
+                cache_stats::record(&drive, nonces_processed * 64, cached_bytes);
             });
-        })
+        }
+    }
+}
+
+/// `, N% from page cache` for the drive stats, the speed means little then.
+fn cached_note(cached_bytes: u64, read_bytes: u64) -> String {
+    if cached_bytes == 0 || read_bytes == 0 {
+        return String::new();
+    }
+    format!(
+        ", {:.0}% from page cache",
+        cached_bytes as f64 * 100.0 / read_bytes as f64
+    )
+}
+
+/// A drive's read task for a round, arrives at the round's barrier when dropped.
+struct Arrival {
+    header: Arc<RoundHeader>,
+    tx_read_replies_cpu: Sender<ReadReply>,
+    tx_read_replies_gpu: Option<Vec<Sender<ReadReply>>>,
+}
+
+impl Drop for Arrival {
+    fn drop(&mut self) {
+        if self.header.barrier.arrive() {
+            finish_round(
+                &self.header,
+                &self.tx_read_replies_cpu,
+                self.tx_read_replies_gpu.as_deref(),
+            );
+        }
+    }
+}
+
+/// Sent once per round by the drive that completes it: the GPUs hash what they still hold, the
+/// CPU workers pass the marker on to the miner.
+fn finish_round(
+    header: &Arc<RoundHeader>,
+    tx_read_replies_cpu: &Sender<ReadReply>,
+    tx_read_replies_gpu: Option<&[Sender<ReadReply>]>,
+) {
+    for tx in tx_read_replies_gpu.unwrap_or_default() {
+        if let Err(e) = tx.send(ReadReply {
+            buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
+            info: BufferInfo {
+                header: header.clone(),
+                len: 1,
+                start_nonce: 0,
+                finished: false,
+                round_finished: false,
+                account_id: 0,
+                gpu_signal: 2,
+                hashing: None,
+            },
+        }) {
+            error!("reader: failed to send 'round finished' signal to GPU thread: {}", e);
+        }
+    }
+    if let Err(e) = tx_read_replies_cpu.send(ReadReply {
+        buffer: Box::new(CpuBuffer::new(0)) as Box<dyn Buffer + Send>,
+        info: BufferInfo {
+            header: header.clone(),
+            len: 0,
+            start_nonce: 0,
+            finished: false,
+            round_finished: true,
+            account_id: 0,
+            gpu_signal: 0,
+            hashing: None,
+        },
+    }) {
+        error!("reader: failed to send 'round finished' signal to CPU thread: {}", e);
+    }
+}
+
+#[cfg(not(feature = "async_io"))]
+fn read_chunk(
+    p: &mut Plot,
+    bs: &mut Vec<u8>,
+    scoop: u32,
+    faults: Option<&FaultInjector>,
+    drive: &str,
+) -> io::Result<(usize, u64, bool)> {
+    let faults = faults.map(|f| f.roll(drive, &p.path)).unwrap_or_default();
+    if let Some(delay) = faults.delay {
+        thread::sleep(delay);
+    }
+    if faults.error {
+        return Err(io::Error::other("injected fault"));
+    }
+    let (bytes_read, start_nonce, next_plot) = p.read(bs, scoop)?;
+    if faults.short_read {
+        Ok((short_read(bytes_read), start_nonce, next_plot))
+    } else {
+        Ok((bytes_read, start_nonce, next_plot))
     }
 }
 
+#[cfg(feature = "async_io")]
+async fn read_chunk_async(
+    p: &mut Plot,
+    bs: &mut Vec<u8>,
+    scoop: u32,
+    faults: Option<&FaultInjector>,
+    drive: &str,
+) -> io::Result<(usize, u64, bool)> {
+    let faults = faults.map(|f| f.roll(drive, &p.path)).unwrap_or_default();
+    if let Some(delay) = faults.delay {
+        tokio::time::sleep(delay).await;
+    }
+    if faults.error {
+        return Err(io::Error::other("injected fault"));
+    }
+    let (bytes_read, start_nonce, next_plot) = p.read_async(bs, scoop).await?;
+    if faults.short_read {
+        Ok((short_read(bytes_read), start_nonce, next_plot))
+    } else {
+        Ok((bytes_read, start_nonce, next_plot))
+    }
+}
+
+#[cfg(not(feature = "async_io"))]
+fn disk_health_lock(
+    disk_health: &SharedDiskHealth,
+) -> std::sync::RwLockWriteGuard<'_, crate::metrics::DiskHealthMonitor> {
+    match disk_health.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("reader: disk health mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+/// Plots are read in order, so the first plot of every drive is where the round's first seek goes.
+/// Plots that are busy (still being read) are skipped rather than waited for.
+fn pre_seek_targets(
+    drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>,
+) -> Vec<PreSeekTarget> {
+    drive_id_to_plots
+        .values()
+        .filter_map(|plots| plots.first())
+        .filter_map(|plot| plot.try_lock().ok().map(|p| p.pre_seek_target()))
+        .collect()
+}
+
+/// I/O priority per drive, looked up by the path of its first plot.
+fn drive_io_priority(
+    io_priorities: Option<&IoPriorities>,
+    drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>,
+) -> HashMap<String, IoPriority> {
+    let Some(io_priorities) = io_priorities else {
+        return HashMap::new();
+    };
+    drive_id_to_plots
+        .iter()
+        .filter_map(|(drive, plots)| {
+            let path = plots.first()?.try_lock().ok()?.path.clone();
+            let priority = io_priorities.lookup(drive, &path)?;
+            info!("reader: io priority of {} is {}", drive, priority);
+            Some((drive.clone(), priority))
+        })
+        .collect()
+}
+
+/// Bytes of one scoop over every drive's plots.
+fn drive_scoop_bytes(
+    drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>,
+) -> Vec<(Arc<str>, u64)> {
+    drive_id_to_plots
+        .iter()
+        .map(|(drive, plots)| {
+            let bytes = plots
+                .iter()
+                .filter_map(|plot| plot.try_lock().ok().map(|p| p.meta.nonces * 64))
+                .sum();
+            (Arc::from(drive.as_str()), bytes)
+        })
+        .collect()
+}
+
+/// Every plot, all of them are read when the page cache is kept warm.
+fn warm_targets(
+    drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>,
+) -> Vec<PreSeekTarget> {
+    drive_id_to_plots
+        .values()
+        .flat_map(|plots| plots.iter())
+        .filter_map(|plot| plot.try_lock().ok().map(|p| p.pre_seek_target()))
+        .collect()
+}
+
 // Don't waste your time striving for perfection; instead, strive for excellence - doing your best.
 // let my_best = perfection;
 pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>) -> bool {
@@ -586,7 +874,7 @@ pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>)
         .map(|plot| {
             #[cfg(feature = "async_io")]
             {
-                plot.blocking_lock().meta.clone()
+                crate::utils::lock_sync(plot).meta.clone()
             }
             #[cfg(not(feature = "async_io"))]
             {
@@ -615,3 +903,125 @@ pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Vec<Mutex<Plot>>>>)
         .count()
         > 0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::CpuBuffer;
+
+    #[test]
+    fn test_read_reply_shares_header() {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let header = Arc::new(RoundHeader {
+            height: 1,
+            block: 2,
+            base_target: 3,
+            gensig: [0u8; 32],
+            drive_id: Arc::from("drive"),
+            stages: Arc::default(),
+            cancel: CancelToken::default(),
+            barrier: Arc::default(),
+            tx_nonce_data: mpsc::channel(1).0,
+        });
+        let mut buffer: Option<Box<dyn Buffer + Send>> = Some(Box::new(CpuBuffer::new(0)));
+
+        for start_nonce in 0..3 {
+            tx.send(ReadReply {
+                buffer: buffer.take().unwrap(),
+                info: BufferInfo {
+                    header: header.clone(),
+                    len: 64,
+                    start_nonce,
+                    finished: false,
+                    round_finished: false,
+                    account_id: 0,
+                    gpu_signal: 0,
+                    hashing: None,
+                },
+            })
+            .unwrap();
+            // a chunk in flight holds a reference to the round's header, not a copy
+            assert_eq!(Arc::strong_count(&header), 2);
+            let read_reply: ReadReply = rx.recv().unwrap();
+            assert!(Arc::ptr_eq(&read_reply.info.header, &header));
+            assert_eq!(read_reply.info.start_nonce, start_nonce);
+            buffer = Some(read_reply.buffer);
+            drop(read_reply.info);
+            assert_eq!(Arc::strong_count(&header), 1);
+        }
+    }
+
+    #[test]
+    fn test_panicking_read_task_finishes_round() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let header = Arc::new(RoundHeader {
+            height: 1,
+            block: 2,
+            base_target: 3,
+            gensig: [0u8; 32],
+            drive_id: Arc::from("drive"),
+            stages: Arc::default(),
+            cancel: CancelToken::default(),
+            barrier: Arc::new(RoundBarrier::new(1)),
+            tx_nonce_data: mpsc::channel(1).0,
+        });
+        let task = thread::spawn(move || {
+            let _arrival = Arrival {
+                header,
+                tx_read_replies_cpu: tx,
+                tx_read_replies_gpu: None,
+            };
+            panic!("drive went away");
+        });
+        assert!(task.join().is_err());
+        assert!(rx.recv().unwrap().info.round_finished);
+    }
+
+    struct CountingBuffer {
+        data: Arc<Mutex<Vec<u8>>>,
+        unmapped: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Buffer for CountingBuffer {
+        fn get_buffer(&mut self) -> Arc<Mutex<Vec<u8>>> {
+            self.data.clone()
+        }
+        fn get_buffer_for_writing(&mut self) -> Arc<Mutex<Vec<u8>>> {
+            self.data.clone()
+        }
+        #[cfg(feature = "opencl")]
+        fn get_gpu_buffers(&self) -> Option<&crate::ocl::GpuBuffer> {
+            None
+        }
+        #[cfg(feature = "opencl")]
+        fn get_gpu_data(&self) -> Option<ocl_core::Mem> {
+            None
+        }
+        fn unmap(&self) {
+            self.unmapped
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+        fn get_id(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn test_mapped_buffer_unmaps_unless_handed_over() {
+        let unmapped = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let buffer = || {
+            Box::new(CountingBuffer {
+                data: Arc::new(Mutex::new(Vec::new())),
+                unmapped: unmapped.clone(),
+            }) as Box<dyn Buffer + Send>
+        };
+        let count = || unmapped.load(std::sync::atomic::Ordering::SeqCst);
+
+        drop(MappedBuffer::map(buffer()));
+        assert_eq!(count(), 1);
+        MappedBuffer::map(buffer()).unmap();
+        assert_eq!(count(), 2);
+        MappedBuffer::map(buffer()).into_mapped();
+        assert_eq!(count(), 2);
+    }
+}
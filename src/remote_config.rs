@@ -0,0 +1,201 @@
+//! Config overlay fetched from a URL, for reconfiguring many rigs from one place.
+//!
+//! The overlay is a YAML mapping whose top level keys replace those of the local config. It is
+//! only used with a valid Ed25519 signature by the configured public key: the signature (hex) is
+//! fetched from `signature_url`, by default the overlay's URL with `.sig` appended. The miner
+//! fetches the overlay at every start, `fetch-config` fetches it on demand. Verified overlays are
+//! kept in `cache` together with their signature, so a rig still starts with the last overlay
+//! while the server is unreachable, and the cache is verified again on every load.
+
+use crate::config::RemoteConfigCfg;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use reqwest::Client as InnerClient;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const REMOTE_CONFIG_KEY: &str = "remote_config";
+
+/// Fetches and verifies the overlay configured in the local `config` file and replaces the cache.
+/// Returns the URL fetched from, `None` without a `remote_config` section.
+pub fn refresh(config: &str) -> Result<Option<String>, String> {
+    let remote = match read_remote_cfg(config)? {
+        Some(remote) => remote,
+        None => return Ok(None),
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let (overlay, signature) = runtime.block_on(fetch(&remote))?;
+    verify(&remote.public_key, &overlay, &signature)?;
+    parse_overlay(&overlay)?;
+    store(&remote.cache, &overlay, &signature)
+        .map_err(|e| format!("can't write {}: {}", remote.cache.display(), e))?;
+    Ok(Some(remote.url))
+}
+
+/// The cached overlay, verified again. `None` if nothing was fetched yet.
+pub fn load_cached(remote: &RemoteConfigCfg) -> Result<Option<serde_yaml::Mapping>, String> {
+    let overlay = match fs::read(&remote.cache) {
+        Ok(overlay) => overlay,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("can't read {}: {}", remote.cache.display(), e)),
+    };
+    let signature_path = signature_path(&remote.cache);
+    let signature = fs::read_to_string(&signature_path)
+        .map_err(|e| format!("can't read {}: {}", signature_path.display(), e))?;
+    verify(&remote.public_key, &overlay, &signature)
+        .map_err(|e| format!("{}: {}", remote.cache.display(), e))?;
+    parse_overlay(&overlay).map(Some)
+}
+
+/// Replaces the top level keys of `value` with those of `overlay`. The overlay can't change where
+/// overlays come from or who signs them.
+pub fn apply(value: &mut serde_yaml::Mapping, overlay: serde_yaml::Mapping) {
+    for (key, v) in overlay {
+        if key.as_str() != Some(REMOTE_CONFIG_KEY) {
+            value.insert(key, v);
+        }
+    }
+}
+
+/// Checks a detached, hex encoded Ed25519 signature of `data`.
+pub fn verify(public_key: &str, data: &[u8], signature: &str) -> Result<(), String> {
+    let public_key: [u8; 32] = hex::decode(public_key.trim())
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or("public key must be 32 bytes, hex encoded")?;
+    let public_key =
+        VerifyingKey::from_bytes(&public_key).map_err(|e| format!("invalid public key: {}", e))?;
+    let signature: [u8; 64] = hex::decode(signature.trim())
+        .ok()
+        .and_then(|signature| signature.try_into().ok())
+        .ok_or("signature must be 64 bytes, hex encoded")?;
+    public_key
+        .verify(data, &Signature::from_bytes(&signature))
+        .map_err(|_| "signature doesn't match, overlay rejected".to_owned())
+}
+
+fn read_remote_cfg(config: &str) -> Result<Option<RemoteConfigCfg>, String> {
+    let cfg_str = fs::read_to_string(config)
+        .map_err(|e| format!("Failed to open config file '{}': {}", config, e))?;
+    let value: serde_yaml::Mapping = serde_yaml::from_str(&cfg_str)
+        .map_err(|e| format!("Failed to parse config file '{}': {}", config, e))?;
+    value
+        .get(&serde_yaml::Value::from(REMOTE_CONFIG_KEY))
+        .filter(|remote| !remote.is_null())
+        .map(|remote| serde_yaml::from_value(remote.clone()))
+        .transpose()
+        .map_err(|e| format!("Failed to parse {} in '{}': {}", REMOTE_CONFIG_KEY, config, e))
+}
+
+async fn fetch(remote: &RemoteConfigCfg) -> Result<(Vec<u8>, String), String> {
+    let inner = InnerClient::builder()
+        .timeout(Duration::from_millis(remote.timeout))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let get = |url: String| {
+        let inner = inner.clone();
+        async move {
+            inner
+                .get(&url)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status())
+                .map_err(|e| format!("can't fetch {}: {}", url, e))?
+                .bytes()
+                .await
+                .map_err(|e| format!("can't fetch {}: {}", url, e))
+        }
+    };
+    let signature_url = remote
+        .signature_url
+        .clone()
+        .unwrap_or_else(|| format!("{}.sig", remote.url));
+    let overlay = get(remote.url.clone()).await?;
+    let signature = get(signature_url).await?;
+    Ok((
+        overlay.to_vec(),
+        String::from_utf8_lossy(&signature).into_owned(),
+    ))
+}
+
+fn parse_overlay(overlay: &[u8]) -> Result<serde_yaml::Mapping, String> {
+    serde_yaml::from_slice(overlay).map_err(|e| format!("overlay isn't a YAML mapping: {}", e))
+}
+
+/// Writes the overlay and its signature next to the cache before moving them in place, a crash
+/// in between leaves a pair that fails verification instead of a half written overlay.
+fn store(cache: &Path, overlay: &[u8], signature: &str) -> io::Result<()> {
+    let tmp = cache.with_extension("tmp");
+    let signature_tmp = signature_path(&tmp);
+    fs::write(&tmp, overlay)?;
+    fs::write(&signature_tmp, signature.trim())?;
+    fs::rename(&signature_tmp, signature_path(cache))?;
+    fs::rename(&tmp, cache)
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const OVERLAY: &[u8] = b"hdd_reader_thread_count: 4\nremote_config: ~\n";
+
+    fn key_and_signature() -> (String, String) {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        (
+            hex::encode(signing_key.verifying_key().to_bytes()),
+            hex::encode(signing_key.sign(OVERLAY).to_bytes()),
+        )
+    }
+
+    #[test]
+    fn test_verify() {
+        let (public_key, signature) = key_and_signature();
+        assert_eq!(verify(&public_key, OVERLAY, &signature), Ok(()));
+        assert!(verify(&public_key, b"hdd_reader_thread_count: 5\n", &signature).is_err());
+        assert!(verify(&public_key, OVERLAY, "00").is_err());
+        assert!(verify("abc", OVERLAY, &signature).is_err());
+    }
+
+    #[test]
+    fn test_cache_and_apply() {
+        let (public_key, signature) = key_and_signature();
+        let dir = std::env::temp_dir().join(format!("signum-miner-remote-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let remote = RemoteConfigCfg {
+            url: "http://localhost/overlay.yaml".to_owned(),
+            signature_url: None,
+            public_key,
+            cache: dir.join("overlay.yaml"),
+            timeout: 1000,
+        };
+        assert_eq!(load_cached(&remote), Ok(None));
+
+        store(&remote.cache, OVERLAY, &signature).unwrap();
+        let overlay = load_cached(&remote).unwrap().unwrap();
+        let mut value: serde_yaml::Mapping =
+            serde_yaml::from_str("hdd_reader_thread_count: 0\nremote_config: {url: x}\n").unwrap();
+        apply(&mut value, overlay);
+        assert_eq!(
+            value
+                .get(&"hdd_reader_thread_count".into())
+                .and_then(serde_yaml::Value::as_u64),
+            Some(4)
+        );
+        assert!(value.get(&REMOTE_CONFIG_KEY.into()).unwrap().is_mapping());
+
+        fs::write(&remote.cache, b"hdd_reader_thread_count: 64\n").unwrap();
+        assert!(load_cached(&remote).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
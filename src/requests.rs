@@ -1,27 +1,234 @@
-use crate::com::api::{FetchError, MiningInfoResponse};
-use crate::com::client::{Client, ProxyDetails, SubmissionParameters};
+use crate::audit::{now_ms, AuditLog, SubmissionRecord};
+use crate::cancel::CancelToken;
+use crate::com::api::{FetchError, MiningInfoResponse, RejectionReason};
+use crate::com::client::{
+    Client, ConnectionSettings, DeadlineFloor, ProxyDetails, SubmissionParameters,
+};
+use crate::com::endpoints::{LatencyProbe, PoolEndpoints};
+use crate::config::{FallbackNodeCfg, PoolCfg};
+use crate::deadline_format::format_deadline;
 use crate::future::prio_retry::PrioRetry;
+use crate::metrics::SharedMetrics;
+use crate::rotation::AccountRotation;
+use crate::stages::{RoundStages, Stage};
+use crate::upgrade::RoundSnapshot;
 use futures_util::stream::{StreamExt};
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use url::Url;
 
 #[derive(Clone)]
 pub struct RequestHandler {
-    client: Client,
+    pool: Arc<PoolEndpoints>,
+    fallback: Option<(Client, Arc<FallbackState>)>,
+    // polled along with the pool for new blocks, never submitted to
+    sources: Arc<Vec<Client>>,
+    // the sources don't know the pool's target deadline
+    pool_target_deadline: Arc<AtomicU64>,
     tx_submit_data: mpsc::UnboundedSender<SubmissionParameters>,
+    round: Arc<CurrentRound>,
+}
+
+/// The round of the last mining info, submissions for any other round are dropped before sending.
+#[derive(Default)]
+struct CurrentRound {
+    state: Mutex<RoundState>,
+}
+
+#[derive(Default)]
+struct RoundState {
+    // height and hex encoded generation signature
+    round: Option<(u64, String)>,
+    // best deadline the pool accepted per account in this round
+    accepted: HashMap<u64, u64>,
+    // (account, nonce) accepted by any endpoint in this round, sending it again after a failover
+    // or from a duplicated plot would only get the miner flagged for duplicates
+    accepted_nonces: HashSet<(u64, u64)>,
+    // time spent per stage, submissions add their wait for the pool
+    stages: Arc<RoundStages>,
+    // cancelled with the next round, pending submissions of the round turn stale at the same time
+    cancel: CancelToken,
+}
+
+impl CurrentRound {
+    fn update(&self, mining_info: &MiningInfoResponse) {
+        let round = Some((
+            mining_info.height,
+            mining_info.generation_signature.to_lowercase(),
+        ));
+        let mut state = self.lock();
+        if state.round != round {
+            state.round = round;
+            state.accepted.clear();
+            state.accepted_nonces.clear();
+            state.stages = Arc::default();
+            state.cancel.cancel();
+            state.cancel = CancelToken::default();
+        }
+    }
+
+    /// A submission is stale once a newer round started, unknown rounds are never stale.
+    fn is_stale(&self, params: &SubmissionParameters) -> bool {
+        is_stale(&self.lock(), params)
+    }
+
+    fn record_accepted(&self, params: &SubmissionParameters) {
+        let mut state = self.lock();
+        if !is_stale(&state, params) {
+            let best = state.accepted.entry(params.account_id).or_insert(u64::MAX);
+            *best = (*best).min(params.deadline);
+            state
+                .accepted_nonces
+                .insert((params.account_id, params.nonce));
+        }
+    }
+
+    /// True if an endpoint already accepted this account and nonce in the submission's round.
+    fn is_duplicate(&self, params: &SubmissionParameters) -> bool {
+        let state = self.lock();
+        !is_stale(&state, params)
+            && state
+                .accepted_nonces
+                .contains(&(params.account_id, params.nonce))
+    }
+
+    /// Best deadline the pool accepted for the submission's account in its round.
+    fn accepted_best(&self, params: &SubmissionParameters) -> Option<u64> {
+        let state = self.lock();
+        if is_stale(&state, params) {
+            None
+        } else {
+            state.accepted.get(&params.account_id).copied()
+        }
+    }
+
+    fn height(&self) -> Option<u64> {
+        self.lock().round.as_ref().map(|(height, _)| *height)
+    }
+
+    fn stages(&self) -> Arc<RoundStages> {
+        self.lock().stages.clone()
+    }
+
+    fn cancel(&self) -> CancelToken {
+        self.lock().cancel.clone()
+    }
+
+    fn snapshot(&self) -> Option<RoundSnapshot> {
+        let state = self.lock();
+        state
+            .round
+            .as_ref()
+            .map(|(height, generation_signature)| RoundSnapshot {
+                height: *height,
+                generation_signature: generation_signature.clone(),
+                account_id_to_best_deadline: state.accepted.clone(),
+            })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RoundState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("round: mutex poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+}
+
+fn is_stale(state: &RoundState, params: &SubmissionParameters) -> bool {
+    match &state.round {
+        Some((height, gen_sig)) => {
+            params.height < *height
+                || (params.height == *height && hex::encode(params.gen_sig) != *gen_sig)
+        }
+        None => false,
+    }
+}
+
+/// Tracks whether mining currently happens against the fallback node instead of the pool.
+struct FallbackState {
+    active: AtomicBool,
+    consecutive_failures: AtomicU32,
+    last_probe: AtomicU64,
+    after_failures: u32,
+    probe_interval: u64,
+}
+
+impl FallbackState {
+    fn new(after_failures: u32, probe_interval: u64) -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            consecutive_failures: AtomicU32::new(0),
+            last_probe: AtomicU64::new(0),
+            after_failures: after_failures.max(1),
+            probe_interval,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// While the fallback is active the pool is only probed every `probe_interval` seconds.
+    fn probe_due(&self) -> bool {
+        let now = unix_time();
+        let last_probe = self.last_probe.load(Ordering::SeqCst);
+        now.saturating_sub(last_probe) >= self.probe_interval
+            && self
+                .last_probe
+                .compare_exchange(last_probe, now, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+    }
+
+    /// Returns true if this failure switched mining over to the fallback node.
+    fn record_failure(&self) -> bool {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= self.after_failures && !self.active.swap(true, Ordering::SeqCst) {
+            self.last_probe.store(unix_time(), Ordering::SeqCst);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if this success switched mining back to the pool.
+    fn record_success(&self) -> bool {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        self.active.swap(false, Ordering::SeqCst)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 impl RequestHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        base_uri: Url,
+        pools: Vec<PoolCfg>,
         secret_phrases: HashMap<u64, String>,
-        timeout: u64,
+        solo_node: Option<Url>,
+        connection: ConnectionSettings,
         total_size_gb: usize,
         send_proxy_details: bool,
         additional_headers: HashMap<String, String>,
+        rotation: Arc<AccountRotation>,
+        pool_failover_after: u32,
+        fallback_node: Option<FallbackNodeCfg>,
+        mining_info_sources: Vec<Url>,
+        audit_log_dir: Option<PathBuf>,
+        dry_run: bool,
+        metrics: SharedMetrics,
         handle: tokio::runtime::Handle,
     ) -> RequestHandler {
         let proxy_details = if send_proxy_details {
@@ -30,31 +237,123 @@ impl RequestHandler {
             ProxyDetails::Disabled
         };
 
-        let client = Client::new(
-            base_uri,
-            secret_phrases,
-            timeout,
-            total_size_gb,
-            proxy_details,
-            additional_headers,
-        );
+        // one connection pool for all endpoints and the fallback node
+        let inner = connection.build();
+
+        // accounts with a passphrase submit to the node and sign there, the others go to the pool,
+        // which then never sees a passphrase. Without a node the url is the node of a solo setup.
+        let solo = solo_node.filter(|_| !secret_phrases.is_empty()).map(|url| {
+            info!(
+                "solo mining: {} accounts submit to {}, the others to the pool",
+                secret_phrases.len(),
+                url
+            );
+            Client::new(
+                url,
+                secret_phrases.clone(),
+                inner.clone(),
+                total_size_gb,
+                ProxyDetails::Disabled,
+                HashMap::new(),
+            )
+        });
+        let pool_secret_phrases = if solo.is_some() {
+            HashMap::new()
+        } else {
+            secret_phrases
+        };
+
+        let clients = pools
+            .into_iter()
+            .map(|pool| {
+                let client = Client::new(
+                    pool.url,
+                    pool_secret_phrases.clone(),
+                    inner.clone(),
+                    total_size_gb,
+                    proxy_details.clone(),
+                    additional_headers.clone(),
+                )
+                .with_deadline_floor(DeadlineFloor {
+                    factor: pool.min_improvement,
+                    secs: pool.min_improvement_secs,
+                })
+                .with_header_templates(pool.user_agent, pool.headers)
+                .with_account_rotation(rotation.clone());
+                (client, pool.priority)
+            })
+            .collect();
+        let pool = Arc::new(PoolEndpoints::new(
+            clients,
+            pool_failover_after,
+            metrics.clone(),
+        ));
+
+        let fallback = fallback_node.map(|node| {
+            info!("fallback node configured: {}", node.url);
+            let node_client = Client::new(
+                node.url,
+                node.account_id_to_secret_phrase,
+                inner.clone(),
+                total_size_gb,
+                ProxyDetails::Disabled,
+                HashMap::new(),
+            );
+            (
+                node_client,
+                Arc::new(FallbackState::new(node.after_failures, node.probe_interval)),
+            )
+        });
+
+        let sources = mining_info_sources
+            .into_iter()
+            .map(|url| {
+                info!("mining info source configured: {}", url);
+                Client::new(
+                    url,
+                    HashMap::new(),
+                    inner.clone(),
+                    total_size_gb,
+                    ProxyDetails::Disabled,
+                    HashMap::new(),
+                )
+            })
+            .collect();
 
         let (tx_submit_data, rx_submit_nonce_data) = mpsc::unbounded_channel();
+        let round = Arc::new(CurrentRound::default());
         RequestHandler::handle_submissions(
-            client.clone(),
+            pool.clone(),
+            solo,
+            fallback.clone(),
+            round.clone(),
+            audit_log_dir.map(AuditLog::new),
+            dry_run,
+            metrics,
             rx_submit_nonce_data,
             tx_submit_data.clone(),
             handle,
         );
 
         RequestHandler {
-            client,
+            pool,
+            fallback,
+            sources: Arc::new(sources),
+            pool_target_deadline: Arc::new(AtomicU64::new(u64::MAX)),
             tx_submit_data,
+            round,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn handle_submissions(
-        client: Client,
+        pool: Arc<PoolEndpoints>,
+        solo: Option<Client>,
+        fallback: Option<(Client, Arc<FallbackState>)>,
+        round: Arc<CurrentRound>,
+        mut audit_log: Option<AuditLog>,
+        dry_run: bool,
+        metrics: SharedMetrics,
         rx: mpsc::UnboundedReceiver<SubmissionParameters>,
         tx_submit_data: mpsc::UnboundedSender<SubmissionParameters>,
         handle: tokio::runtime::Handle,
@@ -63,13 +362,200 @@ impl RequestHandler {
             let wrapped_rx = UnboundedReceiverStream::new(rx);
             let stream = PrioRetry::new(wrapped_rx, Duration::from_secs(3));
 
+            // (height, account, nonce) -> attempts so far
+            let mut attempts: HashMap<(u64, u64, u64), u32> = HashMap::new();
+
             let mut stream = Box::pin(stream);
             while let Some(submission_params) = stream.as_mut().next().await {
                 let tx_submit_data = tx_submit_data.clone();
-                let result = client.clone().submit_nonce(&submission_params).await;
+
+                let key = (
+                    submission_params.height,
+                    submission_params.account_id,
+                    submission_params.nonce,
+                );
+                attempts.retain(|&(height, _, _), _| height >= submission_params.height);
+                let attempt = {
+                    let attempt = attempts.entry(key).or_insert(0);
+                    *attempt += 1;
+                    *attempt
+                };
+
+                // GPU queues can finish after the next block arrived, don't bother the pool
+                if round.is_stale(&submission_params) {
+                    log_stale_submission(
+                        submission_params.height,
+                        submission_params.account_id,
+                        submission_params.nonce,
+                    );
+                    record_stale_submission(&metrics, submission_params.account_id).await;
+                    if let Some(audit_log) = audit_log.as_mut() {
+                        audit_log.write(&audit_record(
+                            &submission_params,
+                            pool.active().base_uri().as_str(),
+                            attempt,
+                            "stale",
+                        ));
+                    }
+                    continue;
+                }
+
+                if round.is_duplicate(&submission_params) {
+                    log_duplicate_submission(
+                        submission_params.account_id,
+                        submission_params.nonce,
+                    );
+                    if let Some(audit_log) = audit_log.as_mut() {
+                        audit_log.write(&audit_record(
+                            &submission_params,
+                            pool.active().base_uri().as_str(),
+                            attempt,
+                            "duplicate",
+                        ));
+                    }
+                    continue;
+                }
+
+                if dry_run {
+                    log_dry_run_submission(
+                        submission_params.height,
+                        submission_params.account_id,
+                        submission_params.nonce,
+                        submission_params.deadline,
+                    );
+                    if let Some(audit_log) = audit_log.as_mut() {
+                        audit_log.write(&audit_record(
+                            &submission_params,
+                            pool.active().base_uri().as_str(),
+                            attempt,
+                            "dry_run",
+                        ));
+                    }
+                    continue;
+                }
+
+                let solo_client = solo
+                    .as_ref()
+                    .filter(|node_client| node_client.has_secret_phrase(submission_params.account_id));
+                let client = match (solo_client, &fallback) {
+                    (Some(node_client), _) => node_client,
+                    (None, Some((node_client, state))) if state.is_active() => {
+                        if !node_client.has_secret_phrase(submission_params.account_id) {
+                            warn!(
+                                "fallback node: no passphrase for account={}, dropping nonce={}",
+                                submission_params.account_id, submission_params.nonce
+                            );
+                            if let Some(audit_log) = audit_log.as_mut() {
+                                audit_log.write(&audit_record(
+                                    &submission_params,
+                                    node_client.base_uri().as_str(),
+                                    attempt,
+                                    "dropped",
+                                ));
+                            }
+                            continue;
+                        }
+                        node_client
+                    }
+                    _ => pool.active(),
+                };
+
+                if let Some(previous) = round.accepted_best(&submission_params) {
+                    if !client
+                        .deadline_floor()
+                        .allows(previous, submission_params.deadline)
+                    {
+                        log_below_floor(
+                            submission_params.account_id,
+                            submission_params.nonce,
+                            submission_params.deadline,
+                            previous,
+                        );
+                        if let Some(audit_log) = audit_log.as_mut() {
+                            audit_log.write(&audit_record(
+                                &submission_params,
+                                client.base_uri().as_str(),
+                                attempt,
+                                "below_floor",
+                            ));
+                        }
+                        continue;
+                    }
+                }
+
+                let stages = round.stages();
+                let waited = Instant::now();
+                let result = client.submit_nonce(&submission_params).await;
+                stages.add(Stage::Pool, waited.elapsed());
+                let available = match &result {
+                    Ok(_) => true,
+                    Err(FetchError::Pool(e)) => e.reason() != RejectionReason::RateLimited,
+                    Err(_) => false,
+                };
+                record_submission_latency(
+                    &metrics,
+                    client.base_uri().as_str(),
+                    waited.elapsed().as_millis() as u64,
+                    available,
+                    (
+                        submission_params.height,
+                        format!(
+                            "{}:{}:{}",
+                            submission_params.account_id, submission_params.nonce, attempt
+                        ),
+                    ),
+                )
+                .await;
+                pool.record_submission(
+                    client.base_uri(),
+                    matches!(result, Ok(_) | Err(FetchError::Pool(_))),
+                )
+                .await;
+
+                if let Some(audit_log) = audit_log.as_mut() {
+                    let pool_url = client.base_uri().as_str();
+                    let http_error = match &result {
+                        Err(e @ (FetchError::Http(_) | FetchError::TooLarge(_))) => {
+                            Some(e.to_string())
+                        }
+                        _ => None,
+                    };
+                    let record = match &result {
+                        Ok(res) => SubmissionRecord {
+                            pool_deadline: Some(res.deadline),
+                            ..audit_record(&submission_params, pool_url, attempt, "accepted")
+                        },
+                        Err(FetchError::Pool(e)) => {
+                            let reason = e.reason();
+                            let outcome = if reason == RejectionReason::RateLimited {
+                                "pool_busy"
+                            } else {
+                                "rejected"
+                            };
+                            SubmissionRecord {
+                                error_code: Some(e.code),
+                                message: Some(&e.message),
+                                reason: Some(reason.as_str()),
+                                ..audit_record(&submission_params, pool_url, attempt, outcome)
+                            }
+                        }
+                        Err(FetchError::Http(_) | FetchError::TooLarge(_)) => SubmissionRecord {
+                            message: http_error.as_deref(),
+                            ..audit_record(&submission_params, pool_url, attempt, "failed")
+                        },
+                    };
+                    audit_log.write(&record);
+                }
 
                 match result {
                     Ok(res) => {
+                        round.record_accepted(&submission_params);
+                        record_submission_success(
+                            &metrics,
+                            submission_params.account_id,
+                            submission_params.deadline,
+                        )
+                        .await;
                         if submission_params.deadline != res.deadline {
                             log_deadline_mismatch(
                                 submission_params.height,
@@ -87,7 +573,9 @@ impl RequestHandler {
                         }
                     }
                     Err(FetchError::Pool(e)) => {
-                        if e.message.is_empty() || e.message == "limit exceeded" {
+                        let reason = e.reason();
+                        record_rejection(&metrics, submission_params.account_id, reason).await;
+                        if reason == RejectionReason::RateLimited {
                             log_pool_busy(
                                 submission_params.account_id,
                                 submission_params.nonce,
@@ -104,10 +592,12 @@ impl RequestHandler {
                                 submission_params.deadline,
                                 e.code,
                                 &e.message,
+                                reason,
                             );
                         }
                     }
-                    Err(FetchError::Http(x)) => {
+                    Err(x @ (FetchError::Http(_) | FetchError::TooLarge(_))) => {
+                        record_submission_failure(&metrics, submission_params.account_id).await;
                         log_submission_failed(
                             submission_params.account_id,
                             submission_params.nonce,
@@ -123,8 +613,86 @@ impl RequestHandler {
         });
     }
 
-    pub fn get_mining_info<'a>(&'a self) -> impl std::future::Future<Output = Result<MiningInfoResponse, FetchError>> + 'a {
-        self.client.get_mining_info()
+    pub async fn get_mining_info(&self) -> Result<MiningInfoResponse, FetchError> {
+        let mining_info = self.fetch_mining_info().await?;
+        self.round.update(&mining_info);
+        Ok(mining_info)
+    }
+
+    async fn fetch_mining_info(&self) -> Result<MiningInfoResponse, FetchError> {
+        let (node_client, state) = match &self.fallback {
+            Some(fallback) => fallback,
+            None => return self.pool_mining_info().await,
+        };
+
+        if state.is_active() && !state.probe_due() {
+            return node_client.get_mining_info().await;
+        }
+
+        match self.pool_mining_info().await {
+            Ok(mining_info) => {
+                if state.record_success() {
+                    info!("{: <80}", "pool reachable again, leaving fallback node");
+                }
+                Ok(mining_info)
+            }
+            Err(e) => {
+                if state.record_failure() {
+                    warn!(
+                        "{: <80}",
+                        "pool unreachable, solo mining against fallback node"
+                    );
+                }
+                if state.is_active() {
+                    node_client.get_mining_info().await
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Mining info of the pool, unless one of the sources has a newer block before the pool
+    /// answers. A source's mining info gets the pool's target deadline.
+    async fn pool_mining_info(&self) -> Result<MiningInfoResponse, FetchError> {
+        if self.sources.is_empty() {
+            return self.record_target_deadline(self.pool.get_mining_info().await);
+        }
+
+        let known = self.round.height().unwrap_or(0);
+        let newer = futures::future::select_ok(self.sources.iter().map(|source| {
+            Box::pin(async move {
+                let mining_info = source.get_mining_info().await.map_err(|_| ())?;
+                if mining_info.height > known {
+                    Ok((mining_info, source.base_uri()))
+                } else {
+                    Err(())
+                }
+            })
+        }));
+
+        tokio::select! {
+            res = self.pool.get_mining_info() => self.record_target_deadline(res),
+            Ok(((mut mining_info, url), _)) = newer => {
+                info!(
+                    "{: <80}",
+                    format!("new block {} from {}, ahead of the pool", mining_info.height, url)
+                );
+                mining_info.target_deadline = self.pool_target_deadline.load(Ordering::Relaxed);
+                Ok(mining_info)
+            }
+        }
+    }
+
+    fn record_target_deadline(
+        &self,
+        res: Result<MiningInfoResponse, FetchError>,
+    ) -> Result<MiningInfoResponse, FetchError> {
+        if let Ok(mining_info) = &res {
+            self.pool_target_deadline
+                .store(mining_info.target_deadline, Ordering::Relaxed);
+        }
+        res
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -152,17 +720,155 @@ impl RequestHandler {
         }
     }
 
+    /// Stage times of the round of the last mining info.
+    pub fn round_stages(&self) -> Arc<RoundStages> {
+        self.round.stages()
+    }
+
+    /// Cancellation token of the round of the last mining info, cancelled by the next round.
+    pub fn round_cancel(&self) -> CancelToken {
+        self.round.cancel()
+    }
+
+    /// The current round and the deadlines the pool accepted in it, `None` before the first
+    /// mining info.
+    pub fn round_snapshot(&self) -> Option<RoundSnapshot> {
+        self.round.snapshot()
+    }
+
+    /// Number of configured pool endpoints.
+    pub fn pool_endpoint_count(&self) -> usize {
+        self.pool.count()
+    }
+
+    pub async fn probe_latencies(&self) -> Vec<LatencyProbe> {
+        self.pool.probe_latencies().await
+    }
+
     #[cfg(feature = "async_io")]
     pub async fn update_capacity(&mut self, total_size_gb: usize) {
-        self.client.update_capacity(total_size_gb).await;
+        for client in self.pool.clients() {
+            client.update_capacity(total_size_gb).await;
+        }
     }
 
     #[cfg(not(feature = "async_io"))]
     pub fn update_capacity(&mut self, total_size_gb: usize) {
-        self.client.update_capacity(total_size_gb);
+        for client in self.pool.clients() {
+            client.update_capacity(total_size_gb);
+        }
+    }
+}
+
+fn audit_record<'a>(
+    params: &SubmissionParameters,
+    pool: &'a str,
+    attempt: u32,
+    result: &'a str,
+) -> SubmissionRecord<'a> {
+    SubmissionRecord {
+        timestamp_ms: now_ms(),
+        height: params.height,
+        account_id: params.account_id,
+        nonce: params.nonce,
+        deadline: params.deadline,
+        deadline_unadjusted: params.deadline_unadjusted,
+        pool,
+        attempt,
+        result,
+        pool_deadline: None,
+        error_code: None,
+        message: None,
+        reason: None,
     }
 }
 
+async fn record_submission_success(metrics: &SharedMetrics, account_id: u64, deadline: u64) {
+    #[cfg(feature = "async_io")]
+    let mut metrics = metrics.write().await;
+    #[cfg(not(feature = "async_io"))]
+    let mut metrics = match metrics.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("metrics: mutex poisoned during submission, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    metrics.record_submission_success(account_id, deadline);
+}
+
+async fn record_submission_failure(metrics: &SharedMetrics, account_id: u64) {
+    #[cfg(feature = "async_io")]
+    let mut metrics = metrics.write().await;
+    #[cfg(not(feature = "async_io"))]
+    let mut metrics = match metrics.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("metrics: mutex poisoned during submission, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    metrics.record_submission_failure(account_id);
+}
+
+async fn record_rejection(metrics: &SharedMetrics, account_id: u64, reason: RejectionReason) {
+    #[cfg(feature = "async_io")]
+    let mut metrics = metrics.write().await;
+    #[cfg(not(feature = "async_io"))]
+    let mut metrics = match metrics.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("metrics: mutex poisoned during rejection, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    metrics.record_rejection(account_id, reason);
+}
+
+async fn record_submission_latency(
+    metrics: &SharedMetrics,
+    pool: &str,
+    latency_ms: u64,
+    available: bool,
+    submission: (u64, String),
+) {
+    #[cfg(feature = "async_io")]
+    let mut metrics = metrics.write().await;
+    #[cfg(not(feature = "async_io"))]
+    let mut metrics = match metrics.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("metrics: mutex poisoned during submission, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    metrics.record_submission_latency(pool, latency_ms, available, submission);
+}
+
+async fn record_stale_submission(metrics: &SharedMetrics, account_id: u64) {
+    #[cfg(feature = "async_io")]
+    let mut metrics = metrics.write().await;
+    #[cfg(not(feature = "async_io"))]
+    let mut metrics = match metrics.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("metrics: mutex poisoned during stale submission, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    metrics.record_stale_submission(account_id);
+}
+
+fn log_dry_run_submission(height: u64, account_id: u64, nonce: u64, deadline: u64) {
+    info!(
+        "dry run, not submitted: height={}, account={}, nonce={}, deadline={}",
+        height,
+        account_id,
+        nonce,
+        format_deadline(deadline)
+    );
+}
+
 fn log_deadline_mismatch(
     height: u64,
     account_id: u64,
@@ -173,7 +879,11 @@ fn log_deadline_mismatch(
     error!(
         "submit: deadlines mismatch, height={}, account={}, nonce={}, \
          deadline_miner={}, deadline_pool={}",
-        height, account_id, nonce, deadline, deadline_pool
+        height,
+        account_id,
+        nonce,
+        format_deadline(deadline),
+        format_deadline(deadline_pool)
     );
 }
 
@@ -182,7 +892,10 @@ fn log_submission_failed(account_id: u64, nonce: u64, deadline: u64, err: &str)
         "{: <80}",
         format!(
             "submission failed, retrying: account={}, nonce={}, deadline={}, description={}",
-            account_id, nonce, deadline, err
+            account_id,
+            nonce,
+            format_deadline(deadline),
+            err
         )
     );
 }
@@ -194,36 +907,375 @@ fn log_submission_not_accepted(
     deadline: u64,
     err_code: i32,
     msg: &str,
+    reason: RejectionReason,
 ) {
     error!(
-        "submission not accepted: height={}, account={}, nonce={}, \
+        "submission not accepted ({}): height={}, account={}, nonce={}, \
          deadline={}\n\tcode: {}\n\tmessage: {}",
-        height, account_id, nonce, deadline, err_code, msg,
+        reason.as_str(),
+        height,
+        account_id,
+        nonce,
+        format_deadline(deadline),
+        err_code,
+        msg,
+    );
+    if let Some(hint) = reason.hint() {
+        warn!("hint: {}", hint);
+    }
+}
+
+fn log_below_floor(account_id: u64, nonce: u64, deadline: u64, previous: u64) {
+    debug!(
+        "not enough of an improvement, skipping: account={}, nonce={}, deadline={}, \
+         best sent={}",
+        account_id,
+        nonce,
+        format_deadline(deadline),
+        format_deadline(previous)
+    );
+}
+
+fn log_stale_submission(height: u64, account_id: u64, nonce: u64) {
+    info!(
+        "round over, dropping submission: height={}, account={}, nonce={}",
+        height, account_id, nonce
+    );
+}
+
+fn log_duplicate_submission(account_id: u64, nonce: u64) {
+    debug!(
+        "already accepted this round, skipping: account={}, nonce={}",
+        account_id, nonce
     );
 }
 
 fn log_submission_accepted(account_id: u64, nonce: u64, deadline: u64) {
     info!(
         "deadline accepted: account={}, nonce={}, deadline={}",
-        account_id, nonce, deadline
+        account_id,
+        nonce,
+        format_deadline(deadline)
     );
 }
 
 fn log_pool_busy(account_id: u64, nonce: u64, deadline: u64) {
     info!(
         "pool busy, retrying: account={}, nonce={}, deadline={}",
-        account_id, nonce, deadline
+        account_id,
+        nonce,
+        format_deadline(deadline)
     );
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http_server::{serve_local, Request, Response};
     use std::collections::HashMap;
+    use std::fs;
     use tokio::runtime::Runtime;
 
     static BASE_URL: &str = "http://94.130.178.37:31000";
 
+    #[test]
+    fn test_stale_round() {
+        let params = |height, gen_sig| SubmissionParameters {
+            account_id: 1,
+            nonce: 2,
+            height,
+            block: 0,
+            deadline_unadjusted: 3,
+            deadline: 3,
+            gen_sig,
+        };
+        let round = CurrentRound::default();
+        assert!(!round.is_stale(&params(10, [0; 32])));
+
+        round.update(&MiningInfoResponse {
+            generation_signature: hex::encode([0xab; 32]),
+            base_target: 1,
+            height: 11,
+            target_deadline: u64::MAX,
+        });
+        assert!(round.is_stale(&params(10, [0xab; 32])));
+        assert!(round.is_stale(&params(11, [0; 32])));
+        assert!(!round.is_stale(&params(11, [0xab; 32])));
+
+        round.record_accepted(&params(11, [0xab; 32]));
+        round.record_accepted(&params(10, [0xab; 32]));
+        let snapshot = round.snapshot().unwrap();
+        assert_eq!(snapshot.height, 11);
+        assert_eq!(snapshot.account_id_to_best_deadline.get(&1), Some(&3));
+        assert_eq!(snapshot.account_id_to_best_deadline.len(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_submission() {
+        let params = |height, nonce| SubmissionParameters {
+            account_id: 1,
+            nonce,
+            height,
+            block: 0,
+            deadline_unadjusted: 3,
+            deadline: 3,
+            gen_sig: [0xab; 32],
+        };
+        let round = CurrentRound::default();
+        let mining_info = |height| MiningInfoResponse {
+            generation_signature: hex::encode([0xab; 32]),
+            base_target: 1,
+            height,
+            target_deadline: u64::MAX,
+        };
+        round.update(&mining_info(11));
+        assert!(!round.is_duplicate(&params(11, 2)));
+
+        round.record_accepted(&params(11, 2));
+        assert!(round.is_duplicate(&params(11, 2)));
+        assert!(!round.is_duplicate(&params(11, 3)));
+
+        round.update(&mining_info(12));
+        assert!(!round.is_duplicate(&params(12, 2)));
+    }
+
+    #[test]
+    fn test_fallback_state() {
+        // 0 would mean switching before anything failed
+        let state = FallbackState::new(0, 60);
+        assert!(!state.is_active());
+        assert!(state.record_failure());
+        assert!(state.is_active());
+        assert!(!state.record_failure());
+        assert!(state.record_success());
+        assert!(!state.is_active());
+        assert!(!state.record_success());
+
+        let state = FallbackState::new(3, 60);
+        assert!(!state.record_failure());
+        assert!(!state.record_failure());
+        // a success starts the count over
+        assert!(!state.record_success());
+        assert!(!state.record_failure());
+        assert!(!state.record_failure());
+        assert!(state.record_failure());
+
+        // switching over counts as a probe
+        assert!(!state.probe_due());
+        state.last_probe.store(unix_time() - 60, Ordering::SeqCst);
+        assert!(state.probe_due());
+        // only one request per interval goes to the pool
+        assert!(!state.probe_due());
+    }
+
+    fn handler(
+        url: Url,
+        secret_phrases: HashMap<u64, String>,
+        solo_node: Option<Url>,
+        mining_info_sources: Vec<Url>,
+        audit_log_dir: Option<PathBuf>,
+        dry_run: bool,
+    ) -> RequestHandler {
+        RequestHandler::new(
+            vec![PoolCfg {
+                url,
+                chain: "mainnet".to_owned(),
+                min_improvement: 0.0,
+                min_improvement_secs: 0,
+                user_agent: None,
+                headers: HashMap::new(),
+                priority: 0,
+            }],
+            secret_phrases,
+            solo_node,
+            ConnectionSettings {
+                timeout: 5000,
+                pool_max_idle_per_host: 1,
+                keep_alive: 0,
+                http2: false,
+            },
+            12,
+            false,
+            HashMap::new(),
+            Arc::new(AccountRotation::default()),
+            1,
+            None,
+            mining_info_sources,
+            audit_log_dir,
+            dry_run,
+            crate::metrics::new_shared_metrics(
+                String::new(),
+                None,
+                Vec::new(),
+                None,
+                None,
+                Vec::new(),
+                None,
+            ),
+            tokio::runtime::Handle::current(),
+        )
+    }
+
+    async fn wait_until(done: impl Fn() -> bool) {
+        for _ in 0..250 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let submissions = Arc::new(AtomicU64::new(0));
+        let url = serve_local({
+            let submissions = submissions.clone();
+            move |request: Request| {
+                if request.params.get("requestType").map(String::as_str) == Some("submitNonce") {
+                    submissions.fetch_add(1, Ordering::SeqCst);
+                }
+                async { Response::json(r#"{"result":"success","deadline":1193}"#.to_owned()) }
+            }
+        })
+        .await;
+        let dir = std::env::temp_dir().join(format!("signum-miner-dry-run-{}", std::process::id()));
+        let audit_log = || {
+            fs::read_dir(&dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| fs::read_to_string(entry.path()).unwrap_or_default())
+                .collect::<String>()
+        };
+
+        let dry_run = handler(
+            url,
+            HashMap::new(),
+            None,
+            Vec::new(),
+            Some(dir.clone()),
+            true,
+        );
+        dry_run.submit_nonce(1337, 12, 111, 0, 7123, 1193, [0; 32]);
+        wait_until(|| audit_log().contains(r#""result":"dry_run""#)).await;
+        assert!(audit_log().contains(r#""result":"dry_run""#));
+        assert_eq!(submissions.load(Ordering::SeqCst), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Serves mining info for `height` after `delay`, the base target tells the servers apart.
+    async fn mining_info_source(
+        height: u64,
+        base_target: u64,
+        target_deadline: Option<u64>,
+        delay: Duration,
+    ) -> Url {
+        let mut mining_info = serde_json::json!({
+            "generationSignature": hex::encode([height as u8; 32]),
+            "baseTarget": base_target,
+            "height": height,
+        });
+        if let Some(target_deadline) = target_deadline {
+            mining_info["targetDeadline"] = target_deadline.into();
+        }
+        let mining_info = mining_info.to_string();
+        serve_local(move |_| {
+            let mining_info = mining_info.clone();
+            async move {
+                tokio::time::sleep(delay).await;
+                Response::json(mining_info)
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_mining_info_sources() {
+        // the pool answers at once for block 10, then takes its time
+        let calls = Arc::new(AtomicU64::new(0));
+        let pool = serve_local({
+            let calls = calls.clone();
+            move |_| {
+                let (height, delay) = match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => (10, 0),
+                    _ => (11, 5000),
+                };
+                async move {
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    Response::json(
+                        serde_json::json!({
+                            "generationSignature": hex::encode([height as u8; 32]),
+                            "baseTarget": 70000,
+                            "height": height,
+                            "targetDeadline": 5000,
+                        })
+                        .to_string(),
+                    )
+                }
+            }
+        })
+        .await;
+        let sources = vec![
+            mining_info_source(10, 1, None, Duration::from_millis(50)).await,
+            mining_info_source(11, 2, Some(1_000_000), Duration::from_millis(150)).await,
+            mining_info_source(11, 3, None, Duration::from_millis(800)).await,
+        ];
+        let handler = handler(pool, HashMap::new(), None, sources, None, false);
+
+        let mining_info = handler.get_mining_info().await.unwrap();
+        assert_eq!(mining_info.height, 10);
+        assert_eq!(mining_info.base_target, 70000);
+
+        // the first source with a newer block wins, the one still at block 10 doesn't count, and
+        // the pool's target deadline replaces the source's
+        let mining_info = handler.get_mining_info().await.unwrap();
+        assert_eq!(mining_info.height, 11);
+        assert_eq!(mining_info.base_target, 2);
+        assert_eq!(mining_info.target_deadline, 5000);
+    }
+
+    /// Records (account, passphrase) of the submissions it gets.
+    async fn submission_sink() -> (Url, Arc<Mutex<Vec<(String, String)>>>) {
+        let submissions = Arc::new(Mutex::new(Vec::new()));
+        let url = serve_local({
+            let submissions = submissions.clone();
+            move |request: Request| {
+                let param = |name: &str| request.params.get(name).cloned().unwrap_or_default();
+                submissions
+                    .lock()
+                    .unwrap()
+                    .push((param("accountId"), param("secretPhrase")));
+                async { Response::json(r#"{"result":"success","deadline":1193}"#.to_owned()) }
+            }
+        })
+        .await;
+        (url, submissions)
+    }
+
+    #[tokio::test]
+    async fn test_solo_accounts() {
+        let (pool, pool_submissions) = submission_sink().await;
+        let (node, node_submissions) = submission_sink().await;
+        let secret_phrases = HashMap::from([(1, "solo secret".to_owned())]);
+        let handler = handler(pool, secret_phrases, Some(node), Vec::new(), None, false);
+
+        // one after the other, the retry queue only sends the best of the submissions waiting in it
+        handler.submit_nonce(1, 12, 111, 0, 7123, 1193, [0; 32]);
+        wait_until(|| !node_submissions.lock().unwrap().is_empty()).await;
+        handler.submit_nonce(2, 13, 111, 0, 7123, 1193, [0; 32]);
+        wait_until(|| !pool_submissions.lock().unwrap().is_empty()).await;
+
+        // the account with a passphrase signs at the node, the pool never sees the passphrase
+        assert_eq!(
+            *node_submissions.lock().unwrap(),
+            vec![("1".to_owned(), "solo secret".to_owned())]
+        );
+        assert_eq!(
+            *pool_submissions.lock().unwrap(),
+            vec![("2".to_owned(), String::new())]
+        );
+    }
+
     #[test]
     fn test_submit_nonce() {
     use url::Url; // sicherstellen, dass url::Url verwendet wird
@@ -234,12 +1286,41 @@ mod tests {
     let base_url: Url = BASE_URL.parse().expect("invalid URL");
 
     let request_handler = RequestHandler::new(
-        base_url,
+        vec![PoolCfg {
+            url: base_url,
+            chain: "mainnet".to_owned(),
+            min_improvement: 0.0,
+            min_improvement_secs: 0,
+            user_agent: None,
+            headers: HashMap::new(),
+            priority: 0,
+        }],
         HashMap::new(),
-        3,
+        None,
+        ConnectionSettings {
+            timeout: 3,
+            pool_max_idle_per_host: 8,
+            keep_alive: 90,
+            http2: true,
+        },
         12,
         true,
         HashMap::new(),
+        Arc::new(AccountRotation::default()),
+        1,
+        None,
+        Vec::new(),
+        None,
+        false,
+        crate::metrics::new_shared_metrics(
+            String::new(),
+            None,
+            Vec::new(),
+            None,
+            None,
+            Vec::new(),
+            None,
+        ),
         handle,
     );
 
@@ -0,0 +1,227 @@
+//! Rescans of the plots on demand, `POST /api/rescan` and the `rescan` command.
+//!
+//! A rescan does what the periodic capacity check does, right away. It is scoped to a plot
+//! directory or a drive id, or covers all of them. The scan still reads every configured
+//! directory, because the reader needs the complete set of plots. The scope only limits the
+//! report of added and removed plots, so the change on one drive doesn't get lost among the
+//! others.
+
+use crate::api_proxy::normalize_base_path;
+use crate::config::Cfg;
+use crate::poc_hashing::NONCE_SIZE;
+use reqwest::{Certificate, Client as InnerClient};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RescanScope {
+    All,
+    Dir(PathBuf),
+    Drive(String),
+}
+
+impl RescanScope {
+    /// From the `dir` or `drive` parameter, neither is all plots.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<RescanScope, &'static str> {
+        match (params.get("dir"), params.get("drive")) {
+            (Some(_), Some(_)) => Err("dir and drive can't be combined"),
+            (Some(dir), None) => Ok(RescanScope::Dir(PathBuf::from(dir))),
+            (None, Some(drive)) => Ok(RescanScope::Drive(drive.clone())),
+            (None, None) => Ok(RescanScope::All),
+        }
+    }
+
+    fn contains(&self, path: &str, drive: Option<&String>) -> bool {
+        match self {
+            RescanScope::All => true,
+            RescanScope::Dir(dir) => Path::new(path).starts_with(dir),
+            RescanScope::Drive(id) => drive == Some(id),
+        }
+    }
+}
+
+impl std::fmt::Display for RescanScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RescanScope::All => write!(f, "all"),
+            RescanScope::Dir(dir) => write!(f, "dir {}", dir.display()),
+            RescanScope::Drive(id) => write!(f, "drive {}", id),
+        }
+    }
+}
+
+/// Plots that appeared and disappeared within the scope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RescanReport {
+    pub context: String,
+    pub scope: String,
+    pub plots: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub old_bytes: u64,
+    pub new_bytes: u64,
+}
+
+impl RescanReport {
+    /// `*_nonces` map plot paths to their nonces, `*_drives` to their drive id.
+    pub fn new(
+        context: &str,
+        scope: &RescanScope,
+        (old_nonces, old_drives): (&HashMap<String, u64>, &HashMap<String, String>),
+        (new_nonces, new_drives): (&HashMap<String, u64>, &HashMap<String, String>),
+    ) -> RescanReport {
+        let scoped = |nonces: &HashMap<String, u64>, drives: &HashMap<String, String>| {
+            nonces
+                .iter()
+                .filter(|&(path, _)| scope.contains(path, drives.get(path)))
+                .map(|(path, &nonces)| (path.clone(), nonces))
+                .collect::<HashMap<String, u64>>()
+        };
+        let old = scoped(old_nonces, old_drives);
+        let new = scoped(new_nonces, new_drives);
+        let changed = |from: &HashMap<String, u64>, to: &HashMap<String, u64>| {
+            let mut paths: Vec<String> = from
+                .iter()
+                .filter(|&(path, nonces)| to.get(path) != Some(nonces))
+                .map(|(path, _)| path.clone())
+                .collect();
+            paths.sort();
+            paths
+        };
+        let bytes = |plots: &HashMap<String, u64>| plots.values().sum::<u64>() * NONCE_SIZE as u64;
+        RescanReport {
+            context: context.to_owned(),
+            scope: scope.to_string(),
+            plots: new.len(),
+            added: changed(&new, &old),
+            removed: changed(&old, &new),
+            old_bytes: bytes(&old),
+            new_bytes: bytes(&new),
+        }
+    }
+}
+
+/// A rescan for the miner's task, answered with the report.
+pub struct RescanRequest {
+    pub scope: RescanScope,
+    pub reply: oneshot::Sender<RescanReport>,
+}
+
+pub type RescanSender = mpsc::UnboundedSender<RescanRequest>;
+
+/// Asks the miner listening on `api_listen` for a rescan, for the `rescan` command. The request
+/// carries the first of `api_admin_tokens`, goes below `api_base_path` and trusts `api_tls_cert`
+/// for HTTPS.
+pub async fn request(
+    cfg: &Cfg,
+    api_listen: &str,
+    context: Option<&str>,
+    scope: &RescanScope,
+) -> Result<RescanReport, String> {
+    let mut params: Vec<(&str, String)> = Vec::new();
+    if let Some(context) = context {
+        params.push(("context", context.to_owned()));
+    }
+    match scope {
+        RescanScope::All => {}
+        RescanScope::Dir(dir) => params.push(("dir", dir.to_string_lossy().into_owned())),
+        RescanScope::Drive(id) => params.push(("drive", id.clone())),
+    }
+    let mut client = InnerClient::builder();
+    let scheme = match &cfg.api_tls_cert {
+        Some(cert) => {
+            let pem = std::fs::read(cert).map_err(|e| format!("{}: {}", cert.display(), e))?;
+            let cert = Certificate::from_pem(&pem).map_err(|e| e.to_string())?;
+            client = client.add_root_certificate(cert);
+            "https"
+        }
+        None => "http",
+    };
+    let client = client.build().map_err(|e| e.to_string())?;
+    let mut request = client
+        .post(format!(
+            "{}://{}{}/api/rescan",
+            scheme,
+            api_listen,
+            normalize_base_path(cfg.api_base_path.as_deref().unwrap_or_default())
+        ))
+        .query(&params)
+        // a scan opens every plot file
+        .timeout(Duration::from_secs(300));
+    if let Some(token) = cfg.api_admin_tokens.first() {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("can't reach the miner at {}: {}", api_listen, e))?;
+    let status = response.status();
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, body));
+    }
+    serde_json::from_str(&body).map_err(|e| format!("unexpected response {}: {}", body, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonces(plots: &[(&str, u64)]) -> HashMap<String, u64> {
+        plots
+            .iter()
+            .map(|&(path, nonces)| (path.to_owned(), nonces))
+            .collect()
+    }
+
+    fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_scoped_report() {
+        let old_nonces = nonces(&[("/a/1", 4), ("/a/2", 4), ("/b/1", 8)]);
+        let old_drives = map(&[("/a/1", "sda"), ("/a/2", "sda"), ("/b/1", "sdb")]);
+        let new_nonces = nonces(&[("/a/1", 4), ("/a/3", 4), ("/b/2", 8)]);
+        let new_drives = map(&[("/a/1", "sda"), ("/a/3", "sda"), ("/b/2", "sdb")]);
+        let old = (&old_nonces, &old_drives);
+        let new = (&new_nonces, &new_drives);
+
+        let all = RescanReport::new("", &RescanScope::All, old, new);
+        assert_eq!(all.added, ["/a/3", "/b/2"]);
+        assert_eq!(all.removed, ["/a/2", "/b/1"]);
+        assert_eq!(
+            (all.plots, all.old_bytes, all.new_bytes),
+            (3, 16 * 262_144, 16 * 262_144)
+        );
+
+        let dir = RescanReport::new("", &RescanScope::Dir(PathBuf::from("/a")), old, new);
+        assert_eq!(
+            (dir.added, dir.removed),
+            (vec!["/a/3".to_owned()], vec!["/a/2".to_owned()])
+        );
+        let drive = RescanReport::new("", &RescanScope::Drive("sdb".to_owned()), old, new);
+        assert_eq!(drive.added, ["/b/2"]);
+        assert_eq!(drive.scope, "drive sdb");
+    }
+
+    #[test]
+    fn test_scope_from_params() {
+        assert_eq!(
+            RescanScope::from_params(&HashMap::new()),
+            Ok(RescanScope::All)
+        );
+        let params = map(&[("drive", "sda")]);
+        assert_eq!(
+            RescanScope::from_params(&params),
+            Ok(RescanScope::Drive("sda".to_owned()))
+        );
+        let params = map(&[("drive", "sda"), ("dir", "/a")]);
+        assert!(RescanScope::from_params(&params).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Retiring plots from a running miner.
+//!
+//! `signum-miner retire-plot <file> [--delete]` appends the plot to the retire list (see
+//! `retire_list`), which the miner checks every few seconds. Retired plots are left out of every
+//! scan, so they drop out of the reader and the announced capacity. Read tasks of the running
+//! round keep their own reference to the plot and finish reading it; plots marked for deletion are
+//! only removed from disk once the round is over.
+//!
+//! Each line of the list is a plot path, optionally prefixed with `delete `. Lines starting with
+//! `#` are ignored.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const DELETE_PREFIX: &str = "delete ";
+
+#[derive(Debug, Default)]
+pub struct RetireList {
+    retired: HashSet<PathBuf>,
+    delete: HashSet<PathBuf>,
+}
+
+impl RetireList {
+    /// A missing file is an empty list.
+    pub fn load(list: &Path) -> RetireList {
+        match fs::read_to_string(list) {
+            Ok(content) => RetireList::parse(&content),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("retire list: can't read {}: {}", list.display(), e);
+                }
+                RetireList::default()
+            }
+        }
+    }
+
+    fn parse(content: &str) -> RetireList {
+        let mut list = RetireList::default();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix(DELETE_PREFIX) {
+                Some(path) => {
+                    let path = normalize(Path::new(path.trim()));
+                    list.delete.insert(path.clone());
+                    list.retired.insert(path);
+                }
+                None => {
+                    list.retired.insert(normalize(Path::new(line)));
+                }
+            }
+        }
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.retired.is_empty()
+    }
+
+    pub fn contains(&self, plot: &Path) -> bool {
+        !self.retired.is_empty() && self.retired.contains(&normalize(plot))
+    }
+
+    pub fn should_delete(&self, plot: &Path) -> bool {
+        !self.delete.is_empty() && self.delete.contains(&normalize(plot))
+    }
+}
+
+/// The same plot can be configured and given on the command line in different ways.
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Adds a plot to the retire list, used by the `retire-plot` command.
+pub fn append(list: &Path, plot: &Path, delete: bool) -> io::Result<()> {
+    let plot = fs::canonicalize(plot)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(list)?;
+    writeln!(
+        file,
+        "{}{}",
+        if delete { DELETE_PREFIX } else { "" },
+        plot.display()
+    )
+}
+
+/// Removes a retired plot from disk, only call once no read task uses it anymore.
+pub fn delete_plot(path: &str) {
+    match fs::remove_file(path) {
+        Ok(()) => info!("retired plot deleted: {}", path),
+        Err(e) => error!("can't delete retired plot {}: {}", path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retire_list() {
+        let list = RetireList::parse("# retired\n/plots/a_0_8\n\ndelete /plots/b_8_8\n");
+        assert!(list.contains(Path::new("/plots/a_0_8")));
+        assert!(!list.should_delete(Path::new("/plots/a_0_8")));
+        assert!(list.contains(Path::new("/plots/b_8_8")));
+        assert!(list.should_delete(Path::new("/plots/b_8_8")));
+        assert!(!list.contains(Path::new("/plots/c_16_8")));
+    }
+}
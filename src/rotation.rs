@@ -0,0 +1,103 @@
+//! Rotation of the account a block is mined for, see `account_rotation`.
+//!
+//! For capacity leasing the plots belong to a proxy account the pool multiplexes: it credits the
+//! deadlines of each block to whichever account the miner names for it. Accounts take turns by
+//! height, an account of weight 2 gets two blocks in a row, so the schedule is the same on every
+//! miner sharing the plots. The account of a submission's block is the `{rotation_account}`
+//! placeholder of the pool headers, the schedule is served at `/api/rotation`.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RotationEntry {
+    pub account_id: u64,
+    /// Blocks in a row for this account.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ScheduledBlock {
+    pub height: u64,
+    pub account_id: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AccountRotation {
+    entries: Vec<RotationEntry>,
+    total_weight: u64,
+}
+
+impl AccountRotation {
+    pub fn new(mut entries: Vec<RotationEntry>) -> AccountRotation {
+        entries.retain(|entry| entry.weight > 0);
+        let total_weight = entries.iter().map(|entry| u64::from(entry.weight)).sum();
+        AccountRotation {
+            entries,
+            total_weight,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The account `height` is mined for, None without a rotation.
+    pub fn account_for(&self, height: u64) -> Option<u64> {
+        if self.total_weight == 0 {
+            return None;
+        }
+        let mut slot = height % self.total_weight;
+        for entry in &self.entries {
+            if slot < u64::from(entry.weight) {
+                return Some(entry.account_id);
+            }
+            slot -= u64::from(entry.weight);
+        }
+        None
+    }
+
+    /// The accounts of `blocks` blocks from `height` on.
+    pub fn schedule(&self, height: u64, blocks: u64) -> Vec<ScheduledBlock> {
+        (height..height.saturating_add(blocks))
+            .filter_map(|height| {
+                self.account_for(height)
+                    .map(|account_id| ScheduledBlock { height, account_id })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_rotation() {
+        let rotation = AccountRotation::new(vec![
+            RotationEntry {
+                account_id: 1,
+                weight: 2,
+            },
+            RotationEntry {
+                account_id: 2,
+                weight: 0,
+            },
+            RotationEntry {
+                account_id: 3,
+                weight: 1,
+            },
+        ]);
+        let accounts: Vec<u64> = rotation
+            .schedule(999, 6)
+            .iter()
+            .map(|block| block.account_id)
+            .collect();
+        // 999 % 3 == 0
+        assert_eq!(accounts, [1, 1, 3, 1, 1, 3]);
+        assert_eq!(rotation.schedule(999, 1)[0].height, 999);
+        assert_eq!(AccountRotation::default().account_for(999), None);
+    }
+}
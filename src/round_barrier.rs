@@ -0,0 +1,116 @@
+//! Completion of a round across drives.
+//!
+//! Every drive's read task arrives at the round's barrier exactly once when it stops, because it
+//! read its last plot, the round was cancelled or the workers went away. The task that arrives last
+//! ends the round: only it sends the GPU termination signals and the "round finished" marker, so
+//! neither depends on which drive happened to read its last plot first, nor on the drive count the
+//! miner saw at startup.
+//!
+//! The barrier also counts the chunks handed to the hash workers that aren't hashed yet. Each
+//! counted chunk carries a [`Hashing`] guard in its `BufferInfo` that counts it as done when the
+//! worker drops it, after sending its result. The CPU workers hash in parallel batches and the GPUs
+//! hold a chunk back until the next one is transferred, so the marker waits for that count to drop
+//! to zero before it goes on to the miner. Otherwise the miner could close the round before its
+//! last deadlines came in.
+
+use crate::cancel::CancelToken;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+// how often a round waiting for its chunks rechecks whether it was cancelled
+const CANCEL_CHECK: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+pub struct RoundBarrier {
+    remaining: AtomicUsize,
+    hashing: Mutex<usize>,
+    hashed: Condvar,
+}
+
+/// A counted chunk, done when dropped.
+pub struct Hashing(Arc<RoundBarrier>);
+
+impl Drop for Hashing {
+    fn drop(&mut self) {
+        let mut hashing = self.0.count();
+        *hashing -= 1;
+        if *hashing == 0 {
+            self.0.hashed.notify_all();
+        }
+    }
+}
+
+impl RoundBarrier {
+    pub fn new(drives: usize) -> RoundBarrier {
+        RoundBarrier {
+            remaining: AtomicUsize::new(drives),
+            ..RoundBarrier::default()
+        }
+    }
+
+    /// Counts a chunk before it's handed to a hash worker. The chunk keeps the guard until the
+    /// worker is done with it.
+    pub fn sent(self: &Arc<Self>) -> Hashing {
+        *self.count() += 1;
+        Hashing(self.clone())
+    }
+
+    /// Waits until the workers are done with every chunk of the round, or the round is cancelled.
+    pub fn wait_hashed(&self, cancel: &CancelToken) {
+        let mut hashing = self.count();
+        while *hashing > 0 && !cancel.is_cancelled() {
+            hashing = match self.hashed.wait_timeout(hashing, CANCEL_CHECK) {
+                Ok((guard, _)) => guard,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+        }
+    }
+
+    /// Marks one drive as done. True for the drive that completes the round.
+    pub fn arrive(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+            == Ok(1)
+    }
+
+    fn count(&self) -> MutexGuard<'_, usize> {
+        // a plain counter, a panic can't leave it half updated
+        self.hashing.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_last_arrival_completes_round() {
+        let barrier = RoundBarrier::new(3);
+        assert!(!barrier.arrive());
+        assert!(!barrier.arrive());
+        assert!(barrier.arrive());
+        // extra arrivals never complete the round twice
+        assert!(!barrier.arrive());
+    }
+
+    #[test]
+    fn test_wait_hashed() {
+        let barrier = Arc::new(RoundBarrier::new(1));
+        let cancel = CancelToken::default();
+        drop(barrier.sent());
+        let last = barrier.sent();
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| barrier.wait_hashed(&cancel));
+            thread::sleep(Duration::from_millis(20));
+            assert!(!waiting.is_finished());
+            drop(last);
+        });
+        barrier.wait_hashed(&cancel);
+
+        let _held = barrier.sent();
+        cancel.cancel();
+        barrier.wait_hashed(&cancel);
+    }
+}
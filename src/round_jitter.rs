@@ -0,0 +1,53 @@
+//! Random delay before the reads of a round start.
+//!
+//! Several miners on shared storage or a shared power supply all start reading when the same
+//! block arrives. With `round_start_jitter_ms` every miner draws its own delay per round, up to
+//! that bound, so their reads and the spin-ups that come with them are spread out. A new block
+//! during the delay ends it early.
+
+use crate::cancel::CancelToken;
+use rand::Rng;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a waiting reader checks for a new block.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Random delay up to `max_ms`, zero if there is no bound.
+pub fn draw(max_ms: u64) -> Duration {
+    if max_ms == 0 {
+        return Duration::from_millis(0);
+    }
+    Duration::from_millis(rand::thread_rng().gen_range(0, max_ms + 1))
+}
+
+/// Blocks until `start` or until the round is cancelled.
+pub fn wait(start: Instant, cancel: &CancelToken) {
+    loop {
+        let now = Instant::now();
+        if now >= start || cancel.is_cancelled() {
+            return;
+        }
+        thread::sleep((start - now).min(CANCEL_POLL_INTERVAL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw() {
+        assert_eq!(draw(0), Duration::from_millis(0));
+        assert!((0..100).all(|_| draw(20) <= Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_wait_ends_on_cancel() {
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let start = Instant::now();
+        wait(start + Duration::from_secs(60), &cancel);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,200 @@
+//! Live view of the running round for `/api/round/current`.
+//!
+//! The reader counts the bytes every drive has read of the round's scoop in `RoundProgress`, the
+//! miner records the block and every account's best deadline as chunks are hashed. The API takes a
+//! snapshot on every request, so external displays see the round as it goes without parsing logs.
+//! The generation signature is only shown hashed, the API may be reachable by anyone who can see
+//! the machine.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+struct DriveProgress {
+    drive: Arc<str>,
+    read: AtomicU64,
+    // bytes of one scoop over all of the drive's plots
+    total: u64,
+}
+
+/// Bytes read per drive in one round.
+#[derive(Default)]
+pub struct RoundProgress {
+    drives: Vec<DriveProgress>,
+}
+
+impl RoundProgress {
+    pub fn new(drives: &[(Arc<str>, u64)]) -> RoundProgress {
+        RoundProgress {
+            drives: drives
+                .iter()
+                .map(|(drive, total)| DriveProgress {
+                    drive: drive.clone(),
+                    read: AtomicU64::new(0),
+                    total: *total,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn add(&self, drive: &str, bytes: u64) {
+        if let Some(progress) = self
+            .drives
+            .iter()
+            .find(|progress| &*progress.drive == drive)
+        {
+            progress.read.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+
+    /// Percent scanned by drive.
+    pub fn percent(&self) -> BTreeMap<String, f64> {
+        self.drives
+            .iter()
+            .map(|progress| {
+                let read = progress.read.load(Ordering::Relaxed);
+                let percent = if progress.total == 0 {
+                    100.0
+                } else {
+                    (read as f64 * 100.0 / progress.total as f64).min(100.0)
+                };
+                (progress.drive.to_string(), percent)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CurrentRound {
+    /// Name of the mining context, empty for an unnamed single one.
+    pub context: String,
+    pub height: u64,
+    pub base_target: u64,
+    /// Hex SHA-256 of the generation signature.
+    pub gensig_hash: String,
+    pub scoop: u32,
+    pub elapsed_ms: u64,
+    pub drives: BTreeMap<String, f64>,
+    /// Best deadline in seconds by account, whether or not it's below the target deadline.
+    pub best_deadlines: BTreeMap<u64, u64>,
+}
+
+#[derive(Default)]
+pub struct RoundStatus {
+    context: String,
+    height: u64,
+    base_target: u64,
+    gensig_hash: String,
+    scoop: u32,
+    started: Option<Instant>,
+    progress: Arc<RoundProgress>,
+    best_deadlines: BTreeMap<u64, u64>,
+}
+
+pub type SharedRoundStatus = Arc<Mutex<RoundStatus>>;
+
+pub fn new_shared_round_status(context: String) -> SharedRoundStatus {
+    Arc::new(Mutex::new(RoundStatus {
+        context,
+        ..RoundStatus::default()
+    }))
+}
+
+impl RoundStatus {
+    pub fn start(
+        &mut self,
+        height: u64,
+        base_target: u64,
+        gensig: &[u8; 32],
+        scoop: u32,
+        progress: Arc<RoundProgress>,
+    ) {
+        self.height = height;
+        self.base_target = base_target;
+        self.gensig_hash = hex::encode(Sha256::digest(gensig));
+        self.scoop = scoop;
+        self.started = Some(Instant::now());
+        self.progress = progress;
+        self.best_deadlines.clear();
+    }
+
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+    /// Height of the running round, 0 before the first one.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Records a chunk's best deadline of `height`, older rounds are ignored.
+    pub fn record(&mut self, height: u64, account_id: u64, deadline: u64) {
+        if height != self.height {
+            return;
+        }
+        let best = self.best_deadlines.entry(account_id).or_insert(u64::MAX);
+        *best = (*best).min(deadline);
+    }
+
+    pub fn snapshot(&self) -> CurrentRound {
+        CurrentRound {
+            context: self.context.clone(),
+            height: self.height,
+            base_target: self.base_target,
+            gensig_hash: self.gensig_hash.clone(),
+            scoop: self.scoop,
+            elapsed_ms: self
+                .started
+                .map_or(0, |started| started.elapsed().as_millis() as u64),
+            drives: self.progress.percent(),
+            best_deadlines: self.best_deadlines.clone(),
+        }
+    }
+}
+
+pub fn lock(status: &SharedRoundStatus) -> MutexGuard<'_, RoundStatus> {
+    match status.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("round status: mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_status() {
+        let status = new_shared_round_status("main".to_owned());
+        let progress = Arc::new(RoundProgress::new(&[
+            (Arc::from("sda"), 1000),
+            (Arc::from("sdb"), 0),
+        ]));
+        lock(&status).start(7, 70_000, &[0u8; 32], 12, progress.clone());
+        progress.add("sda", 250);
+        progress.add("unknown", 250);
+        lock(&status).record(7, 1, 500);
+        lock(&status).record(7, 1, 900);
+        lock(&status).record(6, 2, 10);
+
+        let round = lock(&status).snapshot();
+        assert_eq!(
+            (
+                round.context.as_str(),
+                round.height,
+                round.base_target,
+                round.scoop
+            ),
+            ("main", 7, 70_000, 12)
+        );
+        assert_eq!(round.gensig_hash.len(), 64);
+        assert_eq!(round.drives["sda"], 25.0);
+        assert_eq!(round.drives["sdb"], 100.0);
+        assert_eq!(round.best_deadlines, BTreeMap::from([(1, 500)]));
+    }
+}
@@ -0,0 +1,199 @@
+//! Threads the reader runs its per drive tasks on.
+//!
+//! By default the tasks run on a rayon pool. `queue` runs them on plain threads taking tasks from
+//! a single work queue, for platforms where rayon's pool interacts badly with thread pinning or
+//! with the tokio runtime of `async_io`. `per_drive` gives every drive a dedicated thread, so a
+//! slow drive never holds a thread another drive could read on.
+
+use crate::topology::{topology, CoreSelection};
+use crate::utils::{new_thread_pool, pin_current_thread};
+use crossbeam_channel::Sender;
+use serde::de::{self, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ReaderScheduler {
+    Rayon,
+    Queue,
+    PerDrive,
+}
+
+impl<'de> Deserialize<'de> for ReaderScheduler {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "rayon" => Ok(ReaderScheduler::Rayon),
+            "queue" => Ok(ReaderScheduler::Queue),
+            "per_drive" | "per-drive" => Ok(ReaderScheduler::PerDrive),
+            _ => Err(de::Error::custom(format!(
+                "unknown reader scheduler '{}' (rayon, queue, per_drive)",
+                s
+            ))),
+        }
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+pub enum ReaderPool {
+    Rayon(rayon::ThreadPool),
+    Queue(Sender<Task>),
+    PerDrive {
+        threads: Mutex<HashMap<String, Sender<Task>>>,
+        cores: Vec<usize>,
+    },
+}
+
+impl ReaderPool {
+    pub fn new(
+        scheduler: ReaderScheduler,
+        num_threads: usize,
+        thread_pinning: bool,
+        thread_cores: CoreSelection,
+    ) -> ReaderPool {
+        let cores = if thread_pinning {
+            topology().cores(thread_cores)
+        } else {
+            Vec::new()
+        };
+        match scheduler {
+            ReaderScheduler::Rayon => {
+                ReaderPool::Rayon(new_thread_pool(num_threads, thread_pinning, thread_cores))
+            }
+            ReaderScheduler::Queue => {
+                let (tx, rx) = crossbeam_channel::unbounded::<Task>();
+                for i in 0..num_threads.max(1) {
+                    let rx = rx.clone();
+                    let core = core(&cores, i);
+                    if let Err(e) = spawn_thread(format!("reader-{}", i), core, move || {
+                        for task in rx {
+                            run(task);
+                        }
+                    }) {
+                        error!("reader: can't spawn thread: {}", e);
+                    }
+                }
+                ReaderPool::Queue(tx)
+            }
+            ReaderScheduler::PerDrive => ReaderPool::PerDrive {
+                threads: Mutex::new(HashMap::new()),
+                cores,
+            },
+        }
+    }
+
+    /// Runs `task` for `drive`, on the drive's own thread with `per_drive`.
+    pub fn spawn(&self, drive: &str, task: impl FnOnce() + Send + 'static) {
+        match self {
+            ReaderPool::Rayon(pool) => pool.spawn(task),
+            ReaderPool::Queue(tx) => {
+                if tx.send(Box::new(task)).is_err() {
+                    error!("reader: no threads left, dropping task for {}", drive);
+                }
+            }
+            ReaderPool::PerDrive { threads, cores } => {
+                let mut threads = lock(threads);
+                if !threads.contains_key(drive) {
+                    let (tx, rx) = crossbeam_channel::unbounded::<Task>();
+                    let core = core(cores, threads.len());
+                    if let Err(e) = spawn_thread(format!("reader-{}", drive), core, move || {
+                        for task in rx {
+                            run(task);
+                        }
+                    }) {
+                        error!("reader: can't spawn thread for {}: {}", drive, e);
+                        return;
+                    }
+                    threads.insert(drive.to_owned(), tx);
+                }
+                if threads[drive].send(Box::new(task)).is_err() {
+                    error!("reader: thread for {} is gone, dropping task", drive);
+                }
+            }
+        }
+    }
+}
+
+fn core(cores: &[usize], i: usize) -> Option<usize> {
+    if cores.is_empty() {
+        None
+    } else {
+        Some(cores[i % cores.len()])
+    }
+}
+
+fn spawn_thread(
+    name: String,
+    core: Option<usize>,
+    f: impl FnOnce() + Send + 'static,
+) -> io::Result<()> {
+    thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            if let Some(core) = core {
+                if !pin_current_thread(core) {
+                    warn!("Failed to pin thread to core {}", core);
+                }
+            }
+            f();
+        })
+        .map(|_| ())
+}
+
+// a panicking task must not take the thread and every later task of its queue with it
+fn run(task: Task) {
+    if panic::catch_unwind(AssertUnwindSafe(task)).is_err() {
+        error!("reader: task panicked");
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("reader scheduler: mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_schedulers_run_tasks() {
+        for scheduler in [
+            ReaderScheduler::Rayon,
+            ReaderScheduler::Queue,
+            ReaderScheduler::PerDrive,
+        ] {
+            let pool = ReaderPool::new(scheduler, 2, false, CoreSelection::Any);
+            let (tx, rx) = crossbeam_channel::unbounded();
+            for drive in ["a", "b", "a"] {
+                let tx = tx.clone();
+                pool.spawn(drive, move || tx.send(drive).unwrap());
+            }
+            if scheduler != ReaderScheduler::Rayon {
+                // rayon aborts on a panicking task, the others keep going
+                pool.spawn("b", || panic!("task failed"));
+            }
+            let tx = tx.clone();
+            pool.spawn("b", move || tx.send("c").unwrap());
+
+            let mut drives: Vec<&str> = (0..4)
+                .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+                .collect();
+            drives.sort_unstable();
+            assert_eq!(drives, ["a", "a", "b", "c"], "{:?}", scheduler);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Scoop numbers of recently mined rounds.
+//!
+//! Every scoop is equally likely, so over enough rounds the histogram should be flat. A lopsided
+//! heatmap, or the same scoop round after round, points at a broken generation signature or scoop
+//! calculation rather than bad luck.
+
+use std::collections::VecDeque;
+
+const SCOOPS: u32 = 4096;
+/// About four days of rounds.
+const HISTORY: usize = 1440;
+/// Heatmap characters from coldest to hottest.
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+#[derive(Debug, Clone, Default)]
+pub struct ScoopHistory {
+    recent: VecDeque<u32>,
+}
+
+impl ScoopHistory {
+    pub fn record(&mut self, scoop: u32) {
+        self.recent.push_back(scoop);
+        while self.recent.len() > HISTORY {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Keeps the `keep` most recent rounds.
+    pub fn truncate(&mut self, keep: usize) {
+        while self.recent.len() > keep {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.recent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty()
+    }
+
+    /// Rounds per range of `SCOOPS / buckets` scoops.
+    pub fn histogram(&self, buckets: usize) -> Vec<u64> {
+        let mut histogram = vec![0; buckets];
+        for &scoop in &self.recent {
+            histogram[scoop as usize * buckets / SCOOPS as usize] += 1;
+        }
+        histogram
+    }
+
+    /// One character per bucket, scaled to the fullest one.
+    pub fn heatmap(&self, buckets: usize) -> String {
+        let histogram = self.histogram(buckets);
+        let max = histogram.iter().copied().max().unwrap_or(0).max(1);
+        histogram
+            .iter()
+            .map(|&count| {
+                let shade = (count * (SHADES.len() as u64 - 1)).div_ceil(max);
+                SHADES[shade as usize] as char
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_and_heatmap() {
+        let mut history = ScoopHistory::default();
+        for scoop in [0, 1, 255, 256, 4095, 4095, 4095] {
+            history.record(scoop);
+        }
+        assert_eq!(history.histogram(16)[0], 3);
+        assert_eq!(history.histogram(16)[1], 1);
+        assert_eq!(history.histogram(16)[15], 3);
+        assert_eq!(history.heatmap(4), "@  #");
+        assert_eq!(history.heatmap(16), format!("@-{}@", " ".repeat(13)));
+
+        for _ in 0..HISTORY {
+            history.record(7);
+        }
+        assert_eq!(history.len(), HISTORY);
+        assert_eq!(history.histogram(1), vec![HISTORY as u64]);
+    }
+}
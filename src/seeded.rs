@@ -0,0 +1,338 @@
+//! Deterministic test scenarios for the whole mining pipeline.
+//!
+//! A seed fixes the generation signature, height, base target, account and the nonces of a small
+//! plot, so the best deadline of the round is known before mining. `selftest` writes the plot,
+//! runs it through the real reader and hashing workers and compares the result with the deadline
+//! computed directly from the generated nonces. `mockpool` serves the same scenario, which covers
+//! the submitter as well.
+
+use crate::buffer_pool::BufferPool;
+use crate::cancel::CancelToken;
+use crate::circuit_breaker::BreakerCfg;
+use crate::cpu_worker::create_cpu_worker_task;
+use crate::metrics::new_shared_disk_health;
+use crate::miner::{Buffer, CpuBuffer};
+use crate::plot::Plot;
+use crate::poc_hashing::{calculate_scoop, generate_nonce, NONCE_SIZE};
+use crate::reader::{Reader, RoundHandles};
+use crate::scheduler::{ReaderPool, ReaderScheduler};
+use crate::shabal256::{shabal256, shabal256_deadline_fast};
+use crate::topology::CoreSelection;
+use crate::utils::new_thread_pool;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+#[cfg(feature = "async_io")]
+use tokio::sync::Mutex;
+#[cfg(not(feature = "async_io"))]
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+const SCOOP_SIZE: usize = 64;
+/// Every nonce is generated on the CPU, which takes a moment each.
+pub const MAX_NONCES: u64 = 64;
+const DRIVE_ID: &str = "seeded";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub seed: u64,
+    pub account_id: u64,
+    pub start_nonce: u64,
+    pub nonces: u64,
+    pub height: u64,
+    pub base_target: u64,
+    pub gensig: [u8; 32],
+}
+
+/// Best nonce of a round, the deadline already divided by the base target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Best {
+    pub nonce: u64,
+    pub deadline: u64,
+}
+
+impl Scenario {
+    pub fn from_seed(seed: u64, nonces: u64) -> Scenario {
+        let mut rng = SplitMix64(seed);
+        let account_id = rng.next();
+        let start_nonce = rng.next() >> 24;
+        let height = rng.next() % 1_000_000 + 1;
+        // a few nonces then have deadlines of days, below the default target deadline
+        let base_target = (1 << 40) + rng.next() % (1 << 44);
+        let mut gensig = [0u8; 32];
+        for chunk in gensig.chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next().to_be_bytes());
+        }
+        Scenario {
+            seed,
+            account_id,
+            start_nonce,
+            nonces: nonces.clamp(1, MAX_NONCES),
+            height,
+            base_target,
+            gensig,
+        }
+    }
+
+    pub fn gensig_hex(&self) -> String {
+        hex::encode(self.gensig)
+    }
+
+    pub fn scoop(&self) -> u32 {
+        calculate_scoop(self.height, &self.gensig)
+    }
+
+    pub fn plot_name(&self) -> String {
+        format!("{}_{}_{}", self.account_id, self.start_nonce, self.nonces)
+    }
+
+    /// The following round. Like on chain, the generation signature is derived from the previous
+    /// one and the block's generator.
+    pub fn next_round(&self, generator: u64) -> Scenario {
+        let mut data = self.gensig.to_vec();
+        data.extend_from_slice(&generator.to_be_bytes());
+        Scenario {
+            height: self.height + 1,
+            gensig: shabal256(&data),
+            ..self.clone()
+        }
+    }
+
+    /// Deadline of any account's nonce in this round, what a pool verifies on submission.
+    pub fn deadline(&self, account_id: u64, nonce: u64) -> u64 {
+        self.scoop_deadline(&generate_nonce(account_id, nonce))
+    }
+
+    fn scoop_deadline(&self, nonce_data: &[u8]) -> u64 {
+        self.scoop_raw_deadline(nonce_data) / self.base_target
+    }
+
+    fn scoop_raw_deadline(&self, nonce_data: &[u8]) -> u64 {
+        let scoop = self.scoop() as usize;
+        shabal256_deadline_fast(
+            &nonce_data[scoop * SCOOP_SIZE..(scoop + 1) * SCOOP_SIZE],
+            &self.gensig,
+        )
+    }
+
+    /// Writes the scenario's plot to `dir` in PoC2 layout and returns its path together with the
+    /// best deadline, computed from the generated nonces without going through the reader.
+    pub fn write_plot(&self, dir: &Path) -> io::Result<(PathBuf, Best)> {
+        let nonces = self.nonces as usize;
+        let mut data = vec![0u8; nonces * NONCE_SIZE];
+        // the raw deadline decides like in the miner, nonces can share the divided one
+        let mut best_raw = u64::MAX;
+        let mut best = Best {
+            nonce: 0,
+            deadline: u64::MAX,
+        };
+        for n in 0..nonces {
+            let nonce = self.start_nonce + n as u64;
+            let generated = generate_nonce(self.account_id, nonce);
+            // plot files are ordered by scoop, then by nonce
+            for (s, scoop_data) in generated.chunks_exact(SCOOP_SIZE).enumerate() {
+                let offset = (s * nonces + n) * SCOOP_SIZE;
+                data[offset..offset + SCOOP_SIZE].copy_from_slice(scoop_data);
+            }
+            let raw = self.scoop_raw_deadline(&generated);
+            if raw < best_raw {
+                best_raw = raw;
+                best = Best {
+                    nonce,
+                    deadline: raw / self.base_target,
+                };
+            }
+        }
+
+        fs::create_dir_all(dir)?;
+        let path = dir.join(self.plot_name());
+        fs::write(&path, data)?;
+        Ok((path, best))
+    }
+}
+
+/// Reads and hashes the plot at `path` with the miner's reader and CPU workers.
+pub async fn mine(scenario: &Scenario, path: &Path, cpu_threads: usize) -> Result<Best, String> {
+    let plot = Plot::new(&path.to_path_buf(), false, false, None, false)
+        .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut drive_id_to_plots = HashMap::new();
+    drive_id_to_plots.insert(DRIVE_ID.to_owned(), Arc::new(vec![Mutex::new(plot)]));
+
+    // one buffer holds the whole scoop, so the last reply carries the only deadline
+    let buffer_pool = Arc::new(BufferPool::new(1, 1));
+    buffer_pool.add(Box::new(CpuBuffer::new(scenario.nonces as usize * SCOOP_SIZE))
+        as Box<dyn Buffer + Send>);
+    let (tx_read_replies, rx_read_replies) = crossbeam_channel::bounded(1);
+    let (tx_nonce_data, mut rx_nonce_data) = mpsc::channel(1);
+    thread::spawn(create_cpu_worker_task(
+        false,
+        new_thread_pool(cpu_threads.max(1), false, CoreSelection::Any),
+        rx_read_replies,
+        buffer_pool.clone(),
+    ));
+
+    // GPU builds expect a channel list, an empty one keeps everything on the CPU
+    #[cfg(any(feature = "opencl", feature = "metal", feature = "wgpu"))]
+    let tx_read_replies_gpu = Some(Vec::new());
+    #[cfg(not(any(feature = "opencl", feature = "metal", feature = "wgpu")))]
+    let tx_read_replies_gpu = None;
+    let mut reader = Reader::new(
+        drive_id_to_plots,
+        scenario.nonces * NONCE_SIZE as u64,
+        Arc::new(ReaderPool::new(
+            ReaderScheduler::Rayon,
+            1,
+            false,
+            CoreSelection::Any,
+        )),
+        buffer_pool,
+        tx_read_replies,
+        tx_read_replies_gpu,
+        tx_nonce_data,
+        false,
+        false,
+        false,
+        0,
+        0,
+        new_shared_disk_health(BreakerCfg::default()),
+        None,
+        None,
+        tokio::runtime::Handle::current(),
+    );
+    reader.start_reading(
+        scenario.height,
+        0,
+        scenario.base_target,
+        scenario.scoop(),
+        &Arc::new(scenario.gensig),
+        &RoundHandles {
+            stages: Arc::default(),
+            cancel: CancelToken::default(),
+        },
+    );
+
+    let mut best_raw = u64::MAX;
+    let mut best = Best {
+        nonce: 0,
+        deadline: u64::MAX,
+    };
+    while let Some(nonce_data) = rx_nonce_data.recv().await {
+        if nonce_data.deadline < best_raw {
+            best_raw = nonce_data.deadline;
+            best = Best {
+                nonce: nonce_data.nonce,
+                deadline: nonce_data.deadline / nonce_data.base_target,
+            };
+        }
+        if nonce_data.reader_task_processed {
+            return Ok(best);
+        }
+    }
+    Err("hashing stopped before the plot was read".to_owned())
+}
+
+/// Runs the scenario for `seed` in `dir`, returns whether the pipeline found the expected
+/// deadline.
+pub async fn selftest(seed: u64, nonces: u64, dir: &Path, cpu_threads: usize) -> bool {
+    let scenario = Scenario::from_seed(seed, nonces);
+    info!(
+        "selftest: seed={}, account={}, nonces={}, height={}, base target={}, gensig={}, scoop={}",
+        seed,
+        scenario.account_id,
+        scenario.nonces,
+        scenario.height,
+        scenario.base_target,
+        scenario.gensig_hex(),
+        scenario.scoop()
+    );
+    let (path, expected) = match scenario.write_plot(dir) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("selftest: can't write plot to {}: {}", dir.display(), e);
+            return false;
+        }
+    };
+    info!(
+        "selftest: expected nonce={}, deadline={}",
+        expected.nonce, expected.deadline
+    );
+
+    let result = mine(&scenario, &path, cpu_threads).await;
+    if let Err(e) = fs::remove_file(&path) {
+        warn!("selftest: can't remove {}: {}", path.display(), e);
+    }
+    match result {
+        Ok(found) if found == expected => {
+            info!("selftest: passed");
+            true
+        }
+        Ok(found) => {
+            error!(
+                "selftest: failed, found nonce={}, deadline={}",
+                found.nonce, found.deadline
+            );
+            false
+        }
+        Err(e) => {
+            error!("selftest: failed, {}", e);
+            false
+        }
+    }
+}
+
+/// Small, well distributed and identical on every platform, unlike the `rand` generators.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenario_is_deterministic() {
+        assert_eq!(Scenario::from_seed(7, 4), Scenario::from_seed(7, 4));
+        assert_ne!(Scenario::from_seed(7, 4).gensig, Scenario::from_seed(8, 4).gensig);
+        assert_eq!(Scenario::from_seed(7, 1000).nonces, MAX_NONCES);
+    }
+
+    #[test]
+    fn test_next_round() {
+        let scenario = Scenario::from_seed(7, 1);
+        let next = scenario.next_round(1);
+        assert_eq!(next.height, scenario.height + 1);
+        assert_eq!(next.account_id, scenario.account_id);
+        assert_ne!(next.gensig, scenario.gensig);
+        assert_ne!(next.gensig, scenario.next_round(2).gensig);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_finds_expected_deadline() {
+        #[cfg(any(
+            feature = "simd_avx512f",
+            feature = "simd_avx2",
+            feature = "simd_avx",
+            feature = "simd_sse2",
+            feature = "neon"
+        ))]
+        crate::init_cpu_extensions();
+
+        let dir = std::env::temp_dir().join(format!("signum-miner-seeded-{}", std::process::id()));
+        let scenario = Scenario::from_seed(42, 3);
+        let (path, expected) = scenario.write_plot(&dir).unwrap();
+        let found = mine(&scenario, &path, 2).await;
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, Ok(expected));
+    }
+}
@@ -91,6 +91,20 @@ pub fn shabal256_hash_fast(data: &[u8], term: &[u32; 16]) -> [u8; 32] {
     unsafe { *(b[8..16].as_ptr() as *const [u8; 32]) }
 }
 
+/// Shabal-256 of an arbitrary message.
+pub fn shabal256(data: &[u8]) -> [u8; 32] {
+    let full = data.len() & !63;
+    let mut term_bytes = [0u8; 64];
+    term_bytes[..data.len() - full].copy_from_slice(&data[full..]);
+    term_bytes[data.len() - full] = 0x80;
+
+    let mut term = [0u32; 16];
+    for (word, chunk) in term.iter_mut().zip(term_bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    shabal256_hash_fast(&data[..full], &term)
+}
+
 #[inline(always)]
 fn input_block_add(b: &mut [u32; 16], data: &[u32]) {
     for (element, data) in b.iter_mut().zip(data.iter()) {
@@ -0,0 +1,144 @@
+//! Detection of sparse plot files.
+//!
+//! A plot file with holes (e.g. an interrupted plotter that preallocated the file, or a copy
+//! tool that skipped zero blocks) reads back zeros where the holes are. Those nonces yield
+//! garbage deadlines that the pool rejects. Files whose allocated size is smaller than their
+//! logical size are reported, on Linux/FreeBSD with the exact scoop/nonce ranges that are missing.
+
+use crate::plot::SCOOP_SIZE;
+use serde::de::{self, Deserialize, Deserializer};
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SparsePlotAction {
+    /// Mine the plot anyway, but log the missing ranges.
+    Warn,
+    /// Don't mine sparse plots.
+    Skip,
+    /// Don't check for holes.
+    Ignore,
+}
+
+impl<'de> Deserialize<'de> for SparsePlotAction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(SparsePlotAction::Warn),
+            "skip" => Ok(SparsePlotAction::Skip),
+            "ignore" => Ok(SparsePlotAction::Ignore),
+            _ => Err(de::Error::custom(format!(
+                "unknown sparse_plots action '{}' (warn, skip, ignore)",
+                s
+            ))),
+        }
+    }
+}
+
+pub struct SparseReport {
+    pub size: u64,
+    pub allocated: u64,
+    /// Holes as byte ranges `[start, end)`, empty if the platform can't locate them.
+    pub holes: Vec<(u64, u64)>,
+}
+
+/// Returns a report if the file at `path` is sparse.
+#[cfg(unix)]
+pub fn check(path: &Path) -> io::Result<Option<SparseReport>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    let size = metadata.len();
+    // st_blocks is always in units of 512 bytes
+    let allocated = metadata.blocks() * 512;
+    if allocated >= size {
+        return Ok(None);
+    }
+
+    Ok(Some(SparseReport {
+        size,
+        allocated,
+        holes: find_holes(path, size)?,
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn check(_path: &Path) -> io::Result<Option<SparseReport>> {
+    Ok(None)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn find_holes(path: &Path, size: u64) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    let fd = file.as_raw_fd();
+    // beyond `off_t` on 32-bit targets only the start of the file is checked
+    let size = crate::plot::to_off_t(size).unwrap_or(libc::off_t::MAX);
+    let mut holes = Vec::new();
+    let mut pos: libc::off_t = 0;
+    while pos < size {
+        let hole = unsafe { libc::lseek(fd, pos, libc::SEEK_HOLE) };
+        if hole < 0 || hole >= size {
+            break;
+        }
+        // ENXIO: no data after the hole, it extends to the end of the file
+        let data = unsafe { libc::lseek(fd, hole, libc::SEEK_DATA) };
+        let end = if data < 0 { size } else { data };
+        holes.push((hole as u64, end as u64));
+        pos = end;
+    }
+    Ok(holes)
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "linux", target_os = "android", target_os = "freebsd"))
+))]
+fn find_holes(_path: &Path, _size: u64) -> io::Result<Vec<(u64, u64)>> {
+    Ok(Vec::new())
+}
+
+/// Scoop and nonce (relative to the plot's start nonce) stored at byte `offset` of a PoC2 plot.
+fn position(offset: u64, nonces: u64) -> (u64, u64) {
+    let scoop_bytes = nonces * SCOOP_SIZE;
+    (offset / scoop_bytes, (offset % scoop_bytes) / SCOOP_SIZE)
+}
+
+/// Human readable description of a hole, e.g. `scoop 12 nonce 0 - scoop 12 nonce 4095`.
+pub fn describe_hole(hole: (u64, u64), nonces: u64) -> String {
+    let (first_scoop, first_nonce) = position(hole.0, nonces);
+    let (last_scoop, last_nonce) = position(hole.1.saturating_sub(1), nonces);
+    format!(
+        "scoop {} nonce {} - scoop {} nonce {}",
+        first_scoop, first_nonce, last_scoop, last_nonce
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_hole() {
+        // 8 nonces -> 512 bytes per scoop
+        assert_eq!(
+            describe_hole((0, 512), 8),
+            "scoop 0 nonce 0 - scoop 0 nonce 7"
+        );
+        assert_eq!(
+            describe_hole((576, 1152), 8),
+            "scoop 1 nonce 1 - scoop 2 nonce 1"
+        );
+    }
+
+    #[test]
+    fn test_sparse_action_deserialize() {
+        let action: SparsePlotAction = serde_yaml::from_str("Skip").unwrap();
+        assert_eq!(action, SparsePlotAction::Skip);
+        assert!(serde_yaml::from_str::<SparsePlotAction>("delete").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Separate hashing pools for big and little cores.
+//!
+//! One pool over all cores of a big.LITTLE CPU hands chunks out evenly, and the round ends when
+//! the little cores finish their share. With `cpu_split_pools` the big and the little cores get
+//! a pool each, pinned to their cores. Both pools take a batch of chunks whenever they are idle,
+//! so the faster pool takes more. A startup benchmark measures both core types: the little pool's
+//! batch is what its cores hash while the big pool hashes one chunk per thread, so the little
+//! cores don't hold the round's last chunks much longer than the big ones.
+
+use crate::cpu_worker::{cpu_hasher, find_best_deadline};
+use crate::topology::{topology, CoreSelection};
+use crate::utils::{new_thread_pool, pin_current_thread};
+use rand::Rng;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCOOP_SIZE: usize = 64;
+/// Nonces hashed per benchmark pass.
+const BENCH_NONCES: usize = 4096;
+const BENCH_TIME: Duration = Duration::from_millis(300);
+
+/// A hashing pool and the chunks it takes at once.
+pub struct HashingPool {
+    pub pool: rayon::ThreadPool,
+    pub batch_size: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Plan {
+    pub big_threads: usize,
+    pub little_threads: usize,
+    pub big_batch: usize,
+    pub little_batch: usize,
+}
+
+/// Splits `cpu_threads` over the core types, big cores first. None if one type gets no thread.
+pub fn plan(
+    cpu_threads: usize,
+    big_cores: usize,
+    little_cores: usize,
+    big_speed: f64,
+    little_speed: f64,
+) -> Option<Plan> {
+    let big_threads = cpu_threads.min(big_cores);
+    let little_threads = (cpu_threads - big_threads).min(little_cores);
+    if big_threads == 0 || little_threads == 0 || big_speed <= 0.0 {
+        return None;
+    }
+    let ratio = (little_speed / big_speed).clamp(0.0, 1.0);
+    Some(Plan {
+        big_threads,
+        little_threads,
+        big_batch: big_threads,
+        little_batch: ((little_threads as f64 * ratio).round() as usize).max(1),
+    })
+}
+
+/// Nonces per second one thread hashes on `core`.
+fn measure(core: usize) -> f64 {
+    thread::spawn(move || {
+        pin_current_thread(core);
+        let mut data = vec![0u8; BENCH_NONCES * SCOOP_SIZE];
+        rand::thread_rng().fill(&mut data[..]);
+        let gensig = [0u8; 32];
+        let start = Instant::now();
+        let mut nonces = 0;
+        while start.elapsed() < BENCH_TIME {
+            find_best_deadline(&data, BENCH_NONCES as u64, &gensig, cpu_hasher());
+            nonces += BENCH_NONCES;
+        }
+        nonces as f64 / start.elapsed().as_secs_f64()
+    })
+    .join()
+    .unwrap_or(0.0)
+}
+
+/// Benchmarks both core types and builds their pools, None on CPUs that aren't hybrid.
+pub fn pools(cpu_threads: usize) -> Option<(HashingPool, HashingPool)> {
+    let topology = topology();
+    if !topology.is_hybrid() {
+        warn!("cpu_split_pools: the CPU has only one core type, using one hashing pool");
+        return None;
+    }
+    let big_speed = measure(topology.performance[0]);
+    let little_speed = measure(topology.efficiency[0]);
+    let plan = match plan(
+        cpu_threads,
+        topology.performance.len(),
+        topology.efficiency.len(),
+        big_speed,
+        little_speed,
+    ) {
+        Some(plan) => plan,
+        None => {
+            warn!(
+                "cpu_split_pools: {} CPU threads don't reach the little cores, using one hashing pool",
+                cpu_threads
+            );
+            return None;
+        }
+    };
+    info!(
+        "hashing pools: {} big cores at {:.0} nonces/s each (batch {}), {} little cores at {:.0} \
+         nonces/s each (batch {})",
+        plan.big_threads,
+        big_speed,
+        plan.big_batch,
+        plan.little_threads,
+        little_speed,
+        plan.little_batch
+    );
+    Some((
+        HashingPool {
+            pool: new_thread_pool(plan.big_threads, true, CoreSelection::Performance),
+            batch_size: plan.big_batch,
+        },
+        HashingPool {
+            pool: new_thread_pool(plan.little_threads, true, CoreSelection::Efficiency),
+            batch_size: plan.little_batch,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(
+            plan(8, 4, 4, 1000.0, 400.0),
+            Some(Plan {
+                big_threads: 4,
+                little_threads: 4,
+                big_batch: 4,
+                little_batch: 2,
+            })
+        );
+        // too few threads to reach the little cores
+        assert_eq!(plan(4, 4, 4, 1000.0, 400.0), None);
+        // very slow little cores still take a chunk
+        assert_eq!(plan(6, 2, 4, 1000.0, 1.0).unwrap().little_batch, 1);
+    }
+}
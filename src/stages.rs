@@ -0,0 +1,117 @@
+//! Where a round's time goes.
+//!
+//! Reader threads, hashing workers and the submitter add what they spent per stage to the
+//! round's `RoundStages`, which travels with every chunk in its `RoundHeader`. Times are summed
+//! over all threads, so with several drives the disk stage alone can exceed the round time. What
+//! matters is the share of each stage: a round dominated by waiting for buffers needs more or
+//! larger buffers, not faster drives.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// Reading chunks from the plots.
+    Disk,
+    /// Reader threads waiting for a free buffer.
+    Buffers,
+    /// CPU or GPU workers searching a chunk for its best deadline.
+    Hashing,
+    /// Submissions waiting for the pool's answer.
+    Pool,
+}
+
+const STAGES: [Stage; 4] = [Stage::Disk, Stage::Buffers, Stage::Hashing, Stage::Pool];
+
+impl Stage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Disk => "disk",
+            Stage::Buffers => "buffers",
+            Stage::Hashing => "hashing",
+            Stage::Pool => "pool",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RoundStages {
+    micros: [AtomicU64; 4],
+}
+
+impl RoundStages {
+    pub fn add(&self, stage: Stage, elapsed: Duration) {
+        self.micros[stage as usize].fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn breakdown(&self) -> StageBreakdown {
+        StageBreakdown {
+            micros: [
+                self.micros[0].load(Ordering::Relaxed),
+                self.micros[1].load(Ordering::Relaxed),
+                self.micros[2].load(Ordering::Relaxed),
+                self.micros[3].load(Ordering::Relaxed),
+            ],
+        }
+    }
+}
+
+/// Snapshot of a round's stage times.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StageBreakdown {
+    micros: [u64; 4],
+}
+
+impl StageBreakdown {
+    /// The stage that took longest, `None` if nothing was recorded.
+    pub fn bottleneck(&self) -> Option<Stage> {
+        STAGES
+            .iter()
+            .copied()
+            .filter(|&stage| self.micros[stage as usize] > 0)
+            .max_by_key(|&stage| self.micros[stage as usize])
+    }
+}
+
+impl fmt::Display for StageBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: u64 = self.micros.iter().sum();
+        for (i, &stage) in STAGES.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            let micros = self.micros[stage as usize];
+            write!(
+                f,
+                "{}={:.1}s ({:.0}%)",
+                stage.as_str(),
+                micros as f64 / 1_000_000.0,
+                micros as f64 * 100.0 / total.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakdown() {
+        let stages = RoundStages::default();
+        assert_eq!(stages.breakdown().bottleneck(), None);
+
+        stages.add(Stage::Disk, Duration::from_millis(1500));
+        stages.add(Stage::Buffers, Duration::from_millis(500));
+        stages.add(Stage::Disk, Duration::from_millis(1500));
+        let breakdown = stages.breakdown();
+        assert_eq!(breakdown.micros[Stage::Disk as usize], 3_000_000);
+        assert_eq!(breakdown.bottleneck(), Some(Stage::Disk));
+        assert_eq!(
+            breakdown.to_string(),
+            "disk=3.0s (86%) buffers=0.5s (14%) hashing=0.0s (0%) pool=0.0s (0%)"
+        );
+    }
+}
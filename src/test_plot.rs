@@ -0,0 +1,220 @@
+//! Miniature plot files for tests and bug reports.
+//!
+//! A test plot holds a few real nonces in PoC2 or PoC1 layout, optionally encrypted, so the read
+//! path (alignment, PoC1 mirroring, decryption) runs on it exactly like on a full plot. Next to
+//! the plot a golden file records the shabal256 hash of every scoop's data in PoC2 order, taken
+//! from the generated nonces. `verify_golden` reads every scoop through the miner's reader and
+//! lists the scoops that don't match. The hidden `make-test-plot` command writes both files, so a
+//! user can reproduce a read bug on their own drive and file system.
+
+use crate::plot::Plot;
+use crate::plot_cipher::{PlotCipher, ENCRYPTED_PLOT_SUFFIX};
+use crate::poc_hashing::{generate_nonce, NONCE_SIZE};
+use crate::shabal256::shabal256;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SCOOP_SIZE: usize = 64;
+const HALF: usize = SCOOP_SIZE / 2;
+const SCOOPS_IN_NONCE: usize = 4096;
+/// 64 MiB, generating a nonce takes a moment.
+pub const MAX_NONCES: u64 = 256;
+pub const GOLDEN_SUFFIX: &str = ".golden";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Poc2,
+    /// Optimized PoC1, the second half of scoop `s` is stored with scoop `4095 - s`.
+    Poc1,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestPlot {
+    pub account_id: u64,
+    pub start_nonce: u64,
+    pub nonces: u64,
+    pub layout: Layout,
+    /// Encrypts the plot as with `plot_encryption_key`.
+    pub encryption_key: Option<String>,
+}
+
+impl TestPlot {
+    pub fn name(&self) -> String {
+        match self.layout {
+            Layout::Poc2 => format!("{}_{}_{}", self.account_id, self.start_nonce, self.nonces),
+            Layout::Poc1 => format!(
+                "{}_{}_{}_{}",
+                self.account_id, self.start_nonce, self.nonces, self.nonces
+            ),
+        }
+    }
+
+    /// The plot's data in PoC2 order: by scoop, then by nonce.
+    fn poc2_data(&self) -> Vec<u8> {
+        let nonces = self.nonces as usize;
+        let mut data = vec![0u8; nonces * NONCE_SIZE];
+        for n in 0..nonces {
+            let generated = generate_nonce(self.account_id, self.start_nonce + n as u64);
+            for (s, scoop_data) in generated.chunks_exact(SCOOP_SIZE).enumerate() {
+                let offset = (s * nonces + n) * SCOOP_SIZE;
+                data[offset..offset + SCOOP_SIZE].copy_from_slice(scoop_data);
+            }
+        }
+        data
+    }
+
+    /// The plot file's content.
+    pub fn generate(&self) -> Vec<u8> {
+        let poc2 = self.poc2_data();
+        let mut data = match self.layout {
+            Layout::Poc2 => poc2,
+            Layout::Poc1 => {
+                let region = self.nonces as usize * SCOOP_SIZE;
+                let mut data = poc2.clone();
+                for s in 0..SCOOPS_IN_NONCE {
+                    let mirror = &poc2[(SCOOPS_IN_NONCE - 1 - s) * region..][..region];
+                    let scoop = &mut data[s * region..][..region];
+                    for (stored, mirrored) in scoop
+                        .chunks_exact_mut(SCOOP_SIZE)
+                        .zip(mirror.chunks_exact(SCOOP_SIZE))
+                    {
+                        stored[HALF..].copy_from_slice(&mirrored[HALF..]);
+                    }
+                }
+                data
+            }
+        };
+        if let Some(key) = &self.encryption_key {
+            PlotCipher::new(key, &self.name()).apply(&mut data, 0);
+        }
+        data
+    }
+
+    /// One line per scoop: the scoop and the hex shabal256 of its data over all nonces.
+    pub fn golden(&self) -> String {
+        let region = self.nonces as usize * SCOOP_SIZE;
+        self.poc2_data()
+            .chunks_exact(region)
+            .enumerate()
+            .map(|(scoop, data)| format!("{} {}\n", scoop, hex::encode(shabal256(data))))
+            .collect()
+    }
+
+    /// Writes the plot and its golden file to `dir`, returns the plot's path.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let mut name = self.name();
+        if self.encryption_key.is_some() {
+            name.push_str(ENCRYPTED_PLOT_SUFFIX);
+        }
+        let path = dir.join(&name);
+        fs::write(&path, self.generate())?;
+        fs::write(dir.join(name + GOLDEN_SUFFIX), self.golden())?;
+        Ok(path)
+    }
+}
+
+/// Reads all of `scoop` through the plot's read path, `buffer_size` bytes at a time.
+pub async fn read_scoop(plot: &mut Plot, scoop: u32, buffer_size: usize) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut bs = vec![0u8; buffer_size];
+    #[cfg(feature = "async_io")]
+    plot.prepare_async(scoop).await?;
+    #[cfg(not(feature = "async_io"))]
+    plot.prepare(scoop)?;
+    loop {
+        #[cfg(feature = "async_io")]
+        let (len, _, finished) = plot.read_async(&mut bs, scoop).await?;
+        #[cfg(not(feature = "async_io"))]
+        let (len, _, finished) = plot.read(&mut bs, scoop)?;
+        data.extend_from_slice(&bs[..len]);
+        if finished {
+            return Ok(data);
+        }
+    }
+}
+
+/// Scoops of the plot at `path` that read differently than its golden file says.
+pub async fn verify_golden(
+    path: &Path,
+    use_direct_io: bool,
+    encryption_key: Option<&str>,
+) -> Result<Vec<u32>, String> {
+    let golden_path = PathBuf::from(format!("{}{}", path.display(), GOLDEN_SUFFIX));
+    let golden = fs::read_to_string(&golden_path)
+        .map_err(|e| format!("can't read {}: {}", golden_path.display(), e))?;
+    let mut plot = Plot::new(
+        &path.to_path_buf(),
+        use_direct_io,
+        false,
+        encryption_key,
+        true,
+    )
+    .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut differing = Vec::new();
+    for line in golden.lines() {
+        let (scoop, hash) = line
+            .split_once(' ')
+            .and_then(|(scoop, hash)| Some((scoop.parse::<u32>().ok()?, hash)))
+            .ok_or_else(|| format!("invalid golden line '{}'", line))?;
+        let data = read_scoop(&mut plot, scoop, SCOOP_SIZE * 2)
+            .await
+            .map_err(|e| format!("can't read scoop {}: {}", scoop, e))?;
+        if hex::encode(shabal256(&data)) != hash {
+            differing.push(scoop);
+        }
+    }
+    Ok(differing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_plot(layout: Layout, encryption_key: Option<&str>) -> TestPlot {
+        TestPlot {
+            account_id: 10282355196851764065,
+            start_nonce: 1000,
+            nonces: 3,
+            layout,
+            encryption_key: encryption_key.map(str::to_owned),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layouts_read_the_same() {
+        let dir =
+            std::env::temp_dir().join(format!("signum-miner-test-plot-{}", std::process::id()));
+        let poc2 = test_plot(Layout::Poc2, None);
+        let data = poc2.poc2_data();
+        let expected = &data[7 * 3 * SCOOP_SIZE..8 * 3 * SCOOP_SIZE];
+        for (plot, key) in [
+            (poc2, None),
+            (test_plot(Layout::Poc1, None), None),
+            (test_plot(Layout::Poc2, Some("secret")), Some("secret")),
+        ] {
+            let path = plot.write(&dir).unwrap();
+            let mut opened = Plot::new(&path, false, false, key, true).unwrap();
+            assert_eq!(read_scoop(&mut opened, 7, 128).await.unwrap(), expected);
+            assert_eq!(verify_golden(&path, false, key).await, Ok(Vec::new()));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_golden_finds_corruption() {
+        let dir = std::env::temp_dir().join(format!(
+            "signum-miner-test-plot-corrupt-{}",
+            std::process::id()
+        ));
+        let plot = test_plot(Layout::Poc2, None);
+        let path = plot.write(&dir).unwrap();
+        let mut data = fs::read(&path).unwrap();
+        // the second nonce of scoop 5
+        data[(5 * 3 + 1) * SCOOP_SIZE] ^= 1;
+        fs::write(&path, data).unwrap();
+        assert_eq!(verify_golden(&path, false, None).await, Ok(vec![5]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
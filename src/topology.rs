@@ -0,0 +1,226 @@
+//! Core topology of hybrid CPUs (Intel P-cores/E-cores, ARM big.LITTLE).
+//!
+//! Pinning used to round-robin over all core ids, so a hashing thread could land on an efficiency
+//! core while a reader, which mostly waits for the disk, got a performance core. The topology is
+//! read from sysfs on Linux and from cpuid leaf 0x1A elsewhere on x86; CPUs where every core is
+//! the same report no efficiency cores and pinning behaves as before.
+
+use crate::utils::logical_processors;
+use serde::de::{self, Deserialize, Deserializer};
+use std::fs;
+use std::sync::OnceLock;
+
+/// Which cores a thread pool is pinned to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum CoreSelection {
+    Performance,
+    Efficiency,
+    Any,
+}
+
+impl<'de> Deserialize<'de> for CoreSelection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "performance" | "p" => Ok(CoreSelection::Performance),
+            "efficiency" | "e" => Ok(CoreSelection::Efficiency),
+            "any" => Ok(CoreSelection::Any),
+            _ => Err(de::Error::custom(format!(
+                "unknown core selection '{}' (performance, efficiency, any)",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Topology {
+    pub performance: Vec<usize>,
+    /// Empty on CPUs without efficiency cores.
+    pub efficiency: Vec<usize>,
+}
+
+impl Topology {
+    pub fn is_hybrid(&self) -> bool {
+        !self.performance.is_empty() && !self.efficiency.is_empty()
+    }
+
+    /// Cores for `selection`, all cores if the CPU has none of the requested kind.
+    pub fn cores(&self, selection: CoreSelection) -> Vec<usize> {
+        let mut cores = match selection {
+            CoreSelection::Performance if self.is_hybrid() => self.performance.clone(),
+            CoreSelection::Efficiency if self.is_hybrid() => self.efficiency.clone(),
+            _ => self
+                .performance
+                .iter()
+                .chain(self.efficiency.iter())
+                .copied()
+                .collect(),
+        };
+        cores.sort_unstable();
+        cores
+    }
+}
+
+/// Detected once, the result is logged on first use.
+pub fn topology() -> &'static Topology {
+    static TOPOLOGY: OnceLock<Topology> = OnceLock::new();
+    TOPOLOGY.get_or_init(|| {
+        let topology = detect();
+        if topology.is_hybrid() {
+            info!(
+                "hybrid CPU: performance cores={:?}, efficiency cores={:?}",
+                topology.performance, topology.efficiency
+            );
+        }
+        topology
+    })
+}
+
+fn detect() -> Topology {
+    let cores = logical_processors();
+
+    let topology = detect_sysfs(&cores)
+        .or_else(|| detect_cpuid(&cores))
+        .unwrap_or_default();
+    if topology.performance.is_empty() {
+        Topology {
+            performance: cores,
+            efficiency: Vec::new(),
+        }
+    } else {
+        topology
+    }
+}
+
+/// Intel hybrid CPUs register a separate perf PMU per core type, ARM exposes relative capacities.
+fn detect_sysfs(cores: &[usize]) -> Option<Topology> {
+    if let (Ok(p), Ok(e)) = (
+        fs::read_to_string("/sys/devices/cpu_core/cpus"),
+        fs::read_to_string("/sys/devices/cpu_atom/cpus"),
+    ) {
+        return Some(Topology {
+            performance: parse_cpu_list(&p),
+            efficiency: parse_cpu_list(&e),
+        });
+    }
+
+    let capacities: Vec<(usize, u64)> = cores
+        .iter()
+        .filter_map(|&core| {
+            fs::read_to_string(format!(
+                "/sys/devices/system/cpu/cpu{}/cpu_capacity",
+                core
+            ))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .map(|capacity| (core, capacity))
+        })
+        .collect();
+    if capacities.is_empty() {
+        None
+    } else {
+        Some(classify_by_capacity(&capacities))
+    }
+}
+
+/// Runs cpuid leaf 0x1A on every core, pinning a helper thread to each in turn.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+// the cpuid intrinsics are only unsafe on older toolchains
+#[allow(unused_unsafe)]
+fn detect_cpuid(cores: &[usize]) -> Option<Topology> {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{__cpuid, __cpuid_count};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{__cpuid, __cpuid_count};
+    use crate::utils::pin_current_thread;
+
+    const HYBRID: u32 = 1 << 15;
+    const CORE_TYPE_ATOM: u32 = 0x20;
+
+    if unsafe { __cpuid(0) }.eax < 0x1a || unsafe { __cpuid_count(7, 0) }.edx & HYBRID == 0 {
+        return None;
+    }
+
+    let cores = cores.to_vec();
+    std::thread::spawn(move || {
+        let mut topology = Topology::default();
+        for core in cores {
+            if !pin_current_thread(core) {
+                return None;
+            }
+            let core_type = unsafe { __cpuid_count(0x1a, 0) }.eax >> 24;
+            if core_type == CORE_TYPE_ATOM {
+                topology.efficiency.push(core);
+            } else {
+                topology.performance.push(core);
+            }
+        }
+        Some(topology)
+    })
+    .join()
+    .ok()
+    .flatten()
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn detect_cpuid(_cores: &[usize]) -> Option<Topology> {
+    None
+}
+
+/// Cores with the highest capacity are performance cores.
+fn classify_by_capacity(capacities: &[(usize, u64)]) -> Topology {
+    let max = capacities.iter().map(|&(_, c)| c).max().unwrap_or(0);
+    let (performance, efficiency): (Vec<_>, Vec<_>) =
+        capacities.iter().partition(|&&(_, c)| c == max);
+    Topology {
+        performance: performance.into_iter().map(|&(core, _)| core).collect(),
+        efficiency: efficiency.into_iter().map(|&(core, _)| core).collect(),
+    }
+}
+
+/// Parses the kernel's cpu list format, e.g. `0-7,16,18-19`.
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    list.trim()
+        .split(',')
+        .filter(|range| !range.is_empty())
+        .flat_map(|range| {
+            let mut bounds = range.splitn(2, '-').map(|n| n.trim().parse::<usize>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(start)), Some(Ok(end))) => (start..=end).collect(),
+                (Some(Ok(core)), None) => vec![core],
+                _ => Vec::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list(""), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_classify_by_capacity() {
+        let topology = classify_by_capacity(&[(0, 446), (1, 446), (2, 1024), (3, 1024)]);
+        assert_eq!(topology.performance, vec![2, 3]);
+        assert_eq!(topology.efficiency, vec![0, 1]);
+        assert_eq!(topology.cores(CoreSelection::Efficiency), vec![0, 1]);
+        assert_eq!(topology.cores(CoreSelection::Any), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_uniform_cpu_uses_all_cores() {
+        let topology = classify_by_capacity(&[(0, 1024), (1, 1024)]);
+        assert!(!topology.is_hybrid());
+        assert_eq!(topology.cores(CoreSelection::Efficiency), vec![0, 1]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Feasibility check for compressing GPU transfers.
+//!
+//! On rigs with x1 risers the PCIe copy can take longer than the hashing, and compressing scoops
+//! before the transfer would only help if they compressed. Plot scoops are Shabal256 output, which
+//! a byte-oriented compressor like LZ4 can't shorten. So transfers are never compressed: there is
+//! no LZ4 path on the host and no decompression kernel on the GPUs, and this module is only the
+//! check. With `gpu_transfer_compression` every GPU measures the entropy of its first chunk once
+//! and logs whether compression could have paid off on that device.
+
+use std::time::{Duration, Instant};
+
+/// Estimated size after compression, as a fraction of the input, below which compressing could
+/// beat a plain transfer.
+const MIN_SAVING: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Feasibility {
+    /// Shannon entropy in bits per byte, 8 for random data.
+    pub entropy: f64,
+    /// Best size an order-0 compressor could reach, as a fraction of the input.
+    pub ratio: f64,
+    pub elapsed: Duration,
+}
+
+impl Feasibility {
+    pub fn worthwhile(&self) -> bool {
+        self.ratio < MIN_SAVING
+    }
+}
+
+pub fn probe(data: &[u8]) -> Feasibility {
+    let start = Instant::now();
+    let mut counts = [0u64; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len().max(1) as f64;
+    let entropy = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum::<f64>();
+    Feasibility {
+        entropy,
+        ratio: entropy / 8.0,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Probes a chunk and logs the verdict for `device`.
+#[cfg_attr(
+    not(any(feature = "opencl", feature = "metal", feature = "wgpu")),
+    allow(dead_code)
+)]
+pub fn report(device: &str, data: &[u8]) {
+    let feasibility = probe(data);
+    if feasibility.worthwhile() {
+        info!(
+            "GPU {}: plot data has {:.2} bits/byte of entropy, compressed transfers could save \
+             {:.0}%, but the miner doesn't compress transfers",
+            device,
+            feasibility.entropy,
+            (1.0 - feasibility.ratio) * 100.0
+        );
+    } else {
+        info!(
+            "GPU {}: plot data has {:.2} bits/byte of entropy and doesn't compress, transfers stay \
+             uncompressed",
+            device, feasibility.entropy
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_probe() {
+        let mut random = vec![0u8; 1 << 16];
+        rand::thread_rng().fill(&mut random[..]);
+        let feasibility = probe(&random);
+        assert!(feasibility.entropy > 7.9);
+        assert!(!feasibility.worthwhile());
+
+        assert!(probe(&[0u8; 4096]).worthwhile());
+        assert_eq!(probe(&[]).entropy, 0.0);
+    }
+}
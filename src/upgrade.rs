@@ -0,0 +1,174 @@
+//! Rolling upgrades without losing the running round.
+//!
+//! On SIGUSR2 the miner writes the current round of every mining context and the deadlines the
+//! pool already accepted in it to `upgrade_state_file`, then execs its binary again with
+//! `--upgrade` added. Replace the binary on disk first (e.g. `mv` the new build over it). The new
+//! process loads the file, rescans the round if it's still running and only submits deadlines
+//! better than the ones accepted before the upgrade.
+
+use crate::requests::RequestHandler;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[cfg(unix)]
+const UPGRADE_FLAG: &str = "--upgrade";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundSnapshot {
+    pub height: u64,
+    pub generation_signature: String,
+    pub account_id_to_best_deadline: HashMap<u64, u64>,
+}
+
+/// Snapshots by mining context name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpgradeState {
+    pub contexts: HashMap<String, RoundSnapshot>,
+}
+
+static HANDLERS: Mutex<Vec<(String, RequestHandler)>> = Mutex::new(Vec::new());
+static RESUMED: Mutex<Option<UpgradeState>> = Mutex::new(None);
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            error!("upgrade: mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+/// Makes a mining context part of the state saved on upgrade.
+pub fn register(name: &str, request_handler: RequestHandler) {
+    lock(&HANDLERS).push((name.to_owned(), request_handler));
+}
+
+/// Loads and removes the state left by the previous process.
+pub fn resume(state_file: &Path) {
+    let state = fs::read(state_file)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            serde_json::from_slice::<UpgradeState>(&data).map_err(|e| e.to_string())
+        });
+    match state {
+        Ok(state) => {
+            info!(
+                "upgrade: resuming {} mining context(s) from {}",
+                state.contexts.len(),
+                state_file.display()
+            );
+            *lock(&RESUMED) = Some(state);
+        }
+        Err(e) => warn!("upgrade: can't load {}: {}", state_file.display(), e),
+    }
+    let _ = fs::remove_file(state_file);
+}
+
+/// The deadlines accepted before the upgrade if `generation_signature` is the resumed round,
+/// returned only once per context.
+pub fn take_resumed(name: &str, generation_signature: &str) -> Option<HashMap<u64, u64>> {
+    let mut resumed = lock(&RESUMED);
+    let state = resumed.as_mut()?;
+    let snapshot = state.contexts.remove(name)?;
+    if snapshot
+        .generation_signature
+        .eq_ignore_ascii_case(generation_signature)
+    {
+        info!(
+            "upgrade: round {} still running, keeping {} accepted deadline(s)",
+            snapshot.height,
+            snapshot.account_id_to_best_deadline.len()
+        );
+        Some(snapshot.account_id_to_best_deadline)
+    } else {
+        None
+    }
+}
+
+fn save(state_file: &Path) -> io::Result<()> {
+    let state = UpgradeState {
+        contexts: lock(&HANDLERS)
+            .iter()
+            .filter_map(|(name, handler)| {
+                handler
+                    .round_snapshot()
+                    .map(|snapshot| (name.clone(), snapshot))
+            })
+            .collect(),
+    };
+    fs::write(state_file, serde_json::to_vec(&state)?)
+}
+
+/// Waits for SIGUSR2, saves the state and replaces this process with `exe`.
+#[cfg(unix)]
+pub async fn listen(exe: PathBuf, state_file: PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = match signal(SignalKind::user_defined2()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("upgrade: can't listen for SIGUSR2: {}", e);
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        info!("upgrade: SIGUSR2 received, restarting {}", exe.display());
+        if let Err(e) = save(&state_file) {
+            error!("upgrade: can't save state to {}: {}", state_file.display(), e);
+            continue;
+        }
+        let e = exec(&exe);
+        error!("upgrade: can't exec {}: {}", exe.display(), e);
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn listen(_exe: PathBuf, _state_file: PathBuf) {}
+
+#[cfg(unix)]
+fn exec(exe: &Path) -> io::Error {
+    use std::os::unix::process::CommandExt;
+
+    std::process::Command::new(exe).args(upgrade_args()).exec()
+}
+
+/// The arguments of this process, with `--upgrade` added once.
+#[cfg(unix)]
+fn upgrade_args() -> Vec<String> {
+    let mut args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != UPGRADE_FLAG)
+        .collect();
+    args.push(UPGRADE_FLAG.to_owned());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_resumed() {
+        let snapshot = RoundSnapshot {
+            height: 7,
+            generation_signature: "ABCD".to_owned(),
+            account_id_to_best_deadline: [(1, 100)].into_iter().collect(),
+        };
+        *lock(&RESUMED) = Some(UpgradeState {
+            contexts: [("".to_owned(), snapshot.clone()), ("b".to_owned(), snapshot)]
+                .into_iter()
+                .collect(),
+        });
+
+        assert_eq!(
+            take_resumed("", "abcd").map(|best| best.get(&1).copied()),
+            Some(Some(100))
+        );
+        assert_eq!(take_resumed("", "abcd"), None);
+        assert_eq!(take_resumed("b", "ef01"), None);
+    }
+}
@@ -1,12 +1,33 @@
-pub fn new_thread_pool(num_threads: usize, thread_pinning: bool) -> rayon::ThreadPool {
+use crate::topology::{topology, CoreSelection};
+
+// how long `lock_sync` waits between attempts on a held mutex
+#[cfg(feature = "async_io")]
+const LOCK_RETRY: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Locks an async mutex from synchronous code. Unlike `blocking_lock` this doesn't panic on a
+/// thread of the tokio runtime; buffers and plots are rarely contended, so the retry loop hardly
+/// ever runs.
+#[cfg(feature = "async_io")]
+pub fn lock_sync<T>(mutex: &tokio::sync::Mutex<T>) -> tokio::sync::MutexGuard<'_, T> {
+    loop {
+        if let Ok(guard) = mutex.try_lock() {
+            return guard;
+        }
+        std::thread::sleep(LOCK_RETRY);
+    }
+}
+
+pub fn new_thread_pool(
+    num_threads: usize,
+    thread_pinning: bool,
+    cores: CoreSelection,
+) -> rayon::ThreadPool {
     let core_ids = if thread_pinning {
-        match core_affinity::get_core_ids() {
-            Some(ids) => ids,
-            None => {
-                warn!("Failed to get core IDs for thread pinning, disabling pinning");
-                Vec::new()
-            }
+        let core_ids = topology().cores(cores);
+        if core_ids.is_empty() {
+            warn!("Failed to get core IDs for thread pinning, disabling pinning");
         }
+        core_ids
     } else {
         Vec::new()
     };
@@ -16,13 +37,8 @@ pub fn new_thread_pool(num_threads: usize, thread_pinning: bool) -> rayon::Threa
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_threads)
         .start_handler(move |id| {
-            if has_pinning {
-                #[cfg(not(windows))]
-                let core_id = core_ids[id % core_ids.len()];
-                #[cfg(not(windows))]
-                core_affinity::set_for_current(core_id);
-                #[cfg(windows)]
-                set_thread_ideal_processor(id % core_ids.len());
+            if has_pinning && !pin_current_thread(core_ids[id % core_ids.len()]) {
+                warn!("Failed to pin thread to core {}", core_ids[id % core_ids.len()]);
             }
         })
         .build()
@@ -35,8 +51,35 @@ pub fn new_thread_pool(num_threads: usize, thread_pinning: bool) -> rayon::Threa
         })
 }
 
+/// Maps a logical processor counted across all processor groups to its group and the index
+/// within the group, `groups` holds the number of active processors per group.
+#[cfg(any(windows, test))]
+fn processor_group_of(core: usize, groups: &[u32]) -> Option<(u16, u32)> {
+    let mut first = 0usize;
+    for (group, &count) in groups.iter().enumerate() {
+        if core < first + count as usize {
+            return Some((group as u16, (core - first) as u32));
+        }
+        first += count as usize;
+    }
+    None
+}
+
 cfg_if! {
     if #[cfg(unix)] {
+        /// Logical processors the miner may run on.
+        pub fn logical_processors() -> Vec<usize> {
+            core_affinity::get_core_ids()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|core| core.id)
+                .collect()
+        }
+
+        pub fn pin_current_thread(core: usize) -> bool {
+            core_affinity::set_for_current(core_affinity::CoreId { id: core })
+        }
+
         use std::process::Command;
 
         pub fn get_device_id(path: &str) -> String {
@@ -185,8 +228,10 @@ cfg_if! {
         }
     } else {
         use winapi;
-        use crate::utils::winapi::um::processthreadsapi::SetThreadIdealProcessor;
         use crate::utils::winapi::um::processthreadsapi::GetCurrentThread;
+        use crate::utils::winapi::um::processtopologyapi::SetThreadGroupAffinity;
+        use crate::utils::winapi::um::winbase::{GetActiveProcessorCount, GetActiveProcessorGroupCount};
+        use crate::utils::winapi::um::winnt::GROUP_AFFINITY;
         use std::os::windows::ffi::OsStrExt;
         use std::ffi::OsStr;
         use std::iter::once;
@@ -301,14 +346,31 @@ cfg_if! {
             .to_string()
         }
 
-        pub fn set_thread_ideal_processor(id: usize){
-            // Set core affinity for current thread.
-        unsafe {
-            SetThreadIdealProcessor(
-                GetCurrentThread(),
-                id as u32
-            );
+        fn processor_groups() -> Vec<u32> {
+            let group_count = unsafe { GetActiveProcessorGroupCount() };
+            (0..group_count)
+                .map(|group| unsafe { GetActiveProcessorCount(group) })
+                .collect()
+        }
 
+        /// Logical processors of all processor groups, machines with more than 64 of them have
+        /// several groups and a process only starts out in one.
+        pub fn logical_processors() -> Vec<usize> {
+            (0..processor_groups().iter().map(|&count| count as usize).sum()).collect()
+        }
+
+        /// Pins the current thread with a group affinity, SetThreadIdealProcessor and
+        /// SetThreadAffinityMask only address the first group.
+        pub fn pin_current_thread(core: usize) -> bool {
+            let (group, index) = match processor_group_of(core, &processor_groups()) {
+                Some(x) => x,
+                None => return false,
+            };
+            unsafe {
+                let mut affinity: GROUP_AFFINITY = std::mem::zeroed();
+                affinity.Mask = 1 << index;
+                affinity.Group = group;
+                SetThreadGroupAffinity(GetCurrentThread(), &affinity, std::ptr::null_mut()) != 0
             }
         }
     }
@@ -322,10 +384,20 @@ mod test {
     #[test]
     fn test_get_device_id() {
         if cfg!(unix) {
-            assert_ne!("", get_device_id(&"Cargo.toml".to_string()));
+            assert_ne!("", get_device_id("Cargo.toml"));
         }
     }
 
+    #[test]
+    fn test_processor_group_of() {
+        let groups = [64, 64, 16];
+        assert_eq!(processor_group_of(0, &groups), Some((0, 0)));
+        assert_eq!(processor_group_of(63, &groups), Some((0, 63)));
+        assert_eq!(processor_group_of(64, &groups), Some((1, 0)));
+        assert_eq!(processor_group_of(143, &groups), Some((2, 15)));
+        assert_eq!(processor_group_of(144, &groups), None);
+    }
+
     #[test]
     fn test_get_sector_size() {
         // this should be true for any platform where this test runs
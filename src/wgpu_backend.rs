@@ -0,0 +1,361 @@
+//! Portable GPU backend on top of wgpu (Vulkan, Metal, DX12).
+//!
+//! Uses the host buffer worker like the Metal backend. The work group size is autotuned per
+//! context unless configured, and every context runs a self-test against a known deadline before
+//! it is used for mining.
+
+use crate::config::Cfg;
+use crate::hardware::GpuReport;
+use crate::gpu_worker_host::{find_min, HostGpu};
+use std::process;
+use std::sync::mpsc as std_mpsc;
+use std::time::{Duration, Instant};
+
+static SRC: &str = include_str!("wgpu_backend/kernel.wgsl");
+const SCOOP_SIZE: u64 = 64;
+const WORKGROUP_SIZES: [u32; 4] = [32, 64, 128, 256];
+const TEST_GENSIG: &str = "4a6f686e6e7946464d206861742064656e206772f6df74656e2050656e697321";
+const TEST_DEADLINE: u64 = 18043101931632730606;
+
+fn adapters() -> Vec<wgpu::Adapter> {
+    wgpu::Instance::new(wgpu::InstanceDescriptor::default())
+        .enumerate_adapters(wgpu::Backends::all())
+}
+
+pub fn platform_info() {
+    for (i, adapter) in adapters().iter().enumerate() {
+        let info = adapter.get_info();
+        info!(
+            "wgpu: device {}, {} - {:?} ({:?})",
+            i, info.name, info.backend, info.device_type
+        );
+    }
+}
+
+pub fn gpu_report() -> Vec<GpuReport> {
+    adapters()
+        .iter()
+        .map(|adapter| {
+            let info = adapter.get_info();
+            GpuReport {
+                name: info.name,
+                vendor: format!("{:#06x}", info.vendor),
+                driver: format!("{} {} ({:?})", info.driver, info.driver_info, info.backend),
+            }
+        })
+        .collect()
+}
+
+pub fn gpu_info(cfg: &Cfg) {
+    if cfg.gpu_worker_task_count > 0 {
+        let adapter = match adapters().into_iter().nth(cfg.gpu_device) {
+            Some(adapter) => adapter,
+            None => {
+                error!("wgpu: Selected device doesn't exist. Shutting down...");
+                process::exit(0);
+            }
+        };
+        let info = adapter.get_info();
+        info!("GPU: {} ({:?})", info.name, info.backend);
+
+        let limits = adapter.limits();
+        if (cfg.gpu_nonces_per_cache as u64 * SCOOP_SIZE)
+            > u64::from(limits.max_storage_buffer_binding_size)
+        {
+            error!(
+                "GPU: gpu_nonces_per_cache exceeds the device's storage buffer limit of {}MiB. \
+                 Shutting down...",
+                limits.max_storage_buffer_binding_size / 1024 / 1024
+            );
+            process::exit(0);
+        }
+    } else if cfg.cpu_worker_task_count == 0 {
+        error!("CPU, GPU: no workers configured. Shutting down...");
+        process::exit(0);
+    }
+}
+
+struct Pipeline {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    workgroup_size: u32,
+}
+
+pub struct WgpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: Pipeline,
+    params: wgpu::Buffer,
+    scoops: wgpu::Buffer,
+    deadlines: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    nonces_per_cache: usize,
+}
+
+impl WgpuContext {
+    /// Creates a context on the `gpu_device`th adapter, `workgroup_size` 0 autotunes it.
+    pub fn new(
+        gpu_device: usize,
+        nonces_per_cache: usize,
+        workgroup_size: u32,
+    ) -> Result<WgpuContext, String> {
+        let adapter = adapters()
+            .into_iter()
+            .nth(gpu_device)
+            .ok_or_else(|| "wgpu device doesn't exist".to_owned())?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+            },
+            None,
+        ))
+        .map_err(|e| e.to_string())?;
+
+        let storage = |size: u64, usage: wgpu::BufferUsages| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let params = storage(
+            36,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let scoops = storage(
+            SCOOP_SIZE * nonces_per_cache as u64,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let deadlines = storage(
+            8 * nonces_per_cache as u64,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let staging = storage(
+            8 * nonces_per_cache as u64,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let limits = device.limits();
+        let candidates: Vec<u32> = if workgroup_size > 0 {
+            vec![workgroup_size]
+        } else {
+            WORKGROUP_SIZES
+                .iter()
+                .copied()
+                .filter(|&size| {
+                    size <= limits.max_compute_invocations_per_workgroup
+                        && size <= limits.max_compute_workgroup_size_x
+                })
+                .collect()
+        };
+        let first = *candidates
+            .first()
+            .ok_or_else(|| "no usable work group size".to_owned())?;
+        if (nonces_per_cache as u32).div_ceil(first) > limits.max_compute_workgroups_per_dimension
+        {
+            return Err("gpu_nonces_per_cache too large for the device".to_owned());
+        }
+
+        let pipeline = build_pipeline(&device, first, &params, &scoops, &deadlines);
+        let mut context = WgpuContext {
+            device,
+            queue,
+            pipeline,
+            params,
+            scoops,
+            deadlines,
+            staging,
+            nonces_per_cache,
+        };
+        if candidates.len() > 1 {
+            context.autotune(&candidates);
+        }
+        context.self_test()?;
+        Ok(context)
+    }
+
+    /// Benchmarks every candidate work group size on a full batch and keeps the fastest.
+    fn autotune(&mut self, candidates: &[u32]) {
+        let data = vec![0x5au8; self.nonces_per_cache * SCOOP_SIZE as usize];
+        let gensig = [0xa5u8; 32];
+        let mut best: Option<(Duration, u32)> = None;
+        for &size in candidates {
+            self.pipeline = build_pipeline(
+                &self.device,
+                size,
+                &self.params,
+                &self.scoops,
+                &self.deadlines,
+            );
+            // warm up
+            self.hash(&data, &gensig);
+            let start = Instant::now();
+            for _ in 0..3 {
+                self.hash(&data, &gensig);
+            }
+            let elapsed = start.elapsed();
+            debug!("wgpu: work group size {}: {:?}", size, elapsed / 3);
+            if !matches!(best, Some((fastest, _)) if fastest <= elapsed) {
+                best = Some((elapsed, size));
+            }
+        }
+
+        if let Some((_, size)) = best {
+            if size != self.pipeline.workgroup_size {
+                self.pipeline = build_pipeline(
+                    &self.device,
+                    size,
+                    &self.params,
+                    &self.scoops,
+                    &self.deadlines,
+                );
+            }
+            info!("wgpu: autotuned work group size={}", size);
+        }
+    }
+
+    fn self_test(&self) -> Result<(), String> {
+        let mut gensig = [0u8; 32];
+        gensig.copy_from_slice(&hex::decode(TEST_GENSIG).unwrap());
+        let mut data = [0u8; 64 * 16];
+        for chunk in data.chunks_exact_mut(32) {
+            chunk.copy_from_slice(&gensig);
+        }
+
+        let (deadline, _) = self.hash(&data, &gensig);
+        if deadline != TEST_DEADLINE {
+            return Err(format!(
+                "wgpu self-test failed: got deadline {}, expected {}",
+                deadline, TEST_DEADLINE
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn build_pipeline(
+    device: &wgpu::Device,
+    workgroup_size: u32,
+    params: &wgpu::Buffer,
+    scoops: &wgpu::Buffer,
+    deadlines: &wgpu::Buffer,
+) -> Pipeline {
+    let src = SRC.replace("WORKGROUP_SIZE", &workgroup_size.to_string());
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("calculate_deadlines"),
+        source: wgpu::ShaderSource::Wgsl(src.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("calculate_deadlines"),
+        layout: None,
+        module: &module,
+        entry_point: "calculate_deadlines",
+        compilation_options: Default::default(),
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: scoops.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: deadlines.as_entire_binding(),
+            },
+        ],
+    });
+    Pipeline {
+        pipeline,
+        bind_group,
+        workgroup_size,
+    }
+}
+
+impl HostGpu for WgpuContext {
+    fn hash(&self, data: &[u8], gensig: &[u8; 32]) -> (u64, u64) {
+        let nonce_count = (data.len() / SCOOP_SIZE as usize).min(self.nonces_per_cache);
+        if nonce_count == 0 {
+            return (u64::MAX, 0);
+        }
+
+        let mut params = [0u8; 36];
+        params[..32].copy_from_slice(gensig);
+        params[32..].copy_from_slice(&(nonce_count as u32).to_le_bytes());
+        self.queue.write_buffer(&self.params, 0, &params);
+        self.queue
+            .write_buffer(&self.scoops, 0, &data[..nonce_count * SCOOP_SIZE as usize]);
+
+        let result_size = 8 * nonce_count as u64;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline.pipeline);
+            pass.set_bind_group(0, &self.pipeline.bind_group, &[]);
+            pass.dispatch_workgroups(
+                (nonce_count as u32).div_ceil(self.pipeline.workgroup_size),
+                1,
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&self.deadlines, 0, &self.staging, 0, result_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.staging.slice(..result_size);
+        let (tx, rx) = std_mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        let _ = self.device.poll(wgpu::Maintain::Wait);
+        match rx.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                error!("wgpu: can't read deadlines: {}", e);
+                return (u64::MAX, 0);
+            }
+            Err(e) => {
+                error!("wgpu: can't read deadlines: {}", e);
+                return (u64::MAX, 0);
+            }
+        }
+
+        let best = {
+            let mapped = slice.get_mapped_range();
+            // (low, high) word pairs, i.e. little endian u64
+            find_min(
+                mapped
+                    .chunks_exact(8)
+                    .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())),
+            )
+        };
+        self.staging.unmap();
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test_on_first_adapter() {
+        // machines without any adapter (CI) have nothing to test
+        if adapters().is_empty() {
+            return;
+        }
+        WgpuContext::new(0, 64, 0).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+// WGSL port of ../ocl/kernel.cl, only the deadline calculation. WGSL has no 64 bit integers,
+// deadlines are written as (low, high) pairs and the minimum is searched on the CPU.
+// WORKGROUP_SIZE is substituted before the module is compiled.
+
+// gensig (8 words) followed by the number of nonces in the batch
+@group(0) @binding(0) var<storage, read> params: array<u32>;
+@group(0) @binding(1) var<storage, read> scoop_data: array<u32>;
+@group(0) @binding(2) var<storage, read_write> deadlines: array<vec2<u32>>;
+
+const A_INIT = array<u32, 12>(
+    0x52F84552u, 0xE54B7999u, 0x2D8EE3ECu, 0xB9645191u,
+    0xE0078B86u, 0xBB7C44C9u, 0xD2B5C1CAu, 0xB0D2EB8Cu,
+    0x14CE5A45u, 0x22AF50DCu, 0xEFFDBC6Bu, 0xEB21B74Au
+);
+
+const B_INIT = array<u32, 16>(
+    0xB555C6EEu, 0x3E710596u, 0xA72A652Fu, 0x9301515Fu,
+    0xDA28C1FAu, 0x696FD868u, 0x9CB6BF72u, 0x0AFE4002u,
+    0xA6E03615u, 0x5138C1D4u, 0xBE216306u, 0xB38B8890u,
+    0x3EA8B96Bu, 0x3299ACE4u, 0x30924DD4u, 0x55CB34A5u
+);
+
+const C_INIT = array<u32, 16>(
+    0xB405F031u, 0xC4233EBAu, 0xB3733979u, 0xC0DD9D55u,
+    0xC51C28AEu, 0xA327B8E1u, 0x56C56167u, 0xED614433u,
+    0x88B59D60u, 0x60E2CEBAu, 0x758B4B8Bu, 0x83E82A7Fu,
+    0xBC968828u, 0xE6E00BF7u, 0xBA839E55u, 0x9B491C60u
+);
+
+struct State {
+    a: array<u32, 12>,
+    b: array<u32, 16>,
+    c: array<u32, 16>,
+    m: array<u32, 16>,
+    w_low: u32,
+    w_high: u32,
+}
+
+fn rotl(x: u32, n: u32) -> u32 {
+    return (x << n) | (x >> (32u - n));
+}
+
+fn input_block_add(s: ptr<function, State>) {
+    for (var i = 0u; i < 16u; i++) {
+        (*s).b[i] = (*s).b[i] + (*s).m[i];
+    }
+}
+
+fn input_block_sub(s: ptr<function, State>) {
+    for (var i = 0u; i < 16u; i++) {
+        (*s).c[i] = (*s).c[i] - (*s).m[i];
+    }
+}
+
+fn xor_w(s: ptr<function, State>) {
+    (*s).a[0] ^= (*s).w_low;
+    (*s).a[1] ^= (*s).w_high;
+}
+
+fn swap_bc(s: ptr<function, State>) {
+    let tmp = (*s).b;
+    (*s).b = (*s).c;
+    (*s).c = tmp;
+}
+
+fn apply_p(s: ptr<function, State>) {
+    for (var i = 0u; i < 16u; i++) {
+        (*s).b[i] = rotl((*s).b[i], 17u);
+    }
+    for (var j = 0u; j < 48u; j++) {
+        let xa0 = j % 12u;
+        let xa1 = (j + 11u) % 12u;
+        let xb0 = j % 16u;
+        let xb1 = (j + 13u) % 16u;
+        let xb2 = (j + 9u) % 16u;
+        let xb3 = (j + 6u) % 16u;
+        let xc = (56u - j) % 16u;
+        (*s).a[xa0] = (((*s).a[xa0] ^ (rotl((*s).a[xa1], 15u) * 5u) ^ (*s).c[xc]) * 3u)
+            ^ (*s).b[xb1] ^ ((*s).b[xb2] & ~(*s).b[xb3]) ^ (*s).m[xb0];
+        (*s).b[xb0] = ~(rotl((*s).b[xb0], 1u) ^ (*s).a[xa0]);
+    }
+    for (var k = 0u; k < 36u; k++) {
+        let xa = 11u - k % 12u;
+        let xc = (54u - k) % 16u;
+        (*s).a[xa] = (*s).a[xa] + (*s).c[xc];
+    }
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn calculate_deadlines(@builtin(global_invocation_id) id: vec3<u32>) {
+    let gid = id.x;
+    if (gid >= params[8]) {
+        return;
+    }
+
+    var s: State;
+    s.a = A_INIT;
+    s.b = B_INIT;
+    s.c = C_INIT;
+    s.w_low = 1u;
+    s.w_high = 0u;
+
+    for (var i = 0u; i < 8u; i++) {
+        s.m[i] = params[i];
+        s.m[i + 8u] = scoop_data[gid * 16u + i];
+    }
+
+    input_block_add(&s);
+    xor_w(&s);
+    apply_p(&s);
+    input_block_sub(&s);
+    swap_bc(&s);
+    s.w_low = s.w_low + 1u;
+
+    for (var i = 0u; i < 8u; i++) {
+        s.m[i] = scoop_data[gid * 16u + 8u + i];
+        s.m[i + 8u] = 0u;
+    }
+    s.m[8] = 0x80u;
+
+    input_block_add(&s);
+    xor_w(&s);
+    apply_p(&s);
+    for (var i = 0u; i < 3u; i++) {
+        swap_bc(&s);
+        xor_w(&s);
+        apply_p(&s);
+    }
+
+    deadlines[gid] = vec2<u32>(s.b[8], s.b[9]);
+}
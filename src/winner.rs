@@ -0,0 +1,83 @@
+//! How the miner's best deadline compares with the block winner's.
+//!
+//! After every block the winning block is fetched from the node. The winner's deadline is the time
+//! between the block and its predecessor. The ratio of the miner's best deadline to the winner's
+//! is roughly the factor of capacity the miner lacked, deadlines shrink in proportion to capacity.
+//! A best deadline below the winner's that didn't win means the deadline came too late, faster
+//! scans would have mattered rather than more capacity.
+
+use std::collections::VecDeque;
+
+/// Blocks the ratio median is taken over.
+const HISTORY: usize = 360;
+
+#[derive(Debug, Clone, Default)]
+pub struct WinnerStats {
+    // best deadline / winner's deadline of the recent blocks
+    ratios: VecDeque<f64>,
+    pub blocks: u64,
+    /// Blocks where the best deadline beat the winner's without winning.
+    pub beaten: u64,
+}
+
+impl WinnerStats {
+    /// Records a block, deadlines in seconds. The ratio of best to winning deadline is returned.
+    pub fn record(&mut self, best_deadline: u64, winner_deadline: u64) -> f64 {
+        let ratio = best_deadline as f64 / winner_deadline.max(1) as f64;
+        self.blocks += 1;
+        if best_deadline < winner_deadline {
+            self.beaten += 1;
+        }
+        self.ratios.push_back(ratio);
+        while self.ratios.len() > HISTORY {
+            self.ratios.pop_front();
+        }
+        ratio
+    }
+
+    /// Keeps the ratios of the `keep` most recent blocks.
+    pub fn truncate(&mut self, keep: usize) {
+        while self.ratios.len() > keep {
+            self.ratios.pop_front();
+        }
+    }
+
+    pub fn median_ratio(&self) -> Option<f64> {
+        if self.ratios.is_empty() {
+            return None;
+        }
+        let mut ratios: Vec<f64> = self.ratios.iter().copied().collect();
+        ratios.sort_by(|a, b| a.total_cmp(b));
+        Some(ratios[ratios.len() / 2])
+    }
+
+    pub fn summary(&self) -> Option<String> {
+        let median = self.median_ratio()?;
+        Some(format!(
+            "best deadline {:.1}x the winner's (median of {} blocks), {} of {} blocks beaten \
+             but lost",
+            median,
+            self.ratios.len(),
+            self.beaten,
+            self.blocks
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winner_stats() {
+        let mut stats = WinnerStats::default();
+        assert_eq!(stats.summary(), None);
+        assert_eq!(stats.record(400, 200), 2.0);
+        stats.record(1000, 100);
+        stats.record(50, 100);
+        assert_eq!(stats.median_ratio(), Some(2.0));
+        assert_eq!((stats.blocks, stats.beaten), (3, 1));
+        // a winner's deadline of 0 doesn't divide by zero
+        assert_eq!(stats.record(10, 0), 10.0);
+    }
+}